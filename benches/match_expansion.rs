@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use fungus_cli::{detect_plagiarism, DetectionOptions, File};
+
+/// Generates a large assembly file. Every `divergence`th line is specific to the given seed, so that the files of
+/// different seeds share long runs of code separated by small differences.
+fn generate_file(num_lines: usize, divergence: usize, seed: usize) -> String {
+    (0..num_lines)
+        .map(|i| {
+            if i % divergence == 0 {
+                format!("ldr r{}, =label_{seed}_{i}\n", i % 13)
+            } else {
+                format!(
+                    "add r{}, r{}, #{}\n",
+                    i % 13,
+                    (i * 7) % 13,
+                    (i * 7919) % 10007
+                )
+            }
+        })
+        .collect()
+}
+
+/// Number of lines of every generated file.
+const NUM_LINES: usize = 10_000;
+
+fn files(num_lines: usize) -> Vec<File> {
+    (0..2)
+        .map(|seed| {
+            File::new(
+                format!("P{seed}").into(),
+                format!("P{seed}/main.s").into(),
+                generate_file(num_lines, 100, seed),
+            )
+        })
+        .collect()
+}
+
+fn match_expansion(c: &mut Criterion) {
+    let options = DetectionOptions::default();
+    let documents = files(NUM_LINES);
+    // Starter code at the end of the files, so that its spans are found far from the start of the token list
+    let starter_code = generate_file(NUM_LINES, 100, 0)
+        .lines()
+        .skip(NUM_LINES - 500)
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    let ignored_documents = vec![File::new(
+        "starter".into(),
+        "starter/main.s".into(),
+        starter_code,
+    )];
+
+    let mut group = c.benchmark_group("match_expansion");
+    group.sample_size(10);
+    group.bench_function("expand_matches", |b| {
+        b.iter(|| detect_plagiarism(&options, &documents, &[]))
+    });
+    group.bench_function("remove_ignored_documents", |b| {
+        b.iter(|| detect_plagiarism(&options, &documents, &ignored_documents))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, match_expansion);
+criterion_main!(benches);
@@ -0,0 +1,207 @@
+//! Pairs of projects which are allowed to be similar (e.g., declared collaborations). They are removed from the report,
+//! but still recorded so that the allowlist itself can be audited.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use crate::output::{AllowedPair, Output, ProjectPair};
+
+/// Unordered pairs of projects which are allowed to be similar.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AllowedPairs(BTreeSet<(PathBuf, PathBuf)>);
+
+impl AllowedPairs {
+    pub fn insert(&mut self, project1: PathBuf, project2: PathBuf) {
+        if project1 <= project2 {
+            self.0.insert((project1, project2));
+        } else {
+            self.0.insert((project2, project1));
+        }
+    }
+
+    pub fn contains(&self, project1: &Path, project2: &Path) -> bool {
+        let (p1, p2) = if project1 <= project2 {
+            (project1, project2)
+        } else {
+            (project2, project1)
+        };
+        self.0.contains(&(p1.to_owned(), p2.to_owned()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Parses a pair of project names given as `project1:project2`.
+pub fn parse_pair(s: &str) -> Result<(String, String), String> {
+    let (project1, project2) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected \"project1:project2\", found \"{s}\""))?;
+    check_pair(s, project1.trim().to_owned(), project2.trim().to_owned())
+}
+
+/// Parses a CSV file with one pair of project names per line. Names containing commas must be quoted. Blank lines are
+/// skipped.
+pub fn parse_pairs_csv(contents: &str) -> Result<Vec<(String, String)>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let pair = match split_csv_fields(line)?.as_slice() {
+                [project1, project2] => check_pair(line, project1.clone(), project2.clone()),
+                fields => Err(format!(
+                    "expected two project names, found {} fields in \"{line}\"",
+                    fields.len()
+                )),
+            };
+            pair.map_err(|e| format!("line {}: {e}", i + 1))
+        })
+        .collect()
+}
+
+pub(crate) fn check_pair(
+    s: &str,
+    project1: String,
+    project2: String,
+) -> Result<(String, String), String> {
+    if project1.is_empty() || project2.is_empty() || project1 == project2 {
+        return Err(format!(
+            "expected two different project names, found \"{s}\""
+        ));
+    }
+    Ok((project1, project2))
+}
+
+/// Splits a CSV line into its fields, removing the quotes around quoted fields and the whitespace around unquoted ones.
+pub(crate) fn split_csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ').is_some() {}
+        let mut field = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(format!("unterminated quote in \"{line}\"")),
+                }
+            }
+            while chars.next_if(|c| *c == ' ').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+            field.truncate(field.trim_end().len());
+        }
+        fields.push(field);
+        match chars.next() {
+            Some(',') => {}
+            None => return Ok(fields),
+            Some(c) => {
+                return Err(format!(
+                    "unexpected \"{c}\" after a quoted field in \"{line}\""
+                ))
+            }
+        }
+    }
+}
+
+impl Output {
+    /// Moves the allowed pairs out of every section of the output, recording each pair of `project_pairs` in
+    /// `allowed_pairs` along with the score by which it would have been ranked.
+    pub fn remove_allowed_pairs(&mut self, allowed: &AllowedPairs) {
+        let (removed, kept): (Vec<ProjectPair>, Vec<ProjectPair>) =
+            std::mem::take(&mut self.project_pairs)
+                .into_iter()
+                .partition(|p| allowed.contains(&p.project1, &p.project2));
+        self.project_pairs = kept;
+        self.allowed_pairs = Some(
+            removed
+                .into_iter()
+                .map(|p| AllowedPair {
+                    num_matches: p.matches.len(),
+                    score: p.ranking_score(),
+                    project1: p.project1,
+                    project2: p.project2,
+                })
+                .collect(),
+        );
+
+        for pairs in self.stylometry.iter_mut() {
+            pairs.retain(|p| !allowed.contains(&p.project1, &p.project2));
+        }
+        for pairs in self.formatting.iter_mut() {
+            pairs.retain(|p| !allowed.contains(&p.project1, &p.project2));
+        }
+        for pairs in self.string_literals.iter_mut() {
+            pairs.retain(|p| !allowed.contains(&p.project1, &p.project2));
+        }
+        for pairs in self.composite.iter_mut() {
+            pairs.retain(|p| !allowed.contains(&p.project1, &p.project2));
+        }
+        for pairs in self.external_scores.iter_mut() {
+            pairs.retain(|p| !allowed.contains(&p.project1, &p.project2));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pairs() {
+        assert_eq!(
+            parse_pair("alice:bob"),
+            Ok(("alice".to_owned(), "bob".to_owned()))
+        );
+        assert!(parse_pair("alice").is_err());
+        assert!(parse_pair("alice:alice").is_err());
+        assert_eq!(
+            parse_pairs_csv("alice, bob\n\n\"carol, \"\"jr\"\"\",dave\n"),
+            Ok(vec![
+                ("alice".to_owned(), "bob".to_owned()),
+                ("carol, \"jr\"".to_owned(), "dave".to_owned())
+            ])
+        );
+        assert_eq!(
+            parse_pairs_csv("alice,bob\ncarol\n"),
+            Err("line 2: expected two project names, found 1 fields in \"carol\"".to_owned())
+        );
+        assert!(parse_pairs_csv("\"alice,bob").is_err());
+    }
+
+    #[test]
+    fn allowed_pairs_are_recorded() {
+        let pair = |project1: &str, project2: &str| ProjectPair {
+            project1: project1.into(),
+            project2: project2.into(),
+            matches: Vec::new(),
+            score: Some(2.5),
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        let mut output = Output::new(Vec::new(), vec![pair("a", "b"), pair("a", "c")]);
+        let mut allowed = AllowedPairs::default();
+        allowed.insert("b".into(), "a".into());
+
+        output.remove_allowed_pairs(&allowed);
+        assert_eq!(output.project_pairs, vec![pair("a", "c")]);
+        assert_eq!(
+            output.allowed_pairs,
+            Some(vec![AllowedPair {
+                project1: "a".into(),
+                project2: "b".into(),
+                num_matches: 0,
+                score: 2.5,
+            }])
+        );
+    }
+}
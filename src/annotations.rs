@@ -0,0 +1,131 @@
+//! Review decisions about project pairs (e.g., written by hand while triaging the results), merged into the output so
+//! that the exported reports reflect them.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::output::{Output, ProjectPair};
+
+/// Review decision about the pair of the two given projects, in either order.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Annotation {
+    pub projects: (PathBuf, PathBuf),
+    #[serde(flatten)]
+    pub review: Review,
+}
+
+/// Outcome of the review of a project pair.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Review {
+    pub status: ReviewStatus,
+    /// Free-form notes of the reviewer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    /// The pair still needs to be looked at more closely.
+    Pending,
+    /// The similarity was judged to be copying.
+    Confirmed,
+    /// The similarity was judged to be legitimate (e.g., common code or allowed collaboration).
+    Dismissed,
+}
+
+impl ReviewStatus {
+    pub fn description(&self) -> &'static str {
+        match self {
+            ReviewStatus::Pending => "pending",
+            ReviewStatus::Confirmed => "confirmed",
+            ReviewStatus::Dismissed => "dismissed",
+        }
+    }
+}
+
+impl Output {
+    /// Attaches each annotation to the project pair it names. Returns the annotations which do not name any of the
+    /// project pairs (e.g., pairs which are no longer reported).
+    pub fn add_annotations(&mut self, annotations: Vec<Annotation>) -> Vec<Annotation> {
+        let mut reviews = annotations
+            .into_iter()
+            .map(|a| (pair_key(&a.projects.0, &a.projects.1), a))
+            .collect::<HashMap<_, _>>();
+        for pair in self.project_pairs.iter_mut() {
+            if let Some(annotation) = reviews.remove(&pair_key(&pair.project1, &pair.project2)) {
+                pair.review = Some(annotation.review);
+            }
+        }
+        let mut unmatched = reviews.into_values().collect::<Vec<_>>();
+        unmatched.sort_by(|a1, a2| a1.projects.cmp(&a2.projects));
+        unmatched
+    }
+}
+
+/// Returns the review of the given pair, if one of the annotations names it. As with `Output::add_annotations`, the
+/// last annotation naming a pair takes precedence.
+pub fn review_for<'a>(annotations: &'a [Annotation], pair: &ProjectPair) -> Option<&'a Review> {
+    let key = pair_key(&pair.project1, &pair.project2);
+    annotations
+        .iter()
+        .rev()
+        .find(|a| pair_key(&a.projects.0, &a.projects.1) == key)
+        .map(|a| &a.review)
+}
+
+/// Returns the two projects of a pair in a fixed order.
+fn pair_key(project1: &Path, project2: &Path) -> (PathBuf, PathBuf) {
+    (
+        project1.min(project2).to_owned(),
+        project1.max(project2).to_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotations_are_attached_to_their_pairs() {
+        let pair = |project1: &str, project2: &str| ProjectPair {
+            project1: project1.into(),
+            project2: project2.into(),
+            matches: Vec::new(),
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        let annotations: Vec<Annotation> = serde_json::from_str(
+            r#"[
+                { "projects": ["b", "a"], "status": "confirmed", "notes": "Same comments" },
+                { "projects": ["c", "d"], "status": "dismissed" }
+            ]"#,
+        )
+        .unwrap();
+        let mut output = Output::new(Vec::new(), vec![pair("a", "b"), pair("a", "c")]);
+        assert_eq!(
+            review_for(&annotations, &pair("a", "b")).map(|r| r.status),
+            Some(ReviewStatus::Confirmed)
+        );
+        assert_eq!(review_for(&annotations, &pair("a", "c")), None);
+
+        let unmatched = output.add_annotations(annotations);
+
+        assert_eq!(
+            output.project_pairs[0].review,
+            Some(Review {
+                status: ReviewStatus::Confirmed,
+                notes: Some("Same comments".to_owned())
+            })
+        );
+        assert_eq!(output.project_pairs[1].review, None);
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].projects, ("c".into(), "d".into()));
+    }
+}
@@ -0,0 +1,149 @@
+//! Selection of a single attempt of the projects which contain several attempt subdirectories (e.g., "attempt1" and
+//! "attempt2"), so that a student's resubmissions are not matched against each other.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::File;
+
+/// Which attempts of each project are analyzed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum AttemptPolicy {
+    /// Every attempt.
+    All,
+    /// The attempt with the highest number (e.g., "attempt10" rather than "attempt9").
+    LatestByName,
+    /// The attempt containing the most recently modified file.
+    LatestByMtime,
+}
+
+/// Prefix of the names of attempt subdirectories, which is followed by the number of the attempt.
+const ATTEMPT_PREFIX: &str = "attempt";
+
+/// Returns the number of an attempt subdirectory (e.g., 2 for "attempt2", "Attempt_2", or "attempt-2"), if the name is
+/// that of an attempt subdirectory.
+pub fn attempt_number(name: &str) -> Option<u64> {
+    let prefix = name.get(..ATTEMPT_PREFIX.len())?;
+    if !prefix.eq_ignore_ascii_case(ATTEMPT_PREFIX) {
+        return None;
+    }
+    let number = name[ATTEMPT_PREFIX.len()..].trim_start_matches(['_', '-', ' ']);
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// Removes the files of every attempt but the latest one from each project which contains several attempt
+/// subdirectories directly inside its directory. The files of a project which are outside its attempt subdirectories are
+/// kept. Returns the number of attempts removed.
+pub fn keep_latest_attempts(documents: &mut Vec<File>, policy: AttemptPolicy) -> usize {
+    if policy == AttemptPolicy::All {
+        return 0;
+    }
+
+    // Number and latest modification time of each attempt of each project
+    let mut attempts: BTreeMap<&Path, BTreeMap<&OsStr, (u64, Option<SystemTime>)>> =
+        BTreeMap::new();
+    for document in documents.iter() {
+        let Some((name, number)) = attempt_of(document) else {
+            continue;
+        };
+        let modified = match policy {
+            AttemptPolicy::LatestByMtime => fs::metadata(document.path())
+                .and_then(|m| m.modified())
+                .ok(),
+            _ => None,
+        };
+        let attempt = attempts
+            .entry(document.project())
+            .or_default()
+            .entry(name)
+            .or_insert((number, None));
+        attempt.1 = attempt.1.max(modified);
+    }
+
+    let mut latest: HashMap<PathBuf, OsString> = HashMap::new();
+    let mut num_removed = 0;
+    for (project, project_attempts) in &attempts {
+        if project_attempts.len() < 2 {
+            continue;
+        }
+        num_removed += project_attempts.len() - 1;
+        // Ties are broken by the number of the attempt, then by its name
+        let (name, _) = project_attempts
+            .iter()
+            .max_by_key(|(name, (number, modified))| match policy {
+                AttemptPolicy::LatestByMtime => (*modified, *number, *name),
+                _ => (None, *number, *name),
+            })
+            .unwrap();
+        latest.insert(project.to_path_buf(), name.to_os_string());
+    }
+
+    documents.retain(|d| match (latest.get(d.project()), attempt_of(d)) {
+        (Some(latest), Some((name, _))) => name == latest,
+        _ => true,
+    });
+    num_removed
+}
+
+/// Returns the name and number of the attempt subdirectory containing the file, if any.
+fn attempt_of(document: &File) -> Option<(&OsStr, u64)> {
+    let relative_path = document.path().strip_prefix(document.project()).ok()?;
+    let mut components = relative_path.components();
+    let Some(Component::Normal(name)) = components.next() else {
+        return None;
+    };
+    // A file named like an attempt is not an attempt subdirectory
+    components.next()?;
+    Some((name, attempt_number(name.to_str()?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_latest_attempt_is_kept() {
+        let file = |path: &str| {
+            let project = path.split('/').take(2).collect::<Vec<_>>().join("/");
+            File::new(project.into(), path.into(), String::new())
+        };
+        let mut documents = vec![
+            file("root/alice/attempt2/a.s"),
+            file("root/alice/attempt10/a.s"),
+            file("root/alice/attempt10/lib/b.s"),
+            file("root/alice/README.s"),
+            file("root/bob/attempt1/a.s"),
+            file("root/carol/a.s"),
+        ];
+
+        let num_removed = keep_latest_attempts(&mut documents, AttemptPolicy::LatestByName);
+        assert_eq!(num_removed, 1);
+        let paths = documents
+            .iter()
+            .map(|d| d.path().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "root/alice/attempt10/a.s",
+                "root/alice/attempt10/lib/b.s",
+                "root/alice/README.s",
+                "root/bob/attempt1/a.s",
+                "root/carol/a.s",
+            ]
+        );
+
+        assert_eq!(attempt_number("Attempt_3"), Some(3));
+        assert_eq!(attempt_number("attempts"), None);
+        assert_eq!(attempt_number("attempt"), None);
+    }
+}
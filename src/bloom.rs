@@ -0,0 +1,175 @@
+//! Bloom filters of the projects' fingerprints, used to skip the pairs of projects which share too few hashes before
+//! computing their matches.
+//!
+//! The number of hashes shared by two projects is estimated from the number of bits set in their filters and in the
+//! union of their filters. The estimate is approximate, so a pair sharing slightly more hashes than the threshold may
+//! still be skipped.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    identity_hash::{IdentityHashKey, IdentityHashMap},
+    interning::PathId,
+    lsh, FileId,
+};
+
+/// Number of bits per element of the largest set, which gives a false positive rate of about 1%.
+const BITS_PER_ELEMENT: usize = 10;
+/// Number of bits set for every element, which is optimal for `BITS_PER_ELEMENT`.
+const NUM_HASH_FUNCTIONS: u32 = 7;
+
+/// Set of hashes which may report false positives, but never false negatives.
+#[derive(Clone, Debug)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter with the given number of bits, rounded up to a multiple of 64.
+    pub fn new(num_bits: usize) -> BloomFilter {
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64).max(1)],
+        }
+    }
+
+    fn num_bits(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        // Derive the bit positions from the two halves of the mixed hash by double hashing
+        let hash = lsh::permute(hash, 0);
+        let h1 = hash & 0xffff_ffff;
+        let h2 = hash >> 32;
+        for i in 0..NUM_HASH_FUNCTIONS as u64 {
+            let bit = (h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits() as u64) as usize;
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Estimates the number of hashes in this filter.
+    pub fn estimated_len(&self) -> f64 {
+        let num_ones = self.bits.iter().map(|w| w.count_ones() as usize).sum();
+        self.estimate_from_ones(num_ones)
+    }
+
+    /// Estimates the number of hashes which are in both filters. The filters must have the same size.
+    pub fn estimated_intersection_len(&self, other: &BloomFilter) -> f64 {
+        let union_ones = self
+            .bits
+            .iter()
+            .zip(&other.bits)
+            .map(|(a, b)| (a | b).count_ones() as usize)
+            .sum();
+        let estimate =
+            self.estimated_len() + other.estimated_len() - self.estimate_from_ones(union_ones);
+        estimate.max(0.0)
+    }
+
+    /// Estimates the number of hashes inserted into a filter with `num_ones` bits set.
+    fn estimate_from_ones(&self, num_ones: usize) -> f64 {
+        let m = self.num_bits() as f64;
+        // A saturated filter would give an infinite estimate
+        let x = num_ones.min(self.num_bits() - 1) as f64;
+        -m / NUM_HASH_FUNCTIONS as f64 * (1.0 - x / m).ln()
+    }
+}
+
+/// Returns the pairs of projects which are estimated to share at least `min_shared_hashes` hashes. The first project
+/// of each pair is less than the second. If pairs are given, only those pairs are considered.
+pub fn candidate_pairs<K: IdentityHashKey>(
+    hash_database: &IdentityHashMap<Vec<(&FileId, Range<usize>)>, K>,
+    min_shared_hashes: usize,
+    pairs: Option<&HashSet<(PathId, PathId)>>,
+) -> HashSet<(PathId, PathId)> {
+    let mut project_hashes: HashMap<PathId, Vec<u64>> = HashMap::new();
+    for (&hash, locations) in hash_database.iter() {
+        let hash = hash.into();
+        for project in locations.iter().map(|(file_id, _)| file_id.project).dedup() {
+            project_hashes.entry(project).or_default().push(hash);
+        }
+    }
+
+    // Every filter must have the same size for their unions to be meaningful
+    let max_len = project_hashes.values().map(Vec::len).max().unwrap_or(0);
+    let filters = project_hashes
+        .into_iter()
+        .map(|(project, hashes)| {
+            let mut filter = BloomFilter::new(max_len * BITS_PER_ELEMENT);
+            for hash in hashes {
+                filter.insert(hash);
+            }
+            (project, filter)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let shares_enough = |project1: &PathId, project2: &PathId| {
+        filters[project1].estimated_intersection_len(&filters[project2]) >= min_shared_hashes as f64
+    };
+    match pairs {
+        Some(pairs) => pairs
+            .iter()
+            .filter(|(p1, p2)| shares_enough(p1, p2))
+            .copied()
+            .collect(),
+        None => filters
+            .keys()
+            .tuple_combinations()
+            .map(|(&p1, &p2)| if p1 < p2 { (p1, p2) } else { (p2, p1) })
+            .filter(|(p1, p2)| shares_enough(p1, p2))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interning::PathArena;
+
+    fn filter(hashes: Range<u64>) -> BloomFilter {
+        let mut filter = BloomFilter::new(1000 * BITS_PER_ELEMENT);
+        for hash in hashes {
+            // Spread out the consecutive test values like real hashes
+            filter.insert(hash.wrapping_mul(0x9e3779b97f4a7c15));
+        }
+        filter
+    }
+
+    #[test]
+    fn estimates_are_close() {
+        let a = filter(0..1000);
+        let b = filter(500..1400);
+        let c = filter(5000..6000);
+
+        assert!((a.estimated_len() - 1000.0).abs() < 50.0);
+        assert!((a.estimated_intersection_len(&b) - 500.0).abs() < 50.0);
+        assert!(a.estimated_intersection_len(&c) < 50.0);
+    }
+
+    #[test]
+    fn pairs_sharing_few_hashes_are_skipped() {
+        let mut arena = PathArena::default();
+        let [p1, p2, p3] =
+            ["P1", "P2", "P3"].map(|p| arena.intern_file(p.as_ref(), format!("{p}/a.s").as_ref()));
+        let mut hash_database: IdentityHashMap<Vec<(&FileId, Range<usize>)>> =
+            IdentityHashMap::default();
+        // P1 and P2 share 100 hashes, while P3 only shares a few with each of them
+        for (file_id, hashes) in [(&p1, 0..200), (&p2, 100..300), (&p3, 198..202)] {
+            for hash in hashes {
+                let hash = (hash as u64).wrapping_mul(0x9e3779b97f4a7c15);
+                hash_database.entry(hash).or_default().push((file_id, 0..1));
+            }
+        }
+
+        assert_eq!(
+            candidate_pairs(&hash_database, 50, None),
+            HashSet::from([(p1.project, p2.project)])
+        );
+        assert!(candidate_pairs(&hash_database, 50, Some(&HashSet::new())).is_empty());
+    }
+}
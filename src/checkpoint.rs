@@ -0,0 +1,333 @@
+//! Persists the state of an analysis so that a crashed or interrupted run can be resumed.
+//!
+//! A checkpoint directory contains two files:
+//! - `state.json` holds the hashed tokens of every document (with the starter code already removed), the number of
+//!   tokens removed as starter code, and the warnings produced while computing them. The fingerprints and the hash
+//!   database are cheap to rebuild from these.
+//! - `pairs.ndjson` holds one line per project pair whose matches have been expanded, appended as each pair completes.
+//!
+//! Both files are tagged with a hash of the inputs and options. A checkpoint created for different inputs is discarded.
+
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    interning::PathArena,
+    output::{ProjectPair, Warning, WarningCode, WarningType},
+    DetectionOptions, File, FileId,
+};
+
+const STATE_FILE: &str = "state.json";
+const PAIRS_FILE: &str = "pairs.ndjson";
+
+pub use crate::lexing::HashedTokens;
+
+/// Hashed tokens of every document, keyed by file.
+pub type DocumentHashes = HashMap<FileId, HashedTokens>;
+
+/// Number of tokens removed from each document because they match starter code, keyed by file. Documents without any
+/// starter code are omitted.
+pub type StarterCodeTokens = HashMap<FileId, usize>;
+
+#[derive(Serialize, Deserialize)]
+struct State {
+    input_hash: u64,
+    document_hashes: Vec<(SavedFileId, HashedTokens)>,
+    #[serde(default)]
+    starter_code_tokens: Vec<(SavedFileId, usize)>,
+    warnings: Vec<Warning>,
+}
+
+/// File of a document, saved by its paths since the identifiers of interned paths only hold for one run.
+#[derive(Serialize, Deserialize)]
+struct SavedFileId {
+    project: PathBuf,
+    path: PathBuf,
+}
+
+impl SavedFileId {
+    fn new(file_id: FileId, arena: &PathArena) -> SavedFileId {
+        SavedFileId {
+            project: arena.path(file_id.project).clone(),
+            path: arena.path(file_id.path).clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PairRecord {
+    input_hash: u64,
+    project_pair: ProjectPair,
+}
+
+/// Checkpoint directory for one particular analysis.
+pub struct Checkpoint {
+    dir: PathBuf,
+    input_hash: u64,
+    pairs_writer: Option<BufWriter<fs::File>>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint in `dir` for an analysis of the given documents, creating the directory if necessary.
+    pub fn open(
+        dir: &Path,
+        options: &DetectionOptions,
+        documents: &[File],
+        ignored_documents: &[File],
+    ) -> io::Result<Checkpoint> {
+        fs::create_dir_all(dir)?;
+        Ok(Checkpoint {
+            dir: dir.to_owned(),
+            input_hash: input_hash(options, documents, ignored_documents),
+            pairs_writer: None,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Loads the document hashes, the starter code token counts, and their warnings, if they were saved for the same
+    /// inputs. The paths of the documents must be interned in the arena.
+    pub fn load_document_hashes(
+        &self,
+        arena: &PathArena,
+    ) -> Option<(DocumentHashes, StarterCodeTokens, Vec<Warning>)> {
+        let contents = fs::read(self.dir.join(STATE_FILE)).ok()?;
+        let state: State = serde_json::from_slice(&contents).ok()?;
+        if state.input_hash != self.input_hash {
+            return None;
+        }
+        let file_id = |f: SavedFileId| arena.file_id(&f.project, &f.path);
+        Some((
+            state
+                .document_hashes
+                .into_iter()
+                .map(|(f, hashes)| Some((file_id(f)?, hashes)))
+                .collect::<Option<_>>()?,
+            state
+                .starter_code_tokens
+                .into_iter()
+                .map(|(f, num_tokens)| Some((file_id(f)?, num_tokens)))
+                .collect::<Option<_>>()?,
+            state.warnings,
+        ))
+    }
+
+    /// Saves the document hashes, the starter code token counts, and their warnings, discarding any project pairs saved
+    /// for other inputs.
+    pub fn save_document_hashes(
+        &mut self,
+        arena: &PathArena,
+        document_hashes: &DocumentHashes,
+        starter_code_tokens: &StarterCodeTokens,
+        warnings: &[Warning],
+    ) -> io::Result<()> {
+        let state = State {
+            input_hash: self.input_hash,
+            document_hashes: document_hashes
+                .iter()
+                .map(|(&file_id, hashes)| (SavedFileId::new(file_id, arena), hashes.clone()))
+                .collect(),
+            starter_code_tokens: starter_code_tokens
+                .iter()
+                .map(|(&file_id, &num_tokens)| (SavedFileId::new(file_id, arena), num_tokens))
+                .collect(),
+            warnings: warnings.to_vec(),
+        };
+
+        // Write to a temporary file first so that a crash never leaves a truncated state behind
+        let temp_path = self.dir.join(format!("{STATE_FILE}.tmp"));
+        let mut writer = BufWriter::new(fs::File::create(&temp_path)?);
+        serde_json::to_writer(&mut writer, &state)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(temp_path, self.dir.join(STATE_FILE))?;
+
+        // Start over with the pairs, since they may have been computed from different inputs
+        self.pairs_writer = Some(BufWriter::new(fs::File::create(self.dir.join(PAIRS_FILE))?));
+        Ok(())
+    }
+
+    /// Loads the project pairs which were completed for the same inputs, keyed by their projects.
+    pub fn load_project_pairs(&self) -> HashMap<(PathBuf, PathBuf), ProjectPair> {
+        let file = match fs::File::open(self.dir.join(PAIRS_FILE)) {
+            Err(_) => return HashMap::new(),
+            Ok(f) => f,
+        };
+
+        // The last line may be incomplete if the run was killed while writing it, so unreadable lines are skipped
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<PairRecord>(&line).ok())
+            .filter(|record| record.input_hash == self.input_hash)
+            .map(|record| {
+                let pair = record.project_pair;
+                ((pair.project1.clone(), pair.project2.clone()), pair)
+            })
+            .collect()
+    }
+
+    /// Appends a completed project pair.
+    pub fn save_project_pair(&mut self, project_pair: &ProjectPair) -> io::Result<()> {
+        if self.pairs_writer.is_none() {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.dir.join(PAIRS_FILE))?;
+            self.pairs_writer = Some(BufWriter::new(file));
+        }
+        let writer = self.pairs_writer.as_mut().unwrap();
+
+        let record = PairRecord {
+            input_hash: self.input_hash,
+            project_pair: project_pair.clone(),
+        };
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
+
+/// Converts a checkpoint error into a warning. The analysis continues without the checkpoint.
+pub fn checkpoint_warning(dir: &Path, error: io::Error) -> Warning {
+    Warning {
+        file: Some(dir.to_owned()),
+        message: format!("Failed to update the checkpoint: {error}"),
+        warn_type: WarningType::Checkpoint,
+        code: WarningCode::CheckpointFailed,
+    }
+}
+
+/// Hashes everything that affects the state saved in a checkpoint.
+fn input_hash(options: &DetectionOptions, documents: &[File], ignored_documents: &[File]) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    options.noise_threshold.hash(&mut hasher);
+    options.guarantee_threshold.hash(&mut hasher);
+    options.max_token_offset.hash(&mut hasher);
+    options.fingerprint_algorithm.name().hash(&mut hasher);
+    options.tokenizing_strategy.hash(&mut hasher);
+    options.ignore_whitespace.hash(&mut hasher);
+    options.transforms.hash(&mut hasher);
+    options.key_symbols.hash(&mut hasher);
+    options.hash_dialect.hash(&mut hasher);
+    options.strict_lex.hash(&mut hasher);
+    options.stitch_files.hash(&mut hasher);
+    options.granularity.hash(&mut hasher);
+    options.expand_matches.hash(&mut hasher);
+    options.min_report_length.hash(&mut hasher);
+    options.common_hash_threshold.to_bits().hash(&mut hasher);
+    options.common_hash_min_projects.hash(&mut hasher);
+    options.auto_common_hash_threshold.hash(&mut hasher);
+    options.stop_list.hash(&mut hasher);
+    options
+        .starter_code_similarity
+        .map(f64::to_bits)
+        .hash(&mut hasher);
+    options.min_project_tokens.hash(&mut hasher);
+    options.compare.hash(&mut hasher);
+    options.project_sections.hash(&mut hasher);
+    options.only_pairs.hash(&mut hasher);
+    options.lsh_bands.hash(&mut hasher);
+    options.lsh_rows.hash(&mut hasher);
+    options.bloom_min_shared_hashes.hash(&mut hasher);
+    options.max_matches_per_hash.hash(&mut hasher);
+    options.scoring.hash(&mut hasher);
+    options.hash_width.hash(&mut hasher);
+
+    for documents in [documents, ignored_documents] {
+        documents.len().hash(&mut hasher);
+        for f in documents {
+            f.hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        output::{Location, Match},
+        TokenizingStrategy,
+    };
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fungus-checkpoint-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("round-trip");
+        let options = DetectionOptions::default();
+        let documents = vec![File::new("P1".into(), "P1/a.s".into(), "mov".to_owned())];
+        let arena = PathArena::new(&documents);
+        let file_id = arena.file_id("P1".as_ref(), "P1/a.s".as_ref()).unwrap();
+        let document_hashes = HashMap::from([(file_id, vec![(42, 0..3)])]);
+        let starter_code_tokens = HashMap::from([(file_id, 2)]);
+        let pair = ProjectPair {
+            project1: "P1".into(),
+            project2: "P2".into(),
+            matches: vec![Match {
+                id: None,
+                project_1_location: Location {
+                    file: "P1/a.s".into(),
+                    span: 0..3,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                project_2_location: Location {
+                    file: "P2/b.s".into(),
+                    span: 4..7,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                weight: None,
+            }],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+
+        let mut checkpoint = Checkpoint::open(&dir, &options, &documents, &[]).unwrap();
+        assert!(checkpoint.load_document_hashes(&arena).is_none());
+        checkpoint
+            .save_document_hashes(&arena, &document_hashes, &starter_code_tokens, &[])
+            .unwrap();
+        checkpoint.save_project_pair(&pair).unwrap();
+
+        let checkpoint = Checkpoint::open(&dir, &options, &documents, &[]).unwrap();
+        let (loaded_hashes, loaded_starter_code_tokens, loaded_warnings) =
+            checkpoint.load_document_hashes(&arena).unwrap();
+        assert_eq!(loaded_hashes, document_hashes);
+        assert_eq!(loaded_starter_code_tokens, starter_code_tokens);
+        assert!(loaded_warnings.is_empty());
+        let loaded_pairs = checkpoint.load_project_pairs();
+        assert_eq!(loaded_pairs[&("P1".into(), "P2".into())], pair);
+
+        // A checkpoint for different options is ignored
+        let other_options = DetectionOptions {
+            tokenizing_strategy: TokenizingStrategy::Naive,
+            ..Default::default()
+        };
+        let checkpoint = Checkpoint::open(&dir, &other_options, &documents, &[]).unwrap();
+        assert!(checkpoint.load_document_hashes(&arena).is_none());
+        assert!(checkpoint.load_project_pairs().is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! Composite score combining the signals of the different detectors (matching code, matching comments, stylometry,
+//! formatting, string literals, and external scores) into a single ranking.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use crate::{
+    detect_plagiarism,
+    output::{
+        CompositePair, ExternalScorePair, FormattingPair, ProjectPair, StringLiteralPair,
+        StylometryPair,
+    },
+    DetectionOptions, File, TokenizingStrategy,
+};
+
+/// Noise threshold of the comment analysis, in words. Comments are much shorter than code, so the thresholds of the
+/// code analysis would miss most of them.
+pub const COMMENT_NOISE_THRESHOLD: usize = 8;
+
+/// Detector contributing a component to the composite score. Every component is between 0 and 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Signal {
+    /// Proportion of the code of the more covered project which matches the other project.
+    Code,
+    /// Proportion of the comment words of the more covered project which match the other project.
+    Comments,
+    /// Cosine similarity of the instruction frequency profiles of the projects.
+    Stylometry,
+    /// Cosine similarity of the formatting pattern profiles of the projects.
+    Formatting,
+    /// `1 - 2^-n`, where `n` is the number of rare string literals shared by the projects.
+    StringLiterals,
+    /// Score given by an external tool.
+    External,
+}
+
+impl Signal {
+    fn name(&self) -> &'static str {
+        match self {
+            Signal::Code => "code",
+            Signal::Comments => "comments",
+            Signal::Stylometry => "stylometry",
+            Signal::Formatting => "formatting",
+            Signal::StringLiterals => "string-literals",
+            Signal::External => "external",
+        }
+    }
+}
+
+/// Weight of each signal in the composite score. Signals without a weight are not computed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScoreWeights(pub BTreeMap<Signal, f64>);
+
+impl ScoreWeights {
+    /// Returns whether the signal contributes to the composite score.
+    pub fn uses(&self, signal: Signal) -> bool {
+        self.0.get(&signal).is_some_and(|&w| w > 0.0)
+    }
+}
+
+impl FromStr for ScoreWeights {
+    type Err = String;
+
+    /// Parses a comma-separated list of `signal=weight` entries, e.g. `code=1,stylometry=0.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let signals = [
+            Signal::Code,
+            Signal::Comments,
+            Signal::Stylometry,
+            Signal::Formatting,
+            Signal::StringLiterals,
+            Signal::External,
+        ];
+        let mut weights = BTreeMap::new();
+        for entry in s.split(',') {
+            let (name, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected \"signal=weight\", found \"{entry}\""))?;
+            let signal = signals
+                .into_iter()
+                .find(|signal| signal.name() == name.trim())
+                .ok_or_else(|| {
+                    format!(
+                        "unknown signal \"{name}\", expected one of {}",
+                        signals.map(|s| format!("\"{}\"", s.name())).join(", ")
+                    )
+                })?;
+            let weight = weight
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|w| w.is_finite() && *w >= 0.0)
+                .ok_or_else(|| format!("expected a non-negative weight, found \"{weight}\""))?;
+            weights.insert(signal, weight);
+        }
+        if !weights.values().any(|&w| w > 0.0) {
+            return Err("at least one weight must be positive".to_owned());
+        }
+        Ok(ScoreWeights(weights))
+    }
+}
+
+impl Display for ScoreWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries = self
+            .0
+            .iter()
+            .map(|(signal, weight)| format!("{}={weight}", signal.name()))
+            .collect::<Vec<_>>();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+/// Components of the composite score of each pair of projects, as computed by each detector.
+#[derive(Debug, Default)]
+pub struct Components(BTreeMap<(PathBuf, PathBuf), BTreeMap<Signal, f64>>);
+
+impl Components {
+    /// Adds the coverage of the pairs found by matching code (or comments, if they were found by the "comments"
+    /// tokenizing strategy).
+    pub fn add_matches(&mut self, signal: Signal, project_pairs: &[ProjectPair]) {
+        for pair in project_pairs {
+            if let Some(explanation) = &pair.explanation {
+                let coverage = explanation
+                    .project_1_coverage
+                    .max(explanation.project_2_coverage);
+                self.add(&pair.project1, &pair.project2, signal, coverage);
+            }
+        }
+    }
+
+    pub fn add_stylometry(&mut self, pairs: &[StylometryPair]) {
+        for pair in pairs {
+            self.add(
+                &pair.project1,
+                &pair.project2,
+                Signal::Stylometry,
+                pair.similarity,
+            );
+        }
+    }
+
+    pub fn add_formatting(&mut self, pairs: &[FormattingPair]) {
+        for pair in pairs {
+            self.add(
+                &pair.project1,
+                &pair.project2,
+                Signal::Formatting,
+                pair.similarity,
+            );
+        }
+    }
+
+    pub fn add_string_literals(&mut self, pairs: &[StringLiteralPair]) {
+        for pair in pairs {
+            let component = 1.0 - 0.5f64.powi(pair.literals.len() as i32);
+            self.add(
+                &pair.project1,
+                &pair.project2,
+                Signal::StringLiterals,
+                component,
+            );
+        }
+    }
+
+    pub fn add_external_scores(&mut self, pairs: &[ExternalScorePair]) {
+        for pair in pairs {
+            self.add(&pair.project1, &pair.project2, Signal::External, pair.score);
+        }
+    }
+
+    fn add(&mut self, project1: &PathBuf, project2: &PathBuf, signal: Signal, component: f64) {
+        self.0
+            .entry((project1.to_owned(), project2.to_owned()))
+            .or_default()
+            .insert(signal, component);
+    }
+}
+
+/// Finds the pairs of projects with matching comments, for the comment component of the composite score.
+pub fn comment_pairs(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+) -> Vec<ProjectPair> {
+    let comment_options = DetectionOptions {
+        noise_threshold: COMMENT_NOISE_THRESHOLD,
+        guarantee_threshold: COMMENT_NOISE_THRESHOLD,
+        max_token_offset: 0,
+        tokenizing_strategy: TokenizingStrategy::Comments,
+        transforms: Vec::new(),
+        min_report_length: 0,
+        min_matches: 0,
+        pair_overrides: Vec::new(),
+        stop_list: Vec::new(),
+        min_project_tokens: 0,
+        checkpoint_dir: None,
+        cache_dir: None,
+        ..options.clone()
+    };
+    let (project_pairs, _warnings) =
+        detect_plagiarism(&comment_options, documents, ignored_documents);
+    project_pairs
+}
+
+/// Combines the components of each pair into the weighted average of its components, where missing components count
+/// as zero. Returns the pairs from the highest to the lowest composite score.
+pub fn composite_scores(weights: &ScoreWeights, components: Components) -> Vec<CompositePair> {
+    let total_weight = crate::scoring::sum_weights(weights.0.values().copied());
+    let mut pairs = components
+        .0
+        .into_iter()
+        .map(|((project1, project2), components)| {
+            let score = crate::scoring::sum_weights(
+                components
+                    .iter()
+                    .map(|(signal, c)| weights.0.get(signal).copied().unwrap_or(0.0) * c),
+            ) / total_weight;
+            CompositePair {
+                project1,
+                project2,
+                score,
+                components,
+            }
+        })
+        .collect::<Vec<_>>();
+    // The pairs are already ordered by name, and the sort is stable
+    pairs.sort_by(|p1, p2| p2.score.total_cmp(&p1.score));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_weights() {
+        let weights = "code=1, stylometry=0.5".parse::<ScoreWeights>().unwrap();
+        assert_eq!(
+            weights,
+            ScoreWeights(BTreeMap::from([
+                (Signal::Code, 1.0),
+                (Signal::Stylometry, 0.5)
+            ]))
+        );
+        assert_eq!(weights.to_string(), "code=1,stylometry=0.5");
+        assert!(weights.uses(Signal::Code));
+        assert!(!weights.uses(Signal::Comments));
+
+        assert!("code".parse::<ScoreWeights>().is_err());
+        assert!("tokens=1".parse::<ScoreWeights>().is_err());
+        assert!("code=-1".parse::<ScoreWeights>().is_err());
+        assert!("code=0".parse::<ScoreWeights>().is_err());
+    }
+
+    #[test]
+    fn combines_components() {
+        let weights = "code=3,stylometry=1".parse::<ScoreWeights>().unwrap();
+        let (a, b, c) = (PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("c"));
+        let mut components = Components::default();
+        components.add(&a, &b, Signal::Code, 0.5);
+        components.add(&a, &b, Signal::Stylometry, 1.0);
+        components.add(&a, &c, Signal::Code, 1.0);
+
+        let pairs = composite_scores(&weights, components);
+        assert_eq!(pairs.len(), 2);
+        assert_eq!((&pairs[0].project1, &pairs[0].project2), (&a, &c));
+        assert_eq!(pairs[0].score, 0.75);
+        assert_eq!(pairs[1].score, 0.625);
+        assert_eq!(pairs[1].components.len(), 2);
+    }
+}
@@ -0,0 +1,319 @@
+//! Information about the internals of an analysis, which can help with choosing its parameters.
+
+use std::{cmp::max, collections::BTreeMap, fmt::Display, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    distribution::Scores,
+    output::{serialize_path, ProjectPair, ProjectStats},
+};
+
+/// Projects needed for the common code threshold to be chosen automatically. With fewer projects, there is too little
+/// data to tell boilerplate apart from code shared by a small group of students.
+const MIN_PROJECTS_FOR_AUTO_THRESHOLD: usize = 5;
+
+/// Diagnostics collected while detecting plagiarism.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Diagnostics {
+    pub hash_frequencies: HashFrequencies,
+    pub fingerprint_density: FingerprintDensity,
+    /// Common code threshold which was chosen automatically, as a minimum number of projects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_common_hash_min_projects: Option<usize>,
+    /// Number of pairs of projects selected for comparison by the LSH pre-screening, if it was enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lsh_candidate_pairs: Option<usize>,
+    /// Number of pairs of projects left for comparison by the Bloom filter prefilter, if it was enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bloom_candidate_pairs: Option<usize>,
+    /// Size of every project, sorted by name. It is reported in the `projects` section of the output rather than with
+    /// the other diagnostics.
+    #[serde(skip)]
+    pub projects: Vec<ProjectStats>,
+    /// Every pair of projects whose matches were completed, including the pairs which are not reported. It is used to
+    /// build the similarity matrix and the distribution of the scores.
+    #[serde(skip)]
+    pub completed_pairs: Vec<CompletedPair>,
+    /// Number of pairs of projects which were compared, including those which do not share any code.
+    #[serde(skip)]
+    pub compared_pairs: usize,
+}
+
+impl Diagnostics {
+    /// Returns the scores of every compared pair of projects, where the pairs without any match have a score of 0.
+    pub fn scores(&self) -> Scores {
+        let scores = self.completed_pairs.iter().map(|p| p.score).collect();
+        let num_zero_scores = self
+            .compared_pairs
+            .saturating_sub(self.completed_pairs.len());
+        Scores::new(scores, num_zero_scores)
+    }
+}
+
+/// Pair of projects whose matches were completed, before the thresholds deciding whether it is reported are applied.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletedPair {
+    pub project1: PathBuf,
+    pub project2: PathBuf,
+    /// Value by which the pair is ranked (see `ProjectPair::ranking_score`).
+    pub score: f64,
+    pub project_1_coverage: f64,
+    pub project_2_coverage: f64,
+}
+
+impl CompletedPair {
+    pub(crate) fn new(pair: &ProjectPair) -> CompletedPair {
+        let (project_1_coverage, project_2_coverage) = pair
+            .explanation
+            .as_ref()
+            .map_or((0.0, 0.0), |e| (e.project_1_coverage, e.project_2_coverage));
+        CompletedPair {
+            project1: pair.project1.clone(),
+            project2: pair.project2.clone(),
+            score: pair.ranking_score(),
+            project_1_coverage,
+            project_2_coverage,
+        }
+    }
+}
+
+/// Distribution of the number of projects in which each fingerprint hash occurs, after removing the stop-list but before
+/// applying the common code thresholds.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HashFrequencies {
+    /// Number of projects in the analysis.
+    pub num_projects: usize,
+    /// Number of distinct hashes found in exactly the given number of projects.
+    pub num_hashes_by_projects: BTreeMap<usize, usize>,
+}
+
+impl HashFrequencies {
+    /// Returns the number of distinct hashes found in at least `min_projects` projects. These are the hashes which
+    /// would be ignored as common code with a threshold of `min_projects`.
+    pub fn num_hashes_in_at_least(&self, min_projects: usize) -> usize {
+        self.num_hashes_by_projects
+            .range(min_projects..)
+            .map(|(_, num_hashes)| num_hashes)
+            .sum()
+    }
+
+    /// Returns the total number of distinct hashes.
+    pub fn num_hashes(&self) -> usize {
+        self.num_hashes_by_projects.values().sum()
+    }
+
+    /// Chooses a common code threshold from the distribution, returning the minimum number of projects in which a hash
+    /// must occur to be ignored. Returns `None` if there are too few projects or no hashes shared between projects.
+    ///
+    /// Most shared code is found in only a few projects, and the number of hashes found in at least n projects drops
+    /// quickly as n increases until it reaches the long tail formed by boilerplate. The threshold is the knee of that
+    /// curve, i.e. the point furthest below the straight line between its ends. It is never less than 3 projects or
+    /// 10% of the projects, so that code shared by a small group of students is not mistaken for boilerplate.
+    pub fn auto_threshold(&self) -> Option<usize> {
+        let num_projects = self.num_projects;
+        let num_shared_hashes = self.num_hashes_in_at_least(2);
+        if num_projects < MIN_PROJECTS_FOR_AUTO_THRESHOLD || num_shared_hashes == 0 {
+            return None;
+        }
+
+        // Normalize both axes to [0, 1]
+        let x = |n: usize| (n - 2) as f64 / (num_projects - 2) as f64;
+        let y = |n: usize| self.num_hashes_in_at_least(n) as f64 / num_shared_hashes as f64;
+        let y_last = y(num_projects);
+        let distance_below_line = |n: usize| 1.0 + (y_last - 1.0) * x(n) - y(n);
+
+        // Choose the smallest number of projects in case of ties
+        let mut knee = 2;
+        for n in 3..=num_projects {
+            if distance_below_line(n) > distance_below_line(knee) {
+                knee = n;
+            }
+        }
+
+        let min_threshold = max(3, num_projects.div_ceil(10));
+        Some(max(knee, min_threshold))
+    }
+}
+
+/// Proportion of the hashed substrings of every file which were selected as its fingerprint, showing how aggressively
+/// winnowing samples the files.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FingerprintDensity {
+    /// Winnowing window size derived from the thresholds.
+    pub window_size: usize,
+    /// Density of every fingerprinted file, sorted by project and path.
+    pub files: Vec<FileDensity>,
+}
+
+/// Fingerprint density of a single file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FileDensity {
+    #[serde(serialize_with = "serialize_path")]
+    pub file: PathBuf,
+    /// Number of substrings of k tokens which were hashed.
+    pub num_kgrams: usize,
+    /// Number of those hashes which were selected as the fingerprint.
+    pub num_selected: usize,
+}
+
+impl FileDensity {
+    pub fn density(&self) -> f64 {
+        self.num_selected as f64 / self.num_kgrams as f64
+    }
+}
+
+impl FingerprintDensity {
+    /// Returns the density of winnowing on random hashes, 2 / (w + 1), to which the achieved densities can be compared.
+    pub fn expected_density(&self) -> f64 {
+        2.0 / (self.window_size + 1) as f64
+    }
+
+    /// Returns the density over all the files, or `None` if no file was fingerprinted.
+    pub fn corpus_density(&self) -> Option<f64> {
+        let num_kgrams: usize = self.files.iter().map(|f| f.num_kgrams).sum();
+        let num_selected: usize = self.files.iter().map(|f| f.num_selected).sum();
+        (num_kgrams > 0).then(|| num_selected as f64 / num_kgrams as f64)
+    }
+}
+
+impl Display for FingerprintDensity {
+    /// Formats the corpus-wide density followed by a table of the density of each file.
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(corpus_density) = self.corpus_density() else {
+            return writeln!(formatter, "No file was fingerprinted.");
+        };
+        writeln!(
+            formatter,
+            "Fingerprint density: {:.1}% of the hashes of {} files (window size {}, {:.1}% expected for random code):",
+            corpus_density * 100.0,
+            self.files.len(),
+            self.window_size,
+            self.expected_density() * 100.0
+        )?;
+        writeln!(
+            formatter,
+            "{:>9} {:>9} {:>9}  File",
+            "Density", "Hashes", "Selected"
+        )?;
+        for file in self.files.iter() {
+            writeln!(
+                formatter,
+                "{:>8.1}% {:>9} {:>9}  {}",
+                file.density() * 100.0,
+                file.num_kgrams,
+                file.num_selected,
+                file.file.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HashFrequencies {
+    /// Formats the distribution as a table, along with the proportion of the hashes which a common code threshold at
+    /// each level would ignore.
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let num_hashes = self.num_hashes();
+        writeln!(
+            formatter,
+            "{num_hashes} distinct hashes in {} projects:",
+            self.num_projects
+        )?;
+        writeln!(
+            formatter,
+            "{:>9} {:>9} {:>9} {:>18}",
+            "Projects", "Fraction", "Hashes", "Ignored if common"
+        )?;
+        for (&num_projects, &count) in self.num_hashes_by_projects.iter() {
+            let fraction = num_projects as f64 / self.num_projects as f64;
+            let ignored = self.num_hashes_in_at_least(num_projects) as f64 / num_hashes as f64;
+            writeln!(
+                formatter,
+                "{num_projects:>9} {fraction:>9.2} {count:>9} {:>17.1}%",
+                ignored * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cumulative_counts() {
+        let frequencies = HashFrequencies {
+            num_projects: 4,
+            num_hashes_by_projects: BTreeMap::from([(1, 6), (2, 3), (4, 1)]),
+        };
+        assert_eq!(frequencies.num_hashes(), 10);
+        assert_eq!(frequencies.num_hashes_in_at_least(2), 4);
+        assert_eq!(frequencies.num_hashes_in_at_least(3), 1);
+        assert_eq!(frequencies.num_hashes_in_at_least(5), 0);
+        assert_eq!(
+            frequencies.to_string(),
+            "10 distinct hashes in 4 projects:
+ Projects  Fraction    Hashes  Ignored if common
+        1      0.25         6             100.0%
+        2      0.50         3              40.0%
+        4      1.00         1              10.0%
+"
+        );
+    }
+
+    #[test]
+    fn fingerprint_density() {
+        let density = FingerprintDensity {
+            window_size: 4,
+            files: vec![
+                FileDensity {
+                    file: PathBuf::from("a/main.s"),
+                    num_kgrams: 100,
+                    num_selected: 40,
+                },
+                FileDensity {
+                    file: PathBuf::from("b/main.s"),
+                    num_kgrams: 300,
+                    num_selected: 60,
+                },
+            ],
+        };
+        assert_eq!(density.expected_density(), 0.4);
+        assert_eq!(density.corpus_density(), Some(0.25));
+        assert_eq!(
+            density.to_string(),
+            "Fingerprint density: 25.0% of the hashes of 2 files (window size 4, 40.0% expected for random code):
+  Density    Hashes  Selected  File
+    40.0%       100        40  a/main.s
+    20.0%       300        60  b/main.s
+"
+        );
+        assert_eq!(FingerprintDensity::default().corpus_density(), None);
+    }
+
+    #[test]
+    fn auto_threshold() {
+        let frequencies = |num_projects, counts: &[(usize, usize)]| HashFrequencies {
+            num_projects,
+            num_hashes_by_projects: counts.iter().copied().collect(),
+        };
+
+        // Boilerplate found in most of the projects is ignored, but code shared by 3 projects is kept
+        let with_boilerplate =
+            frequencies(30, &[(1, 5000), (2, 1000), (3, 100), (25, 40), (30, 10)]);
+        assert_eq!(with_boilerplate.auto_threshold(), Some(4));
+
+        // The threshold is at least 10% of the projects
+        let large_class = frequencies(100, &[(2, 1000), (3, 100), (4, 10), (90, 40)]);
+        assert_eq!(large_class.auto_threshold(), Some(10));
+
+        // Without boilerplate, nothing shared by fewer than 3 projects is ignored
+        let without_boilerplate = frequencies(10, &[(1, 5000), (2, 1000)]);
+        assert_eq!(without_boilerplate.auto_threshold(), Some(3));
+
+        assert_eq!(frequencies(4, &[(2, 10), (4, 10)]).auto_threshold(), None);
+        assert_eq!(frequencies(10, &[(1, 10)]).auto_threshold(), None);
+    }
+}
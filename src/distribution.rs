@@ -0,0 +1,124 @@
+//! Distribution of the scores of all the compared pairs of projects, which serves as a baseline to judge how anomalous
+//! the score of a reported pair is.
+
+use serde::Serialize;
+
+/// Statistics of the scores of the compared pairs of projects.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ScoreDistribution {
+    /// Number of compared pairs, including those without any match.
+    pub num_pairs: usize,
+    pub mean: f64,
+    pub standard_deviation: f64,
+    pub median: f64,
+    pub percentile_90: f64,
+    pub percentile_95: f64,
+    pub percentile_99: f64,
+    pub max: f64,
+}
+
+/// Non-negative scores of the compared pairs of projects, in increasing order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scores {
+    /// Scores which are not 0.
+    scores: Vec<f64>,
+    /// Number of scores of 0, which are not listed since most pairs of a large class usually share no code.
+    num_zero_scores: usize,
+}
+
+impl Scores {
+    /// Collects the given scores along with `num_zero_scores` scores of 0.
+    pub fn new(mut scores: Vec<f64>, num_zero_scores: usize) -> Scores {
+        scores.sort_by(f64::total_cmp);
+        Scores {
+            scores,
+            num_zero_scores,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len() + self.num_zero_scores
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the score below which the given percentage of the scores fall, interpolating linearly between the
+    /// closest scores, or `None` if there are no scores.
+    pub fn percentile(&self, percentage: f64) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        let rank = percentage.clamp(0.0, 100.0) / 100.0 * (self.len() - 1) as f64;
+        let (lower, upper) = (
+            self.nth(rank.floor() as usize),
+            self.nth(rank.ceil() as usize),
+        );
+        Some(lower + (upper - lower) * rank.fract())
+    }
+
+    /// Computes the statistics of the scores, or returns `None` if there are no scores.
+    pub fn distribution(&self) -> Option<ScoreDistribution> {
+        let num_pairs = self.len();
+        if num_pairs == 0 {
+            return None;
+        }
+        let mean = self.scores.iter().sum::<f64>() / num_pairs as f64;
+        let squared_deviations = self.scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>()
+            + self.num_zero_scores as f64 * mean.powi(2);
+        let percentile = |percentage| self.percentile(percentage).unwrap_or_default();
+
+        Some(ScoreDistribution {
+            num_pairs,
+            mean,
+            standard_deviation: (squared_deviations / num_pairs as f64).sqrt(),
+            median: percentile(50.0),
+            percentile_90: percentile(90.0),
+            percentile_95: percentile(95.0),
+            percentile_99: percentile(99.0),
+            max: self.nth(num_pairs - 1),
+        })
+    }
+
+    /// Returns the nth lowest score, counting the zeros first.
+    fn nth(&self, n: usize) -> f64 {
+        n.checked_sub(self.num_zero_scores)
+            .map_or(0.0, |i| self.scores[i])
+    }
+}
+
+impl ScoreDistribution {
+    /// Returns the number of standard deviations by which the score is above the mean, or `None` if every pair has
+    /// the same score.
+    pub fn z_score(&self, score: f64) -> Option<f64> {
+        (self.standard_deviation > 0.0).then(|| (score - self.mean) / self.standard_deviation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_scores_are_counted_without_being_listed() {
+        let scores = Scores::new(vec![8.0, 2.0, 4.0], 5);
+        let distribution = scores.distribution().unwrap();
+
+        assert_eq!(distribution.num_pairs, 8);
+        assert_eq!(distribution.mean, 1.75);
+        // Variance of [0, 0, 0, 0, 0, 2, 4, 8]
+        assert!((distribution.standard_deviation - (59.5f64 / 8.0).sqrt()).abs() < 1e-9);
+        assert_eq!(distribution.median, 0.0);
+        assert!((distribution.percentile_90 - 5.2).abs() < 1e-9);
+        assert_eq!(distribution.max, 8.0);
+        assert_eq!(distribution.z_score(1.75), Some(0.0));
+        assert_eq!(scores.percentile(100.0), Some(8.0));
+        assert_eq!(scores.percentile(0.0), Some(0.0));
+
+        assert_eq!(Scores::new(Vec::new(), 0).distribution(), None);
+        let uniform = Scores::new(vec![3.0, 3.0], 0).distribution().unwrap();
+        assert_eq!(uniform.median, 3.0);
+        assert_eq!(uniform.z_score(3.0), None);
+    }
+}
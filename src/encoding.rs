@@ -0,0 +1,131 @@
+//! Decoding of submitted files, which are usually UTF-8 but may start with a byte order mark or be encoded in UTF-16
+//! by editors on Windows.
+
+use std::fmt::Display;
+
+/// Encoding from which the contents of a file were converted to plain UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 starting with a byte order mark, which was removed.
+    Utf8Bom,
+    /// UTF-16, little endian.
+    Utf16Le,
+    /// UTF-16, big endian.
+    Utf16Be,
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8Bom => "UTF-8 with a byte order mark",
+            Encoding::Utf16Le => "UTF-16 (little endian)",
+            Encoding::Utf16Be => "UTF-16 (big endian)",
+        })
+    }
+}
+
+/// Decodes the contents of a file. Plain UTF-8 is returned as is. A UTF-8 byte order mark is removed, and UTF-16 is
+/// converted to UTF-8, in which case the original encoding is also returned. UTF-16 is recognized by its byte order
+/// mark, or without one by the zero bytes of ASCII characters, which make up at least half of the byte pairs at the
+/// same position (odd for little endian, even for big endian).
+pub fn decode(bytes: Vec<u8>) -> Result<(String, Option<Encoding>), String> {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return String::from_utf8(rest.to_vec())
+            .map(|s| (s, Some(Encoding::Utf8Bom)))
+            .map_err(|e| e.to_string());
+    }
+    let encoding = match bytes.get(..2) {
+        Some(b"\xFF\xFE") => Some((Encoding::Utf16Le, 2)),
+        Some(b"\xFE\xFF") => Some((Encoding::Utf16Be, 2)),
+        _ => utf16_without_bom(&bytes).map(|encoding| (encoding, 0)),
+    };
+    match encoding {
+        Some((encoding, bom_len)) => decode_utf16(&bytes[bom_len..], encoding)
+            .map(|s| (s, Some(encoding)))
+            .map_err(|e| format!("Invalid {encoding}: {e}")),
+        None => String::from_utf8(bytes)
+            .map(|s| (s, None))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Guesses whether contents without a byte order mark are UTF-16, from the positions of their zero bytes.
+fn utf16_without_bom(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let num_pairs = bytes.len() / 2;
+    let count_zeros = |offset: usize| {
+        bytes
+            .chunks_exact(2)
+            .filter(|pair| pair[offset] == 0)
+            .count()
+    };
+    let (zeros_low, zeros_high) = (count_zeros(0), count_zeros(1));
+    if zeros_high * 2 >= num_pairs && zeros_low == 0 {
+        Some(Encoding::Utf16Le)
+    } else if zeros_low * 2 >= num_pairs && zeros_high == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(bytes: &[u8], encoding: Encoding) -> Result<String, String> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err("odd number of bytes".to_owned());
+    }
+    let units = bytes.chunks_exact(2).map(|pair| match encoding {
+        Encoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+        _ => u16::from_le_bytes([pair[0], pair[1]]),
+    });
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(s: &str, big_endian: bool) -> Vec<u8> {
+        s.encode_utf16()
+            .flat_map(|u| {
+                if big_endian {
+                    u.to_be_bytes()
+                } else {
+                    u.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encodings_are_converted() {
+        let contents = "main:\n\tmov r0, #1 @ é\n";
+        assert_eq!(
+            decode(contents.as_bytes().to_vec()),
+            Ok((contents.to_owned(), None))
+        );
+        assert_eq!(
+            decode([b"\xEF\xBB\xBF".as_slice(), contents.as_bytes()].concat()),
+            Ok((contents.to_owned(), Some(Encoding::Utf8Bom)))
+        );
+        for (bom, big_endian, encoding) in [
+            (b"\xFF\xFE".as_slice(), false, Encoding::Utf16Le),
+            (b"\xFE\xFF".as_slice(), true, Encoding::Utf16Be),
+            (b"".as_slice(), false, Encoding::Utf16Le),
+            (b"".as_slice(), true, Encoding::Utf16Be),
+        ] {
+            let bytes = [bom, &utf16(contents, big_endian)].concat();
+            assert_eq!(decode(bytes), Ok((contents.to_owned(), Some(encoding))));
+        }
+    }
+
+    #[test]
+    fn invalid_contents() {
+        assert!(decode(vec![b'a', 0xFF, b'b']).is_err());
+        // Unpaired surrogate
+        assert!(decode(vec![0xFF, 0xFE, 0x00, 0xD8]).is_err());
+    }
+}
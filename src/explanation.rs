@@ -0,0 +1,188 @@
+//! Evidence behind each reported pair of projects.
+
+use std::{cmp::Ordering, collections::HashMap, ops::Range, path::PathBuf};
+
+use itertools::Itertools;
+
+use crate::{
+    checkpoint::DocumentHashes,
+    interning::PathArena,
+    match_expansion::{token_index_ending_at, token_index_starting_at},
+    output::{Location, MoreUniqueContent, ProjectPair},
+};
+
+/// Proportion by which a filter must be tightened to remove a pair for the pair to count as nearly removed by it.
+const NEAR_FILTER_MARGIN: f64 = 0.2;
+
+/// Returns whether a hash found in `num_projects_with_hash` of the `num_projects` projects is removed by the common
+/// code thresholds.
+pub(crate) fn is_common_hash(
+    num_projects_with_hash: usize,
+    num_projects: usize,
+    common_hash_threshold: f64,
+    common_hash_min_projects: usize,
+) -> bool {
+    let exceeds_fraction = common_hash_threshold == 0.0
+        || (num_projects_with_hash as f64) >= (num_projects as f64) * common_hash_threshold;
+    let exceeds_count = num_projects_with_hash >= common_hash_min_projects;
+    exceeds_fraction && exceeds_count
+}
+
+/// Returns whether slightly stricter common code thresholds would have removed every code snippet shared by a pair,
+/// given the number of projects containing the rarest one. Disabled thresholds (i.e., both zero) never remove a pair.
+pub(crate) fn nearly_common(
+    rarest_hash_projects: usize,
+    num_projects: usize,
+    common_hash_threshold: f64,
+    common_hash_min_projects: usize,
+) -> bool {
+    if common_hash_threshold == 0.0 && common_hash_min_projects == 0 {
+        return false;
+    }
+    is_common_hash(
+        rarest_hash_projects,
+        num_projects,
+        common_hash_threshold * (1.0 - NEAR_FILTER_MARGIN),
+        (common_hash_min_projects as f64 * (1.0 - NEAR_FILTER_MARGIN)) as usize,
+    )
+}
+
+/// Returns whether a slightly higher minimum number of matches would have removed a pair with `num_matches` matches.
+pub(crate) fn nearly_too_few_matches(num_matches: usize, min_matches: usize) -> bool {
+    min_matches > 0 && (num_matches as f64) < (min_matches as f64) * (1.0 + NEAR_FILTER_MARGIN)
+}
+
+/// Returns whether a slightly higher minimum coverage would have removed a pair whose more covered project has the
+/// given coverage.
+pub(crate) fn nearly_too_low_coverage(coverage: f64, min_coverage: f64) -> bool {
+    min_coverage > 0.0 && coverage < min_coverage * (1.0 + NEAR_FILTER_MARGIN)
+}
+
+/// How much of a project is covered by the matches of a pair.
+pub(crate) struct Coverage {
+    /// Proportion of the tokens of the project covered by the matches.
+    pub proportion: f64,
+    /// Number of tokens of the project which are not covered by the matches.
+    pub unmatched_tokens: usize,
+}
+
+/// Returns how much of each project of the pair is covered by its matches. `project_tokens` gives the total number of
+/// tokens in each project.
+pub(crate) fn coverage(
+    pair: &ProjectPair,
+    document_hashes: &DocumentHashes,
+    arena: &PathArena,
+    project_tokens: &HashMap<&PathBuf, usize>,
+) -> (Coverage, Coverage) {
+    let project_coverage = |project: &PathBuf, locations: Vec<&Location>| {
+        let covered_tokens = locations
+            .into_iter()
+            .into_group_map_by(|location| &location.file)
+            .into_iter()
+            .map(|(file, locations)| {
+                let tokens = arena
+                    .file_id(project, file)
+                    .and_then(|file_id| document_hashes.get(&file_id))
+                    .map_or(&[][..], Vec::as_slice);
+                count_covered_tokens(tokens, locations.iter().map(|l| l.span.clone()))
+            })
+            .sum::<usize>();
+        let total = project_tokens.get(project).copied().unwrap_or(0);
+        Coverage {
+            proportion: match total {
+                0 => 0.0,
+                _ => covered_tokens as f64 / total as f64,
+            },
+            unmatched_tokens: total.saturating_sub(covered_tokens),
+        }
+    };
+
+    (
+        project_coverage(
+            &pair.project1,
+            pair.matches.iter().map(|m| &m.project_1_location).collect(),
+        ),
+        project_coverage(
+            &pair.project2,
+            pair.matches.iter().map(|m| &m.project_2_location).collect(),
+        ),
+    )
+}
+
+/// Returns which project of a pair has more content of its own, given the number of unmatched tokens of each.
+pub(crate) fn more_unique_content(
+    project_1_unmatched_tokens: usize,
+    project_2_unmatched_tokens: usize,
+) -> MoreUniqueContent {
+    match project_1_unmatched_tokens.cmp(&project_2_unmatched_tokens) {
+        Ordering::Greater => MoreUniqueContent::Project1,
+        Ordering::Less => MoreUniqueContent::Project2,
+        Ordering::Equal => MoreUniqueContent::Same,
+    }
+}
+
+/// Counts the tokens covered by at least one of the spans, which may overlap. Spans which do not start and end on
+/// token boundaries are ignored.
+fn count_covered_tokens(
+    tokens: &[(u64, Range<usize>)],
+    spans: impl Iterator<Item = Range<usize>>,
+) -> usize {
+    let mut token_ranges = spans
+        .filter_map(|span| {
+            let start = token_index_starting_at(tokens, span.start)?;
+            let end = token_index_ending_at(tokens, span.end)?;
+            Some(start..end + 1)
+        })
+        .sorted_by_key(|range| range.start);
+
+    let mut count = 0;
+    let Some(mut current) = token_ranges.next() else {
+        return 0;
+    };
+    for range in token_ranges {
+        if range.start <= current.end {
+            current.end = current.end.max(range.end);
+        } else {
+            count += current.len();
+            current = range;
+        }
+    }
+    count + current.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_overlapping_spans_once() {
+        let tokens: Vec<(u64, Range<usize>)> = (0..10)
+            .map(|i| (i, 2 * i as usize..2 * i as usize + 2))
+            .collect();
+        assert_eq!(count_covered_tokens(&tokens, [].into_iter()), 0);
+        assert_eq!(count_covered_tokens(&tokens, [0..4, 2..8].into_iter()), 4);
+        assert_eq!(
+            count_covered_tokens(&tokens, [0..2, 10..14, 4..6].into_iter()),
+            4
+        );
+        // Not on token boundaries
+        assert_eq!(count_covered_tokens(&tokens, std::iter::once(1..4)), 0);
+    }
+
+    #[test]
+    fn near_filters() {
+        assert!(nearly_too_few_matches(11, 10));
+        assert!(!nearly_too_few_matches(12, 10));
+        assert!(!nearly_too_few_matches(1, 0));
+        assert!(nearly_too_low_coverage(0.55, 0.5));
+        assert!(!nearly_too_low_coverage(0.65, 0.5));
+        assert!(!nearly_too_low_coverage(0.0, 0.0));
+
+        // Hashes in 8 projects out of 20 are removed from 0.4, and nearly removed from 0.5
+        assert!(nearly_common(8, 20, 0.5, 0));
+        assert!(!nearly_common(7, 20, 0.5, 0));
+        assert!(nearly_common(4, 20, 0.0, 5));
+        assert!(!nearly_common(3, 20, 0.0, 5));
+        assert!(!nearly_common(2, 20, 0.0, 0));
+    }
+}
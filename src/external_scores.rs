@@ -0,0 +1,109 @@
+//! Similarity scores of pairs of projects computed by external tools (e.g., an embedding model), so that FUNGUS can
+//! combine them with its own signals and report them alongside.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use crate::{
+    allowlist::{check_pair, split_csv_fields},
+    output::ExternalScorePair,
+};
+
+/// Parses a CSV file with the names of the two projects of a pair and their score on each line. The score must be
+/// between 0 and 1. Names containing commas must be quoted. Blank lines and a header line (whose score is not a number)
+/// are skipped.
+pub fn parse_scores_csv(contents: &str) -> Result<Vec<(String, String, f64)>, String> {
+    let mut scores = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match split_csv_fields(line)?.as_slice() {
+            [_, _, score] if i == 0 && score.parse::<f64>().is_err() => continue,
+            [project1, project2, score] => check_pair(line, project1.clone(), project2.clone())
+                .and_then(|(project1, project2)| Ok((project1, project2, parse_score(score)?))),
+            fields => Err(format!(
+                "expected two project names and a score, found {} fields in \"{line}\"",
+                fields.len()
+            )),
+        };
+        scores.push(entry.map_err(|e| format!("line {}: {e}", i + 1))?);
+    }
+    Ok(scores)
+}
+
+fn parse_score(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|score| (0.0..=1.0).contains(score))
+        .ok_or_else(|| format!("expected a score between 0 and 1, found \"{s}\""))
+}
+
+/// Orders the projects of each pair by name, and sorts the pairs from the highest to the lowest score. If a pair is
+/// given more than once, its last score is kept.
+pub fn external_score_pairs(scores: Vec<(PathBuf, PathBuf, f64)>) -> Vec<ExternalScorePair> {
+    let scores = scores
+        .into_iter()
+        .map(|(project1, project2, score)| {
+            if project1 <= project2 {
+                ((project1, project2), score)
+            } else {
+                ((project2, project1), score)
+            }
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut pairs = scores
+        .into_iter()
+        .map(|((project1, project2), score)| ExternalScorePair {
+            project1,
+            project2,
+            score,
+        })
+        .collect::<Vec<_>>();
+    // The pairs are already ordered by name, and the sort is stable
+    pairs.sort_by(|p1, p2| p2.score.total_cmp(&p1.score));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_orders_scores() {
+        let scores =
+            parse_scores_csv("project1,project2,score\nb, a, 0.5\n\n\"c, jr\",a,0.9\nb,a,0.25\n")
+                .unwrap();
+        assert_eq!(
+            scores,
+            vec![
+                ("b".to_owned(), "a".to_owned(), 0.5),
+                ("c, jr".to_owned(), "a".to_owned(), 0.9),
+                ("b".to_owned(), "a".to_owned(), 0.25),
+            ]
+        );
+        assert_eq!(
+            parse_scores_csv("a,b,1.5"),
+            Err("line 1: expected a score between 0 and 1, found \"1.5\"".to_owned())
+        );
+        assert!(parse_scores_csv("a,b,0.5\nc,d,high").is_err());
+        assert!(parse_scores_csv("a,b").is_err());
+
+        let pairs = external_score_pairs(
+            scores
+                .into_iter()
+                .map(|(p1, p2, score)| (p1.into(), p2.into(), score))
+                .collect(),
+        );
+        let pairs = pairs
+            .iter()
+            .map(|p| {
+                (
+                    p.project1.to_str().unwrap(),
+                    p.project2.to_str().unwrap(),
+                    p.score,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![("a", "c, jr", 0.9), ("a", "b", 0.25)]);
+    }
+}
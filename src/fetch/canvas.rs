@@ -0,0 +1,383 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use fungus_cli::{
+    project_metadata::{ProjectMetadata, PROJECT_METADATA_FILE},
+    submissions::parse_timestamp,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::DetectArgs;
+
+/// Maximum page size allowed by the Canvas REST API.
+const PAGE_SIZE: usize = 100;
+/// Cost left in the rate limit bucket below which requests are spaced out by `RATE_LIMIT_DELAY`, so that the bucket
+/// can refill before it runs out.
+const RATE_LIMIT_THRESHOLD: f64 = 200.0;
+const RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+/// Number of times a request is retried after being throttled, waiting twice as long each time.
+const MAX_RETRIES: u32 = 6;
+
+#[derive(clap::Args, Debug)]
+pub struct FetchCanvasArgs {
+    /// Base URL of the Canvas instance (e.g., "https://canvas.example.edu").
+    #[arg(long, env = "CANVAS_URL")]
+    url: String,
+    /// Identifier of the course.
+    #[arg(long)]
+    course: String,
+    /// Identifier of the assignment within the course.
+    #[arg(long)]
+    assignment: String,
+    /// Directory in which to place the submissions. Each student's submission becomes one project, named after their
+    /// Canvas user ID.
+    #[arg(long, default_value = "./submissions")]
+    dest: PathBuf,
+    /// Canvas access token of a user allowed to view the submissions (e.g., an instructor or TA).
+    #[arg(long, env = "CANVAS_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Whether to analyze the projects directory once all submissions have been fetched.
+    #[arg(long, default_value_t = false)]
+    analyze: bool,
+    #[command(flatten)]
+    detect: DetectArgs,
+}
+
+#[derive(Deserialize)]
+struct Submission {
+    user_id: u64,
+    submitted_at: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    user: Option<User>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    name: Option<String>,
+    sis_user_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    id: u64,
+    display_name: String,
+    url: String,
+}
+
+/// Downloads the files of every submission to a Canvas assignment into the destination directory, along with a
+/// project metadata file recording the student and the submission time, and optionally analyzes the result.
+///
+/// Submissions whose metadata file already records the same submission time are skipped, so an interrupted fetch can
+/// be resumed by running it again, and a later fetch only downloads the submissions which changed since.
+pub fn fetch_canvas(args: FetchCanvasArgs) -> anyhow::Result<()> {
+    let base_url = args.url.trim_end_matches('/');
+    let url = format!(
+        "{base_url}/api/v1/courses/{}/assignments/{}/submissions?include[]=user&per_page={PAGE_SIZE}",
+        args.course, args.assignment
+    );
+    let submissions = list_submissions(&url, &args.token)?;
+
+    fs::create_dir_all(&args.dest)
+        .with_context(|| format!("Failed to create directory '{}'.", args.dest.display()))?;
+
+    let (mut num_fetched, mut num_unchanged, mut num_submitted) = (0, 0, 0);
+    for submission in submissions.iter() {
+        let Some(submitted_at) = &submission.submitted_at else {
+            continue;
+        };
+        if submission.attachments.is_empty() {
+            continue;
+        }
+        num_submitted += 1;
+
+        let metadata = project_metadata(submission, submitted_at);
+        let project_dir = args.dest.join(submission.user_id.to_string());
+        if is_up_to_date(read_project_metadata(&project_dir).as_ref(), &metadata) {
+            num_unchanged += 1;
+            continue;
+        }
+
+        match download_submission(submission, &metadata, &project_dir, &args.token) {
+            Ok(()) => num_fetched += 1,
+            Err(e) => {
+                // Clean up so that the submission is fetched again on the next run
+                let _ = fs::remove_dir_all(&project_dir);
+                eprintln!(
+                    "Failed to fetch the submission of user {}:\n  {e:#}",
+                    submission.user_id
+                );
+            }
+        }
+    }
+
+    println!(
+        "Fetched {num_fetched} of {num_submitted} submissions into \"{}\" ({num_unchanged} already up to date).",
+        args.dest.display()
+    );
+
+    if args.analyze {
+        crate::run(&args.dest, args.detect)?;
+    }
+
+    Ok(())
+}
+
+/// Lists the submissions, following the pagination links of the Canvas API.
+fn list_submissions(url: &str, token: &str) -> anyhow::Result<Vec<Submission>> {
+    let mut submissions = Vec::new();
+    let mut next = Some(url.to_owned());
+    while let Some(url) = next {
+        let response = get(&url, token)?;
+        next = response.header("Link").and_then(next_page);
+        let page: Vec<Submission> = parse_json(response, &url)?;
+        submissions.extend(page);
+    }
+
+    submissions.sort_unstable_by_key(|s| s.user_id);
+    Ok(submissions)
+}
+
+/// Returns the URL of the next page from the `Link` header of a response, which has the form
+/// `<url>; rel="current", <url>; rel="next", ...`.
+fn next_page(link: &str) -> Option<String> {
+    link.split(',').find_map(|entry| {
+        let (url, params) = entry.split_once(';')?;
+        params
+            .split(';')
+            .any(|p| p.trim() == "rel=\"next\"")
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_owned()
+            })
+    })
+}
+
+fn project_metadata(submission: &Submission, submitted_at: &str) -> ProjectMetadata {
+    // Canvas timestamps are in UTC, e.g. "2023-03-01T23:59:59Z"
+    let timestamp = parse_timestamp(submitted_at.trim_end_matches('Z'));
+    if let Err(e) = &timestamp {
+        eprintln!(
+            "Ignoring the submission time of user {}: {e}",
+            submission.user_id
+        );
+    }
+    let user = submission.user.as_ref();
+    ProjectMetadata {
+        name: user.and_then(|u| u.name.clone()),
+        student_id: user.and_then(|u| u.sis_user_id.clone()),
+        timestamp: timestamp.ok(),
+        ..Default::default()
+    }
+}
+
+/// Returns whether a submission was already fetched, which is the case if the metadata file of its project records the
+/// same submission time. A submission whose time is unknown is always fetched again.
+fn is_up_to_date(existing: Option<&ProjectMetadata>, metadata: &ProjectMetadata) -> bool {
+    metadata.timestamp.is_some() && existing.is_some_and(|m| m.timestamp == metadata.timestamp)
+}
+
+fn read_project_metadata(project_dir: &Path) -> Option<ProjectMetadata> {
+    let contents = fs::read_to_string(project_dir.join(PROJECT_METADATA_FILE)).ok()?;
+    ProjectMetadata::parse(&contents).ok()
+}
+
+/// Replaces the project directory with the attachments of the submission. The metadata file is written last, so that
+/// a partially downloaded submission is fetched again when resuming.
+fn download_submission(
+    submission: &Submission,
+    metadata: &ProjectMetadata,
+    project_dir: &Path,
+    token: &str,
+) -> anyhow::Result<()> {
+    if project_dir.exists() {
+        fs::remove_dir_all(project_dir)
+            .with_context(|| format!("Failed to remove '{}'.", project_dir.display()))?;
+    }
+    fs::create_dir_all(project_dir)
+        .with_context(|| format!("Failed to create directory '{}'.", project_dir.display()))?;
+
+    for (attachment, file_name) in submission
+        .attachments
+        .iter()
+        .zip(attachment_file_names(&submission.attachments)?)
+    {
+        let path = project_dir.join(file_name);
+        let mut file = fs::File::create(&path)
+            .with_context(|| format!("Failed to create '{}'.", path.display()))?;
+        io::copy(&mut get(&attachment.url, token)?.into_reader(), &mut file)
+            .with_context(|| format!("Failed to download '{}'.", attachment.display_name))?;
+    }
+
+    let path = project_dir.join(PROJECT_METADATA_FILE);
+    let contents = serde_json::to_string_pretty(metadata)?;
+    fs::write(&path, contents).with_context(|| format!("Failed to write '{}'.", path.display()))
+}
+
+/// Returns the names under which the attachments are saved. Only the file name of each attachment is kept, so that
+/// attachments cannot be written outside of the project directory, and the identifier of the attachment is appended to
+/// the names shared by several attachments (e.g., "main-42.s"), so that they do not overwrite each other.
+fn attachment_file_names(attachments: &[Attachment]) -> anyhow::Result<Vec<OsString>> {
+    let names = attachments
+        .iter()
+        .map(|attachment| {
+            Path::new(&attachment.display_name)
+                .file_name()
+                .filter(|n| n.to_str() != Some(PROJECT_METADATA_FILE))
+                .with_context(|| format!("Invalid attachment name '{}'.", attachment.display_name))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut counts = HashMap::new();
+    for name in names.iter() {
+        *counts.entry(*name).or_insert(0) += 1;
+    }
+    Ok(attachments
+        .iter()
+        .zip(names.iter())
+        .map(|(attachment, &name)| {
+            if counts[name] == 1 {
+                return name.to_owned();
+            }
+            let name = Path::new(name);
+            let mut unique_name = name.file_stem().unwrap_or_default().to_owned();
+            unique_name.push(format!("-{}", attachment.id));
+            if let Some(extension) = name.extension() {
+                unique_name.push(".");
+                unique_name.push(extension);
+            }
+            unique_name
+        })
+        .collect())
+}
+
+/// Sends a request to the Canvas API, respecting its rate limit. Requests are spaced out when the remaining cost
+/// reported by the `X-Rate-Limit-Remaining` header runs low, and retried with exponential backoff when throttled.
+fn get(url: &str, token: &str) -> anyhow::Result<ureq::Response> {
+    let mut delay = RATE_LIMIT_DELAY;
+    let mut retries = 0;
+    loop {
+        let result = ureq::get(url)
+            .set("User-Agent", "fungus-cli")
+            .set("Authorization", &format!("Bearer {token}"))
+            .call();
+        match result {
+            Ok(response) => {
+                if rate_limit_remaining(&response).is_some_and(|r| r < RATE_LIMIT_THRESHOLD) {
+                    thread::sleep(RATE_LIMIT_DELAY);
+                }
+                return Ok(response);
+            }
+            Err(ureq::Error::Status(status, response))
+                if retries < MAX_RETRIES
+                    && is_throttled(status, rate_limit_remaining(&response)) =>
+            {
+                eprintln!(
+                    "Throttled by the Canvas API, retrying in {} seconds.",
+                    delay.as_secs()
+                );
+                thread::sleep(delay);
+                delay *= 2;
+                retries += 1;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Canvas API request to '{url}' failed."))
+            }
+        }
+    }
+}
+
+/// Returns whether a request failed because of the rate limit. Canvas responds with 403 Forbidden when throttling,
+/// unlike most APIs, so other 403 responses are told apart by the remaining cost.
+fn is_throttled(status: u16, rate_limit_remaining: Option<f64>) -> bool {
+    status == 429 || (status == 403 && rate_limit_remaining.is_some_and(|r| r <= 0.0))
+}
+
+fn rate_limit_remaining(response: &ureq::Response) -> Option<f64> {
+    response.header("X-Rate-Limit-Remaining")?.parse().ok()
+}
+
+fn parse_json<T: DeserializeOwned>(response: ureq::Response, url: &str) -> anyhow::Result<T> {
+    response
+        .into_json()
+        .with_context(|| format!("Failed to parse Canvas API response from '{url}'."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_page_is_found_in_the_link_header() {
+        let link = concat!(
+            r#"<https://canvas.example.edu/api/v1/courses/1/assignments/2/submissions?page=1&per_page=100>; rel="current","#,
+            r#"<https://canvas.example.edu/api/v1/courses/1/assignments/2/submissions?page=2&per_page=100>; rel="next","#,
+            r#"<https://canvas.example.edu/api/v1/courses/1/assignments/2/submissions?page=1&per_page=100>; rel="first""#,
+        );
+        assert_eq!(
+            next_page(link).as_deref(),
+            Some("https://canvas.example.edu/api/v1/courses/1/assignments/2/submissions?page=2&per_page=100")
+        );
+        assert_eq!(
+            next_page(r#"<https://canvas.example.edu/a?page=3>; rel="last""#),
+            None
+        );
+        assert_eq!(next_page(""), None);
+    }
+
+    #[test]
+    fn unchanged_submissions_are_skipped() {
+        let metadata = |submitted_at: Option<&str>| ProjectMetadata {
+            timestamp: submitted_at.map(|t| parse_timestamp(t).unwrap()),
+            ..Default::default()
+        };
+        let fetched = metadata(Some("2023-03-01T23:59:59"));
+        assert!(is_up_to_date(Some(&fetched), &fetched));
+        assert!(!is_up_to_date(None, &fetched));
+        assert!(!is_up_to_date(
+            Some(&fetched),
+            &metadata(Some("2023-03-02T10:00:00"))
+        ));
+        assert!(!is_up_to_date(Some(&metadata(None)), &metadata(None)));
+    }
+
+    #[test]
+    fn throttling_is_recognized() {
+        assert!(is_throttled(429, None));
+        assert!(is_throttled(403, Some(0.0)));
+        assert!(!is_throttled(403, Some(150.0)));
+        assert!(!is_throttled(403, None));
+        assert!(!is_throttled(404, Some(0.0)));
+    }
+
+    #[test]
+    fn attachment_names_are_unique() {
+        let attachment = |id, name: &str| Attachment {
+            id,
+            display_name: name.to_owned(),
+            url: String::new(),
+        };
+        let attachments = [
+            attachment(1, "main.s"),
+            attachment(2, "../../lib.s"),
+            attachment(3, "main.s"),
+            attachment(4, "Makefile"),
+            attachment(5, "Makefile"),
+        ];
+        assert_eq!(
+            attachment_file_names(&attachments).unwrap(),
+            ["main-1.s", "lib.s", "main-3.s", "Makefile-4", "Makefile-5"]
+        );
+        assert!(attachment_file_names(&[attachment(1, PROJECT_METADATA_FILE)]).is_err());
+        assert!(attachment_file_names(&[attachment(1, "..")]).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{git, GitCredentials};
+use crate::DetectArgs;
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+/// Prefix of the URLs of the repositories, to which the access token is sent when cloning them.
+const GITHUB_URL: &str = "https://github.com/";
+/// Maximum page size allowed by the GitHub REST API.
+const PAGE_SIZE: usize = 100;
+
+#[derive(clap::Args, Debug)]
+pub struct FetchClassroomArgs {
+    /// GitHub organization in which the classroom's repositories are created.
+    #[arg(long)]
+    org: String,
+    /// Assignment slug. Student repositories are expected to be named `<assignment>-<username>`, as GitHub Classroom
+    /// does by default.
+    #[arg(long)]
+    assignment: String,
+    /// Directory in which to place the student repositories. Each repository becomes one project.
+    #[arg(long, default_value = "./submissions")]
+    dest: PathBuf,
+    /// Submission deadline as an ISO 8601 timestamp (e.g., "2023-03-01T23:59:59Z"). If given, each repository is
+    /// checked out at its last commit before the deadline. Otherwise, the latest commit is used.
+    #[arg(long)]
+    deadline: Option<String>,
+    /// GitHub access token with read access to the student repositories.
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Whether to analyze the projects directory once all repositories have been fetched.
+    #[arg(long, default_value_t = false)]
+    analyze: bool,
+    #[command(flatten)]
+    detect: DetectArgs,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    name: String,
+    full_name: String,
+    clone_url: String,
+}
+
+#[derive(Deserialize)]
+struct Commit {
+    sha: String,
+}
+
+/// Clones every accepted student repository of a GitHub Classroom assignment into the destination directory and
+/// optionally analyzes the result.
+pub fn fetch_classroom(args: FetchClassroomArgs) -> anyhow::Result<()> {
+    let token = args.token.as_deref();
+    let repositories = list_assignment_repositories(&args.org, &args.assignment, token)?;
+    if repositories.is_empty() {
+        anyhow::bail!(
+            "No repositories found for assignment '{}' in organization '{}'.",
+            args.assignment,
+            args.org
+        );
+    }
+
+    fs::create_dir_all(&args.dest)
+        .with_context(|| format!("Failed to create directory '{}'.", args.dest.display()))?;
+
+    let mut num_fetched = 0;
+    for repository in repositories.iter() {
+        let student = student_name(&repository.name, &args.assignment)
+            .expect("the repositories were selected based on their names");
+        let project_dir = args.dest.join(student);
+        if project_dir.exists() {
+            eprintln!(
+                "Skipping '{}' since '{}' already exists.",
+                repository.full_name,
+                project_dir.display()
+            );
+            continue;
+        }
+
+        let result = match &args.deadline {
+            None => clone_latest(repository, &project_dir, token),
+            Some(deadline) => clone_at_deadline(repository, &project_dir, deadline, token),
+        };
+        match result {
+            Err(e) => {
+                // Clean up so that the repository is fetched again on the next run
+                let _ = fs::remove_dir_all(&project_dir);
+                eprintln!("Failed to fetch '{}':\n  {e:#}", repository.full_name);
+            }
+            Ok(false) => {
+                eprintln!(
+                    "Skipping '{}' since it has no commits before the deadline.",
+                    repository.full_name
+                );
+            }
+            Ok(true) => {
+                num_fetched += 1;
+            }
+        }
+    }
+
+    println!(
+        "Fetched {num_fetched} of {} repositories into \"{}\".",
+        repositories.len(),
+        args.dest.display()
+    );
+
+    if args.analyze {
+        crate::run(&args.dest, args.detect)?;
+    }
+
+    Ok(())
+}
+
+/// Lists the repositories in the organization which belong to the given assignment.
+fn list_assignment_repositories(
+    org: &str,
+    assignment: &str,
+    token: Option<&str>,
+) -> anyhow::Result<Vec<Repository>> {
+    let mut repositories = Vec::new();
+
+    for page in 1.. {
+        let url =
+            format!("{GITHUB_API_URL}/orgs/{org}/repos?type=all&per_page={PAGE_SIZE}&page={page}");
+        let page_repositories: Vec<Repository> = get_json(&url, token)?;
+        let is_last_page = page_repositories.len() < PAGE_SIZE;
+
+        repositories.extend(
+            page_repositories
+                .into_iter()
+                .filter(|r| student_name(&r.name, assignment).is_some()),
+        );
+
+        if is_last_page {
+            break;
+        }
+    }
+
+    repositories.sort_unstable_by(|r1, r2| r1.name.cmp(&r2.name));
+    Ok(repositories)
+}
+
+/// Returns the name of the student owning a repository of the assignment, which is named `<assignment>-<username>`, or
+/// `None` if the repository does not belong to the assignment.
+fn student_name<'a>(repository_name: &'a str, assignment: &str) -> Option<&'a str> {
+    repository_name
+        .strip_prefix(assignment)?
+        .strip_prefix('-')
+        .filter(|student| !student.is_empty())
+}
+
+/// Shallowly clones the latest commit of the repository.
+fn clone_latest(
+    repository: &Repository,
+    project_dir: &Path,
+    token: Option<&str>,
+) -> anyhow::Result<bool> {
+    let dir = project_dir.to_string_lossy();
+    git(
+        &[
+            "clone",
+            "--quiet",
+            "--depth",
+            "1",
+            &repository.clone_url,
+            &dir,
+        ],
+        None,
+        credentials(token).as_ref(),
+        &format!("clone '{}'", repository.full_name),
+    )?;
+    remove_git_dir(project_dir)?;
+    Ok(true)
+}
+
+/// Shallowly fetches the last commit of the repository made before the deadline. Returns `false` if there is no such
+/// commit.
+fn clone_at_deadline(
+    repository: &Repository,
+    project_dir: &Path,
+    deadline: &str,
+    token: Option<&str>,
+) -> anyhow::Result<bool> {
+    let commits: Vec<Commit> = get_json(&commits_url(&repository.full_name, deadline), token)?;
+    let Some(commit) = commits.first() else {
+        return Ok(false);
+    };
+
+    fs::create_dir_all(project_dir)
+        .with_context(|| format!("Failed to create directory '{}'.", project_dir.display()))?;
+
+    let description = format!("fetch commit {} of '{}'", commit.sha, repository.full_name);
+    let credentials = credentials(token);
+    git(&["init", "--quiet"], Some(project_dir), None, &description)?;
+    git(
+        &[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            &repository.clone_url,
+            &commit.sha,
+        ],
+        Some(project_dir),
+        credentials.as_ref(),
+        &description,
+    )?;
+    git(
+        &["checkout", "--quiet", "FETCH_HEAD"],
+        Some(project_dir),
+        None,
+        &description,
+    )?;
+    remove_git_dir(project_dir)?;
+    Ok(true)
+}
+
+/// Removes the `.git` directory so that its contents are not analyzed as part of the project.
+fn remove_git_dir(project_dir: &Path) -> anyhow::Result<()> {
+    let git_dir = project_dir.join(".git");
+    fs::remove_dir_all(&git_dir)
+        .with_context(|| format!("Failed to remove '{}'.", git_dir.display()))
+}
+
+/// Returns the URL listing the last commit of the repository made before the deadline.
+fn commits_url(full_name: &str, deadline: &str) -> String {
+    let deadline = utf8_percent_encode(deadline, NON_ALPHANUMERIC);
+    format!("{GITHUB_API_URL}/repos/{full_name}/commits?until={deadline}&per_page=1")
+}
+
+/// Returns the credentials with which `git` authenticates to GitHub with the access token, if any.
+fn credentials(token: Option<&str>) -> Option<GitCredentials<'_>> {
+    token.map(|token| GitCredentials {
+        url: GITHUB_URL,
+        username: "x-access-token",
+        password: token,
+    })
+}
+
+fn get_json<T: DeserializeOwned>(url: &str, token: Option<&str>) -> anyhow::Result<T> {
+    let mut request = ureq::get(url)
+        .set("Accept", "application/vnd.github+json")
+        .set("User-Agent", "fungus-cli");
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+
+    request
+        .call()
+        .with_context(|| format!("GitHub API request to '{url}' failed."))?
+        .into_json()
+        .with_context(|| format!("Failed to parse GitHub API response from '{url}'."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repositories_are_matched_to_the_assignment() {
+        assert_eq!(student_name("lab1-alice", "lab1"), Some("alice"));
+        assert_eq!(student_name("lab1-bob-smith", "lab1"), Some("bob-smith"));
+        assert_eq!(student_name("lab1-", "lab1"), None);
+        assert_eq!(student_name("lab1", "lab1"), None);
+        assert_eq!(student_name("lab10-alice", "lab1"), None);
+        assert_eq!(student_name("lab2-alice", "lab1"), None);
+    }
+
+    #[test]
+    fn deadline_is_encoded() {
+        assert_eq!(
+            commits_url("course/lab1-alice", "2023-03-01T23:59:59+01:00"),
+            "https://api.github.com/repos/course/lab1-alice/commits?until=2023%2D03%2D01T23%3A59%3A59%2B01%3A00&per_page=1"
+        );
+    }
+
+    #[test]
+    fn token_is_only_sent_to_github() {
+        assert!(credentials(None).is_none());
+        let credentials = credentials(Some("ghp_secret")).unwrap();
+        assert_eq!(credentials.url, GITHUB_URL);
+        assert_eq!(credentials.password, "ghp_secret");
+    }
+}
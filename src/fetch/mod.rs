@@ -0,0 +1,103 @@
+//! Subcommands which download student submissions and lay them out as a projects directory.
+
+use std::{path::Path, process::Command};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+pub mod canvas;
+pub mod classroom;
+
+/// HTTP credentials passed to `git` for the requests to one host.
+struct GitCredentials<'a> {
+    /// URL prefix of the requests which carry the credentials (e.g., "https://github.com/").
+    url: &'a str,
+    username: &'a str,
+    password: &'a str,
+}
+
+impl GitCredentials<'_> {
+    /// Returns the environment variables which make `git` send the credentials as an extra HTTP header. Unlike a URL or
+    /// a command-line argument, they are neither visible to other users in the process list nor saved in the
+    /// configuration of the repository.
+    fn env(&self) -> [(String, String); 3] {
+        let basic = STANDARD.encode(format!("{}:{}", self.username, self.password));
+        [
+            ("GIT_CONFIG_COUNT".to_owned(), "1".to_owned()),
+            (
+                "GIT_CONFIG_KEY_0".to_owned(),
+                format!("http.{}.extraHeader", self.url),
+            ),
+            (
+                "GIT_CONFIG_VALUE_0".to_owned(),
+                format!("Authorization: Basic {basic}"),
+            ),
+        ]
+    }
+
+    /// Replaces the password, and its encoded form, in the output of `git`.
+    fn redact(&self, output: &str) -> String {
+        let basic = STANDARD.encode(format!("{}:{}", self.username, self.password));
+        output.replace(&basic, "***").replace(self.password, "***")
+    }
+}
+
+/// Runs `git` with the given arguments, failing if it exits unsuccessfully.
+///
+/// `description` is used in error messages in place of the arguments. Any credentials are removed from the error
+/// output of `git`.
+fn git(
+    args: &[&str],
+    working_dir: Option<&Path>,
+    credentials: Option<&GitCredentials>,
+    description: &str,
+) -> anyhow::Result<()> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(dir) = working_dir {
+        command.current_dir(dir);
+    }
+    if let Some(credentials) = credentials {
+        command.envs(credentials.env());
+    }
+
+    let output = command
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run git to {description}: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = match credentials {
+            None => stderr.trim().to_owned(),
+            Some(credentials) => credentials.redact(stderr.trim()),
+        };
+        anyhow::bail!("Failed to {description}: {stderr}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_stay_out_of_the_arguments() {
+        let credentials = GitCredentials {
+            url: "https://github.com/",
+            username: "x-access-token",
+            password: "ghp_secret",
+        };
+        let env = credentials.env();
+        assert_eq!(env[1].1, "http.https://github.com/.extraHeader");
+        assert_eq!(
+            env[2].1,
+            format!(
+                "Authorization: Basic {}",
+                STANDARD.encode("x-access-token:ghp_secret")
+            )
+        );
+        assert_eq!(
+            credentials.redact("fatal: token ghp_secret rejected"),
+            "fatal: token *** rejected"
+        );
+    }
+}
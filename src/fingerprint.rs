@@ -1,4 +1,5 @@
 use std::{
+    fmt::{Debug, Display},
     hash::{Hash, Hasher},
     ops::Range,
 };
@@ -9,6 +10,96 @@ pub struct Fingerprint {
     pub spanned_hashes: Vec<(u64, Range<usize>)>,
 }
 
+/// Reason why a file could not be fingerprinted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FingerprintError {
+    /// The file contains fewer tokens than the noise threshold, so no substring is long enough to be hashed.
+    TooFewTokens {
+        num_tokens: usize,
+        noise_threshold: usize,
+    },
+}
+
+impl Display for FingerprintError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FingerprintError::TooFewTokens {
+                num_tokens,
+                noise_threshold,
+            } => write!(formatter, "File could not be fingerprinted because it contains {num_tokens} tokens, which is less than the noise threshold of {noise_threshold}."),
+        }
+    }
+}
+
+impl std::error::Error for FingerprintError {}
+
+/// Algorithm which selects the hashes of a document that are compared with other documents. Implement it to replace
+/// winnowing in `DetectionOptions::fingerprint_algorithm`.
+pub trait FingerprintAlgorithm: Debug + Send + Sync {
+    /// Identifies the algorithm along with any parameters of its own, so that a checkpoint is only resumed with the
+    /// same algorithm.
+    fn name(&self) -> String;
+
+    /// Fingerprints the hashed tokens of a document, with the same meaning of the thresholds as in `fingerprint`.
+    fn fingerprint(
+        &self,
+        noise_threshold: usize,
+        guarantee_threshold: usize,
+        max_token_offset: usize,
+        tokens: &[(u64, Range<usize>)],
+    ) -> Result<Fingerprint, FingerprintError>;
+}
+
+/// Which hash winnowing selects when the minimum hash occurs more than once in a window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreaking {
+    /// The leftmost occurrence, and a hash is not selected again while it stays the minimum of consecutive windows.
+    #[default]
+    Leftmost,
+    /// The rightmost occurrence, as in the original winnowing paper, and a hash is selected again at every new
+    /// position. Runs of identical hashes yield denser fingerprints than with the leftmost rule.
+    Rightmost,
+}
+
+/// The winnowing algorithm, used by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Winnowing {
+    pub tie_breaking: TieBreaking,
+}
+
+impl Winnowing {
+    pub fn new(tie_breaking: TieBreaking) -> Self {
+        Winnowing { tie_breaking }
+    }
+}
+
+impl FingerprintAlgorithm for Winnowing {
+    fn name(&self) -> String {
+        match self.tie_breaking {
+            TieBreaking::Leftmost => "winnowing".to_owned(),
+            TieBreaking::Rightmost => "winnowing-rightmost".to_owned(),
+        }
+    }
+
+    fn fingerprint(
+        &self,
+        noise_threshold: usize,
+        guarantee_threshold: usize,
+        max_token_offset: usize,
+        tokens: &[(u64, Range<usize>)],
+    ) -> Result<Fingerprint, FingerprintError> {
+        fingerprint_with_tie_breaking(
+            noise_threshold,
+            guarantee_threshold,
+            max_token_offset,
+            tokens,
+            self.tie_breaking,
+        )
+    }
+}
+
 /// Generates a `Fingerprint` for the given list of tokens using the winnowing algorithm.
 /// Tokens can be any type that implements the `Hash` trait (chars, tokens from a lexer, etc.).
 ///
@@ -33,7 +124,21 @@ pub fn fingerprint<T>(
     t: usize,
     m: usize,
     tokens: &[(T, Range<usize>)],
-) -> anyhow::Result<Fingerprint>
+) -> Result<Fingerprint, FingerprintError>
+where
+    T: Hash,
+{
+    fingerprint_with_tie_breaking(k, t, m, tokens, TieBreaking::Leftmost)
+}
+
+/// Same as `fingerprint`, but with the given rule for windows in which the minimum hash occurs more than once.
+pub fn fingerprint_with_tie_breaking<T>(
+    k: usize,
+    t: usize,
+    m: usize,
+    tokens: &[(T, Range<usize>)],
+    tie_breaking: TieBreaking,
+) -> Result<Fingerprint, FingerprintError>
 where
     T: Hash,
 {
@@ -42,9 +147,38 @@ where
 
     let num_tokens = tokens.len();
     if num_tokens < k {
-        anyhow::bail!("File could not be fingerprinted because it contains {num_tokens} tokens, which is less than the noise threshold of {k}.");
+        return Err(FingerprintError::TooFewTokens {
+            num_tokens,
+            noise_threshold: k,
+        });
     }
 
+    let w = window_size(k, t, m);
+
+    // Generate the hashes of all valid k-grams in the document.
+    // By hashing k-grams, we guarantee that no match shorter than k will be included in the
+    // fingerprint.
+    let hashes = tokens
+        .windows(k)
+        .map(|w| hash_window(w))
+        .collect::<Vec<_>>();
+
+    let fingerprint = match tie_breaking {
+        TieBreaking::Leftmost => choose_fingerprint(&hashes, w),
+        TieBreaking::Rightmost => choose_rightmost_fingerprint(&hashes, w),
+    };
+    Ok(fingerprint)
+}
+
+/// Returns the winnowing window size for the noise threshold `k`, the guarantee threshold `t`, and the maximum offset
+/// `m` for relative tokens.
+///
+/// # Panics
+///
+/// * Panics if `t < k + m`
+pub fn window_size(k: usize, t: usize, m: usize) -> usize {
+    assert!(t >= k + m);
+
     // ORIGINAL FORMULA:
     //   The window size is set to t - k + 1 such that at least one hash is
     //   picked from every sequence of hashes of length greater than t - k.
@@ -56,18 +190,7 @@ where
     //   offsets are limited to m and these code snippets are assumed to be
     //   identical). So by choosing the window size to be (t - m) - k + 1, we
     //   can make the same guarantee as with the original formula.
-    let w = t - m - k + 1;
-
-    // Generate the hashes of all valid k-grams in the document.
-    // By hashing k-grams, we guarantee that no match shorter than k will be included in the
-    // fingerprint.
-    let hashes = tokens
-        .windows(k)
-        .map(|w| hash_window(w))
-        .collect::<Vec<_>>();
-
-    let fingerprint = choose_fingerprint(&hashes, w);
-    Ok(fingerprint)
+    t - m - k + 1
 }
 
 #[inline]
@@ -131,6 +254,33 @@ fn choose_fingerprint(spanned_hashes: &[(u64, Range<usize>)], w: usize) -> Finge
     }
 }
 
+/// Selects the rightmost minimum hash of every window, recording it unless it is at the same position as the hash
+/// selected in the previous window.
+#[inline]
+fn choose_rightmost_fingerprint(spanned_hashes: &[(u64, Range<usize>)], w: usize) -> Fingerprint {
+    let mut fingerprint_hashes = vec![];
+    let mut previously_picked_index: Option<usize> = None;
+
+    for (start, window) in spanned_hashes.windows(w).enumerate() {
+        let (offset, (min_hash, min_hash_span)) = window
+            .iter()
+            .enumerate()
+            .rev()
+            .min_by_key(|(_, (hash, _))| hash)
+            .unwrap();
+        let index = start + offset;
+
+        if previously_picked_index != Some(index) {
+            previously_picked_index = Some(index);
+            fingerprint_hashes.push((*min_hash, min_hash_span.clone()));
+        }
+    }
+
+    Fingerprint {
+        spanned_hashes: fingerprint_hashes,
+    }
+}
+
 #[cfg(test)]
 mod fingerprint_tests {
     use super::*;
@@ -173,4 +323,58 @@ mod fingerprint_tests {
         let fingerprint = choose_fingerprint(&hashes, w);
         assert_eq!(fingerprint.spanned_hashes, vec![(1, 0..1)]);
     }
+
+    #[test]
+    fn identical_hashes_rightmost() {
+        let hashes = vec![(1, 0..1), (1, 1..2), (1, 2..3), (1, 3..4), (1, 4..5)];
+        let w = 2;
+        let fingerprint = choose_rightmost_fingerprint(&hashes, w);
+        assert_eq!(
+            fingerprint.spanned_hashes,
+            vec![(1, 1..2), (1, 2..3), (1, 3..4), (1, 4..5)]
+        );
+    }
+
+    #[test]
+    fn moss_example_rightmost() {
+        // Example from page 4 of the MOSS paper, which uses the rightmost rule
+        let hashes = vec![
+            (77, 0..1),
+            (74, 1..2),
+            (42, 2..3),
+            (17, 3..4),
+            (98, 4..5),
+            (50, 5..6),
+            (17, 6..7),
+            (98, 7..8),
+            (8, 8..9),
+            (88, 9..10),
+            (67, 10..11),
+            (39, 11..12),
+            (77, 12..13),
+            (74, 13..14),
+            (42, 14..15),
+            (17, 15..16),
+            (98, 16..17),
+        ];
+        let w = 4;
+        let fingerprint = choose_rightmost_fingerprint(&hashes, w);
+        assert_eq!(
+            fingerprint.spanned_hashes,
+            vec![
+                (17, 3..4),
+                (17, 6..7),
+                (8, 8..9),
+                (39, 11..12),
+                (17, 15..16)
+            ]
+        );
+    }
+
+    #[test]
+    fn window_sizes() {
+        assert_eq!(window_size(5, 8, 0), 4);
+        assert_eq!(window_size(40, 80, 39), 2);
+        assert_eq!(window_size(3, 3, 0), 1);
+    }
 }
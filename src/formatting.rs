@@ -0,0 +1,153 @@
+//! Secondary detector comparing how the projects are formatted (indentation, blank lines, and the alignment of
+//! comments), which the tokenizers normalize away. The same idiosyncratic formatting corroborates other evidence of
+//! copying, since it tends to survive when the code itself is edited.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use itertools::Itertools;
+
+use crate::{heuristics, output::FormattingPair, stylometry, DetectionOptions, File};
+
+/// Number of consecutive lines whose formatting makes up a pattern.
+const PATTERN_LINES: usize = 3;
+
+/// Width of a tab, used to find the column at which comments start.
+const TAB_WIDTH: usize = 8;
+
+/// Returns the pairs of projects whose formatting pattern profiles have a cosine similarity of at least
+/// `min_similarity`, from the most to the least similar.
+///
+/// Each pattern is weighted by its frequency in the project and by how rare it is across the projects (TF-IDF), like
+/// the instructions of the stylometry, so the formatting shared by every project carries no weight.
+pub fn similar_projects(
+    options: &DetectionOptions,
+    documents: &[File],
+    min_similarity: f64,
+) -> Vec<FormattingPair> {
+    let profiles = stylometry::weight_profiles(&formatting_profiles(documents));
+
+    let mut pairs = profiles
+        .iter()
+        .tuple_combinations()
+        .filter(|((project1, _), (project2, _))| options.compares(project1, project2))
+        .filter_map(|((project1, profile1), (project2, profile2))| {
+            let (similarity, distinctive_patterns) =
+                stylometry::compare_profiles(profile1, profile2);
+            (similarity >= min_similarity).then(|| FormattingPair {
+                project1: (*project1).to_owned(),
+                project2: (*project2).to_owned(),
+                similarity,
+                distinctive_patterns,
+            })
+        })
+        .collect::<Vec<_>>();
+    pairs.sort_by(|p1, p2| {
+        p2.similarity
+            .total_cmp(&p1.similarity)
+            .then_with(|| (&p1.project1, &p1.project2).cmp(&(&p2.project1, &p2.project2)))
+    });
+    pairs
+}
+
+/// Counts the occurrences of each formatting pattern in each project. Patterns do not span several files.
+fn formatting_profiles(documents: &[File]) -> BTreeMap<&PathBuf, BTreeMap<String, usize>> {
+    let mut profiles: BTreeMap<&PathBuf, BTreeMap<String, usize>> = BTreeMap::new();
+    for document in documents {
+        let profile = profiles.entry(&document.project).or_default();
+        let lines = document
+            .contents
+            .lines()
+            .map(line_formatting)
+            .collect::<Vec<_>>();
+        for pattern in lines.windows(PATTERN_LINES) {
+            *profile.entry(pattern.join(" / ")).or_default() += 1;
+        }
+    }
+    profiles
+}
+
+/// Describes the formatting of a line, leaving out its contents.
+fn line_formatting(line: &str) -> String {
+    if line.trim().is_empty() {
+        return "blank".to_owned();
+    }
+    let code = line.trim_start();
+    let indentation = &line[..line.len() - code.len()];
+    let mut formatting = if indentation.is_empty() {
+        "flush".to_owned()
+    } else if indentation.chars().all(|c| c == '\t') {
+        format!("{} tabs", indentation.len())
+    } else if indentation.chars().all(|c| c == ' ') {
+        format!("{} spaces", indentation.len())
+    } else {
+        format!("mixed indentation of width {}", column(indentation))
+    };
+    match heuristics::comment_start(line) {
+        Some((start, _)) if start == indentation.len() => formatting.push_str(", comment"),
+        Some((start, _)) => {
+            formatting.push_str(&format!(", comment at column {}", column(&line[..start])))
+        }
+        None => {}
+    }
+    if line.ends_with(char::is_whitespace) {
+        formatting.push_str(", trailing whitespace");
+    }
+    formatting
+}
+
+/// Returns the column (zero-based) following the given start of a line, expanding tabs.
+fn column(start: &str) -> usize {
+    start.chars().fold(0, |column, c| match c {
+        '\t' => (column / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => column + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idiosyncratic_formatting_is_similar() {
+        let file = |project: &str, contents: &str| {
+            File::new(
+                project.into(),
+                format!("{project}/main.s").into(),
+                contents.to_owned(),
+            )
+        };
+        let usual = "main:\n\tmov r0, #1\n\tbx lr\n";
+        let documents = vec![
+            // Alice and Bob wrote different code, but align their comments the same unusual way
+            file(
+                "alice",
+                "main:\n  mov r0, #1      @ one\n\n  add r0, r0, r1  @ sum \n",
+            ),
+            file(
+                "bob",
+                "main:\n  sub r2, r2, #2  @ two\n\n  mul r3, r2, r2  @ square \n",
+            ),
+            file("carol", usual),
+            file("dave", usual),
+        ];
+
+        let pairs = similar_projects(&DetectionOptions::default(), &documents, 0.9);
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(
+            (&pairs[0].project1, &pairs[0].project2),
+            (&PathBuf::from("alice"), &PathBuf::from("bob"))
+        );
+        assert!((pairs[0].similarity - 1.0).abs() < 1e-9);
+        assert_eq!(
+            pairs[0].distinctive_patterns,
+            vec![
+                "2 spaces, comment at column 18 / blank / 2 spaces, comment at column 18, trailing whitespace",
+                "flush / 2 spaces, comment at column 18 / blank",
+            ]
+        );
+        assert_eq!(
+            (&pairs[1].project1, &pairs[1].project2),
+            (&PathBuf::from("carol"), &PathBuf::from("dave"))
+        );
+    }
+}
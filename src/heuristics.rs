@@ -0,0 +1,221 @@
+//! Heuristics flagging anomalies within a single file which may suggest that parts of it were copied from elsewhere,
+//! such as a mix of comment styles. These are informational findings, independent of the matches between projects.
+
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use crate::{
+    output::{Finding, Heuristic},
+    File,
+};
+
+/// Minimum number of lines on which the less common of two styles must appear for a file to be flagged, so that a single
+/// stray line is not reported.
+const MIN_MINORITY_LINES: usize = 2;
+
+/// Markers starting a comment, as written by different assemblers and editors.
+const COMMENT_MARKERS: [&str; 4] = ["@", "//", "/*", ";"];
+
+/// Registers which have both a numbered name and an alias.
+const REGISTER_ALIASES: [(&str, &str); 5] = [
+    ("r11", "fp"),
+    ("r12", "ip"),
+    ("r13", "sp"),
+    ("r14", "lr"),
+    ("r15", "pc"),
+];
+
+/// Returns the findings of every heuristic for every document, ordered by file.
+pub fn file_findings(documents: &[File]) -> Vec<Finding> {
+    let mut findings = documents
+        .iter()
+        .flat_map(|d| {
+            let lines = d.contents.lines().collect::<Vec<_>>();
+            [
+                mixed_comment_styles(&lines),
+                mixed_register_names(&lines),
+                mixed_indentation(&lines),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|(heuristic, line, message)| Finding {
+                file: d.path.clone(),
+                heuristic,
+                line,
+                message,
+            })
+        })
+        .collect::<Vec<_>>();
+    findings.sort_by(|f1, f2| {
+        (&f1.file, f1.line, f1.heuristic).cmp(&(&f2.file, f2.line, f2.heuristic))
+    });
+    findings
+}
+
+/// Heuristic which was triggered, along with the line on which the less common style first appears (one-based) and a
+/// description.
+type RawFinding = (Heuristic, usize, String);
+
+fn mixed_comment_styles(lines: &[&str]) -> Option<RawFinding> {
+    let styles = lines
+        .iter()
+        .map(|line| comment_start(line).map(|(_, marker)| marker));
+    let (majority, minority) = two_most_common(styles)?;
+    Some((
+        Heuristic::MixedCommentStyles,
+        minority.first_line,
+        format!(
+            "Comments start with \"{}\" on {} lines but with \"{}\" on {} lines.",
+            majority.style, majority.num_lines, minority.style, minority.num_lines
+        ),
+    ))
+}
+
+fn mixed_register_names(lines: &[&str]) -> Option<RawFinding> {
+    // Only the register with the most even mix of names is reported
+    REGISTER_ALIASES
+        .iter()
+        .filter_map(|&(numbered, alias)| {
+            let names = lines.iter().map(|line| {
+                let code = comment_start(line).map_or(*line, |(start, _)| &line[..start]);
+                // A line using both names is counted for the first one
+                code.split(|c: char| !c.is_ascii_alphanumeric())
+                    .map(str::to_ascii_lowercase)
+                    .find_map(|w| [numbered, alias].into_iter().find(|name| w == *name))
+            });
+            let (majority, minority) = two_most_common(names)?;
+            Some((
+                minority.num_lines,
+                (
+                    Heuristic::MixedRegisterNames,
+                    minority.first_line,
+                    format!(
+                        "The same register is named \"{}\" on {} lines but \"{}\" on {} lines.",
+                        majority.style, majority.num_lines, minority.style, minority.num_lines
+                    ),
+                ),
+            ))
+        })
+        .max_by_key(|(num_lines, _)| *num_lines)
+        .map(|(_, finding)| finding)
+}
+
+fn mixed_indentation(lines: &[&str]) -> Option<RawFinding> {
+    let styles = lines.iter().map(|line| {
+        let indentation = &line[..line.len() - line.trim_start().len()];
+        if indentation.is_empty() || line.trim().is_empty() {
+            None
+        } else if indentation.chars().all(|c| c == '\t') {
+            Some("tabs")
+        } else if indentation.chars().all(|c| c == ' ') {
+            Some("spaces")
+        } else {
+            Some("tabs and spaces")
+        }
+    });
+    let (majority, minority) = two_most_common(styles)?;
+    Some((
+        Heuristic::MixedIndentation,
+        minority.first_line,
+        format!(
+            "Lines are indented with {} on {} lines but with {} on {} lines.",
+            majority.style, majority.num_lines, minority.style, minority.num_lines
+        ),
+    ))
+}
+
+/// Returns the position of the start of the comment of a line, if any, along with its marker.
+pub(crate) fn comment_start(line: &str) -> Option<(usize, &'static str)> {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            in_string = !in_string;
+        }
+        if in_string {
+            continue;
+        }
+        if let Some(marker) = COMMENT_MARKERS.iter().find(|m| line[i..].starts_with(**m)) {
+            return Some((i, marker));
+        }
+    }
+    None
+}
+
+/// Number of lines written in a style, and the first of these lines (one-based).
+struct StyleCount<T> {
+    style: T,
+    num_lines: usize,
+    first_line: usize,
+}
+
+/// Returns the two styles found on the most lines, if the less common of them is found on at least
+/// `MIN_MINORITY_LINES` lines. Lines without a style are skipped.
+fn two_most_common<T: Ord + Copy>(
+    styles: impl Iterator<Item = Option<T>>,
+) -> Option<(StyleCount<T>, StyleCount<T>)> {
+    let mut counts: BTreeMap<T, StyleCount<T>> = BTreeMap::new();
+    for (i, style) in styles.enumerate() {
+        if let Some(style) = style {
+            counts
+                .entry(style)
+                .or_insert(StyleCount {
+                    style,
+                    num_lines: 0,
+                    first_line: i + 1,
+                })
+                .num_lines += 1;
+        }
+    }
+    let mut counts = counts.into_values().collect::<Vec<_>>();
+    // The sort is stable, so ties are broken by the order of the styles
+    counts.sort_by_key(|c| Reverse(c.num_lines));
+    let mut counts = counts.into_iter();
+    let (majority, minority) = (counts.next()?, counts.next()?);
+    (minority.num_lines >= MIN_MINORITY_LINES).then_some((majority, minority))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_mixed_styles() {
+        let contents = "\
+main:
+\tpush {lr} @ save
+\tsub sp, sp, #8 @ frame
+\tstr r0, [sp] @ store
+\t.asciz \"a // b\" @ text
+    add r13, r13, #4 // copied
+    sub r13, r13, #4 // copied
+\tadd sp, sp, #8
+\tpop {lr}
+\tbx lr
+";
+        let documents = vec![
+            File::new("P1".into(), "P1/a.s".into(), contents.to_owned()),
+            File::new(
+                "P1".into(),
+                "P1/b.s".into(),
+                "\tmov r0, #1 @ one\n".to_owned(),
+            ),
+        ];
+
+        let findings = file_findings(&documents);
+        let summary = findings
+            .iter()
+            .map(|f| (f.file.to_str().unwrap(), f.heuristic, f.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("P1/a.s", Heuristic::MixedCommentStyles, 6),
+                ("P1/a.s", Heuristic::MixedRegisterNames, 6),
+                ("P1/a.s", Heuristic::MixedIndentation, 6),
+            ]
+        );
+        assert_eq!(
+            findings[1].message,
+            "The same register is named \"sp\" on 3 lines but \"r13\" on 2 lines."
+        );
+    }
+}
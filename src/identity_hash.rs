@@ -10,18 +10,52 @@
 //! map.insert(22, 44);
 //! let mut set: IdentityHashSet = IdentityHashSet::default();
 //! set.insert(22);
+//! let mut compact_map: IdentityHashMap<u32, u32> = IdentityHashMap::default();
+//! compact_map.insert(22, 44);
 //! # }
 
 use std::{
     collections::{HashMap, HashSet},
-    hash::{BuildHasherDefault, Hasher},
+    hash::{BuildHasherDefault, Hash, Hasher},
 };
 
-/// Hasher which simply returns the passed-in value. To be used exclusively with u64 values, panics otherwise.
+/// Number of bits of the fingerprint hashes used as the keys of the hash database.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HashWidth {
+    /// Full 64-bit hashes.
+    #[default]
+    #[cfg_attr(feature = "cli", value(name = "64"))]
+    Bits64,
+    /// Hashes truncated to 32 bits, which saves memory for enormous corpora. Unrelated code snippets are more likely to
+    /// share a hash and be reported as a match.
+    #[cfg_attr(feature = "cli", value(name = "32"))]
+    Bits32,
+}
+
+/// Precomputed hash which can be used as the key of an `IdentityHashMap`.
+pub trait IdentityHashKey: Copy + Eq + Hash + Into<u64> + Send + Sync {
+    /// Converts a 64-bit hash to this type, truncating it if necessary.
+    fn from_hash(hash: u64) -> Self;
+}
+
+impl IdentityHashKey for u64 {
+    fn from_hash(hash: u64) -> Self {
+        hash
+    }
+}
+
+impl IdentityHashKey for u32 {
+    fn from_hash(hash: u64) -> Self {
+        hash as u32
+    }
+}
+
+/// Hasher which simply returns the passed-in value. To be used exclusively with u64 and u32 values, panics otherwise.
 ///
 /// # Panics
 ///
-/// Panics if any method other than `write_u64` is called.
+/// Panics if any method other than `write_u64` or `write_u32` is called.
 #[derive(Default)]
 pub struct IdentityHasher {
     hash: u64,
@@ -35,14 +69,20 @@ impl Hasher for IdentityHasher {
 
     #[inline]
     fn write(&mut self, _bytes: &[u8]) {
-        panic!("IdentityHasher should only be used with u64 values")
+        panic!("IdentityHasher should only be used with u64 and u32 values")
     }
 
     #[inline]
     fn write_u64(&mut self, i: u64) {
         self.hash = i;
     }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        // The hash table also uses the top bits of the hash, so they must not all be zero
+        self.hash = (i as u64) << 32 | i as u64;
+    }
 }
 
-pub type IdentityHashMap<V> = HashMap<u64, V, BuildHasherDefault<IdentityHasher>>;
-pub type IdentityHashSet = HashSet<u64, BuildHasherDefault<IdentityHasher>>;
+pub type IdentityHashMap<V, K = u64> = HashMap<K, V, BuildHasherDefault<IdentityHasher>>;
+pub type IdentityHashSet<K = u64> = HashSet<K, BuildHasherDefault<IdentityHasher>>;
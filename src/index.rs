@@ -0,0 +1,364 @@
+//! Index of the fingerprints of past projects (e.g., the submissions of previous terms), which persists the hash
+//! database of a corpus so that new projects can be compared with it without fingerprinting the corpus again.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fingerprint_multiple, hash_documents, interning::PathArena, output::stable_path,
+    DetectionOptions, File, Progress, Warning,
+};
+
+/// Version of the index format, which is increased whenever the fingerprints of the same code change.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Fingerprints of the files of every indexed project, along with the options they were computed with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    pub noise_threshold: usize,
+    pub guarantee_threshold: usize,
+    pub max_token_offset: usize,
+    /// Hash of every option which affects the fingerprints. Projects can only be compared with the index (or other
+    /// indexes merged into it) if their options have the same hash.
+    pub options_hash: u64,
+    /// Indexed files of each project, by project name (relative to the directory it was indexed from).
+    pub projects: BTreeMap<String, Vec<IndexedFile>>,
+}
+
+/// Fingerprint of a single file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Path of the file, relative to the directory its project was indexed from.
+    pub path: String,
+    /// Each hash of the fingerprint, along with the start (inclusive) and end (exclusive) of its span in bytes.
+    pub hashes: Vec<(u64, usize, usize)>,
+}
+
+impl Index {
+    /// Creates an empty index for the given options.
+    pub fn new(options: &DetectionOptions) -> Index {
+        Index {
+            version: INDEX_VERSION,
+            noise_threshold: options.noise_threshold,
+            guarantee_threshold: options.guarantee_threshold,
+            max_token_offset: options.max_token_offset,
+            options_hash: options_hash(options),
+            projects: BTreeMap::new(),
+        }
+    }
+
+    /// Fingerprints the documents with the options of the index, without the code found in the ignored documents, and
+    /// adds their projects to the index. Projects and files are named relative to `root`. A project which is already in
+    /// the index is replaced. Returns the names of the added projects along with the warnings.
+    pub fn add_projects(
+        &mut self,
+        options: &DetectionOptions,
+        documents: &[File],
+        ignored_documents: &[File],
+        root: &Path,
+    ) -> Result<(Vec<String>, Vec<Warning>), String> {
+        self.check_options(options)?;
+
+        let (projects, warnings) =
+            fingerprint_projects(options, documents, ignored_documents, root);
+        let names = projects.keys().cloned().collect();
+        self.projects.extend(projects);
+        Ok((names, warnings))
+    }
+
+    /// Fingerprints the documents of a new project like [`Index::add_projects`], without adding them to the index, and
+    /// returns the indexed projects sharing at least `min_shared_hashes` hashes with it, from the most to the least
+    /// similar.
+    pub fn query(
+        &self,
+        options: &DetectionOptions,
+        documents: &[File],
+        ignored_documents: &[File],
+        root: &Path,
+        min_shared_hashes: usize,
+    ) -> Result<(Vec<IndexMatch>, Vec<Warning>), String> {
+        self.check_options(options)?;
+
+        let (projects, warnings) =
+            fingerprint_projects(options, documents, ignored_documents, root);
+        let mut files_by_hash: HashMap<u64, BTreeSet<&str>> = HashMap::new();
+        for file in projects.values().flatten() {
+            for (hash, _, _) in &file.hashes {
+                files_by_hash.entry(*hash).or_default().insert(&file.path);
+            }
+        }
+
+        let mut matches = self
+            .projects
+            .iter()
+            .filter_map(|(name, indexed_files)| {
+                let mut shared_hashes = BTreeSet::new();
+                let mut file_pairs = BTreeSet::new();
+                for indexed_file in indexed_files {
+                    for (hash, _, _) in &indexed_file.hashes {
+                        if let Some(files) = files_by_hash.get(hash) {
+                            shared_hashes.insert(*hash);
+                            file_pairs.extend(
+                                files
+                                    .iter()
+                                    .map(|f| (f.to_string(), indexed_file.path.clone())),
+                            );
+                        }
+                    }
+                }
+                (!shared_hashes.is_empty() && shared_hashes.len() >= min_shared_hashes).then(|| {
+                    IndexMatch {
+                        project: name.clone(),
+                        shared_hashes: shared_hashes.len(),
+                        proportion: shared_hashes.len() as f64 / files_by_hash.len() as f64,
+                        file_pairs: file_pairs.into_iter().collect(),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|m1, m2| {
+            m2.shared_hashes
+                .cmp(&m1.shared_hashes)
+                .then_with(|| m1.project.cmp(&m2.project))
+        });
+        Ok((matches, warnings))
+    }
+
+    /// Adds the projects of another index to this one, replacing the projects with the same names. Returns the number of
+    /// projects added.
+    pub fn merge(&mut self, other: Index) -> Result<usize, String> {
+        if other.version != self.version || other.options_hash != self.options_hash {
+            return Err(format!(
+                "The indexes were built with different options ({} and {}).",
+                other.describe_options(),
+                self.describe_options()
+            ));
+        }
+        let num_projects = other.projects.len();
+        self.projects.extend(other.projects);
+        Ok(num_projects)
+    }
+
+    /// Returns an error if the index was built with other options than those given, or by another version.
+    pub fn check_options(&self, options: &DetectionOptions) -> Result<(), String> {
+        if self.version != INDEX_VERSION {
+            return Err(format!(
+                "The index has version {}, but this version of FUNGUS reads version {INDEX_VERSION}. Build it again.",
+                self.version
+            ));
+        }
+        if self.options_hash != options_hash(options) {
+            return Err(format!(
+                "The index was built with different options ({}). Use the same tokenizing and fingerprinting options.",
+                self.describe_options()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of files and of hashes in the index.
+    pub fn size(&self) -> (usize, usize) {
+        let files = self.projects.values().flatten();
+        let num_hashes = files.clone().map(|f| f.hashes.len()).sum();
+        (files.count(), num_hashes)
+    }
+
+    fn describe_options(&self) -> String {
+        format!(
+            "noise {}, guarantee {}, max token offset {}",
+            self.noise_threshold, self.guarantee_threshold, self.max_token_offset
+        )
+    }
+}
+
+/// Indexed project sharing code with a new project.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IndexMatch {
+    /// Name of the indexed project.
+    pub project: String,
+    /// Number of distinct hashes of the new project found in the indexed project.
+    pub shared_hashes: usize,
+    /// Proportion of the distinct hashes of the new project found in the indexed project.
+    pub proportion: f64,
+    /// Pairs of a file of the new project and a file of the indexed project which share at least one hash.
+    pub file_pairs: Vec<(String, String)>,
+}
+
+/// Fingerprints the documents without the code found in the ignored documents, and groups the fingerprints by project.
+/// Projects and files are named relative to `root`.
+fn fingerprint_projects(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    root: &Path,
+) -> (BTreeMap<String, Vec<IndexedFile>>, Vec<Warning>) {
+    let arena = PathArena::new(documents.iter().chain(ignored_documents));
+    let (document_hashes, _starter_code_tokens, mut warnings) = hash_documents(
+        options,
+        documents,
+        ignored_documents,
+        &arena,
+        &mut |_: Progress| {},
+    );
+    let (fingerprints, fingerprinting_warnings) = fingerprint_multiple(
+        options.fingerprint_algorithm.as_ref(),
+        &document_hashes,
+        &arena,
+        options.noise_threshold,
+        options.guarantee_threshold,
+        options.max_token_offset,
+    );
+    warnings.extend(fingerprinting_warnings);
+
+    let mut projects: BTreeMap<String, Vec<IndexedFile>> = BTreeMap::new();
+    for (file_id, fingerprint) in fingerprints {
+        projects
+            .entry(stable_path(arena.path(file_id.project), root))
+            .or_default()
+            .push(IndexedFile {
+                path: stable_path(arena.path(file_id.path), root),
+                hashes: fingerprint
+                    .spanned_hashes
+                    .into_iter()
+                    .map(|(hash, span)| (hash, span.start, span.end))
+                    .collect(),
+            });
+    }
+    // The fingerprints are not ordered by file
+    for files in projects.values_mut() {
+        files.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+    }
+    (projects, warnings)
+}
+
+/// Hashes every option which affects the fingerprints of the projects.
+fn options_hash(options: &DetectionOptions) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    options.noise_threshold.hash(&mut hasher);
+    options.guarantee_threshold.hash(&mut hasher);
+    options.max_token_offset.hash(&mut hasher);
+    options.fingerprint_algorithm.name().hash(&mut hasher);
+    options.tokenizing_strategy.hash(&mut hasher);
+    options.ignore_whitespace.hash(&mut hasher);
+    options.transforms.hash(&mut hasher);
+    options.key_symbols.hash(&mut hasher);
+    options.hash_dialect.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenizingStrategy;
+
+    #[test]
+    fn indexes_are_merged_only_with_the_same_options() {
+        let options = DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let documents = vec![
+            File::new(
+                "root/P1".into(),
+                "root/P1/a.txt".into(),
+                "abcdef".to_owned(),
+            ),
+            File::new("root/P2".into(), "root/P2/a.txt".into(), "ab".to_owned()),
+        ];
+
+        let mut index = Index::new(&options);
+        let (names, warnings) = index
+            .add_projects(&options, &documents, &[], Path::new("root"))
+            .unwrap();
+        // P2 is too short to be fingerprinted
+        assert_eq!(names, vec!["P1"]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(index.projects["P1"][0].path, "P1/a.txt");
+        assert_eq!(index.size(), (1, 4));
+
+        let mut other = Index::new(&options);
+        other
+            .add_projects(&options, &documents[..1], &[], Path::new("root/P1"))
+            .unwrap();
+        assert_eq!(index.clone().merge(other), Ok(1));
+
+        let other_options = DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            ..options.clone()
+        };
+        assert!(index.merge(Index::new(&other_options)).is_err());
+        assert!(index.check_options(&other_options).is_err());
+    }
+
+    #[test]
+    fn queries_find_the_indexed_projects_sharing_code() {
+        let options = DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let documents = vec![
+            File::new(
+                "past/P1".into(),
+                "past/P1/a.txt".into(),
+                "abcdef".to_owned(),
+            ),
+            File::new(
+                "past/P2".into(),
+                "past/P2/a.txt".into(),
+                "uvwxyz".to_owned(),
+            ),
+            File::new(
+                "past/P3".into(),
+                "past/P3/a.txt".into(),
+                "abcxyz".to_owned(),
+            ),
+        ];
+        let mut index = Index::new(&options);
+        index
+            .add_projects(&options, &documents, &[], Path::new("past"))
+            .unwrap();
+
+        let new_project = vec![File::new(
+            "new/N".into(),
+            "new/N/b.txt".into(),
+            "abcdxyz".to_owned(),
+        )];
+        let (matches, _) = index
+            .query(&options, &new_project, &[], Path::new("new"), 1)
+            .unwrap();
+        let summary = matches
+            .iter()
+            .map(|m| (m.project.as_str(), m.shared_hashes))
+            .collect::<Vec<_>>();
+        // "abc", "bcd", and "xyz" are in P1, P2, and P3, out of the 5 substrings of the new project
+        assert_eq!(summary, vec![("P1", 2), ("P3", 2), ("P2", 1)]);
+        assert_eq!(matches[0].proportion, 0.4);
+        assert_eq!(
+            matches[0].file_pairs,
+            vec![("N/b.txt".to_owned(), "P1/a.txt".to_owned())]
+        );
+
+        let (matches, _) = index
+            .query(&options, &new_project, &[], Path::new("new"), 2)
+            .unwrap();
+        assert_eq!(matches.len(), 2);
+    }
+}
@@ -0,0 +1,350 @@
+//! Subcommands maintaining an index of the fingerprints of past projects, saved as a gzipped JSON file, and comparing
+//! new projects with it.
+
+use std::{
+    fs,
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+use clap::Subcommand;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use fungus_cli::{
+    index::Index,
+    lexing::{HashDialect, TokenTransform, TokenizingStrategy},
+    output::Warning,
+    DetectionOptions, TieBreaking, Winnowing,
+};
+
+use crate::{
+    read_files, read_key_symbols, read_projects, read_starter_code, ModifiedRange, TraversalLimits,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    command: IndexCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Fingerprints the projects in a directory and adds them to the index, which is created if it does not exist.
+    /// Projects which are already in the index are replaced.
+    Build(BuildArgs),
+    /// Writes the index as plain JSON, e.g. to share it with another department.
+    Export(ExportArgs),
+    /// Adds the projects of an index exported as JSON to the index, which is created if it does not exist. Both must
+    /// have been built with the same options.
+    Import(ImportArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BuildArgs {
+    /// Directory in which to search for code, containing one directory per project.
+    root: PathBuf,
+    /// Index file to update.
+    #[arg(long)]
+    index: PathBuf,
+    #[command(flatten)]
+    fingerprint: FingerprintArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// Index file to export.
+    #[arg(long)]
+    index: PathBuf,
+    /// JSON file to write.
+    #[arg(short, long)]
+    output_file: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ImportArgs {
+    /// JSON file written by `fungus index export`.
+    input_file: PathBuf,
+    /// Index file to update.
+    #[arg(long)]
+    index: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct QueryArgs {
+    /// Directory of the new project.
+    project: PathBuf,
+    /// Index file to compare the project with.
+    #[arg(long)]
+    index: PathBuf,
+    /// Minimum number of hashes an indexed project must share with the new project to be reported.
+    #[arg(long, default_value_t = 1)]
+    min_shared_hashes: usize,
+    /// JSON file to which to write the matching indexed projects, in addition to printing them.
+    #[arg(short, long)]
+    output_file: Option<PathBuf>,
+    #[command(flatten)]
+    fingerprint: FingerprintArgs,
+}
+
+/// Options which affect the fingerprints of the projects, with the same meaning and defaults as those of the analysis.
+/// The projects analyzed with other options cannot be compared with the index.
+#[derive(clap::Args, Debug)]
+struct FingerprintArgs {
+    /// Noise threshold. Matches whose length is less than this value will not be flagged.
+    #[arg(short, long, default_value_t = 40)]
+    noise: usize,
+    /// Guarantee threshold. Matches at least as long as this value are guaranteed to be flagged. Zero means the
+    /// smallest possible value (the noise threshold plus the max token offset).
+    #[arg(short, long, default_value_t = 80)]
+    guarantee: usize,
+    /// Maximum offset for relative tokens.
+    #[arg(long, default_value_t = 0)]
+    max_token_offset: usize,
+    /// Files and directories containing starter code, which is left out of the fingerprints.
+    #[arg(short, long)]
+    ignore: Vec<PathBuf>,
+    /// Tokenizing strategy to use. Can be one of "bytes", "naive", "relative", "comments", or "auto".
+    #[arg(value_enum, short, long, default_value = "auto")]
+    tokenizing_strategy: TokenizingStrategy,
+    /// Whether to ignore comments, whitespace, and newlines while tokenizing.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    ignore_whitespace: bool,
+    /// Comma-separated list of transforms applied to the tokens, in order.
+    #[arg(value_enum, long = "transform", value_delimiter = ',')]
+    transforms: Vec<TokenTransform>,
+    /// Dictionary of the instructions recognized by the "relative" tokenizing strategy, either "armv7" or a file
+    /// containing one instruction per line.
+    #[arg(long)]
+    key_symbols: Option<String>,
+    /// Meaning of the `#` character. Can be one of "immediate", "comment", or "auto".
+    #[arg(value_enum, long, default_value = "immediate")]
+    hash_dialect: HashDialect,
+    /// Which hash winnowing selects when the minimum hash occurs more than once in a window. Can be one of "leftmost"
+    /// or "rightmost".
+    #[arg(value_enum, long, default_value = "leftmost")]
+    tie_breaking: TieBreaking,
+}
+
+impl FingerprintArgs {
+    /// Returns the detection options fingerprinting the projects as requested.
+    fn detection_options(&self) -> anyhow::Result<DetectionOptions> {
+        let guarantee = match self.guarantee {
+            0 => self.noise + self.max_token_offset,
+            guarantee => guarantee,
+        };
+        if guarantee < self.noise + self.max_token_offset {
+            anyhow::bail!("Guarantee threshold must be greater than or equal to noise threshold plus max token offset. Received arguments guarantee = {guarantee}, noise = {}, max token offset = {}", self.noise, self.max_token_offset);
+        }
+        Ok(DetectionOptions {
+            noise_threshold: self.noise,
+            guarantee_threshold: guarantee,
+            max_token_offset: self.max_token_offset,
+            fingerprint_algorithm: Arc::new(Winnowing::new(self.tie_breaking)),
+            tokenizing_strategy: self.tokenizing_strategy,
+            ignore_whitespace: self.ignore_whitespace,
+            transforms: self.transforms.clone(),
+            key_symbols: read_key_symbols(self.key_symbols.as_deref())?,
+            hash_dialect: self.hash_dialect,
+            ..Default::default()
+        })
+    }
+}
+
+pub fn index(args: IndexArgs) -> anyhow::Result<()> {
+    match args.command {
+        IndexCommand::Build(args) => build(args),
+        IndexCommand::Export(args) => export(args),
+        IndexCommand::Import(args) => import(args),
+    }
+}
+
+fn build(args: BuildArgs) -> anyhow::Result<()> {
+    if !args.root.is_dir() {
+        anyhow::bail!(
+            "Projects directory '{}' is not a directory.",
+            args.root.display()
+        );
+    }
+    let options = args.fingerprint.detection_options()?;
+    let mut index = match args.index.exists() {
+        true => read_index(&args.index)?,
+        false => Index::new(&options),
+    };
+
+    let (documents, mut warnings) = read_projects(
+        &args.root,
+        &args.fingerprint.ignore,
+        ModifiedRange::default(),
+        TraversalLimits::default(),
+        None,
+        None,
+    );
+    let (ignored_documents, ignored_warnings) = read_starter_code(&args.fingerprint.ignore);
+    warnings.extend(ignored_warnings);
+    let (names, fingerprinting_warnings) = index
+        .add_projects(&options, &documents, &ignored_documents, &args.root)
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("Cannot add projects to \"{}\".", args.index.display()))?;
+    warnings.extend(fingerprinting_warnings);
+    print_warnings(&warnings);
+
+    write_index(&args.index, &index)?;
+    let (num_files, num_hashes) = index.size();
+    println!(
+        "Indexed {} project(s). \"{}\" now contains {} project(s), {num_files} file(s), and {num_hashes} hash(es).",
+        names.len(),
+        args.index.display(),
+        index.projects.len()
+    );
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> anyhow::Result<()> {
+    let index = read_index(&args.index)?;
+    let context = || {
+        format!(
+            "Failed to write the index to \"{}\".",
+            args.output_file.display()
+        )
+    };
+    let mut writer = BufWriter::new(fs::File::create(&args.output_file).with_context(context)?);
+    serde_json::to_writer(&mut writer, &index).with_context(context)?;
+    writer.flush().with_context(context)?;
+    println!(
+        "Exported {} project(s) to \"{}\".",
+        index.projects.len(),
+        args.output_file.display()
+    );
+    Ok(())
+}
+
+fn import(args: ImportArgs) -> anyhow::Result<()> {
+    let context = || {
+        format!(
+            "Failed to read the index \"{}\".",
+            args.input_file.display()
+        )
+    };
+    let reader = BufReader::new(fs::File::open(&args.input_file).with_context(context)?);
+    let imported: Index = serde_json::from_reader(reader).with_context(context)?;
+
+    let index = match args.index.exists() {
+        true => {
+            let mut index = read_index(&args.index)?;
+            let num_projects = index
+                .merge(imported)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| {
+                    format!(
+                        "Cannot import \"{}\" into \"{}\".",
+                        args.input_file.display(),
+                        args.index.display()
+                    )
+                })?;
+            println!("Imported {num_projects} project(s).");
+            index
+        }
+        false => {
+            println!("Imported {} project(s).", imported.projects.len());
+            imported
+        }
+    };
+    write_index(&args.index, &index)
+}
+
+/// Reports the indexed projects sharing code with a new project.
+pub fn query(args: QueryArgs) -> anyhow::Result<()> {
+    if !args.project.is_dir() {
+        anyhow::bail!(
+            "Project directory '{}' is not a directory.",
+            args.project.display()
+        );
+    }
+    let options = args.fingerprint.detection_options()?;
+    let index = read_index(&args.index)?;
+
+    let (documents, mut warnings) = read_files(
+        &args.project,
+        &args.fingerprint.ignore,
+        ModifiedRange::default(),
+        TraversalLimits::default(),
+    );
+    let (ignored_documents, ignored_warnings) = read_starter_code(&args.fingerprint.ignore);
+    warnings.extend(ignored_warnings);
+    // The project is named after its directory, like the indexed projects
+    let root = args.project.parent().unwrap_or(&args.project);
+    let (matches, fingerprinting_warnings) = index
+        .query(
+            &options,
+            &documents,
+            &ignored_documents,
+            root,
+            args.min_shared_hashes,
+        )
+        .map_err(anyhow::Error::msg)
+        .with_context(|| {
+            format!(
+                "Cannot compare the project with \"{}\".",
+                args.index.display()
+            )
+        })?;
+    warnings.extend(fingerprinting_warnings);
+    print_warnings(&warnings);
+
+    if matches.is_empty() {
+        println!(
+            "No indexed project shares code with \"{}\".",
+            args.project.display()
+        );
+    }
+    for m in &matches {
+        println!(
+            "{}: {} shared hash(es) ({:.0}% of the project)",
+            m.project,
+            m.shared_hashes,
+            m.proportion * 100.0
+        );
+        for (file, indexed_file) in &m.file_pairs {
+            println!("    {file} ~ {indexed_file}");
+        }
+    }
+
+    if let Some(path) = &args.output_file {
+        let context = || format!("Failed to write the matches to \"{}\".", path.display());
+        let mut writer = BufWriter::new(fs::File::create(path).with_context(context)?);
+        serde_json::to_writer_pretty(&mut writer, &matches).with_context(context)?;
+        writer.flush().with_context(context)?;
+    }
+    Ok(())
+}
+
+fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        match &warning.file {
+            Some(file) => eprintln!("Warning: {}: {}", file.display(), warning.message),
+            None => eprintln!("Warning: {}", warning.message),
+        }
+    }
+}
+
+/// Reads an index written by `fungus index build` or `fungus index import`.
+fn read_index(path: &Path) -> anyhow::Result<Index> {
+    let context = || format!("Failed to read the index \"{}\".", path.display());
+    let file = fs::File::open(path).with_context(context)?;
+    serde_json::from_reader(BufReader::new(GzDecoder::new(file))).with_context(context)
+}
+
+fn write_index(path: &Path, index: &Index) -> anyhow::Result<()> {
+    let context = || format!("Failed to write the index \"{}\".", path.display());
+    let mut encoder = GzEncoder::new(
+        BufWriter::new(fs::File::create(path).with_context(context)?),
+        Compression::default(),
+    );
+    serde_json::to_writer(&mut encoder, index).with_context(context)?;
+    encoder
+        .finish()
+        .and_then(|mut writer| writer.flush())
+        .with_context(context)
+}
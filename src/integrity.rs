@@ -0,0 +1,139 @@
+//! Integrity hashes of the JSON output, so that a committee can check that a report was not edited after it was
+//! generated. The hash covers the canonical form of the output (compact JSON with the keys of every object sorted), so
+//! it does not depend on `--pretty` or on the order in which the fields were written.
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::output::{Integrity, IntegrityAlgorithm};
+
+/// Name of the field of the output holding its integrity hash, which is left out of the hashed contents.
+const INTEGRITY_FIELD: &str = "integrity";
+
+/// Computes the integrity hash of the output, which must serialize to a JSON object. The hash is an HMAC-SHA256 if a
+/// key is given, or else a SHA-256. Any existing `integrity` field is left out.
+pub fn integrity<T: Serialize>(output: &T, key: Option<&[u8]>) -> Result<Integrity, String> {
+    let value = serde_json::to_value(output).map_err(|e| e.to_string())?;
+    let contents = canonical_contents(value)?;
+    Ok(match key {
+        None => Integrity {
+            algorithm: IntegrityAlgorithm::Sha256,
+            digest: format!("{:x}", Sha256::digest(contents.as_bytes())),
+        },
+        Some(key) => {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| e.to_string())?;
+            mac.update(contents.as_bytes());
+            Integrity {
+                algorithm: IntegrityAlgorithm::HmacSha256,
+                digest: format!("{:x}", mac.finalize().into_bytes()),
+            }
+        }
+    })
+}
+
+/// Checks the integrity hash embedded in the JSON output. Returns whether the output is intact, or an error if it has
+/// no integrity hash or if the hash is an HMAC and no key is given. With a key, only an HMAC is accepted, since anyone
+/// could edit the output and replace its HMAC with a plain SHA-256.
+pub fn verify(output: &str, key: Option<&[u8]>) -> Result<bool, String> {
+    let value: Value = serde_json::from_str(output).map_err(|e| e.to_string())?;
+    let embedded: Integrity = value
+        .get(INTEGRITY_FIELD)
+        .cloned()
+        .ok_or_else(|| "The output has no integrity hash.".to_owned())
+        .and_then(|v| serde_json::from_value(v).map_err(|e| e.to_string()))?;
+    let key =
+        match (embedded.algorithm, key) {
+            (IntegrityAlgorithm::Sha256, None) => None,
+            (IntegrityAlgorithm::Sha256, Some(_)) => return Err(
+                "The integrity hash of the output is a plain SHA-256, not an HMAC with the key."
+                    .to_owned(),
+            ),
+            (IntegrityAlgorithm::HmacSha256, Some(key)) => Some(key),
+            (IntegrityAlgorithm::HmacSha256, None) => {
+                return Err(
+                    "The integrity hash of the output is an HMAC, which requires a key.".to_owned(),
+                )
+            }
+        };
+    Ok(integrity(&value, key)?.digest == embedded.digest)
+}
+
+/// Returns the compact JSON of the object with the keys of every object sorted, leaving out its integrity field.
+fn canonical_contents(value: Value) -> Result<String, String> {
+    let Value::Object(mut object) = value else {
+        return Err("The output is not a JSON object.".to_owned());
+    };
+    object.remove(INTEGRITY_FIELD);
+    serde_json::to_string(&canonicalize(Value::Object(object))).map_err(|e| e.to_string())
+}
+
+/// Sorts the keys of every object, whether or not the JSON maps preserve the insertion order.
+fn canonicalize(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries = object.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k, canonicalize(v)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonicalize).collect()),
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn edits_are_detected() {
+        let output =
+            json!({ "project_pairs": [{ "score": 0.5, "project1": "a" }], "warnings": [] });
+        for key in [None, Some(b"secret".as_slice())] {
+            let mut signed = output.clone();
+            signed["integrity"] = serde_json::to_value(integrity(&output, key).unwrap()).unwrap();
+            let pretty = serde_json::to_string_pretty(&signed).unwrap();
+            assert_eq!(verify(&pretty, key), Ok(true));
+
+            signed["project_pairs"][0]["score"] = json!(0.25);
+            assert_eq!(verify(&signed.to_string(), key), Ok(false));
+        }
+    }
+
+    #[test]
+    fn hmac_requires_the_key() {
+        let output = json!({ "warnings": [] });
+        let mut signed = output.clone();
+        signed["integrity"] =
+            serde_json::to_value(integrity(&output, Some(b"secret")).unwrap()).unwrap();
+        assert!(verify(&signed.to_string(), None).is_err());
+        assert_eq!(verify(&signed.to_string(), Some(b"other")), Ok(false));
+        assert!(verify(&output.to_string(), None).is_err());
+    }
+
+    #[test]
+    fn sha256_is_rejected_with_a_key() {
+        let output = json!({ "project_pairs": [{ "score": 0.5 }] });
+        let mut signed = output.clone();
+        signed["integrity"] = serde_json::to_value(integrity(&output, None).unwrap()).unwrap();
+        assert_eq!(verify(&signed.to_string(), None), Ok(true));
+        assert!(verify(&signed.to_string(), Some(b"secret")).is_err());
+    }
+
+    #[test]
+    fn floats_round_trip() {
+        // Parses to a different float without exact float parsing
+        let output = json!({ "project_pairs": [{ "coverage": 0.24405029187247418 }] });
+        let mut signed = output.clone();
+        signed["integrity"] = serde_json::to_value(integrity(&output, None).unwrap()).unwrap();
+        assert_eq!(verify(&signed.to_string(), None), Ok(true));
+    }
+}
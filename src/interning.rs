@@ -0,0 +1,113 @@
+//! Interning of the paths of the projects and files, so that the analysis refers to them by small integer identifiers
+//! instead of cloning, hashing, and comparing whole paths. The paths are only looked up again to build the project
+//! pairs of the output.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+
+use crate::{File, FileId};
+
+/// Identifier of an interned path, which is its index in its [`PathArena`].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PathId(u32);
+
+/// Arena holding every path of an analysis once.
+#[derive(Clone, Debug, Default)]
+pub struct PathArena {
+    paths: Vec<PathBuf>,
+    ids: HashMap<PathBuf, PathId>,
+}
+
+impl PathArena {
+    /// Interns the projects and paths of the files. The identifiers are assigned in the order of the paths, so that they
+    /// compare like the paths they stand for.
+    pub fn new<'a>(files: impl IntoIterator<Item = &'a File>) -> PathArena {
+        let mut arena = PathArena::default();
+        let paths = files
+            .into_iter()
+            .flat_map(|f| [&f.project, &f.path])
+            .sorted_unstable()
+            .dedup();
+        for path in paths {
+            arena.intern(path);
+        }
+        arena
+    }
+
+    /// Returns the identifier of the path, interning it first if needed. A path interned after others gets a greater
+    /// identifier than all of them, whatever their order.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the arena already holds 2^32 paths
+    pub fn intern(&mut self, path: &Path) -> PathId {
+        if let Some(&id) = self.ids.get(path) {
+            return id;
+        }
+        let id = PathId(u32::try_from(self.paths.len()).expect("too many paths to intern"));
+        self.paths.push(path.to_owned());
+        self.ids.insert(path.to_owned(), id);
+        id
+    }
+
+    /// Interns the project and the path of a file.
+    pub fn intern_file(&mut self, project: &Path, path: &Path) -> FileId {
+        FileId::new(self.intern(project), self.intern(path))
+    }
+
+    /// Returns the identifier of the path, if it was interned.
+    pub fn id(&self, path: &Path) -> Option<PathId> {
+        self.ids.get(path).copied()
+    }
+
+    /// Returns the identifier of a file, if both its project and its path were interned.
+    pub fn file_id(&self, project: &Path, path: &Path) -> Option<FileId> {
+        Some(FileId::new(self.id(project)?, self.id(path)?))
+    }
+
+    /// Returns the path with the given identifier.
+    ///
+    /// # Panics
+    ///
+    /// * Panics if the identifier was not returned by this arena
+    pub fn path(&self, id: PathId) -> &PathBuf {
+        &self.paths[id.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_follow_the_paths() {
+        let documents = [
+            File::new("P2".into(), "P2/b.s".into(), String::new()),
+            File::new("P1".into(), "P1/a.s".into(), String::new()),
+            File::new("P2".into(), "P2/a.s".into(), String::new()),
+        ];
+        let mut arena = PathArena::new(&documents);
+        for (f1, f2) in documents.iter().tuple_combinations() {
+            let id1 = arena.file_id(&f1.project, &f1.path).unwrap();
+            let id2 = arena.file_id(&f2.project, &f2.path).unwrap();
+            assert_eq!(
+                id1.cmp(&id2),
+                (&f1.project, &f1.path).cmp(&(&f2.project, &f2.path))
+            );
+        }
+
+        let p2 = arena.id(Path::new("P2")).unwrap();
+        let routine = arena.intern_file(Path::new("P2"), Path::new("P2/a.s#main"));
+        assert_eq!(routine.project, p2);
+        assert!(routine.path > arena.id(Path::new("P2/b.s")).unwrap());
+        assert_eq!(arena.path(routine.path), &PathBuf::from("P2/a.s#main"));
+        assert_eq!(arena.intern(Path::new("P2/a.s#main")), routine.path);
+        assert!(arena
+            .file_id(Path::new("P3"), Path::new("P3/a.s"))
+            .is_none());
+    }
+}
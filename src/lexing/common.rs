@@ -0,0 +1,105 @@
+//! Callbacks and types shared by the lexers of every tokenizing strategy, so that the lexers handle the tokens they
+//! have in common (comments, symbols, and constants) identically.
+
+use std::hash::{Hash, Hasher};
+
+use logos::{Lexer, Logos};
+
+#[inline]
+pub fn parse_multiline_comment<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> &'source str {
+    &lex.slice()[2..lex.slice().len() - 2]
+}
+
+#[inline]
+pub fn parse_cstyle_line_comment<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> &'source str {
+    &lex.slice()[2..]
+}
+
+#[inline]
+pub fn parse_single_char_line_comment<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> &'source str {
+    &lex.slice()[1..]
+}
+
+#[inline]
+pub fn parse_unquoted_symbol<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> String {
+    lex.slice().to_ascii_lowercase()
+}
+
+#[inline]
+pub fn parse_quoted_symbol<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> String {
+    let s = lex.slice();
+    s[1..s.len() - 1].to_ascii_lowercase()
+}
+
+#[inline]
+pub fn parse_binary_integer<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> i64 {
+    i64::from_str_radix(&lex.slice()[2..], 2).unwrap()
+}
+
+#[inline]
+pub fn parse_octal_integer<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> i64 {
+    i64::from_str_radix(&lex.slice()[1..], 8).unwrap()
+}
+
+#[inline]
+pub fn parse_decimal_integer<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> i64 {
+    lex.slice().parse().unwrap()
+}
+
+#[inline]
+pub fn parse_hexadecimal_integer<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> i64 {
+    i64::from_str_radix(&lex.slice()[2..], 16).unwrap()
+}
+
+#[inline]
+pub fn parse_floating_point<'source, T: Logos<'source, Source = str>>(
+    lex: &mut Lexer<'source, T>,
+) -> HashableFloat {
+    HashableFloat(lex.slice()[2..].parse().unwrap())
+}
+
+/// Returns whether a symbol is the name of an ARMv7 register, using the same names as the naive lexer.
+pub fn is_register(symbol: &str) -> bool {
+    let symbol = symbol.to_ascii_lowercase();
+    let number = |prefix| symbol.strip_prefix(prefix)?.parse::<u8>().ok();
+    matches!(symbol.as_str(), "tr" | "sb" | "ip" | "sp" | "lr" | "pc")
+        || number("r").is_some_and(|n| n <= 15)
+        || number("a").is_some_and(|n| (1..=4).contains(&n))
+        || number("v").is_some_and(|n| (1..=8).contains(&n))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HashableFloat(pub f64);
+
+impl Hash for HashableFloat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let bits = self.0.to_bits();
+        bits.hash(state);
+    }
+}
+
+impl PartialEq for HashableFloat {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for HashableFloat {}
@@ -0,0 +1,170 @@
+//! Detection of the language of a file, so that directories mixing assembly with other languages (e.g., C) can be
+//! analyzed without tokenizing the other files as assembly.
+
+use std::path::Path;
+
+use super::TokenizingStrategy;
+
+/// Extensions of assembly files.
+const ASSEMBLY_EXTENSIONS: &[&str] = &["s", "asm", "arm"];
+
+/// Extensions of files in other languages, which are compared as bytes.
+const OTHER_EXTENSIONS: &[&str] = &[
+    "c", "h", "cc", "cpp", "cxx", "hpp", "hh", "py", "java", "js", "ts", "rs", "go", "txt", "md",
+];
+
+/// Tokenizing strategy detected for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectedStrategy {
+    /// Strategy used for the file, which is never "auto".
+    pub strategy: TokenizingStrategy,
+    /// Whether the contents of the file look like assembly as much as like another language (or like neither), so that
+    /// the strategy is only a best guess.
+    pub ambiguous: bool,
+}
+
+/// Chooses the tokenizing strategy of a file: "relative" for GNU ARM assembly, and "bytes" for any other language. The
+/// extension of the path decides if it is known (case-insensitively, so `.S` files are also assembly). Otherwise, the
+/// lines of the contents are sniffed for assembly (directives, labels, instructions with register operands, and `@`
+/// comments) and for C-like code (preprocessor directives, braces, statements ending with a semicolon, and `//`
+/// comments). The detection is ambiguous unless one kind of line is at least twice as common as the other.
+pub fn detect_tokenizing_strategy(path: Option<&Path>, contents: &str) -> DetectedStrategy {
+    let extension = path
+        .and_then(Path::extension)
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    if let Some(extension) = extension {
+        if ASSEMBLY_EXTENSIONS.contains(&extension.as_str()) {
+            return DetectedStrategy {
+                strategy: TokenizingStrategy::Relative,
+                ambiguous: false,
+            };
+        }
+        if OTHER_EXTENSIONS.contains(&extension.as_str()) {
+            return DetectedStrategy {
+                strategy: TokenizingStrategy::Bytes,
+                ambiguous: false,
+            };
+        }
+    }
+
+    let (mut assembly_lines, mut other_lines) = (0, 0);
+    for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if is_assembly(line) {
+            assembly_lines += 1;
+        } else if is_c_like(line) {
+            other_lines += 1;
+        }
+    }
+    if assembly_lines == 0 && other_lines == 0 {
+        // Files without any code (e.g., empty files) are left to the default tokenizer, which finds too few tokens
+        let ambiguous = !contents.trim().is_empty();
+        return DetectedStrategy {
+            strategy: if ambiguous {
+                TokenizingStrategy::Bytes
+            } else {
+                TokenizingStrategy::Relative
+            },
+            ambiguous,
+        };
+    }
+    DetectedStrategy {
+        strategy: if assembly_lines >= other_lines {
+            TokenizingStrategy::Relative
+        } else {
+            TokenizingStrategy::Bytes
+        },
+        ambiguous: assembly_lines < 2 * other_lines && other_lines < 2 * assembly_lines,
+    }
+}
+
+/// Returns whether the trimmed line looks like GNU ARM assembly.
+fn is_assembly(line: &str) -> bool {
+    if line.starts_with('@') {
+        return true;
+    }
+    let statement = line.split('@').next().unwrap_or_default().trim_end();
+    let (first, operands) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    // Directive (e.g., `.text`) or label (e.g., `main:` or `.L2:`)
+    if first.len() > 1
+        && (first.starts_with('.') || first.ends_with(':'))
+        && first
+            .trim_end_matches(':')
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_.$".contains(c))
+    {
+        return true;
+    }
+    // Instruction whose operands include a register or an immediate (e.g., `add r0, r1, #1`)
+    let is_mnemonic = (1..=8).contains(&first.len())
+        && first.chars().all(|c| c.is_ascii_alphanumeric() || c == '.');
+    is_mnemonic
+        && !statement.ends_with(';')
+        && operands
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+            .any(is_operand)
+}
+
+/// Returns whether the word is an ARM register or an immediate.
+fn is_operand(word: &str) -> bool {
+    let word = word.to_ascii_lowercase();
+    let numbered = |prefix: char| {
+        word.strip_prefix(prefix)
+            .is_some_and(|n| !n.is_empty() && n.len() <= 2 && n.chars().all(|c| c.is_ascii_digit()))
+    };
+    numbered('r')
+        || numbered('s')
+        || numbered('d')
+        || ["sp", "lr", "pc", "fp", "ip"].contains(&word.as_str())
+        || (word.starts_with('#') && word.len() > 1)
+}
+
+/// Returns whether the trimmed line looks like C or a similar language.
+fn is_c_like(line: &str) -> bool {
+    ["#include", "#define", "#if", "#pragma", "//"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+        || line.ends_with(';')
+        || line.ends_with('{')
+        || line.starts_with('}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSEMBLY: &str = ".text\n.global main\nmain:\n\tpush {r4, lr}\n\tmov r0, #1 @ one\n\tbl helper\n\tpop {r4, pc}\n";
+    const C: &str = "#include <stdio.h>\n\nint main(void) {\n\tint x = 1;\n\tprintf(\"%d\\n\", x);\n\treturn 0;\n}\n";
+
+    #[test]
+    fn extensions_decide() {
+        let detect = |path: &str| detect_tokenizing_strategy(Some(Path::new(path)), C);
+        assert_eq!(detect("a/main.S").strategy, TokenizingStrategy::Relative);
+        assert_eq!(detect("a/main.c").strategy, TokenizingStrategy::Bytes);
+        assert!(!detect("a/main.c").ambiguous);
+    }
+
+    #[test]
+    fn contents_are_sniffed() {
+        let assembly = detect_tokenizing_strategy(Some(Path::new("a/main")), ASSEMBLY);
+        assert_eq!(
+            assembly,
+            DetectedStrategy {
+                strategy: TokenizingStrategy::Relative,
+                ambiguous: false
+            }
+        );
+        let c = detect_tokenizing_strategy(None, C);
+        assert_eq!(
+            c,
+            DetectedStrategy {
+                strategy: TokenizingStrategy::Bytes,
+                ambiguous: false
+            }
+        );
+        assert!(detect_tokenizing_strategy(None, &format!("{ASSEMBLY}{C}")).ambiguous);
+        assert!(!detect_tokenizing_strategy(None, "\n").ambiguous);
+    }
+}
@@ -0,0 +1,73 @@
+//! Assembler dialects which the lexers must account for before tokenizing.
+
+use std::borrow::Cow;
+
+/// Meaning of the `#` character, which prefixes immediates in ARM syntax, but starts line comments for some toolchains.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HashDialect {
+    /// `#` prefixes immediates (e.g., `mov r0, #1`).
+    #[default]
+    Immediate,
+    /// `#` starts a line comment, like `@`.
+    Comment,
+    /// `#` starts a line comment if it is the first character of a line (ignoring whitespace) or if it is surrounded
+    /// by whitespace, and prefixes an immediate otherwise.
+    Auto,
+}
+
+/// Replaces every `#` which starts a comment in the given dialect with `@`, which always starts a line comment. Since
+/// both characters are one byte long, the spans of the tokens are unchanged.
+pub fn mark_hash_comments(source: &str, dialect: HashDialect) -> Cow<'_, str> {
+    if dialect == HashDialect::Immediate || !source.contains('#') {
+        return Cow::Borrowed(source);
+    }
+
+    let mut marked = String::with_capacity(source.len());
+    for line in source.split_inclusive('\n') {
+        let mut previous = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let is_comment = c == '#'
+                && match dialect {
+                    HashDialect::Immediate => false,
+                    HashDialect::Comment => true,
+                    HashDialect::Auto => {
+                        let at_line_start = line.trim_start().starts_with('#')
+                            && previous.is_none_or(char::is_whitespace);
+                        let surrounded_by_whitespace = previous.is_some_and(char::is_whitespace)
+                            && chars.peek().is_none_or(|next| next.is_whitespace());
+                        at_line_start || surrounded_by_whitespace
+                    }
+                };
+            if is_comment {
+                // The rest of the line is part of the comment
+                marked.push('@');
+                marked.extend(chars);
+                break;
+            }
+            marked.push(c);
+            previous = Some(c);
+        }
+    }
+    Cow::Owned(marked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_comments_are_marked() {
+        let source = "# setup\n  mov r0, #1 # one\nadd r0, r0, #-2\n";
+        assert_eq!(mark_hash_comments(source, HashDialect::Immediate), source);
+        assert_eq!(
+            mark_hash_comments(source, HashDialect::Comment),
+            "@ setup\n  mov r0, @1 # one\nadd r0, r0, @-2\n"
+        );
+        assert_eq!(
+            mark_hash_comments(source, HashDialect::Auto),
+            "@ setup\n  mov r0, #1 @ one\nadd r0, r0, #-2\n"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Dictionaries of the symbols which can begin a statement (i.e., instructions and directives), used by the relative
+//! tokenizer to tell them apart from operands.
+
+use std::collections::BTreeSet;
+
+/// Instructions and directives recognized by the relative tokenizer. Without a dictionary, the first non-label symbol
+/// of every statement is assumed to be an instruction or a directive, which misclassifies operands on continuation
+/// lines.
+///
+/// Symbols starting with a period (i.e., directives) are always recognized at the start of a statement.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum KeySymbols {
+    /// Built-in set of ARMv7 instructions, with any condition code, flag-setting suffix, and qualifier (e.g., `.w` or
+    /// `.f32`).
+    Armv7,
+    /// Custom set of lower case instructions, matched exactly.
+    Custom(BTreeSet<String>),
+}
+
+/// Base mnemonics of the ARMv7 (ARM, Thumb, and VFP) instructions.
+#[rustfmt::skip]
+const ARMV7_MNEMONICS: &[&str] = &[
+    // Data processing
+    "adc", "add", "adr", "and", "asr", "bic", "cmn", "cmp", "eor", "lsl", "lsr", "mov", "movt",
+    "movw", "mvn", "neg", "orn", "orr", "ror", "rrx", "rsb", "rsc", "sbc", "sub", "teq", "tst",
+    // Multiplication and division
+    "mla", "mls", "mul", "sdiv", "smlal", "smull", "umaal", "umlal", "umull", "udiv",
+    // Bit manipulation and saturation
+    "bfc", "bfi", "clz", "rbit", "rev", "rev16", "revsh", "sbfx", "ssat", "sxtb", "sxth", "ubfx",
+    "usat", "uxtb", "uxth",
+    // Branches
+    "b", "bl", "blx", "bx", "bxj", "cbnz", "cbz", "tbb", "tbh",
+    // Loads and stores
+    "ldm", "ldmda", "ldmdb", "ldmea", "ldmed", "ldmfa", "ldmfd", "ldmia", "ldmib", "ldr", "ldrb",
+    "ldrd", "ldrex", "ldrexb", "ldrexd", "ldrexh", "ldrh", "ldrsb", "ldrsh", "pld", "pli", "pop",
+    "push", "stm", "stmda", "stmdb", "stmea", "stmed", "stmfa", "stmfd", "stmia", "stmib", "str",
+    "strb", "strd", "strex", "strexb", "strexd", "strexh", "strh", "swp", "swpb",
+    // System and miscellaneous
+    "bkpt", "cpsid", "cpsie", "dmb", "dsb", "isb", "mrs", "msr", "nop", "sev", "svc", "swi", "wfe",
+    "wfi", "yield",
+    // Floating point
+    "vabs", "vadd", "vcmp", "vcmpe", "vcvt", "vdiv", "vldm", "vldr", "vmla", "vmls", "vmov", "vmrs",
+    "vmsr", "vmul", "vneg", "vpop", "vpush", "vsqrt", "vstm", "vstr", "vsub",
+];
+
+/// Condition codes which can follow an ARMv7 mnemonic.
+const ARMV7_CONDITIONS: &[&str] = &[
+    "eq", "ne", "cs", "hs", "cc", "lo", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le",
+    "al",
+];
+
+impl KeySymbols {
+    /// Parses a dictionary containing one instruction per line. Blank lines are ignored.
+    pub fn parse(contents: &str) -> KeySymbols {
+        KeySymbols::Custom(
+            contents
+                .lines()
+                .map(|line| line.trim().to_ascii_lowercase())
+                .filter(|line| !line.is_empty())
+                .collect(),
+        )
+    }
+
+    /// Returns whether the (lower case) symbol is an instruction or a directive.
+    pub fn contains(&self, symbol: &str) -> bool {
+        if symbol.starts_with('.') {
+            return true;
+        }
+        match self {
+            KeySymbols::Armv7 => is_armv7_instruction(symbol),
+            KeySymbols::Custom(symbols) => symbols.contains(symbol),
+        }
+    }
+}
+
+fn is_armv7_instruction(symbol: &str) -> bool {
+    // Ignore qualifiers such as the instruction width (e.g., `ldr.w`) or the data type (e.g., `vadd.f32`)
+    let symbol = symbol.split('.').next().unwrap_or(symbol);
+
+    // If-then blocks have up to three additional "then" or "else" conditions
+    if let Some(conditions) = symbol.strip_prefix("it") {
+        if conditions.len() <= 3 && conditions.chars().all(|c| c == 't' || c == 'e') {
+            return true;
+        }
+    }
+
+    ARMV7_MNEMONICS.iter().any(|mnemonic| {
+        let Some(suffix) = symbol.strip_prefix(mnemonic) else {
+            return false;
+        };
+        // Flag-setting suffix and condition code, in either order (pre-UAL and UAL syntax)
+        let suffix = suffix.strip_prefix('s').unwrap_or(suffix);
+        let suffix = suffix.strip_suffix('s').unwrap_or(suffix);
+        suffix.is_empty() || ARMV7_CONDITIONS.contains(&suffix)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armv7_instructions() {
+        for instruction in [
+            "add", "adds", "addne", "addsne", "addnes", "bl", "blt", "ldr.w", "vadd.f32", "ite",
+            "pop", ".word",
+        ] {
+            assert!(KeySymbols::Armv7.contains(instruction), "{instruction}");
+        }
+        for symbol in ["r1", "sp", "main", "loop", "addx", "itx"] {
+            assert!(!KeySymbols::Armv7.contains(symbol), "{symbol}");
+        }
+    }
+
+    #[test]
+    fn custom_dictionary() {
+        let key_symbols = KeySymbols::parse("ADD\n\n  mov \n");
+        assert!(key_symbols.contains("add"));
+        assert!(key_symbols.contains("mov"));
+        assert!(key_symbols.contains(".text"));
+        assert!(!key_symbols.contains("addne"));
+    }
+}
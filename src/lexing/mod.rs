@@ -5,11 +5,21 @@ use std::{
 
 use rustc_hash::FxHasher;
 
+mod common;
+mod detection;
+mod dialect;
+pub mod key_symbols;
 mod naive;
 mod preprocessing;
 mod relative;
 
-#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq, Eq)]
+pub use detection::{detect_tokenizing_strategy, DetectedStrategy};
+pub use dialect::HashDialect;
+pub use key_symbols::KeySymbols;
+pub use preprocessing::TokenTransform;
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum TokenizingStrategy {
     /// Do not tokenize the input. Instead, process the input as a sequence of bytes.
     Bytes,
@@ -21,41 +31,107 @@ pub enum TokenizingStrategy {
     /// This requires an additional pass over the input to compute the offsets and identify key symbols
     /// (i.e. instructions and directives).
     Relative,
+    /// Only compare the text of the comments found by the naive tokenizer, one token per word (ignoring case and
+    /// punctuation). Identical idiosyncratic comments then match even if the code around them differs.
+    Comments,
+    /// Detect the language of each file from its extension or contents: GNU ARM assembly is tokenized with the
+    /// "relative" tokenizing strategy, and any other language (e.g., C) is processed as bytes.
+    Auto,
 }
 
+/// Hashes of the tokens of a document along with their spans.
+pub type HashedTokens = Vec<(u64, Range<usize>)>;
+
+/// Tokenizes the string, applies the transforms to the tokens in order, and hashes them. The transforms are ignored by
+/// the "bytes" and "comments" tokenizing strategies, and the key symbols are only used by the "relative" tokenizing
+/// strategy. The `#` dialect decides which `#` characters start comments, and is ignored by the "bytes" tokenizing
+/// strategy. The "auto" tokenizing strategy only sniffs the contents, since the path of the string is unknown.
+///
+/// Also returns the spans of the characters which could not be tokenized, with adjacent spans merged. These characters
+/// are still hashed as error tokens.
 pub fn tokenize_and_hash(
     string: &str,
     tokenizing_strategy: TokenizingStrategy,
-    ignore_whitespace: bool,
+    transforms: &[TokenTransform],
+    key_symbols: Option<&KeySymbols>,
+    hash_dialect: HashDialect,
     max_token_offset: usize,
-) -> Vec<(u64, Range<usize>)> {
+) -> (HashedTokens, Vec<Range<usize>>) {
     match tokenizing_strategy {
+        TokenizingStrategy::Auto => tokenize_and_hash(
+            string,
+            detect_tokenizing_strategy(None, string).strategy,
+            transforms,
+            key_symbols,
+            hash_dialect,
+            max_token_offset,
+        ),
         TokenizingStrategy::Bytes => {
             // Use bytes instead of chars since it shouldn't affect the result and is faster.
             let characters = string.as_bytes();
-            characters
+            let hashes = characters
                 .iter()
                 .enumerate()
                 .map(|(i, &c)| (c, i..i + 1))
                 .map(|(c, span)| (hash_token(c), span))
-                .collect()
+                .collect();
+            (hashes, Vec::new())
         }
         TokenizingStrategy::Naive => {
-            let mut tokens = naive::lex(string);
-            if ignore_whitespace {
-                tokens = preprocessing::whitespace_removal::remove_whitespace_naive(tokens);
+            let string = dialect::mark_hash_comments(string, hash_dialect);
+            let mut tokens = naive::lex(&string);
+            if transforms.contains(&TokenTransform::CanonicalStatements) {
+                tokens = preprocessing::statements::canonicalize_statements(
+                    tokens,
+                    |t| matches!(t, naive::Token::Newline),
+                    |t| matches!(t, naive::Token::Whitespace),
+                );
             }
-            tokens
+            let errors = error_spans(
+                tokens
+                    .iter()
+                    .filter(|(t, _)| matches!(t, naive::Token::Error))
+                    .map(|(_, span)| span),
+            );
+            let hashes = preprocessing::transform_naive(tokens, transforms)
                 .into_iter()
                 .map(|(t, span)| (hash_token(t), span))
-                .collect()
+                .collect();
+            (hashes, errors)
+        }
+        TokenizingStrategy::Comments => {
+            let string = dialect::mark_hash_comments(string, hash_dialect);
+            let tokens = naive::lex(&string);
+            let errors = error_spans(
+                tokens
+                    .iter()
+                    .filter(|(t, _)| matches!(t, naive::Token::Error))
+                    .map(|(_, span)| span),
+            );
+            let hashes = tokens
+                .into_iter()
+                .filter(|(t, _)| matches!(t, naive::Token::Comment(_)))
+                .flat_map(|(_, span)| comment_words(&string, span))
+                .map(|(word, span)| (hash_token(word), span))
+                .collect();
+            (hashes, errors)
         }
         TokenizingStrategy::Relative => {
-            let mut tokens = relative::lex(string);
-            if ignore_whitespace {
-                tokens = preprocessing::whitespace_removal::remove_whitespace_relative(tokens);
-            }
-            tokens
+            let string = dialect::mark_hash_comments(string, hash_dialect);
+            let tokens = relative::lex(
+                &string,
+                key_symbols,
+                transforms.contains(&TokenTransform::CanonicalStatements),
+            );
+            let errors = error_spans(
+                tokens
+                    .iter()
+                    .filter(|(t, _)| matches!(t, relative::Token::Error))
+                    .map(|(_, span)| span),
+            );
+            let tokens =
+                preprocessing::transform_relative(tokens, transforms, &string, max_token_offset);
+            let hashes = tokens
                 .into_iter()
                 .map(|(t, span)| {
                     if let relative::Token::RelativeSymbol(n) = t {
@@ -66,11 +142,76 @@ pub fn tokenize_and_hash(
                     (t, span)
                 })
                 .map(|(t, span)| (hash_token(t), span))
-                .collect()
+                .collect();
+            (hashes, errors)
         }
     }
 }
 
+/// Returns the instructions of the string in order, in lower case. Directives are not included. Instructions are
+/// identified as by the "relative" tokenizing strategy.
+pub fn instructions(
+    string: &str,
+    key_symbols: Option<&KeySymbols>,
+    hash_dialect: HashDialect,
+) -> Vec<String> {
+    let string = dialect::mark_hash_comments(string, hash_dialect);
+    relative::lex(&string, key_symbols, false)
+        .into_iter()
+        .filter_map(|(t, _)| match t {
+            relative::Token::KeySymbol(symbol) if !symbol.starts_with('.') => Some(symbol),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the quoted string literals of the string in order, including their quotes and with their original case.
+pub fn string_literals(string: &str, hash_dialect: HashDialect) -> Vec<&str> {
+    let marked = dialect::mark_hash_comments(string, hash_dialect);
+    naive::lex(&marked)
+        .into_iter()
+        .filter(|(t, span)| {
+            matches!(t, naive::Token::Symbol(_)) && marked[span.clone()].starts_with('"')
+        })
+        // Marking comments does not move any token, so the spans are also valid in the original string
+        .map(|(_, span)| &string[span])
+        .collect()
+}
+
+/// Returns the words of the comment at the given span in lower case, along with their spans. Words are runs of
+/// alphanumeric characters, so the comment delimiters are skipped.
+fn comment_words(string: &str, span: Range<usize>) -> Vec<(String, Range<usize>)> {
+    let comment = &string[span.clone()];
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (i, c) in comment.char_indices().chain([(comment.len(), ' ')]) {
+        match (word_start, c.is_alphanumeric()) {
+            (None, true) => word_start = Some(i),
+            (Some(start), false) => {
+                words.push((
+                    comment[start..i].to_lowercase(),
+                    span.start + start..span.start + i,
+                ));
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Merges adjacent spans, which are given in order.
+fn error_spans<'a>(spans: impl Iterator<Item = &'a Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.end == span.start => last.end = span.end,
+            _ => merged.push(span.clone()),
+        }
+    }
+    merged
+}
+
 fn hash_token<T: Hash>(token: T) -> u64 {
     // IMPORTANT: create a new hasher each time because hasher.finish() does NOT
     // clear the hasher, it only returns the hash.
@@ -78,3 +219,32 @@ fn hash_token<T: Hash>(token: T) -> u64 {
     token.hash(&mut hasher);
     hasher.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_words_are_tokens() {
+        let source = "mov r0, #1 @ Set the COUNTER\n/* counter,\n  again */ add r0, r0, r0";
+        let (hashes, errors) = tokenize_and_hash(
+            source,
+            TokenizingStrategy::Comments,
+            &[],
+            None,
+            HashDialect::Immediate,
+            0,
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            hashes,
+            vec![
+                (hash_token("set"), 13..16),
+                (hash_token("the"), 17..20),
+                (hash_token("counter"), 21..28),
+                (hash_token("counter"), 32..39),
+                (hash_token("again"), 43..48),
+            ]
+        );
+    }
+}
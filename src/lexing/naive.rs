@@ -1,10 +1,13 @@
-use std::{
-    hash::{Hash, Hasher},
-    ops::Range,
-};
+use std::ops::Range;
 
 use logos::{Lexer, Logos};
 
+use super::common::{
+    parse_binary_integer, parse_cstyle_line_comment, parse_decimal_integer, parse_floating_point,
+    parse_hexadecimal_integer, parse_multiline_comment, parse_octal_integer, parse_quoted_symbol,
+    parse_single_char_line_comment, parse_unquoted_symbol, HashableFloat,
+};
+
 // Implemented using information from the [GNU assembler documentation](https://sourceware.org/binutils/docs/as/)
 // and the [ARM developer documentation](https://developer.arm.com/documentation/).
 #[derive(Logos, Clone, Debug, PartialEq, Eq, Hash)]
@@ -133,38 +136,10 @@ pub enum Token<'source> {
 }
 
 #[must_use]
-pub fn lex(s: &str) -> Vec<(Token, Range<usize>)> {
+pub fn lex(s: &str) -> Vec<(Token<'_>, Range<usize>)> {
     Token::lexer(s).spanned().collect()
 }
 
-#[inline]
-fn parse_multiline_comment<'source>(lex: &mut Lexer<'source, Token<'source>>) -> &'source str {
-    &lex.slice()[2..lex.slice().len() - 2]
-}
-
-#[inline]
-fn parse_cstyle_line_comment<'source>(lex: &mut Lexer<'source, Token<'source>>) -> &'source str {
-    &lex.slice()[2..]
-}
-
-#[inline]
-fn parse_single_char_line_comment<'source>(
-    lex: &mut Lexer<'source, Token<'source>>,
-) -> &'source str {
-    &lex.slice()[1..]
-}
-
-#[inline]
-fn parse_unquoted_symbol<'source>(lex: &mut Lexer<'source, Token<'source>>) -> String {
-    lex.slice().to_ascii_lowercase()
-}
-
-#[inline]
-fn parse_quoted_symbol<'source>(lex: &mut Lexer<'source, Token<'source>>) -> String {
-    let s = lex.slice();
-    s[1..s.len() - 1].to_ascii_lowercase()
-}
-
 #[inline]
 fn parse_unquoted_label<'source>(lex: &mut Lexer<'source, Token<'source>>) -> String {
     let s = lex.slice();
@@ -177,31 +152,6 @@ fn parse_quoted_label<'source>(lex: &mut Lexer<'source, Token<'source>>) -> Stri
     s[1..s.len() - 2].to_ascii_lowercase()
 }
 
-#[inline]
-fn parse_binary_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[2..], 2).unwrap()
-}
-
-#[inline]
-fn parse_octal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[1..], 8).unwrap()
-}
-
-#[inline]
-fn parse_decimal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    lex.slice().parse().unwrap()
-}
-
-#[inline]
-fn parse_hexadecimal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[2..], 16).unwrap()
-}
-
-#[inline]
-fn parse_floating_point<'source>(lex: &mut Lexer<'source, Token<'source>>) -> HashableFloat {
-    HashableFloat(lex.slice()[2..].parse().unwrap())
-}
-
 #[inline]
 fn parse_register<'source>(lex: &mut Lexer<'source, Token<'source>>) -> Result<u8, ()> {
     match lex.slice()[1..].parse() {
@@ -226,24 +176,6 @@ fn parse_v_register<'source>(lex: &mut Lexer<'source, Token<'source>>) -> Result
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct HashableFloat(f64);
-
-impl Hash for HashableFloat {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        let bits = self.0.to_bits();
-        bits.hash(state);
-    }
-}
-
-impl PartialEq for HashableFloat {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.to_bits() == other.0.to_bits()
-    }
-}
-
-impl Eq for HashableFloat {}
-
 #[cfg(test)]
 mod tests {
     use super::Token::*;
@@ -1 +1,282 @@
-pub mod whitespace_removal;
+//! Passes applied to the tokens of a file before they are hashed.
+
+use std::ops::Range;
+
+use super::{
+    common::{is_register, HashableFloat},
+    naive::Token as NaiveToken,
+    relative::Token as RelativeToken,
+};
+
+pub mod statements;
+pub mod token_removal;
+
+/// Pass which transforms the tokens of a file before they are hashed, e.g. to make the analysis resistant to some
+/// superficial changes. Transforms are not supported by the "bytes" tokenizing strategy.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TokenTransform {
+    /// Remove whitespace and newlines.
+    StripWhitespace,
+    /// Remove comments.
+    StripComments,
+    /// Replace every register with the same register, so that code which only differs by its choice of registers
+    /// matches.
+    NormalizeRegisters,
+    /// Replace every numeric and character literal with the same value, so that code which only differs by its
+    /// constants matches.
+    AbstractLiterals,
+    /// Replace the offset of every relative symbol with that of its bucket (0, 1-2, 3-5, or 6 and more), so that small
+    /// insertions between two occurrences of a symbol do not change its token. Offsets beyond the maximum token offset
+    /// still count as first occurrences. This only affects the "relative" tokenizing strategy.
+    BucketOffsets,
+    /// Drop blank lines, empty statements, and the whitespace around statement separators, so that writing several
+    /// statements per line separated by `;` produces the same tokens as writing one statement per line. This transform
+    /// is applied before the other ones, and before the relative tokenizer identifies key symbols and computes offsets.
+    CanonicalStatements,
+}
+
+/// Applies the transforms, in order, to the tokens of the naive tokenizer.
+pub fn transform_naive<'source>(
+    mut tokens: Vec<(NaiveToken<'source>, Range<usize>)>,
+    transforms: &[TokenTransform],
+) -> Vec<(NaiveToken<'source>, Range<usize>)> {
+    for transform in transforms {
+        tokens = match transform {
+            TokenTransform::StripWhitespace => token_removal::remove_tokens_naive(tokens, |t| {
+                matches!(t, NaiveToken::Whitespace | NaiveToken::Newline)
+            }),
+            TokenTransform::StripComments => {
+                token_removal::remove_tokens_naive(tokens, |t| matches!(t, NaiveToken::Comment(_)))
+            }
+            TokenTransform::NormalizeRegisters => tokens
+                .into_iter()
+                .map(|(t, span)| match t {
+                    NaiveToken::Register(_) => (NaiveToken::Register(0), span),
+                    t => (t, span),
+                })
+                .collect(),
+            TokenTransform::AbstractLiterals => tokens
+                .into_iter()
+                .map(|(t, span)| match t {
+                    NaiveToken::Integer(_) => (NaiveToken::Integer(0), span),
+                    NaiveToken::FloatingPoint(_) => {
+                        (NaiveToken::FloatingPoint(HashableFloat(0.0)), span)
+                    }
+                    NaiveToken::Character(_) => (NaiveToken::Character(""), span),
+                    t => (t, span),
+                })
+                .collect(),
+            // The naive tokenizer has no offsets
+            TokenTransform::BucketOffsets => tokens,
+            // Already applied before the other transforms
+            TokenTransform::CanonicalStatements => tokens,
+        };
+    }
+    tokens
+}
+
+/// Applies the transforms, in order, to the tokens of the relative tokenizer. Offsets greater than `max_token_offset` are
+/// those of first occurrences. `source` is the string from which the
+/// tokens were lexed.
+pub fn transform_relative<'source>(
+    mut tokens: Vec<(RelativeToken<'source>, Range<usize>)>,
+    transforms: &[TokenTransform],
+    source: &str,
+    max_token_offset: usize,
+) -> Vec<(RelativeToken<'source>, Range<usize>)> {
+    for transform in transforms {
+        tokens = match transform {
+            TokenTransform::StripWhitespace => token_removal::remove_tokens_relative(tokens, |t| {
+                matches!(t, RelativeToken::Whitespace | RelativeToken::Newline)
+            }),
+            TokenTransform::StripComments => token_removal::remove_tokens_relative(tokens, |t| {
+                matches!(t, RelativeToken::Comment(_))
+            }),
+            TokenTransform::NormalizeRegisters => normalize_registers_relative(tokens, source),
+            TokenTransform::AbstractLiterals => tokens
+                .into_iter()
+                .map(|(t, span)| match t {
+                    RelativeToken::Integer(_) => (RelativeToken::Integer(0), span),
+                    RelativeToken::FloatingPoint(_) => {
+                        (RelativeToken::FloatingPoint(HashableFloat(0.0)), span)
+                    }
+                    RelativeToken::Character(_) => (RelativeToken::Character(""), span),
+                    t => (t, span),
+                })
+                .collect(),
+            TokenTransform::BucketOffsets => tokens
+                .into_iter()
+                .map(|(t, span)| match t {
+                    RelativeToken::RelativeSymbol(n) if n > max_token_offset => {
+                        (RelativeToken::RelativeSymbol(0), span)
+                    }
+                    RelativeToken::RelativeSymbol(n) => {
+                        (RelativeToken::RelativeSymbol(offset_bucket(n)), span)
+                    }
+                    t => (t, span),
+                })
+                .collect(),
+            // Already applied before the tokens were parsed
+            TokenTransform::CanonicalStatements => tokens,
+        };
+    }
+    tokens
+}
+
+/// Returns the smallest offset of the bucket containing the offset.
+fn offset_bucket(offset: usize) -> usize {
+    match offset {
+        0 => 0,
+        1..=2 => 1,
+        3..=5 => 3,
+        _ => 6,
+    }
+}
+
+/// Treats every register as an occurrence of the same symbol, so that the offset of a register is the distance to the
+/// previous register rather than to the previous occurrence of the same register.
+fn normalize_registers_relative<'source>(
+    tokens: Vec<(RelativeToken<'source>, Range<usize>)>,
+    source: &str,
+) -> Vec<(RelativeToken<'source>, Range<usize>)> {
+    let mut last_register = None;
+    tokens
+        .into_iter()
+        .enumerate()
+        .map(|(i, (t, span))| match t {
+            RelativeToken::RelativeSymbol(_) if is_register(&source[span.clone()]) => {
+                let offset = last_register.map_or(0, |j| i - j);
+                last_register = Some(i);
+                (RelativeToken::RelativeSymbol(offset), span)
+            }
+            t => (t, span),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexing::{naive, relative, TokenizingStrategy};
+
+    #[test]
+    fn registers_are_normalized() {
+        let source = "add r0, r1, x\nadd r2, r2, x";
+        let tokens = transform_relative(
+            relative::lex(source, None, false),
+            &[
+                TokenTransform::StripWhitespace,
+                TokenTransform::NormalizeRegisters,
+            ],
+            source,
+            usize::MAX,
+        );
+        assert_eq!(
+            tokens.into_iter().map(|(t, _)| t).collect::<Vec<_>>(),
+            vec![
+                RelativeToken::KeySymbol("add".to_owned()),
+                RelativeToken::RelativeSymbol(0),
+                RelativeToken::Comma,
+                RelativeToken::RelativeSymbol(2),
+                RelativeToken::Comma,
+                RelativeToken::RelativeSymbol(0),
+                RelativeToken::KeySymbol("add".to_owned()),
+                RelativeToken::RelativeSymbol(4),
+                RelativeToken::Comma,
+                RelativeToken::RelativeSymbol(2),
+                RelativeToken::Comma,
+                RelativeToken::RelativeSymbol(6),
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets_are_bucketed() {
+        let transform = |source| {
+            transform_relative(
+                relative::lex(source, None, false),
+                &[
+                    TokenTransform::StripWhitespace,
+                    TokenTransform::BucketOffsets,
+                ],
+                source,
+                8,
+            )
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>()
+        };
+        let offset_of_last_x = |source| transform(source).into_iter().rev().nth(4).unwrap();
+
+        // An instruction inserted between two uses of `x` changes its offset from 6 to 7, but not its bucket
+        assert_eq!(
+            offset_of_last_x("ldr x, [y]\nstr x, [z]"),
+            RelativeToken::RelativeSymbol(6)
+        );
+        assert_eq!(
+            offset_of_last_x("ldr x, [y]\nnop\nstr x, [z]"),
+            RelativeToken::RelativeSymbol(6)
+        );
+        // Beyond the maximum offset, `x` is a first occurrence
+        assert_eq!(
+            offset_of_last_x("ldr x, [y]\nnop\nnop\nnop\nstr x, [z]"),
+            RelativeToken::RelativeSymbol(0)
+        );
+        assert_eq!(
+            offset_of_last_x("mov y, x\nstr x, [z]"),
+            RelativeToken::RelativeSymbol(1)
+        );
+    }
+
+    #[test]
+    fn statement_boundaries_are_canonical() {
+        let hashes = |strategy, source| {
+            crate::lexing::tokenize_and_hash(
+                source,
+                strategy,
+                &[TokenTransform::CanonicalStatements],
+                None,
+                crate::lexing::HashDialect::Immediate,
+                usize::MAX,
+            )
+            .0
+            .into_iter()
+            .map(|(hash, _)| hash)
+            .collect::<Vec<_>>()
+        };
+        for strategy in [TokenizingStrategy::Naive, TokenizingStrategy::Relative] {
+            assert_eq!(
+                hashes(strategy, "mov r0, #1 ; add r1, r0, r0\n\n"),
+                hashes(strategy, "\n\tmov r0, #1\n\n\tadd r1, r0, r0")
+            );
+            assert_ne!(
+                hashes(strategy, "mov r0, #1; add r1, r0, r0"),
+                hashes(strategy, "mov r0, #1 add r1, r0, r0")
+            );
+        }
+    }
+
+    #[test]
+    fn literals_are_abstracted() {
+        let transform = |source| {
+            transform_naive(
+                naive::lex(source),
+                &[
+                    TokenTransform::StripComments,
+                    TokenTransform::StripWhitespace,
+                    TokenTransform::AbstractLiterals,
+                    TokenTransform::NormalizeRegisters,
+                ],
+            )
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            transform("mov r0, #5"),
+            transform("mov r1, #0x10 @ sixteen")
+        );
+        assert_ne!(transform("mov r0, #5"), transform("add r0, #5"));
+    }
+}
@@ -0,0 +1,33 @@
+use std::ops::Range;
+
+/// Makes the statement boundaries of a token stream canonical: blank lines and empty statements are dropped, along with
+/// the whitespace at the start and end of every statement, so that consecutive statements are separated by exactly one
+/// newline token (the first separator found between them, either a newline or a `;`). Separators at the start and end
+/// of the stream are dropped.
+pub fn canonicalize_statements<T>(
+    tokens: impl IntoIterator<Item = (T, Range<usize>)>,
+    is_newline: impl Fn(&T) -> bool,
+    is_whitespace: impl Fn(&T) -> bool,
+) -> Vec<(T, Range<usize>)> {
+    let mut canonical = Vec::new();
+    // Separator and whitespace which are only kept if another token follows them in the statement
+    let mut separator = None;
+    let mut whitespace = None;
+    for (t, span) in tokens {
+        if is_newline(&t) {
+            if !canonical.is_empty() && separator.is_none() {
+                separator = Some((t, span));
+            }
+            whitespace = None;
+        } else if is_whitespace(&t) {
+            if !canonical.is_empty() && separator.is_none() {
+                whitespace = Some((t, span));
+            }
+        } else {
+            canonical.extend(separator.take());
+            canonical.extend(whitespace.take());
+            canonical.push((t, span));
+        }
+    }
+    canonical
+}
@@ -3,12 +3,13 @@ use std::ops::Range;
 use crate::lexing::naive::Token as NaiveToken;
 use crate::lexing::relative::Token as RelativeToken;
 
-/// Removes whitespace, comments, and newline tokens from the given token stream, updating the offsets of RelativeSymbol
-/// tokens as necessary.
-pub fn remove_whitespace_relative(
+/// Removes the tokens for which `should_remove` returns true from the given token stream, updating the offsets of
+/// RelativeSymbol tokens as necessary.
+pub fn remove_tokens_relative(
     tokens: Vec<(RelativeToken, Range<usize>)>,
+    should_remove: impl Fn(&RelativeToken) -> bool,
 ) -> Vec<(RelativeToken, Range<usize>)> {
-    // For each index in tokens, we store whether or not a whitespace token was removed.
+    // For each index in tokens, we store whether or not a token was removed.
     let mut removed = Vec::new();
 
     fn tokens_removed_in_last_n_tokens(removed: &[bool], n: usize) -> usize {
@@ -18,8 +19,7 @@ pub fn remove_whitespace_relative(
     tokens
         .into_iter()
         .filter_map(|(token, range)| match token {
-            // Remove whitespace, comments, and newline tokens
-            RelativeToken::Whitespace | RelativeToken::Newline | RelativeToken::Comment(_) => {
+            token if should_remove(&token) => {
                 removed.push(true);
                 None
             }
@@ -45,18 +45,14 @@ pub fn remove_whitespace_relative(
         .collect()
 }
 
-/// Removes whitespace, comments, and newline tokens from the given token stream.
-pub fn remove_whitespace_naive(
+/// Removes the tokens for which `should_remove` returns true from the given token stream.
+pub fn remove_tokens_naive(
     tokens: Vec<(NaiveToken, Range<usize>)>,
+    should_remove: impl Fn(&NaiveToken) -> bool,
 ) -> Vec<(NaiveToken, Range<usize>)> {
     tokens
         .into_iter()
-        .filter(|(token, _)| {
-            !matches!(
-                token,
-                NaiveToken::Whitespace | NaiveToken::Newline | NaiveToken::Comment(_)
-            )
-        })
+        .filter(|(token, _)| !should_remove(token))
         .collect()
 }
 
@@ -66,7 +62,7 @@ mod tests {
     use crate::lexing::relative::Token as RelativeToken;
 
     #[test]
-    fn remove_whitespace_relative_works() {
+    fn remove_tokens_relative_works() {
         let original_tokens = vec![
             (RelativeToken::RelativeSymbol(0), 0..2),
             (RelativeToken::Comma, 2..3),
@@ -86,12 +82,17 @@ mod tests {
             (RelativeToken::RelativeSymbol(0), 10..12),
             (RelativeToken::RelativeSymbol(2), 12..14),
         ];
-        let actual_tokens = remove_whitespace_relative(original_tokens);
+        let actual_tokens = remove_tokens_relative(original_tokens, |t| {
+            matches!(
+                t,
+                RelativeToken::Whitespace | RelativeToken::Newline | RelativeToken::Comment(_)
+            )
+        });
         assert_eq!(actual_tokens, expected_tokens);
     }
 
     #[test]
-    fn remove_whitespace_naive_works() {
+    fn remove_tokens_naive_works() {
         let original_tokens = vec![
             (NaiveToken::Symbol("test".to_owned()), 0..4),
             (NaiveToken::Whitespace, 4..5),
@@ -103,7 +104,12 @@ mod tests {
             (NaiveToken::Symbol("test".to_owned()), 0..4),
             (NaiveToken::Symbol("test".to_owned()), 7..11),
         ];
-        let actual_tokens = remove_whitespace_naive(original_tokens);
+        let actual_tokens = remove_tokens_naive(original_tokens, |t| {
+            matches!(
+                t,
+                NaiveToken::Whitespace | NaiveToken::Newline | NaiveToken::Comment(_)
+            )
+        });
         assert_eq!(actual_tokens, expected_tokens);
     }
 }
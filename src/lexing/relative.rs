@@ -1,11 +1,16 @@
 mod parser;
 
-use std::{
-    hash::{Hash, Hasher},
-    ops::Range,
-};
+use std::ops::Range;
+
+use logos::Logos;
+
+use super::{preprocessing::statements, KeySymbols};
 
-use logos::{Lexer, Logos};
+use super::common::{
+    parse_binary_integer, parse_cstyle_line_comment, parse_decimal_integer, parse_floating_point,
+    parse_hexadecimal_integer, parse_multiline_comment, parse_octal_integer, parse_quoted_symbol,
+    parse_single_char_line_comment, parse_unquoted_symbol, HashableFloat,
+};
 
 // Implemented using information from the [GNU assembler documentation](https://sourceware.org/binutils/docs/as/)
 // and the [ARM developer documentation](https://developer.arm.com/documentation/).
@@ -127,90 +132,38 @@ pub enum Token<'source> {
     Hash,
 }
 
+/// Lexes the string. If a dictionary of key symbols is given, only those symbols are treated as key symbols. If
+/// `canonical_statements` is set, the statement boundaries are made canonical before the statements are parsed.
 #[must_use]
-pub fn lex(s: &str) -> Vec<(Token, Range<usize>)> {
+pub fn lex<'source>(
+    s: &'source str,
+    key_symbols: Option<&KeySymbols>,
+    canonical_statements: bool,
+) -> Vec<(Token<'source>, Range<usize>)> {
     let lexer = Token::lexer(s).spanned();
 
     // Perform a simple parsing pass, replacing `Symbol`s with `KeySymbol`s and `RelativeSymbol`s
-    parser::parse(lexer)
-}
-
-#[inline]
-fn parse_multiline_comment<'source>(lex: &mut Lexer<'source, Token<'source>>) -> &'source str {
-    &lex.slice()[2..lex.slice().len() - 2]
-}
-
-#[inline]
-fn parse_cstyle_line_comment<'source>(lex: &mut Lexer<'source, Token<'source>>) -> &'source str {
-    &lex.slice()[2..]
-}
-
-#[inline]
-fn parse_single_char_line_comment<'source>(
-    lex: &mut Lexer<'source, Token<'source>>,
-) -> &'source str {
-    &lex.slice()[1..]
-}
-
-#[inline]
-fn parse_unquoted_symbol<'source>(lex: &mut Lexer<'source, Token<'source>>) -> String {
-    lex.slice().to_ascii_lowercase()
-}
-
-#[inline]
-fn parse_quoted_symbol<'source>(lex: &mut Lexer<'source, Token<'source>>) -> String {
-    let s = lex.slice();
-    s[1..s.len() - 1].to_ascii_lowercase()
-}
-
-#[inline]
-fn parse_binary_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[2..], 2).unwrap()
-}
-
-#[inline]
-fn parse_octal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[1..], 8).unwrap()
-}
-
-#[inline]
-fn parse_decimal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    lex.slice().parse().unwrap()
-}
-
-#[inline]
-fn parse_hexadecimal_integer<'source>(lex: &mut Lexer<'source, Token<'source>>) -> i64 {
-    i64::from_str_radix(&lex.slice()[2..], 16).unwrap()
-}
-
-#[inline]
-fn parse_floating_point<'source>(lex: &mut Lexer<'source, Token<'source>>) -> HashableFloat {
-    HashableFloat(lex.slice()[2..].parse().unwrap())
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct HashableFloat(f64);
-
-impl Hash for HashableFloat {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        let bits = self.0.to_bits();
-        bits.hash(state);
-    }
-}
-
-impl PartialEq for HashableFloat {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.to_bits() == other.0.to_bits()
+    if canonical_statements {
+        let tokens = statements::canonicalize_statements(
+            lexer,
+            |t| matches!(t, Token::Newline),
+            |t| matches!(t, Token::Whitespace),
+        );
+        parser::parse(tokens.into_iter(), key_symbols)
+    } else {
+        parser::parse(lexer, key_symbols)
     }
 }
 
-impl Eq for HashableFloat {}
-
 #[cfg(test)]
 mod tests {
     use super::Token::*;
     use super::*;
 
+    fn lex(s: &str) -> Vec<(Token<'_>, Range<usize>)> {
+        super::lex(s, None, false)
+    }
+
     #[test]
     fn test_registers() {
         let tokens = lex("add sP");
@@ -322,6 +275,23 @@ mod tests {
         )
     }
 
+    #[test]
+    fn key_symbol_dictionary() {
+        let source = "mov r0,\nr1";
+        assert_eq!(lex(source)[5], (KeySymbol("r1".to_owned()), 8..10));
+        assert_eq!(
+            super::lex(source, Some(&KeySymbols::Armv7), false),
+            vec![
+                (KeySymbol("mov".to_owned()), 0..3),
+                (Whitespace, 3..4),
+                (RelativeSymbol(0), 4..6),
+                (Comma, 6..7),
+                (Newline, 7..8),
+                (RelativeSymbol(0), 8..10),
+            ]
+        );
+    }
+
     #[test]
     fn test_windows_carriage_return_handling() {
         assert_eq!(
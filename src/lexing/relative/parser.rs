@@ -1,33 +1,37 @@
 use std::{collections::HashMap, ops::Range};
 
 use itertools::{peek_nth, PeekNth};
-use logos::SpannedIter;
 
 use super::Token::{self, *};
+use crate::lexing::KeySymbols;
 
 pub fn parse<'source>(
-    lexer: SpannedIter<'source, Token<'source>>,
+    lexer: impl Iterator<Item = (Token<'source>, Range<usize>)>,
+    key_symbols: Option<&KeySymbols>,
 ) -> Vec<(Token<'source>, Range<usize>)> {
-    Parser::new(lexer).parse()
+    Parser::new(lexer, key_symbols).parse()
 }
 
-struct Parser<'source> {
-    lexer: PeekNth<SpannedIter<'source, Token<'source>>>,
+struct Parser<'source, 'k, I: Iterator<Item = (Token<'source>, Range<usize>)>> {
+    lexer: PeekNth<I>,
     result: Vec<(Token<'source>, Range<usize>)>,
     /// The number of tokens consumed so far
     token_count: usize,
     /// Maps symbol names to the last token index at which they were encountered
     symbol_occurrences: HashMap<String, usize>,
+    /// The symbols which can be key symbols. If `None`, the first non-label symbol of every statement is a key symbol.
+    key_symbols: Option<&'k KeySymbols>,
 }
 
-impl<'source> Parser<'source> {
+impl<'source, 'k, I: Iterator<Item = (Token<'source>, Range<usize>)>> Parser<'source, 'k, I> {
     #[inline]
-    fn new(lexer: SpannedIter<'source, Token<'source>>) -> Self {
+    fn new(lexer: I, key_symbols: Option<&'k KeySymbols>) -> Self {
         Self {
             lexer: peek_nth(lexer),
             result: Vec::new(),
             token_count: 0,
             symbol_occurrences: HashMap::new(),
+            key_symbols,
         }
     }
 
@@ -85,10 +89,16 @@ impl<'source> Parser<'source> {
                     if let Some((Colon, _)) = self.peek() {
                         let relative_symbol = self.relative_symbol(s);
                         self.result.push((relative_symbol, span));
-                    } else {
+                    } else if self.key_symbols.is_none_or(|k| k.contains(&s)) {
                         // This is a key symbol, stop looking for a key symbol
                         self.result.push((KeySymbol(s), span));
                         break;
+                    } else {
+                        // This is not an instruction or a directive (e.g., it is an operand on a continuation line),
+                        // so the statement does not have a key symbol
+                        let relative_symbol = self.relative_symbol(s);
+                        self.result.push((relative_symbol, span));
+                        break;
                     }
                 }
                 // All other tokens, even syntactically invalid ones are ignored and returned without modifications
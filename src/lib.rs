@@ -1,21 +1,90 @@
-use std::cmp::max;
-use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io;
+use std::num::NonZeroUsize;
 use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
+use checkpoint::{Checkpoint, DocumentHashes, StarterCodeTokens};
+use diagnostics::{CompletedPair, Diagnostics, FileDensity, FingerprintDensity, HashFrequencies};
 use fingerprint::Fingerprint;
-use identity_hash::IdentityHashMap;
+use identity_hash::{IdentityHashKey, IdentityHashMap};
+use interning::{PathArena, PathId};
 use itertools::{iproduct, Itertools};
-use lexing::TokenizingStrategy;
-use output::{Location, Match, ProjectPair, Warning, WarningType};
+use lexing::HashedTokens;
+use memory::MemoryBudget;
+use output::Filter;
+use pair_cache::PairCache;
+use pipeline::map_in_parallel;
+use routines::{Granularity, RoutineFiles};
+use stitching::StitchedProjects;
 
+pub mod allowlist;
+pub mod annotations;
+pub mod attempts;
+pub mod bloom;
+pub mod checkpoint;
+pub mod composite;
+pub mod diagnostics;
+pub mod distribution;
+pub mod encoding;
+pub mod explanation;
+pub mod external_scores;
 pub mod fingerprint;
+pub mod formatting;
+pub mod heuristics;
 pub mod identity_hash;
+pub mod index;
+#[cfg(feature = "cli")]
+pub mod integrity;
+pub mod interning;
 pub mod lexing;
+pub mod lsh;
+pub mod manifest;
 pub mod match_expansion;
+pub mod matrix;
+pub mod memory;
+pub mod observer;
+pub mod online_solutions;
 pub mod output;
+pub mod pair_cache;
+pub mod pair_overrides;
+pub mod pipeline;
+pub mod project_metadata;
+#[cfg(feature = "cli")]
+pub mod report;
+#[cfg(feature = "cli")]
+pub mod reproducibility;
+pub mod routines;
+pub mod scoring;
+pub mod self_test;
+pub mod snapshots;
+pub mod starter_diff;
+pub mod stitching;
+pub mod stop_list;
+pub mod stream;
+pub mod string_literals;
+pub mod stylometry;
+pub mod submissions;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+pub use fingerprint::{FingerprintAlgorithm, FingerprintError, TieBreaking, Winnowing};
+pub use identity_hash::HashWidth;
+pub use lexing::{HashDialect, KeySymbols, TokenTransform, TokenizingStrategy};
+pub use observer::{DetectionObserver, Progress, Stage};
+pub use output::{
+    Location, Match, MatchOrder, Metadata, Output, PairExplanation, PairOrder, PathStyle,
+    ProjectPair, ProjectStats, StringLiteralPair, StylometryPair, Summary, Warning, WarningCode,
+    WarningType,
+};
+pub use pair_overrides::PairOverride;
+pub use project_metadata::CompareMode;
+pub use scoring::ScoringMode;
+pub use stream::{detect_plagiarism_iter, PairStream};
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct File {
     project: PathBuf,
     path: PathBuf,
@@ -30,276 +99,1341 @@ impl File {
             contents,
         }
     }
+
+    /// Returns the name of the project containing the file.
+    pub fn project(&self) -> &PathBuf {
+        &self.project
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Returns the contents of the file.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
 }
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+/// Identifies a document by the interned paths of its project and its file (see [`PathArena`]).
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FileId {
-    pub project: PathBuf,
-    pub path: PathBuf,
+    pub project: PathId,
+    pub path: PathId,
 }
 
 impl FileId {
-    pub fn new(project: PathBuf, path: PathBuf) -> FileId {
+    pub fn new(project: PathId, path: PathId) -> FileId {
         FileId { project, path }
     }
 }
 
+/// Code snippet of a document, given by the interned path of its file and its span in bytes.
+type DocumentLocation = (PathId, Range<usize>);
+
+/// Match found between the documents of two projects, which refers to its files by their interned paths until the
+/// project pairs are built.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct DocumentMatch {
+    location_1: DocumentLocation,
+    location_2: DocumentLocation,
+    weight: Option<f64>,
+}
+
+impl DocumentMatch {
+    fn into_match(self, arena: &PathArena) -> Match {
+        let location = |(file, span): DocumentLocation| Location {
+            file: arena.path(file).clone(),
+            span,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        Match {
+            id: None,
+            project_1_location: location(self.location_1),
+            project_2_location: location(self.location_2),
+            weight: self.weight,
+        }
+    }
+}
+
+/// How the files of the projects were turned into the documents which are fingerprinted.
+enum DocumentLayout {
+    Files,
+    /// The files of each project were concatenated.
+    Stitched(StitchedProjects),
+    /// Each file was split into its routines.
+    Routines(RoutineFiles),
+}
+
+/// Interned paths of the two projects of a pair.
+type ProjectIds = (PathId, PathId);
+
+/// Locations of each fingerprinted hash, with the hashes as keys of type `K`.
+type HashDatabase<'a, K> = IdentityHashMap<Vec<(&'a FileId, Range<usize>)>, K>;
+
+/// Parameters of the analysis.
+#[derive(Clone, Debug)]
+pub struct DetectionOptions {
+    /// Matches of length less than this value are guaranteed to be ignored.
+    pub noise_threshold: usize,
+    /// Matches of length at least this value are guaranteed to be included.
+    pub guarantee_threshold: usize,
+    /// Maximum offset for relative tokens. Must be zero for non-relative tokenizing strategies.
+    pub max_token_offset: usize,
+    /// Algorithm which selects the hashes of each document that are compared. Starter code and stop-list entries are
+    /// always fingerprinted with every hash, regardless of this algorithm.
+    pub fingerprint_algorithm: Arc<dyn FingerprintAlgorithm>,
+    pub tokenizing_strategy: TokenizingStrategy,
+    /// Whether to ignore comments, whitespace, and newlines while tokenizing.
+    pub ignore_whitespace: bool,
+    /// Transforms applied to the tokens, in order, after whitespace and comments are removed (if `ignore_whitespace`
+    /// is set). Transforms are not supported by the "bytes" tokenizing strategy.
+    pub transforms: Vec<TokenTransform>,
+    /// Instructions and directives recognized by the "relative" tokenizing strategy. If `None`, the first non-label
+    /// symbol of every statement is assumed to be an instruction or a directive.
+    pub key_symbols: Option<KeySymbols>,
+    /// Which `#` characters start line comments rather than prefixing immediates. Ignored by the "bytes" tokenizing
+    /// strategy.
+    pub hash_dialect: HashDialect,
+    /// Whether to exclude the files containing characters which could not be tokenized from the analysis. Either way,
+    /// a warning is issued for each such file.
+    pub strict_lex: bool,
+    /// Whether to concatenate the files of each project (in the order of their paths) into a single stream of tokens
+    /// before fingerprinting, so that code split across several files still matches. The matches are mapped back to
+    /// the original files.
+    pub stitch_files: bool,
+    /// Whether files are matched as a whole or routine by routine. Ignored if `stitch_files` is set.
+    pub granularity: Granularity,
+    /// Whether to expand matches as much as possible before reporting them.
+    pub expand_matches: bool,
+    /// Matches with fewer than this number of tokens on either side once expanded are discarded before the pairs are
+    /// scored. Zero disables this filter.
+    pub min_report_length: usize,
+    /// Pairs of projects with fewer than this number of matches are not reported.
+    pub min_matches: usize,
+    /// Thresholds overriding `min_matches` (and adding a minimum coverage) for the pairs of specific projects.
+    pub pair_overrides: Vec<PairOverride>,
+    /// Code which is found in at least this proportion of the projects is ignored. Zero disables this filter.
+    pub common_hash_threshold: f64,
+    /// Code which is found in at least this number of projects is ignored. Zero disables this filter. If both this and
+    /// `common_hash_threshold` are set, code is only ignored if it exceeds both thresholds.
+    pub common_hash_min_projects: usize,
+    /// Whether to choose the common code threshold automatically from the distribution of the number of projects in
+    /// which each hash occurs. This replaces `common_hash_threshold`.
+    pub auto_common_hash_threshold: bool,
+    /// Code snippets which are never reported as matches, regardless of how many projects contain them.
+    pub stop_list: Vec<String>,
+    /// If given, also removes the regions of the projects (of four times the noise threshold) in which at least this
+    /// proportion of the tokens match starter code, so that starter code which was lightly edited is removed as well.
+    /// Must be in the range (0, 1].
+    pub starter_code_similarity: Option<f64>,
+    /// Projects with fewer than this number of tokens (not counting starter code) are excluded from the analysis.
+    pub min_project_tokens: usize,
+    /// Which pairs of projects are compared, according to `project_sections`.
+    pub compare: CompareMode,
+    /// Section or cohort of each project. Projects without a section are only compared when `compare` is "all".
+    pub project_sections: BTreeMap<PathBuf, String>,
+    /// If given, only these pairs of projects are compared (in either order). All projects still count towards the
+    /// common code thresholds and the IDF scores.
+    pub only_pairs: Option<BTreeSet<(PathBuf, PathBuf)>>,
+    /// Number of bands of the MinHash signatures used to select the pairs of projects to compare. Only the pairs of
+    /// projects which share a band are compared. Zero disables this pre-screening, so that every pair is compared.
+    pub lsh_bands: usize,
+    /// Number of values in each band of the MinHash signatures. More rows make the pre-screening stricter.
+    pub lsh_rows: usize,
+    /// Pairs of projects which are estimated to share fewer than this number of hashes (using Bloom filters) are
+    /// skipped before their matches are computed. Zero disables this filter.
+    pub bloom_min_shared_hashes: usize,
+    /// How the project pairs are scored and ranked.
+    pub scoring: ScoringMode,
+    /// Maximum number of matches between two projects for a single hash. If a hash occurs so many times in both
+    /// projects that pairing every occurrence would exceed this value, each occurrence is only paired with the
+    /// occurrence at the nearest position in the other project. Zero means no limit.
+    pub max_matches_per_hash: usize,
+    /// Number of bits of the hashes used to find matches. Truncating them to 32 bits halves the memory used by the keys
+    /// of the hash database, but unrelated code snippets are more likely to share a hash.
+    pub hash_width: HashWidth,
+    /// Estimated number of bytes which the hash database and the matches may take. If the analysis would exceed it, it
+    /// stops early with a warning, and returns the project pairs found so far. `None` means no limit.
+    pub max_memory: Option<usize>,
+    /// Number of threads of the CPU-bound stages (lexing and hashing the files, and expanding the matches of the project
+    /// pairs). `None` means one thread per core.
+    pub cpu_threads: Option<NonZeroUsize>,
+    /// Documents which were already lexed with these options (e.g., while the other documents were still being read).
+    /// The analysis takes their tokens instead of lexing them again.
+    pub lexed_documents: Option<Arc<LexedDocuments>>,
+    /// Directory in which to save the state of the analysis as it progresses. If it contains the state of a previous
+    /// run on the same inputs, that run is resumed.
+    pub checkpoint_dir: Option<PathBuf>,
+    /// Directory in which to cache the completed project pairs. Pairs whose projects and matches are unchanged since an
+    /// earlier run with the same options are reused instead of being completed again.
+    pub cache_dir: Option<PathBuf>,
+    /// Flag which stops the analysis early once it is set (e.g., by a signal handler). The project pairs which were
+    /// completed before that point are still returned.
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+impl Default for DetectionOptions {
+    /// Returns the same defaults as the command-line interface.
+    fn default() -> Self {
+        DetectionOptions {
+            noise_threshold: 40,
+            guarantee_threshold: 80,
+            max_token_offset: 39,
+            fingerprint_algorithm: Arc::new(Winnowing::default()),
+            tokenizing_strategy: TokenizingStrategy::Auto,
+            ignore_whitespace: true,
+            transforms: Vec::new(),
+            key_symbols: None,
+            hash_dialect: HashDialect::Immediate,
+            strict_lex: false,
+            stitch_files: false,
+            granularity: Granularity::File,
+            expand_matches: true,
+            min_report_length: 0,
+            min_matches: 0,
+            pair_overrides: Vec::new(),
+            common_hash_threshold: 0.0,
+            common_hash_min_projects: 0,
+            auto_common_hash_threshold: false,
+            stop_list: Vec::new(),
+            starter_code_similarity: None,
+            min_project_tokens: 0,
+            compare: CompareMode::All,
+            project_sections: BTreeMap::new(),
+            only_pairs: None,
+            lsh_bands: 0,
+            lsh_rows: 2,
+            bloom_min_shared_hashes: 0,
+            scoring: ScoringMode::Matches,
+            max_matches_per_hash: 10_000,
+            hash_width: HashWidth::Bits64,
+            max_memory: None,
+            cpu_threads: None,
+            lexed_documents: None,
+            checkpoint_dir: None,
+            cache_dir: None,
+            interrupt: None,
+        }
+    }
+}
+
+impl DetectionOptions {
+    /// Returns the full chain of transforms applied to the tokens, including the removal of whitespace and comments.
+    fn token_transforms(&self) -> Vec<TokenTransform> {
+        let mut transforms = Vec::new();
+        if self.ignore_whitespace {
+            transforms.extend([
+                TokenTransform::StripWhitespace,
+                TokenTransform::StripComments,
+            ]);
+        }
+        transforms.extend(&self.transforms);
+        transforms
+    }
+
+    /// Returns whether a pair of projects is compared, according to their sections and the restriction to some pairs.
+    pub(crate) fn compares(&self, project1: &PathBuf, project2: &PathBuf) -> bool {
+        let listed = self.only_pairs.as_ref().is_none_or(|pairs| {
+            pairs.contains(&(project1.clone(), project2.clone()))
+                || pairs.contains(&(project2.clone(), project1.clone()))
+        });
+        listed
+            && project_metadata::is_compared(
+                self.compare,
+                &self.project_sections,
+                project1,
+                project2,
+            )
+    }
+
+    /// Returns the number of threads of the CPU-bound stages.
+    pub fn num_cpu_threads(&self) -> usize {
+        self.cpu_threads
+            .or_else(|| thread::available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupt
+            .as_ref()
+            .is_some_and(|interrupt| interrupt.load(Ordering::Relaxed))
+    }
+}
+
 /// Detects matches between files in different projects and constructs a summary of the results.
 ///
-/// Matches of length less than `noise_threshold` are guaranteed to be ignored.
-/// Matches of length at least `guarantee_threshold` are guaranteed to be included.
-#[allow(clippy::too_many_arguments)]
+/// Matches of length less than `options.noise_threshold` are guaranteed to be ignored.
+/// Matches of length at least `options.guarantee_threshold` are guaranteed to be included.
 pub fn detect_plagiarism(
-    noise_threshold: usize,
-    guarantee_threshold: usize,
-    max_token_offset: usize,
-    tokenizing_strategy: TokenizingStrategy,
-    ignore_whitespace: bool,
-    expand_matches: bool,
-    min_matches: usize,
-    common_hash_threshold: f64,
+    options: &DetectionOptions,
     documents: &[File],
     ignored_documents: &[File],
 ) -> (Vec<ProjectPair>, Vec<Warning>) {
-    let mut warnings = Vec::new();
+    let (project_pairs, warnings, _diagnostics) =
+        detect_plagiarism_with_diagnostics(options, documents, ignored_documents);
+    (project_pairs, warnings)
+}
 
-    let mut document_hashes = documents
-        .iter()
-        .map(|f| {
-            (
-                FileId::new(f.project.clone(), f.path.clone()),
-                lexing::tokenize_and_hash(
-                    &f.contents,
-                    tokenizing_strategy,
-                    ignore_whitespace,
-                    max_token_offset,
-                ),
-            )
-        })
-        .collect::<HashMap<_, _>>();
+/// Same as `detect_plagiarism`, but also returns diagnostics about the analysis (e.g., to help choose its parameters).
+pub fn detect_plagiarism_with_diagnostics(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+) -> (Vec<ProjectPair>, Vec<Warning>, Diagnostics) {
+    detect_plagiarism_with_observer(options, documents, ignored_documents, &mut |_: Progress| {})
+}
 
-    let ignored_document_hashes = ignored_documents
-        .iter()
-        .map(|f| {
-            (
-                FileId::new(f.project.clone(), f.path.clone()),
-                lexing::tokenize_and_hash(
-                    &f.contents,
-                    tokenizing_strategy,
-                    ignore_whitespace,
-                    max_token_offset,
-                ),
-            )
-        })
-        .collect::<HashMap<_, _>>();
+/// Same as `detect_plagiarism_with_diagnostics`, but notifies `observer` as the analysis progresses.
+pub fn detect_plagiarism_with_observer(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    observer: &mut dyn DetectionObserver,
+) -> (Vec<ProjectPair>, Vec<Warning>, Diagnostics) {
+    let mut project_pairs = Vec::new();
+    let (warnings, diagnostics) = detect_plagiarism_streaming(
+        options,
+        documents,
+        ignored_documents,
+        &mut |p| project_pairs.push(p),
+        observer,
+    );
+    sort_output(&mut project_pairs);
+    (project_pairs, warnings, diagnostics)
+}
 
-    // Remove the contents of the ignored documents from the input documents
-    let ignored_docs_warnings = remove_ignored_documents(
-        &mut document_hashes,
-        &ignored_document_hashes,
+/// Runs the analysis, passing each project pair to `on_pair` as soon as it is complete, in no particular order, and
+/// notifying `observer` as it progresses.
+pub(crate) fn detect_plagiarism_streaming(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    on_pair: &mut dyn FnMut(ProjectPair),
+    observer: &mut dyn DetectionObserver,
+) -> (Vec<Warning>, Diagnostics) {
+    let DetectionOptions {
         noise_threshold,
+        guarantee_threshold,
         max_token_offset,
-    );
+        fingerprint_algorithm: _,
+        tokenizing_strategy: _,
+        ignore_whitespace: _,
+        transforms: _,
+        key_symbols: _,
+        hash_dialect: _,
+        strict_lex: _,
+        stitch_files: _,
+        granularity: _,
+        expand_matches: _,
+        min_report_length: _,
+        min_matches,
+        pair_overrides: _,
+        common_hash_threshold: _,
+        common_hash_min_projects: _,
+        auto_common_hash_threshold: _,
+        stop_list: _,
+        starter_code_similarity: _,
+        min_project_tokens,
+        compare: _,
+        project_sections: _,
+        only_pairs: _,
+        lsh_bands: _,
+        lsh_rows: _,
+        bloom_min_shared_hashes: _,
+        scoring: _,
+        max_matches_per_hash: _,
+        hash_width: _,
+        max_memory: _,
+        cpu_threads: _,
+        lexed_documents: _,
+        checkpoint_dir: _,
+        cache_dir: _,
+        interrupt: _,
+    } = *options;
+
+    let mut warnings = Vec::new();
+
+    let mut checkpoint = match &options.checkpoint_dir {
+        None => None,
+        Some(dir) => match Checkpoint::open(dir, options, documents, ignored_documents) {
+            Err(e) => {
+                warnings.push(checkpoint::checkpoint_warning(dir, e));
+                None
+            }
+            Ok(c) => Some(c),
+        },
+    };
+
+    // The paths are interned once, and the documents are referred to by their identifiers until the pairs are built
+    let mut arena = PathArena::new(documents.iter().chain(ignored_documents));
+    let saved_document_hashes = checkpoint
+        .as_ref()
+        .and_then(|c| c.load_document_hashes(&arena));
+    let (mut document_hashes, starter_code_tokens) = match saved_document_hashes {
+        Some((document_hashes, starter_code_tokens, hashing_warnings)) => {
+            warnings.extend(hashing_warnings);
+            (document_hashes, starter_code_tokens)
+        }
+        None => {
+            let (document_hashes, starter_code_tokens, hashing_warnings) =
+                hash_documents(options, documents, ignored_documents, &arena, observer);
+            update_checkpoint(&mut checkpoint, &mut warnings, |c| {
+                c.save_document_hashes(
+                    &arena,
+                    &document_hashes,
+                    &starter_code_tokens,
+                    &hashing_warnings,
+                )
+            });
+            warnings.extend(hashing_warnings);
+            (document_hashes, starter_code_tokens)
+        }
+    };
+
+    let project_stats = project_stats(documents, &arena, &document_hashes, &starter_code_tokens);
+
+    // Exclude tiny projects (e.g., unmodified templates) before they can be paired with anything
+    if min_project_tokens > 0 {
+        let small_project_warnings =
+            remove_small_projects(&mut document_hashes, &arena, min_project_tokens);
+        warnings.extend(small_project_warnings);
+    }
+
+    // From now on, the documents are the streams of the projects or the routines of the files if requested
+    let layout = if options.stitch_files {
+        let (streams, layout) =
+            stitching::stitch(std::mem::take(&mut document_hashes), documents, &arena);
+        document_hashes = streams;
+        DocumentLayout::Stitched(layout)
+    } else if options.granularity == Granularity::Function {
+        let (routines, layout) = routines::split_into_routines(
+            std::mem::take(&mut document_hashes),
+            documents,
+            &mut arena,
+        );
+        document_hashes = routines;
+        DocumentLayout::Routines(layout)
+    } else {
+        DocumentLayout::Files
+    };
 
-    warnings.extend(ignored_docs_warnings);
+    let arena = arena;
+    let projects = document_hashes
+        .keys()
+        .map(|file_id| file_id.project)
+        .sorted()
+        .dedup()
+        .collect::<Vec<_>>();
 
+    observer.on_stage_started(Stage::Fingerprint);
     let (document_fingerprints, fingerprinting_warnings) = fingerprint_multiple(
+        &*options.fingerprint_algorithm,
         &document_hashes,
+        &arena,
         noise_threshold,
         guarantee_threshold,
         max_token_offset,
     );
+    observer.on_stage_finished(Stage::Fingerprint);
 
+    // Many routines are too short to be fingerprinted, which is expected rather than worth a warning each
+    let fingerprinting_warnings = fingerprinting_warnings
+        .into_iter()
+        .filter(|w| match &layout {
+            DocumentLayout::Routines(r) => !w.file.as_ref().is_some_and(|f| r.is_routine(f)),
+            _ => true,
+        });
     warnings.extend(fingerprinting_warnings);
+    observer.on_fingerprinted(document_fingerprints.len());
+    let fingerprint_density = FingerprintDensity {
+        window_size: fingerprint::window_size(
+            noise_threshold,
+            guarantee_threshold,
+            max_token_offset,
+        ),
+        files: document_fingerprints
+            .iter()
+            .map(|(file_id, fingerprint)| FileDensity {
+                file: arena.path(file_id.path).clone(),
+                num_kgrams: document_hashes[*file_id].len() + 1 - noise_threshold,
+                num_selected: fingerprint.spanned_hashes.len(),
+            })
+            .collect(),
+    };
 
-    // Map hashes to their locations
-    let mut hash_locations = build_hash_database(document_fingerprints);
-
-    // Filter out hashes that are common to too many projects
-    let num_projects = documents
+    // Projects without any fingerprinted files cannot match anything, which is easy to miss in the results
+    let fingerprinted_projects = document_fingerprints
         .iter()
-        .map(|f| &f.project)
-        .sorted()
-        .dedup()
-        .count();
+        .map(|(file_id, _)| file_id.project)
+        .collect::<HashSet<_>>();
+    let empty_projects = projects
+        .iter()
+        .filter(|project| !fingerprinted_projects.contains(*project));
+    for &project in empty_projects {
+        warnings.push(Warning::empty_project(
+            arena.path(project),
+            "every file is too small or consists only of starter code",
+        ));
+    }
 
-    if common_hash_threshold > 0.0 {
-        remove_common_hashes(&mut hash_locations, num_projects, common_hash_threshold);
+    if options.interrupted() {
+        let diagnostics = Diagnostics {
+            fingerprint_density,
+            projects: project_stats,
+            ..Default::default()
+        };
+        return (warnings, diagnostics);
     }
 
-    // Turn each set of locations that share a hash into a set of "matches" between distinct projects
-    let mut project_pairs: HashMap<(&PathBuf, &PathBuf), Vec<Match>> = HashMap::default();
-    for (_, locations) in hash_locations.iter() {
-        let matches = locations_to_matches(locations);
+    // Find the matches between every pair of projects, using truncated hashes as keys if requested to save memory
+    let (project_pairs, mut diagnostics) = match options.hash_width {
+        HashWidth::Bits64 => find_matches::<u64>(
+            options,
+            &projects,
+            &arena,
+            document_fingerprints,
+            &mut warnings,
+            observer,
+        ),
+        HashWidth::Bits32 => find_matches::<u32>(
+            options,
+            &projects,
+            &arena,
+            document_fingerprints,
+            &mut warnings,
+            observer,
+        ),
+    };
+    // The matches found before running out of memory are incomplete, so they must not be saved for later runs
+    let memory_exceeded = warnings.iter().any(|w| w.code == WarningCode::MemoryLimit);
+    if memory_exceeded {
+        checkpoint = None;
+    }
+    diagnostics.fingerprint_density = fingerprint_density;
+    diagnostics.projects = project_stats;
+    diagnostics.compared_pairs = projects
+        .iter()
+        .tuple_combinations()
+        .filter(|(&project1, &project2)| {
+            options.compares(arena.path(project1), arena.path(project2))
+        })
+        .count();
 
-        for (project1, project2, m) in matches {
-            match project_pairs.get_mut(&(project1, project2)) {
-                None => {
-                    project_pairs.insert((project1, project2), vec![m]);
-                }
-                Some(lst) => {
-                    lst.push(m);
+    // Pairs which were completed by a previous run do not need to be expanded again
+    let mut completed_pairs = checkpoint
+        .as_ref()
+        .map(|c| c.load_project_pairs())
+        .unwrap_or_default();
+    let mut cache = match &options.cache_dir {
+        _ if memory_exceeded => None,
+        None => None,
+        Some(dir) => match PairCache::open(dir, options, documents, ignored_documents) {
+            Err(e) => {
+                warnings.push(pair_cache::cache_warning(dir, e));
+                None
+            }
+            Ok(c) => Some(c),
+        },
+    };
+
+    let mut resumed_pairs = Vec::new();
+    let mut pending_pairs = Vec::new();
+    for ((p1, p2), (matches, explanation)) in project_pairs {
+        let pair = ProjectPair {
+            project1: arena.path(p1).clone(),
+            project2: arena.path(p2).clone(),
+            matches: matches.into_iter().map(|m| m.into_match(&arena)).collect(),
+            score: None,
+            explanation: Some(explanation),
+            earlier_submission: None,
+            review: None,
+        };
+        if let Some(p) = cache.as_mut().and_then(|c| c.lookup(&pair)) {
+            resumed_pairs.push(p);
+            continue;
+        }
+        match completed_pairs.remove(&(pair.project1.clone(), pair.project2.clone())) {
+            Some(p) => {
+                if let Some(c) = &mut cache {
+                    c.insert(&p);
                 }
+                resumed_pairs.push(p);
             }
+            None => pending_pairs.push(pair),
         }
     }
 
-    let mut project_pairs = project_pairs
-        .into_iter()
-        .map(|((p1, p2), matches)| ProjectPair {
-            project1: p1.to_owned(),
-            project2: p2.to_owned(),
-            matches,
-        })
-        .map(|p| {
-            if expand_matches {
-                match_expansion::expand_matches(p, &document_hashes)
-            } else {
-                p
+    // The coverage of each pair is relative to the size of its projects
+    let mut project_tokens: HashMap<&PathBuf, usize> = HashMap::new();
+    for (file_id, tokens) in document_hashes.iter() {
+        *project_tokens
+            .entry(arena.path(file_id.project))
+            .or_default() += tokens.len();
+    }
+
+    let mut progress = Progress {
+        completed_pairs: 0,
+        total_pairs: resumed_pairs.len() + pending_pairs.len(),
+    };
+    observer.on_stage_started(Stage::Expansion);
+    observer.on_progress(progress);
+    let mut completed_pairs = Vec::new();
+    let mut complete_pair = |p: ProjectPair| {
+        completed_pairs.push(CompletedPair::new(&p));
+        if let Some(p) = apply_thresholds(p, &options.pair_overrides, min_matches) {
+            observer.on_pair_scored(&p);
+            on_pair(p);
+        }
+        progress.completed_pairs += 1;
+        observer.on_progress(progress);
+    };
+    for p in resumed_pairs {
+        complete_pair(p);
+    }
+
+    // Expansion and scoring are independent for every pair, so the pairs are processed in parallel. Completed pairs
+    // are streamed back to this thread, which saves them to the checkpoint before passing them on.
+    let pending_pairs = Mutex::new(pending_pairs.into_iter());
+    let num_workers = options.num_cpu_threads();
+    thread::scope(|s| {
+        let (sender, receiver) = mpsc::sync_channel(num_workers);
+        for _ in 0..num_workers {
+            let sender = sender.clone();
+            let pending_pairs = &pending_pairs;
+            let document_hashes = &document_hashes;
+            let project_tokens = &project_tokens;
+            let (arena, layout) = (&arena, &layout);
+            s.spawn(move || {
+                // Stop before expanding the next pair once interrupted, keeping the pairs which are already complete
+                while !options.interrupted() {
+                    let Some(p) = pending_pairs.lock().unwrap().next() else {
+                        break;
+                    };
+                    let p =
+                        process_pair(p, document_hashes, arena, project_tokens, layout, options);
+                    if sender.send(p).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for p in receiver {
+            update_checkpoint(&mut checkpoint, &mut warnings, |c| c.save_project_pair(&p));
+            if let Some(c) = &mut cache {
+                c.insert(&p);
             }
-        })
-        .filter(|p| p.matches.len() >= min_matches)
-        .collect();
+            complete_pair(p);
+        }
+    });
+    observer.on_stage_finished(Stage::Expansion);
 
-    sort_output(&mut project_pairs);
+    if let Some(c) = &cache {
+        if let Err(e) = c.save() {
+            warnings.push(pair_cache::cache_warning(c.dir(), e));
+        }
+    }
+    diagnostics.completed_pairs = completed_pairs;
 
-    (project_pairs, warnings)
+    (warnings, diagnostics)
 }
 
-fn remove_ignored_documents(
-    document_hashes: &mut HashMap<FileId, Vec<(u64, Range<usize>)>>,
-    ignored_document_hashes: &HashMap<FileId, Vec<(u64, Range<usize>)>>,
-    noise_threshold: usize,
-    max_token_offset: usize,
-) -> Vec<Warning> {
-    // Discard the fingerprinting warnings from the input documents here since they will always be a
-    // subset of the warnings obtained in the second fingerprinting pass when detecting plagiarism.
-    let (document_fingerprints, _fingerprinting_warnings) = fingerprint_multiple(
-        document_hashes,
-        noise_threshold,
-        // Choose the fingerprinting parameters so that the window size is 1.
-        //
-        // Suppose the window size was 2. Suppose the hashes from the starter code were [0, 5] and the hashes from the
-        // assignment code were [..., 0, 5, 6, ...]. In the starter code, the fingerprint would be {0}. In the
-        // assignment code, the fingerprint would be {..., 0, 5, ...}. Only the hash 0 would be discarded, not 5 (even
-        // though 5 matches starter code). If the window size is set to 1 for the starter code, any code snippet that
-        // fully matches _any_ part of the starter code is guaranteed to be ignored.
-        //
-        // Letting the window size be 1 for starter code shouldn't have a huge impact on performance, since there's
-        // normally less starter code than assignment code. Normally, starter code is a strict subset of each student's
-        // submission and there are many students.
-        noise_threshold + max_token_offset,
-        max_token_offset,
-    );
+/// Finds the matches between every pair of projects, using hashes of type `K` as the keys of the hash database. Also
+/// returns the explanation of each pair, without the parts which depend on its final matches.
+fn find_matches<K: IdentityHashKey>(
+    options: &DetectionOptions,
+    projects: &[PathId],
+    arena: &PathArena,
+    document_fingerprints: Vec<(&FileId, Fingerprint)>,
+    warnings: &mut Vec<Warning>,
+    observer: &mut dyn DetectionObserver,
+) -> (
+    HashMap<ProjectIds, (Vec<DocumentMatch>, PairExplanation)>,
+    Diagnostics,
+) {
+    let DetectionOptions {
+        common_hash_threshold,
+        common_hash_min_projects,
+        auto_common_hash_threshold,
+        lsh_bands,
+        lsh_rows,
+        bloom_min_shared_hashes,
+        scoring,
+        max_matches_per_hash,
+        hash_width,
+        max_memory,
+        ..
+    } = *options;
 
-    let (ignored_document_fingerprints, ignored_docs_fingerprinting_warnings) =
-        fingerprint_multiple(
-            ignored_document_hashes,
-            noise_threshold,
-            noise_threshold + max_token_offset,
-            max_token_offset,
+    // Map hashes to their locations
+    observer.on_stage_started(Stage::DatabaseBuild);
+    let mut budget = MemoryBudget::new(max_memory);
+    let Some(mut hash_locations) =
+        build_hash_database_within_budget::<K, _>(document_fingerprints, &mut budget)
+    else {
+        warnings.push(budget.exceeded_warning("building the hash database"));
+        observer.on_stage_finished(Stage::DatabaseBuild);
+        return (HashMap::new(), Diagnostics::default());
+    };
+    if hash_width == HashWidth::Bits32 {
+        warnings.push(truncated_hashes_warning(hash_locations.len()));
+    }
+
+    // Filter out the code in the stop-list
+    if !options.stop_list.is_empty() {
+        let (stop_list_hashes, stop_list_warnings) = stop_list::stop_list_hashes(options);
+        warnings.extend(stop_list_warnings);
+        let stop_list_hashes = stop_list_hashes
+            .into_iter()
+            .map(K::from_hash)
+            .collect::<HashSet<_>>();
+        hash_locations.retain(|hash, _| !stop_list_hashes.contains(hash));
+    }
+
+    let mut diagnostics = Diagnostics {
+        hash_frequencies: hash_frequencies(&hash_locations, projects.len()),
+        ..Default::default()
+    };
+
+    // Filter out hashes that are common to too many projects. A threshold chosen automatically replaces the fractional
+    // one.
+    let (common_hash_threshold, common_hash_min_projects) = if auto_common_hash_threshold {
+        let auto_min_projects = diagnostics.hash_frequencies.auto_threshold();
+        if auto_min_projects.is_none() {
+            warnings.push(Warning {
+                file: None,
+                message: "The common code threshold could not be chosen automatically because there are too few projects or no code is shared between projects.".to_owned(),
+                warn_type: WarningType::Args,
+                code: WarningCode::NoAutoThreshold,
+            });
+        }
+        diagnostics.auto_common_hash_min_projects = auto_min_projects;
+        (
+            0.0,
+            max(auto_min_projects.unwrap_or(0), common_hash_min_projects),
+        )
+    } else {
+        (common_hash_threshold, common_hash_min_projects)
+    };
+    if common_hash_threshold > 0.0 || common_hash_min_projects > 0 {
+        remove_common_hashes(
+            &mut hash_locations,
+            projects.len(),
+            common_hash_threshold,
+            common_hash_min_projects,
         );
+    }
 
-    // Map hashes to their locations
-    let hash_locations = build_hash_database(document_fingerprints);
+    observer.on_stage_finished(Stage::DatabaseBuild);
 
-    // Find locations of hashes that are also in the ignored documents
-    let mut matches: HashMap<FileId, Vec<Range<usize>>> = HashMap::new();
+    // In very large corpora, only compare the pairs of projects which are likely to be similar
+    observer.on_stage_started(Stage::Matching);
+    let mut candidate_pairs =
+        (lsh_bands > 0).then(|| lsh::candidate_pairs(&hash_locations, lsh_bands, lsh_rows));
+    diagnostics.lsh_candidate_pairs = candidate_pairs.as_ref().map(|c| c.len());
+    if bloom_min_shared_hashes > 0 {
+        let bloom_candidate_pairs = bloom::candidate_pairs(
+            &hash_locations,
+            bloom_min_shared_hashes,
+            candidate_pairs.as_ref(),
+        );
+        diagnostics.bloom_candidate_pairs = Some(bloom_candidate_pairs.len());
+        candidate_pairs = Some(bloom_candidate_pairs);
+    }
 
-    for (_, ignored_fingerprint) in ignored_document_fingerprints {
-        for (ignored_hash, _) in ignored_fingerprint.spanned_hashes {
-            if let Some(locations) = hash_locations.get(&ignored_hash) {
-                for (input_file_id, input_doc_span) in locations {
-                    if let Some(input_file_matches) = matches.get_mut(input_file_id) {
-                        input_file_matches.push(input_doc_span.clone());
-                    } else {
-                        matches.insert((*input_file_id).clone(), vec![input_doc_span.clone()]);
-                    }
-                }
+    // Turn each set of locations that share a hash into a set of "matches" between distinct projects. When scoring by
+    // IDF, each match is weighted by how rare its hash is.
+    let mut project_pairs: HashMap<ProjectIds, (Vec<DocumentMatch>, PairExplanation)> =
+        HashMap::default();
+    let mut num_capped_hashes: BTreeMap<ProjectIds, usize> = BTreeMap::new();
+    'hashes: for (_, locations) in hash_locations.iter() {
+        let num_projects_with_hash = count_projects(locations);
+        let weight = match scoring {
+            ScoringMode::Matches => None,
+            ScoringMode::Idf => Some(scoring::idf(projects.len(), num_projects_with_hash)),
+        };
+        let (matches, capped_pairs) = locations_to_matches(
+            locations,
+            weight,
+            candidate_pairs.as_ref(),
+            |project1, project2| options.compares(arena.path(project1), arena.path(project2)),
+            max_matches_per_hash,
+        );
+
+        for pair in capped_pairs {
+            *num_capped_hashes.entry(pair).or_default() += 1;
+        }
+        // The matches of each pair of projects are consecutive, so the hash is counted once per pair
+        let mut previous_pair = None;
+        for (project1, project2, m) in matches {
+            let (pair_matches, explanation) =
+                project_pairs.entry((project1, project2)).or_default();
+            if previous_pair != Some((project1, project2)) {
+                explanation.rarest_hash_projects = match explanation.shared_hashes {
+                    0 => num_projects_with_hash,
+                    _ => min(explanation.rarest_hash_projects, num_projects_with_hash),
+                };
+                explanation.shared_hashes += 1;
+                previous_pair = Some((project1, project2));
             }
+            if !budget.add(MemoryBudget::match_bytes()) {
+                warnings.push(budget.exceeded_warning("finding the matches"));
+                break 'hashes;
+            }
+            pair_matches.push(m);
         }
     }
 
-    // Turn the byte spans into token spans
-    for (file_id, spans) in matches.iter_mut() {
-        for span in spans {
-            let file_hashes = document_hashes.get(file_id).unwrap();
-            span.start = file_hashes
-                .iter()
-                .position(|(_, token_span)| token_span.start == span.start)
-                .unwrap();
-            span.end = file_hashes
-                .iter()
-                .position(|(_, token_span)| token_span.end == span.end)
-                .unwrap();
+    // Flag the pairs whose shared code is all close to the common code thresholds
+    for (_, explanation) in project_pairs.values_mut() {
+        if explanation::nearly_common(
+            explanation.rarest_hash_projects,
+            projects.len(),
+            common_hash_threshold,
+            common_hash_min_projects,
+        ) {
+            explanation.nearly_filtered_by.push(Filter::CommonCode);
         }
     }
 
-    // Remove the matches from `document_hashes`
-    for (file_id, spans) in matches {
-        remove_spans_from_vec(document_hashes.get_mut(&file_id).unwrap(), &spans);
+    for ((project1, project2), num_hashes) in num_capped_hashes {
+        warnings.push(Warning {
+            file: None,
+            message: format!("{num_hashes} code snippet(s) occur too many times in both \"{}\" and \"{}\". Only the occurrences at the nearest positions were matched, so some matches may be missing.", arena.path(project1).display(), arena.path(project2).display()),
+            warn_type: WarningType::Fingerprint,
+            code: WarningCode::CappedMatches,
+        });
     }
+    observer.on_stage_finished(Stage::Matching);
 
-    ignored_docs_fingerprinting_warnings
+    (project_pairs, diagnostics)
 }
 
-// Removes the elements whose indices fall into any of the given spans.
-//
-// The spans may be in any order and overlap.
-fn remove_spans_from_vec<T>(v: &mut Vec<T>, spans: &[Range<usize>]) {
-    if spans.is_empty() {
-        return;
+/// Returns a warning about the hash collisions to expect with 32-bit hashes, given the number of distinct hashes.
+fn truncated_hashes_warning(num_hashes: usize) -> Warning {
+    // Birthday problem: each of the n(n - 1) / 2 pairs of hashes collides with probability 2^-32
+    let expected_collisions = num_hashes as f64 * (num_hashes as f64 - 1.0) / 2.0 / 2f64.powi(32);
+    Warning {
+        file: None,
+        message: format!("Fingerprint hashes are truncated to 32 bits to save memory. With {num_hashes} distinct code snippets, about {expected_collisions:.0} pair(s) of unrelated snippets are expected to share a hash, which may produce false matches."),
+        warn_type: WarningType::Args,
+        code: WarningCode::TruncatedHashes,
     }
+}
 
-    // Sort spans
-    let mut spans = spans.to_vec();
-    spans.sort_unstable_by_key(|s| s.start);
-
-    // Merge overlapping spans
-    let mut merged_spans = vec![spans[0].clone()];
+/// Expands the matches of a pair if requested, scores the pair, and completes its explanation, except for the
+/// thresholds applied afterwards. `project_tokens` gives the total number of tokens in each project. If the documents
+/// are not the files themselves, the matches are finally mapped back to the original files.
+fn process_pair(
+    pair: ProjectPair,
+    document_hashes: &DocumentHashes,
+    arena: &PathArena,
+    project_tokens: &HashMap<&PathBuf, usize>,
+    layout: &DocumentLayout,
+    options: &DetectionOptions,
+) -> ProjectPair {
+    let DetectionOptions {
+        expand_matches,
+        min_report_length,
+        scoring,
+        ..
+    } = *options;
+    let mut pair = if expand_matches {
+        match_expansion::expand_matches(pair, document_hashes, arena)
+    } else {
+        pair
+    };
+    measure_matches(&mut pair, document_hashes, arena);
+    if min_report_length > 0 {
+        pair.matches.retain(|m| {
+            [&m.project_1_location, &m.project_2_location]
+                .iter()
+                .all(|l| l.length_tokens.is_none_or(|n| n >= min_report_length))
+        });
+    }
+    if scoring == ScoringMode::Idf {
+        pair.score = Some(scoring::total_weight(&pair.matches));
+    }
+    let (project_1_coverage, project_2_coverage) =
+        explanation::coverage(&pair, document_hashes, arena, project_tokens);
+    if let Some(explanation) = &mut pair.explanation {
+        explanation.project_1_coverage = project_1_coverage.proportion;
+        explanation.project_2_coverage = project_2_coverage.proportion;
+        explanation.project_1_unmatched_tokens = project_1_coverage.unmatched_tokens;
+        explanation.project_2_unmatched_tokens = project_2_coverage.unmatched_tokens;
+        explanation.more_unique_content = Some(explanation::more_unique_content(
+            project_1_coverage.unmatched_tokens,
+            project_2_coverage.unmatched_tokens,
+        ));
+    }
+    match layout {
+        DocumentLayout::Files => pair,
+        DocumentLayout::Stitched(s) => s.unstitch_pair(pair, document_hashes, arena),
+        DocumentLayout::Routines(r) => r.restore_pair(pair),
+    }
+}
 
-    for span in &spans[1..] {
-        let last_merged_span = merged_spans.last_mut().unwrap();
-        if span.start <= last_merged_span.end {
-            last_merged_span.end = max(span.end, last_merged_span.end);
-        } else {
-            merged_spans.push(span.clone());
+/// Records the length of both locations of every match, in bytes and in tokens.
+fn measure_matches(pair: &mut ProjectPair, document_hashes: &DocumentHashes, arena: &PathArena) {
+    for m in pair.matches.iter_mut() {
+        for (project, location) in [
+            (&pair.project1, &mut m.project_1_location),
+            (&pair.project2, &mut m.project_2_location),
+        ] {
+            let tokens = arena
+                .file_id(project, &location.file)
+                .and_then(|file_id| document_hashes.get(&file_id));
+            let Some(tokens) = tokens else {
+                continue;
+            };
+            let start = tokens.partition_point(|(_, range)| range.start < location.span.start);
+            let end = tokens.partition_point(|(_, range)| range.end <= location.span.end);
+            location.length_bytes = Some(location.span.len());
+            location.length_tokens = Some(end.saturating_sub(start));
         }
     }
+}
 
-    // Remove the spans from the vector
-    for span in merged_spans.into_iter().rev() {
-        v.drain(span.clone());
+/// Removes a pair which does not meet its thresholds (i.e., the global ones unless they are overridden for its
+/// projects), and otherwise records which of them nearly removed it.
+fn apply_thresholds(
+    mut pair: ProjectPair,
+    pair_overrides: &[PairOverride],
+    min_matches: usize,
+) -> Option<ProjectPair> {
+    let thresholds = pair_overrides::pair_thresholds(
+        pair_overrides,
+        &pair.project1,
+        &pair.project2,
+        min_matches,
+    );
+    let num_matches = pair.matches.len();
+    // A pair can be left without matches once the short ones are discarded
+    if num_matches == 0 || num_matches < thresholds.min_matches {
+        return None;
+    }
+    if let Some(explanation) = &mut pair.explanation {
+        let coverage = explanation
+            .project_1_coverage
+            .max(explanation.project_2_coverage);
+        if coverage < thresholds.min_coverage {
+            return None;
+        }
+        if explanation::nearly_too_few_matches(num_matches, thresholds.min_matches) {
+            explanation.nearly_filtered_by.push(Filter::MinMatches);
+        }
+        if explanation::nearly_too_low_coverage(coverage, thresholds.min_coverage) {
+            explanation.nearly_filtered_by.push(Filter::MinCoverage);
+        }
     }
+    Some(pair)
 }
 
-fn fingerprint_multiple(
-    document_hashes: &HashMap<FileId, Vec<(u64, Range<usize>)>>,
-    noise_threshold: usize,
-    guarantee_threshold: usize,
-    max_token_offset: usize,
-) -> (Vec<(&FileId, Fingerprint)>, Vec<Warning>) {
-    let fingerprint_results = document_hashes.iter().map(|(file_id, hashes)| {
-        (
-            file_id,
-            fingerprint::fingerprint(
-                noise_threshold,
-                guarantee_threshold,
-                max_token_offset,
-                hashes,
-            ),
-        )
-    });
+/// Hashed tokens of a document, along with the warnings about its lexing.
+#[derive(Debug)]
+pub struct LexedDocument {
+    /// `None` if the document was left out because of lexing errors.
+    hashes: Option<HashedTokens>,
+    warnings: Vec<Warning>,
+}
 
-    let mut fingerprints = Vec::new();
+/// Documents lexed ahead of the analysis, which can be filled from several threads.
+#[derive(Debug, Default)]
+pub struct LexedDocuments(Mutex<HashMap<(PathBuf, PathBuf), LexedDocument>>);
+
+impl LexedDocuments {
+    pub fn insert(&self, document: &File, lexed: LexedDocument) {
+        let key = (document.project.clone(), document.path.clone());
+        self.0.lock().unwrap().insert(key, lexed);
+    }
+
+    /// Returns the number of documents which were lexed but not yet used by an analysis.
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the lexed document, so that its tokens are moved into the analysis rather than copied.
+    fn take(&self, document: &File) -> Option<LexedDocument> {
+        let key = (document.project.clone(), document.path.clone());
+        self.0.lock().unwrap().remove(&key)
+    }
+}
+
+/// Tokenizes and hashes a document with the given options, so that it can be lexed as soon as it is read (see
+/// [`DetectionOptions::lexed_documents`]).
+pub fn lex_document(options: &DetectionOptions, document: &File) -> LexedDocument {
+    lex(options, &options.token_transforms(), document)
+}
+
+fn lex(options: &DetectionOptions, transforms: &[TokenTransform], f: &File) -> LexedDocument {
+    let mut warnings = Vec::new();
+    let tokenizing_strategy = match options.tokenizing_strategy {
+        TokenizingStrategy::Auto => {
+            let detected = lexing::detect_tokenizing_strategy(Some(&f.path), &f.contents);
+            if detected.ambiguous {
+                warnings.push(Warning::ambiguous_language(&f.path, detected.strategy));
+            }
+            detected.strategy
+        }
+        strategy => strategy,
+    };
+    let (hashes, lex_errors) = lexing::tokenize_and_hash(
+        &f.contents,
+        tokenizing_strategy,
+        transforms,
+        options.key_symbols.as_ref(),
+        options.hash_dialect,
+        options.max_token_offset,
+    );
+    if !lex_errors.is_empty() {
+        warnings.push(Warning::lex_errors(
+            &f.path,
+            &f.contents,
+            &lex_errors,
+            options.strict_lex,
+        ));
+        if options.strict_lex {
+            return LexedDocument {
+                hashes: None,
+                warnings,
+            };
+        }
+    }
+    LexedDocument {
+        hashes: Some(hashes),
+        warnings,
+    }
+}
+
+/// Tokenizes and hashes the documents, removing any code which is also found in the ignored documents. Also returns the
+/// number of tokens removed from each document.
+fn hash_documents(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    arena: &PathArena,
+    observer: &mut dyn DetectionObserver,
+) -> (DocumentHashes, StarterCodeTokens, Vec<Warning>) {
+    let transforms = options.token_transforms();
+    let mut warnings = Vec::new();
+    observer.on_stage_started(Stage::Lex);
+    let hash = |f: &File| {
+        let lexed = options
+            .lexed_documents
+            .as_ref()
+            .and_then(|lexed| lexed.take(f))
+            .unwrap_or_else(|| lex(options, &transforms, f));
+        let hashes = lexed.hashes.map(|hashes| {
+            let file_id = arena
+                .file_id(&f.project, &f.path)
+                .expect("the paths of the documents are interned");
+            (file_id, hashes)
+        });
+        (hashes, lexed.warnings)
+    };
+    let mut document_hashes = HashMap::new();
+    let mut ignored_document_hashes = HashMap::new();
+    for (files, hashes) in [
+        (documents, &mut document_hashes),
+        (ignored_documents, &mut ignored_document_hashes),
+    ] {
+        map_in_parallel(files, options.num_cpu_threads(), hash, |f, (h, w)| {
+            observer.on_file_read(f);
+            hashes.extend(h);
+            warnings.extend(w);
+        });
+    }
+    observer.on_stage_finished(Stage::Lex);
+
+    // Remove the contents of the ignored documents from the input documents
+    observer.on_stage_started(Stage::IgnoreRemoval);
+    let (starter_code_tokens, ignored_documents_warnings) = remove_ignored_documents(
+        &mut document_hashes,
+        &ignored_document_hashes,
+        arena,
+        options.noise_threshold,
+        options.max_token_offset,
+        options.starter_code_similarity,
+    );
+    warnings.extend(ignored_documents_warnings);
+    observer.on_stage_finished(Stage::IgnoreRemoval);
+
+    (document_hashes, starter_code_tokens, warnings)
+}
+
+/// Counts the files and tokens of every project of the documents, including those without any tokenized files.
+fn project_stats(
+    documents: &[File],
+    arena: &PathArena,
+    document_hashes: &DocumentHashes,
+    starter_code_tokens: &StarterCodeTokens,
+) -> Vec<ProjectStats> {
+    let mut stats = documents
+        .iter()
+        .map(|f| {
+            let stats = ProjectStats {
+                project: f.project.clone(),
+                num_files: 0,
+                num_tokens: 0,
+                starter_code_tokens: 0,
+                metadata: None,
+            };
+            (&f.project, stats)
+        })
+        .collect::<BTreeMap<_, _>>();
+    for (file_id, hashes) in document_hashes.iter() {
+        if let Some(s) = stats.get_mut(arena.path(file_id.project)) {
+            s.num_files += 1;
+            s.num_tokens += hashes.len();
+            s.starter_code_tokens += starter_code_tokens.get(file_id).copied().unwrap_or(0);
+        }
+    }
+    stats.into_values().collect()
+}
+
+/// Removes the documents of every project with fewer than `min_project_tokens` tokens, returning a warning for each
+/// such project.
+fn remove_small_projects(
+    document_hashes: &mut DocumentHashes,
+    arena: &PathArena,
+    min_project_tokens: usize,
+) -> Vec<Warning> {
+    let mut project_sizes: BTreeMap<PathId, usize> = BTreeMap::new();
+    for (file_id, hashes) in document_hashes.iter() {
+        *project_sizes.entry(file_id.project).or_default() += hashes.len();
+    }
+
+    let small_projects = project_sizes
+        .into_iter()
+        .filter(|(_, num_tokens)| *num_tokens < min_project_tokens)
+        .collect::<BTreeMap<_, _>>();
+    document_hashes.retain(|file_id, _| !small_projects.contains_key(&file_id.project));
+
+    small_projects
+        .into_iter()
+        .map(|(project, num_tokens)| Warning {
+            file: Some(arena.path(project).clone()),
+            message: format!("Project was excluded from the analysis because it contains {num_tokens} tokens (not counting starter code), which is less than the minimum of {min_project_tokens}."),
+            warn_type: WarningType::Input,
+            code: WarningCode::SmallProject,
+        })
+        .collect()
+}
+
+/// Applies an update to the checkpoint, if any. If the update fails, a warning is recorded and checkpointing is
+/// disabled for the rest of the analysis.
+fn update_checkpoint<F>(checkpoint: &mut Option<Checkpoint>, warnings: &mut Vec<Warning>, update: F)
+where
+    F: FnOnce(&mut Checkpoint) -> io::Result<()>,
+{
+    if let Some(c) = checkpoint {
+        if let Err(e) = update(c) {
+            warnings.push(checkpoint::checkpoint_warning(c.dir(), e));
+            *checkpoint = None;
+        }
+    }
+}
+
+/// Length of the windows in which `DetectionOptions::starter_code_similarity` is measured, in multiples of the noise
+/// threshold. Each edit of the starter code breaks the exact matches of about a noise threshold of tokens.
+const FUZZY_STARTER_CODE_WINDOW: usize = 4;
+
+fn remove_ignored_documents(
+    document_hashes: &mut HashMap<FileId, Vec<(u64, Range<usize>)>>,
+    ignored_document_hashes: &HashMap<FileId, Vec<(u64, Range<usize>)>>,
+    arena: &PathArena,
+    noise_threshold: usize,
+    max_token_offset: usize,
+    starter_code_similarity: Option<f64>,
+) -> (StarterCodeTokens, Vec<Warning>) {
+    // Discard the fingerprinting warnings from the input documents here since they will always be a
+    // subset of the warnings obtained in the second fingerprinting pass when detecting plagiarism.
+    let (document_fingerprints, _fingerprinting_warnings) = fingerprint_multiple(
+        &Winnowing::default(),
+        document_hashes,
+        arena,
+        noise_threshold,
+        // Choose the fingerprinting parameters so that the window size is 1.
+        //
+        // Suppose the window size was 2. Suppose the hashes from the starter code were [0, 5] and the hashes from the
+        // assignment code were [..., 0, 5, 6, ...]. In the starter code, the fingerprint would be {0}. In the
+        // assignment code, the fingerprint would be {..., 0, 5, ...}. Only the hash 0 would be discarded, not 5 (even
+        // though 5 matches starter code). If the window size is set to 1 for the starter code, any code snippet that
+        // fully matches _any_ part of the starter code is guaranteed to be ignored.
+        //
+        // Letting the window size be 1 for starter code shouldn't have a huge impact on performance, since there's
+        // normally less starter code than assignment code. Normally, starter code is a strict subset of each student's
+        // submission and there are many students.
+        noise_threshold + max_token_offset,
+        max_token_offset,
+    );
+
+    let (ignored_document_fingerprints, ignored_docs_fingerprinting_warnings) =
+        fingerprint_multiple(
+            &Winnowing::default(),
+            ignored_document_hashes,
+            arena,
+            noise_threshold,
+            noise_threshold + max_token_offset,
+            max_token_offset,
+        );
+
+    // Map hashes to their locations
+    let hash_locations = build_hash_database::<u64, _>(document_fingerprints);
+
+    // Find locations of hashes that are also in the ignored documents
+    let mut matches: HashMap<FileId, Vec<Range<usize>>> = HashMap::new();
+
+    for (_, ignored_fingerprint) in ignored_document_fingerprints {
+        for (ignored_hash, _) in ignored_fingerprint.spanned_hashes {
+            if let Some(locations) = hash_locations.get(&ignored_hash) {
+                for (input_file_id, input_doc_span) in locations {
+                    if let Some(input_file_matches) = matches.get_mut(input_file_id) {
+                        input_file_matches.push(input_doc_span.clone());
+                    } else {
+                        matches.insert(**input_file_id, vec![input_doc_span.clone()]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Turn the byte spans into token spans
+    for (file_id, spans) in matches.iter_mut() {
+        for span in spans {
+            let file_hashes = document_hashes.get(file_id).unwrap();
+            span.start = match_expansion::token_index_starting_at(file_hashes, span.start).unwrap();
+            span.end = match_expansion::token_index_ending_at(file_hashes, span.end).unwrap();
+        }
+    }
+
+    // Also remove the regions consisting mostly of starter code, in case it was edited
+    if let Some(min_similarity) = starter_code_similarity {
+        let window = FUZZY_STARTER_CODE_WINDOW * noise_threshold;
+        for (file_id, spans) in matches.iter_mut() {
+            let num_tokens = document_hashes[file_id].len();
+            extend_starter_code_spans(spans, num_tokens, window, min_similarity);
+        }
+    }
+
+    // Remove the matches from `document_hashes`, counting the removed tokens
+    let mut starter_code_tokens = StarterCodeTokens::new();
+    for (file_id, spans) in matches {
+        let hashes = document_hashes.get_mut(&file_id).unwrap();
+        let num_tokens = hashes.len();
+        remove_spans_from_vec(hashes, &spans);
+        starter_code_tokens.insert(file_id, num_tokens - hashes.len());
+    }
+
+    (starter_code_tokens, ignored_docs_fingerprinting_warnings)
+}
+
+/// Adds every window of `window` tokens (or the whole file, if it is shorter) in which at least the proportion
+/// `min_similarity` of the tokens are in the given spans of starter code, out of the `num_tokens` tokens of the file.
+fn extend_starter_code_spans(
+    spans: &mut Vec<Range<usize>>,
+    num_tokens: usize,
+    window: usize,
+    min_similarity: f64,
+) {
+    let window = window.min(num_tokens);
+    if window == 0 {
+        return;
+    }
+    let mut is_starter_code = vec![false; num_tokens];
+    for span in spans.iter() {
+        is_starter_code[span.start.min(num_tokens)..span.end.min(num_tokens)].fill(true);
+    }
+
+    let min_starter_code_tokens = (min_similarity * window as f64).ceil() as usize;
+    let mut starter_code_tokens = is_starter_code[..window].iter().filter(|&&s| s).count();
+    for start in 0..=num_tokens - window {
+        if start > 0 {
+            // Slide the window by one token
+            starter_code_tokens -= is_starter_code[start - 1] as usize;
+            starter_code_tokens += is_starter_code[start + window - 1] as usize;
+        }
+        if starter_code_tokens >= min_starter_code_tokens {
+            spans.push(start..start + window);
+        }
+    }
+}
+
+// Removes the elements whose indices fall into any of the given spans.
+//
+// The spans may be in any order and overlap.
+fn remove_spans_from_vec<T>(v: &mut Vec<T>, spans: &[Range<usize>]) {
+    if spans.is_empty() {
+        return;
+    }
+
+    // Sort spans
+    let mut spans = spans.to_vec();
+    spans.sort_unstable_by_key(|s| s.start);
+
+    // Merge overlapping spans
+    let mut merged_spans = vec![spans[0].clone()];
+
+    for span in &spans[1..] {
+        let last_merged_span = merged_spans.last_mut().unwrap();
+        if span.start <= last_merged_span.end {
+            last_merged_span.end = max(span.end, last_merged_span.end);
+        } else {
+            merged_spans.push(span.clone());
+        }
+    }
+
+    // Remove the spans from the vector
+    for span in merged_spans.into_iter().rev() {
+        v.drain(span.clone());
+    }
+}
+
+fn fingerprint_multiple<'a>(
+    algorithm: &dyn FingerprintAlgorithm,
+    document_hashes: &'a HashMap<FileId, Vec<(u64, Range<usize>)>>,
+    arena: &PathArena,
+    noise_threshold: usize,
+    guarantee_threshold: usize,
+    max_token_offset: usize,
+) -> (Vec<(&'a FileId, Fingerprint)>, Vec<Warning>) {
+    // Iterate in a fixed order so that the warnings (and the order of locations in the hash database) do not depend on
+    // the iteration order of the map
+    let fingerprint_results = document_hashes
+        .iter()
+        .sorted_unstable_by_key(|(file_id, _)| *file_id)
+        .map(|(file_id, hashes)| {
+            (
+                file_id,
+                algorithm.fingerprint(
+                    noise_threshold,
+                    guarantee_threshold,
+                    max_token_offset,
+                    hashes,
+                ),
+            )
+        });
+
+    let mut fingerprints = Vec::new();
     let mut warnings = Vec::new();
     for (document, result) in fingerprint_results {
         match result {
             Err(e) => {
                 warnings.push(Warning {
-                    file: Some(document.path.to_owned()),
+                    file: Some(arena.path(document.path).clone()),
                     message: e.to_string(),
                     warn_type: WarningType::Fingerprint,
+                    code: match e {
+                        FingerprintError::TooFewTokens { .. } => WarningCode::TooFewTokens,
+                    },
                 });
             }
             Ok(f) => {
@@ -312,52 +1446,112 @@ fn fingerprint_multiple(
 }
 
 /// Constructs a "hash database" that maps a hash to all the locations in which it was found in the code.
-fn build_hash_database<'a, I>(fingerprints: I) -> IdentityHashMap<Vec<(&'a FileId, Range<usize>)>>
+fn build_hash_database<'a, K, I>(
+    fingerprints: I,
+) -> IdentityHashMap<Vec<(&'a FileId, Range<usize>)>, K>
+where
+    K: IdentityHashKey,
+    I: IntoIterator<Item = (&'a FileId, Fingerprint)>,
+{
+    build_hash_database_within_budget(fingerprints, &mut MemoryBudget::new(None))
+        .unwrap_or_default()
+}
+
+/// Same as `build_hash_database`, but adds the estimated memory of the database to the budget as it grows, and gives
+/// up as soon as the budget is exceeded.
+fn build_hash_database_within_budget<'a, K, I>(
+    fingerprints: I,
+    budget: &mut MemoryBudget,
+) -> Option<HashDatabase<'a, K>>
 where
+    K: IdentityHashKey,
     I: IntoIterator<Item = (&'a FileId, Fingerprint)>,
 {
-    let mut hash_locations: IdentityHashMap<Vec<(&'a FileId, Range<usize>)>> =
+    let mut hash_locations: IdentityHashMap<Vec<(&'a FileId, Range<usize>)>, K> =
         IdentityHashMap::default();
 
     for (file_id, fingerprint) in fingerprints.into_iter() {
         for (hash, span) in fingerprint.spanned_hashes {
-            match hash_locations.get_mut(&hash) {
+            let hash = K::from_hash(hash);
+            let bytes = match hash_locations.get_mut(&hash) {
                 None => {
                     hash_locations.insert(hash, vec![(file_id, span)]);
+                    MemoryBudget::hash_entry_bytes::<K>() + MemoryBudget::hash_location_bytes()
                 }
                 Some(lst) => {
                     lst.push((file_id, span));
+                    MemoryBudget::hash_location_bytes()
                 }
+            };
+            if !budget.add(bytes) {
+                return None;
             }
         }
     }
 
-    hash_locations
+    Some(hash_locations)
+}
+
+/// Counts the number of hashes which occur in each number of projects.
+fn hash_frequencies<K: IdentityHashKey>(
+    hash_database: &IdentityHashMap<Vec<(&FileId, Range<usize>)>, K>,
+    num_projects: usize,
+) -> HashFrequencies {
+    let mut num_hashes_by_projects = BTreeMap::new();
+    for locations in hash_database.values() {
+        *num_hashes_by_projects
+            .entry(count_projects(locations))
+            .or_default() += 1;
+    }
+    HashFrequencies {
+        num_projects,
+        num_hashes_by_projects,
+    }
 }
 
-fn remove_common_hashes(
-    hash_database: &mut IdentityHashMap<Vec<(&FileId, Range<usize>)>>,
+/// Removes the hashes which occur in too many projects. A threshold of zero is ignored, and if both thresholds are
+/// non-zero, a hash is only removed if it exceeds both.
+fn remove_common_hashes<K: IdentityHashKey>(
+    hash_database: &mut IdentityHashMap<Vec<(&FileId, Range<usize>)>, K>,
     num_projects: usize,
     common_hash_threshold: f64,
+    common_hash_min_projects: usize,
 ) {
     hash_database.retain(|_hash, locations| {
-        let num_projects_where_this_hash_occurs = locations
-            .iter()
-            .map(|(file_id, _)| &file_id.project)
-            .sorted()
-            .dedup()
-            .count();
-        (num_projects_where_this_hash_occurs as f64) < (num_projects as f64) * common_hash_threshold
+        !explanation::is_common_hash(
+            count_projects(locations),
+            num_projects,
+            common_hash_threshold,
+            common_hash_min_projects,
+        )
     });
 }
 
-/// Converts a set of locations (i.e., identical code snippets) into a set of matches between distinct projects.
-fn locations_to_matches<'a>(
-    locations: &[(&'a FileId, Range<usize>)],
-) -> Vec<(&'a PathBuf, &'a PathBuf, Match)> {
+/// Returns the number of distinct projects among a set of locations.
+fn count_projects(locations: &[(&FileId, Range<usize>)]) -> usize {
+    locations
+        .iter()
+        .map(|(file_id, _)| file_id.project)
+        .sorted()
+        .dedup()
+        .count()
+}
+
+/// Converts a set of locations (i.e., identical code snippets) into a set of matches between distinct projects, each
+/// with the given weight. If candidate pairs are given, only the matches between those pairs of projects are returned.
+///
+/// Also returns the pairs of projects for which the number of matches was capped at `max_matches`.
+fn locations_to_matches(
+    locations: &[(&FileId, Range<usize>)],
+    weight: Option<f64>,
+    candidate_pairs: Option<&HashSet<ProjectIds>>,
+    is_compared: impl Fn(PathId, PathId) -> bool,
+    max_matches: usize,
+) -> (Vec<(PathId, PathId, DocumentMatch)>, Vec<ProjectIds>) {
     let grouped_locations = group_locations(locations);
 
     let mut matches = Vec::new();
+    let mut capped_pairs = Vec::new();
     for ((&project_1, project_1_occurrences), (&project_2, project_2_occurrences)) in
         iproduct!(grouped_locations.iter(), grouped_locations.iter())
     {
@@ -365,35 +1559,73 @@ fn locations_to_matches<'a>(
         if project_1 >= project_2 {
             continue;
         }
-
-        for (project_1_location, project_2_location) in
-            iproduct!(project_1_occurrences, project_2_occurrences)
+        if candidate_pairs.is_some_and(|c| !c.contains(&(project_1, project_2)))
+            || !is_compared(project_1, project_2)
         {
-            let m = Match {
-                project_1_location: project_1_location.to_owned(),
-                project_2_location: project_2_location.to_owned(),
+            continue;
+        }
+
+        let (location_pairs, capped) =
+            pair_occurrences(project_1_occurrences, project_2_occurrences, max_matches);
+        if capped {
+            capped_pairs.push((project_1, project_2));
+        }
+        for (location_1, location_2) in location_pairs {
+            let m = DocumentMatch {
+                location_1: location_1.to_owned(),
+                location_2: location_2.to_owned(),
+                weight,
             };
             matches.push((project_1, project_2, m));
         }
     }
 
-    matches
+    (matches, capped_pairs)
+}
+
+/// Pairs every occurrence of a code snippet in one project with every occurrence in another, unless that would produce
+/// more than `max_pairs` pairs (zero means no limit). In that case, the occurrences are sorted by position and each one
+/// is only paired with the occurrence at the same relative position in the other project, keeping at most `max_pairs`
+/// pairs. Also returns whether the pairs were capped in this way.
+fn pair_occurrences<'a>(
+    occurrences_1: &'a [DocumentLocation],
+    occurrences_2: &'a [DocumentLocation],
+    max_pairs: usize,
+) -> (Vec<(&'a DocumentLocation, &'a DocumentLocation)>, bool) {
+    let (n1, n2) = (occurrences_1.len(), occurrences_2.len());
+    if max_pairs == 0 || n1.saturating_mul(n2) <= max_pairs {
+        return (iproduct!(occurrences_1, occurrences_2).collect(), false);
+    }
+
+    // The identifiers of the files compare like their paths
+    let sort_key = |(file, span): &&DocumentLocation| (*file, span.start, span.end);
+    let sorted_1 = occurrences_1
+        .iter()
+        .sorted_by_key(sort_key)
+        .collect::<Vec<_>>();
+    let sorted_2 = occurrences_2
+        .iter()
+        .sorted_by_key(sort_key)
+        .collect::<Vec<_>>();
+    let n = max(n1, n2);
+    let pairs = (0..n)
+        .map(|i| (sorted_1[i * n1 / n], sorted_2[i * n2 / n]))
+        .take(max_pairs)
+        .collect();
+    (pairs, true)
 }
 
 /// Groups a set of locations by project.
-fn group_locations<'a>(
-    locations: &[(&'a FileId, Range<usize>)],
-) -> HashMap<&'a PathBuf, Vec<Location>> {
-    let mut grouped_locations: HashMap<&PathBuf, Vec<Location>> = HashMap::default();
+fn group_locations(
+    locations: &[(&FileId, Range<usize>)],
+) -> HashMap<PathId, Vec<DocumentLocation>> {
+    let mut grouped_locations: HashMap<PathId, Vec<DocumentLocation>> = HashMap::default();
 
     for (file_id, span) in locations {
-        let location = Location {
-            file: file_id.path.to_owned(),
-            span: span.to_owned(),
-        };
+        let location = (file_id.path, span.to_owned());
         match grouped_locations.get_mut(&file_id.project) {
             None => {
-                grouped_locations.insert(&file_id.project, vec![location]);
+                grouped_locations.insert(file_id.project, vec![location]);
             }
             Some(lst) => {
                 lst.push(location);
@@ -405,28 +1637,24 @@ fn group_locations<'a>(
 }
 
 /// Sorts the project pairs, the matches, and the locations.
+///
+/// Every field takes part in the ordering, so the output is the same regardless of the iteration order of the maps it
+/// was built from.
 fn sort_output(project_pairs: &mut Vec<ProjectPair>) {
-    project_pairs.sort_unstable_by_key(|p| p.matches.len());
-    project_pairs.reverse();
+    project_pairs.sort_unstable_by(|p1, p2| PairOrder::Score.compare(p1, p2));
 
     for pp in project_pairs {
-        pp.matches.sort_unstable_by(|m1, m2| {
-            (
-                &m1.project_1_location.file,
-                m1.project_1_location.span.start,
-            )
-                .cmp(&(
-                    &m2.project_1_location.file,
-                    m2.project_1_location.span.start,
-                ))
-        });
+        pp.matches
+            .sort_unstable_by(|m1, m2| MatchOrder::Location.compare(m1, m2));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use output::MoreUniqueContent;
     use pretty_assertions::assert_eq;
+    use std::path::Path;
 
     #[test]
     fn simple_sentences() {
@@ -441,14 +1669,17 @@ mod tests {
 
         let documents = vec![file1, file2, file3, file4];
         let (matches, warnings) = detect_plagiarism(
-            3,
-            3,
-            0,
-            TokenizingStrategy::Bytes,
-            false,
-            false,
-            0,
-            0.0,
+            &DetectionOptions {
+                noise_threshold: 3,
+                guarantee_threshold: 3,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                min_matches: 0,
+                common_hash_threshold: 0.0,
+                ..Default::default()
+            },
             &documents,
             &[],
         );
@@ -461,56 +1692,100 @@ mod tests {
                 project2: "P2".into(),
                 matches: vec![
                     Match {
+                        id: None,
                         project_1_location: Location {
                             file: "C:/P1/file1.txt".into(),
-                            span: 0..3
+                            span: 0..3,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
                         project_2_location: Location {
                             file: "C:/P2/file.txt".into(),
-                            span: 3..6
-                        }
+                            span: 3..6,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
+                        },
+                        weight: None,
                     },
                     Match {
+                        id: None,
                         project_1_location: Location {
                             file: "C:/P1/file2.txt".into(),
-                            span: 0..3
+                            span: 0..3,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
                         project_2_location: Location {
                             file: "C:/P2/file.txt".into(),
-                            span: 3..6
-                        }
+                            span: 3..6,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
+                        },
+                        weight: None,
                     },
                     Match {
+                        id: None,
                         project_1_location: Location {
                             file: "C:/P1/file2.txt".into(),
-                            span: 3..6
+                            span: 3..6,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
                         project_2_location: Location {
                             file: "C:/P2/file.txt".into(),
                             span: 0..3,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
+                        weight: None,
                     },
                     Match {
+                        id: None,
                         project_1_location: Location {
                             file: "C:/P1/file2.txt".into(),
-                            span: 9..12
+                            span: 9..12,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
                         project_2_location: Location {
                             file: "C:/P2/file.txt".into(),
-                            span: 3..6
-                        }
+                            span: 3..6,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
+                        },
+                        weight: None,
                     },
                     Match {
+                        id: None,
                         project_1_location: Location {
                             file: "C:/P1/file2.txt".into(),
                             span: 15..18,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
                         project_2_location: Location {
                             file: "C:/P2/file.txt".into(),
-                            span: 6..9
+                            span: 6..9,
+                            length_bytes: Some(3),
+                            length_tokens: Some(3),
                         },
+                        weight: None,
                     }
-                ]
+                ],
+                score: None,
+                explanation: Some(PairExplanation {
+                    shared_hashes: 3,
+                    rarest_hash_projects: 2,
+                    project_1_coverage: 15.0 / 21.0,
+                    project_2_coverage: 1.0,
+                    project_1_unmatched_tokens: 6,
+                    project_2_unmatched_tokens: 0,
+                    more_unique_content: Some(MoreUniqueContent::Project1),
+                    nearly_filtered_by: Vec::new(),
+                    z_score: None,
+                }),
+                earlier_submission: None,
+                review: None,
             }]
         );
     }
@@ -527,14 +1802,17 @@ mod tests {
         let guarantee = 1500;
 
         let (project_pairs, warnings) = detect_plagiarism(
-            noise,
-            guarantee,
-            0,
-            TokenizingStrategy::Bytes,
-            false,
-            false,
-            5,
-            0.0,
+            &DetectionOptions {
+                noise_threshold: noise,
+                guarantee_threshold: guarantee,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                min_matches: 5,
+                common_hash_threshold: 0.0,
+                ..Default::default()
+            },
             &[file.to_owned()],
             &[ignored_file.to_owned()],
         );
@@ -547,12 +1825,18 @@ mod tests {
                     file: Some("Ignored File".into()),
                     message: format!("File could not be fingerprinted because it contains {} tokens, which is less than the noise threshold of {}.", &ignored_file.contents.len(), noise),
                     warn_type: WarningType::Fingerprint,
+                    code: WarningCode::TooFewTokens,
                 },
                 Warning {
                     file: Some("File".into()),
                     message: format!("File could not be fingerprinted because it contains {} tokens, which is less than the noise threshold of {}.", &file.contents.len(), noise),
                     warn_type: WarningType::Fingerprint,
+                    code: WarningCode::TooFewTokens,
                 },
+                Warning::empty_project(
+                    Path::new("Project"),
+                    "every file is too small or consists only of starter code"
+                ),
             ]
         );
     }
@@ -579,14 +1863,17 @@ mod tests {
             contents: "aaa".to_owned(),
         }];
         let (project_pairs, warnings) = detect_plagiarism(
-            noise,
-            guarantee,
-            0,
-            TokenizingStrategy::Bytes,
-            false,
-            false,
-            0,
-            0.0,
+            &DetectionOptions {
+                noise_threshold: noise,
+                guarantee_threshold: guarantee,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                min_matches: 0,
+                common_hash_threshold: 0.0,
+                ..Default::default()
+            },
             &files,
             &ignored_files,
         );
@@ -598,15 +1885,35 @@ mod tests {
                 project1: "Project 1".into(),
                 project2: "Project 2".into(),
                 matches: vec![Match {
+                    id: None,
                     project_1_location: Location {
                         file: "File 1".into(),
-                        span: 6..9
+                        span: 6..9,
+                        length_bytes: Some(3),
+                        length_tokens: Some(3),
                     },
                     project_2_location: Location {
                         file: "File 2".into(),
-                        span: 0..3
-                    }
-                }]
+                        span: 0..3,
+                        length_bytes: Some(3),
+                        length_tokens: Some(3),
+                    },
+                    weight: None,
+                }],
+                score: None,
+                explanation: Some(PairExplanation {
+                    shared_hashes: 1,
+                    rarest_hash_projects: 2,
+                    project_1_coverage: 3.0 / 7.0,
+                    project_2_coverage: 3.0 / 7.0,
+                    project_1_unmatched_tokens: 4,
+                    project_2_unmatched_tokens: 4,
+                    more_unique_content: Some(MoreUniqueContent::Same),
+                    nearly_filtered_by: Vec::new(),
+                    z_score: None,
+                }),
+                earlier_submission: None,
+                review: None,
             }]
         );
     }
@@ -638,14 +1945,17 @@ mod tests {
             },
         ];
         let (project_pairs, warnings) = detect_plagiarism(
-            noise,
-            guarantee,
-            0,
-            TokenizingStrategy::Bytes,
-            false,
-            false,
-            0,
-            0.75,
+            &DetectionOptions {
+                noise_threshold: noise,
+                guarantee_threshold: guarantee,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                min_matches: 0,
+                common_hash_threshold: 0.75,
+                ..Default::default()
+            },
             &files,
             &[],
         );
@@ -657,51 +1967,598 @@ mod tests {
                 project1: "Project 1".into(),
                 project2: "Project 2".into(),
                 matches: vec![Match {
+                    id: None,
                     project_1_location: Location {
                         file: "File 1".into(),
-                        span: 6..9
+                        span: 6..9,
+                        length_bytes: Some(3),
+                        length_tokens: Some(3),
                     },
                     project_2_location: Location {
                         file: "File 2".into(),
-                        span: 0..3
-                    }
-                }]
+                        span: 0..3,
+                        length_bytes: Some(3),
+                        length_tokens: Some(3),
+                    },
+                    weight: None,
+                }],
+                score: None,
+                explanation: Some(PairExplanation {
+                    shared_hashes: 1,
+                    rarest_hash_projects: 2,
+                    project_1_coverage: 1.0 / 3.0,
+                    project_2_coverage: 1.0 / 3.0,
+                    project_1_unmatched_tokens: 6,
+                    project_2_unmatched_tokens: 6,
+                    more_unique_content: Some(MoreUniqueContent::Same),
+                    nearly_filtered_by: Vec::new(),
+                    z_score: None,
+                }),
+                earlier_submission: None,
+                review: None,
             }]
         );
     }
 
     #[test]
-    fn limited_relative_offsets() {
-        let noise = 8;
-        let guarantee = 12;
-        let max_token_offset = 4;
+    fn common_hashes_min_projects() {
+        // "aaa" occurs in 3 projects and "ccc" in 2
         let files = vec![
-            File {
-                project: "Project 1".into(),
-                path: "File 1".into(),
-                // The 2nd r1 has an offset of 14
-                contents: "mov r1, sp\nfoo\nbar\nsub r0, r2, r0\nadd r0, r1, r2".to_owned(),
-            },
-            File {
-                project: "Project 2".into(),
-                path: "File 2".into(),
-                // The 2nd r1 has an offset of 12 (different from File 1!)
-                contents: "baz\nwaldo\nmov r1, sp\nsub r0, r2, r0\nadd r0, r1, r2".to_owned(),
-            },
+            File::new("Project 1".into(), "File 1".into(), "aaabbbccc".to_owned()),
+            File::new("Project 2".into(), "File 2".into(), "cccxyzaaa".to_owned()),
+            File::new("Project 3".into(), "File 3".into(), "aaa".to_owned()),
+            File::new("Project 4".into(), "File 4".into(), "111".to_owned()),
         ];
-        let (project_pairs, warnings) = detect_plagiarism(
-            noise,
-            guarantee,
-            max_token_offset,
-            TokenizingStrategy::Relative,
-            true,
-            true,
-            0,
-            0.0,
-            &files,
-            &[],
-        );
-
+        let run = |common_hash_threshold, common_hash_min_projects| {
+            let (project_pairs, _) = detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    expand_matches: false,
+                    common_hash_threshold,
+                    common_hash_min_projects,
+                    ..Default::default()
+                },
+                &files,
+                &[],
+            );
+            project_pairs
+                .iter()
+                .flat_map(|pp| &pp.matches)
+                .map(|m| m.project_1_location.span.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(0.0, 3), vec![6..9]);
+        assert!(run(0.5, 0).is_empty());
+        // Both thresholds must be exceeded
+        assert_eq!(run(0.5, 3), vec![6..9]);
+        assert_eq!(run(0.9, 2), vec![0..3, 6..9, 0..3, 6..9]);
+    }
+
+    #[test]
+    fn auto_common_hashes() {
+        // "zzz" occurs in every project, while "aaa" only occurs in 2
+        let mut files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "aaa1zzz".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "aaa2zzz".to_owned()),
+        ];
+        for i in 3..=6 {
+            let project = format!("P{i}");
+            files.push(File::new(
+                project.clone().into(),
+                format!("{project}/file.txt").into(),
+                format!("{i}zzz"),
+            ));
+        }
+        let (project_pairs, warnings, diagnostics) = detect_plagiarism_with_diagnostics(
+            &DetectionOptions {
+                noise_threshold: 3,
+                guarantee_threshold: 3,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                auto_common_hash_threshold: true,
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+
+        assert!(warnings.is_empty());
+        assert_eq!(diagnostics.auto_common_hash_min_projects, Some(3));
+        let pair_names = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.project2.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(pair_names, vec![("P1", "P2")]);
+    }
+
+    #[test]
+    fn stop_list() {
+        let files = vec![
+            File::new("Project 1".into(), "File 1".into(), "aaabbbccc".to_owned()),
+            File::new("Project 2".into(), "File 2".into(), "cccxyzaaa".to_owned()),
+        ];
+        let (project_pairs, warnings) = detect_plagiarism(
+            &DetectionOptions {
+                noise_threshold: 3,
+                guarantee_threshold: 3,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                stop_list: vec!["aaa".to_owned(), "c".to_owned()],
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+
+        assert_eq!(
+            warnings,
+            vec![Warning {
+                file: None,
+                message: "Stop-list entry 2 is ignored. File could not be fingerprinted because it contains 1 tokens, which is less than the noise threshold of 3.".to_owned(),
+                warn_type: WarningType::Args,
+                code: WarningCode::ShortStopListEntry,
+            }]
+        );
+        let spans = project_pairs
+            .iter()
+            .flat_map(|pp| &pp.matches)
+            .map(|m| m.project_1_location.span.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![6..9]);
+    }
+
+    #[test]
+    fn lsh_pre_screening() {
+        // P1 and P2 are identical, while P3 only shares "abc" with them
+        let files = vec![
+            File::new(
+                "P1".into(),
+                "P1/file.txt".into(),
+                "abcdefghijklmnop".to_owned(),
+            ),
+            File::new(
+                "P2".into(),
+                "P2/file.txt".into(),
+                "abcdefghijklmnop".to_owned(),
+            ),
+            File::new(
+                "P3".into(),
+                "P3/file.txt".into(),
+                "abc0123456789".to_owned(),
+            ),
+        ];
+        let run = |lsh_bands| {
+            let (project_pairs, _, diagnostics) = detect_plagiarism_with_diagnostics(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    lsh_bands,
+                    lsh_rows: 8,
+                    ..Default::default()
+                },
+                &files,
+                &[],
+            );
+            let pair_names = project_pairs
+                .iter()
+                .map(|pp| (pp.project1.to_owned(), pp.project2.to_owned()))
+                .collect::<Vec<_>>();
+            (pair_names, diagnostics.lsh_candidate_pairs)
+        };
+        let pair = |p1: &str, p2: &str| (PathBuf::from(p1), PathBuf::from(p2));
+
+        assert_eq!(
+            run(0),
+            (
+                vec![pair("P1", "P2"), pair("P1", "P3"), pair("P2", "P3")],
+                None
+            )
+        );
+        assert_eq!(run(1), (vec![pair("P1", "P2")], Some(1)));
+    }
+
+    #[test]
+    fn only_listed_pairs_and_sections_are_compared() {
+        let files = ["P1", "P2", "P3"].map(|p| {
+            File::new(
+                p.into(),
+                format!("{p}/file.txt").into(),
+                "abcdef".to_owned(),
+            )
+        });
+        let run = |options: DetectionOptions| {
+            let (project_pairs, _) = detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    ..options
+                },
+                &files,
+                &[],
+            );
+            project_pairs
+                .into_iter()
+                .map(|pp| (pp.project1, pp.project2))
+                .collect::<Vec<_>>()
+        };
+        let pair = |p1: &str, p2: &str| (PathBuf::from(p1), PathBuf::from(p2));
+
+        // Listed pairs may be in either order
+        let only_pairs = BTreeSet::from([pair("P3", "P1")]);
+        assert_eq!(
+            run(DetectionOptions {
+                only_pairs: Some(only_pairs),
+                ..Default::default()
+            }),
+            vec![pair("P1", "P3")]
+        );
+
+        let project_sections = BTreeMap::from([
+            ("P1".into(), "L01".to_owned()),
+            ("P2".into(), "L01".to_owned()),
+            ("P3".into(), "L02".to_owned()),
+        ]);
+        assert_eq!(
+            run(DetectionOptions {
+                compare: CompareMode::Within,
+                project_sections: project_sections.clone(),
+                ..Default::default()
+            }),
+            vec![pair("P1", "P2")]
+        );
+        assert_eq!(
+            run(DetectionOptions {
+                compare: CompareMode::Across,
+                project_sections,
+                ..Default::default()
+            }),
+            vec![pair("P1", "P3"), pair("P2", "P3")]
+        );
+    }
+
+    #[test]
+    fn short_matches_are_discarded_after_expansion() {
+        // "xyz" is a match of 3 bytes, while "abcdef" is found as several overlapping matches expanded into one of 6
+        let files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdef-xyz".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "xyz+abcdef".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "xyz".to_owned()),
+        ];
+        let run = |min_report_length| {
+            let (project_pairs, _) = detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    min_report_length,
+                    ..Default::default()
+                },
+                &files,
+                &[],
+            );
+            project_pairs
+                .iter()
+                .map(|p| {
+                    let lengths = p
+                        .matches
+                        .iter()
+                        .map(|m| m.project_1_location.length_tokens.unwrap())
+                        .collect::<Vec<_>>();
+                    (p.project1.to_str().unwrap().to_owned(), lengths)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            run(0),
+            vec![
+                ("P1".to_owned(), vec![6, 3]),
+                ("P1".to_owned(), vec![3]),
+                ("P2".to_owned(), vec![3])
+            ]
+        );
+        // The pairs left without matches are not reported
+        assert_eq!(run(4), vec![("P1".to_owned(), vec![6])]);
+    }
+
+    #[test]
+    fn bloom_prefilter() {
+        // P1 and P2 share 6 substrings of length 3, while P3 only shares 1 with each of them
+        let files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "abc0123".to_owned()),
+        ];
+        let (project_pairs, _, diagnostics) = detect_plagiarism_with_diagnostics(
+            &DetectionOptions {
+                noise_threshold: 3,
+                guarantee_threshold: 3,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                bloom_min_shared_hashes: 3,
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+
+        let pair_names = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.project2.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(pair_names, vec![("P1", "P2")]);
+        assert_eq!(diagnostics.bloom_candidate_pairs, Some(1));
+    }
+
+    #[test]
+    fn capped_matches() {
+        let file = PathArena::default().intern(Path::new("f"));
+        let location = |start: usize| (file, start..start + 1);
+        let occurrences_1 = vec![location(4), location(0), location(2)];
+        let occurrences_2 = vec![location(10), location(20)];
+        let spans = |max_pairs| {
+            let (pairs, capped) = pair_occurrences(&occurrences_1, &occurrences_2, max_pairs);
+            let spans = pairs
+                .into_iter()
+                .map(|((_, span_1), (_, span_2))| (span_1.start, span_2.start))
+                .collect::<Vec<_>>();
+            (spans, capped)
+        };
+
+        assert_eq!(spans(0).0.len(), 6);
+        assert_eq!(spans(6), (spans(0).0, false));
+        assert_eq!(spans(5), (vec![(0, 10), (2, 10), (4, 20)], true));
+        assert_eq!(spans(2), (vec![(0, 10), (2, 10)], true));
+
+        // The capping is reported once per pair of projects
+        let files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "ababab".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abab".to_owned()),
+        ];
+        let (project_pairs, warnings) = detect_plagiarism(
+            &DetectionOptions {
+                noise_threshold: 2,
+                guarantee_threshold: 2,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                max_matches_per_hash: 4,
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+        // "ab" occurs 3 times in P1 and 2 times in P2, and is capped to 3 matches. "ba" has 2 matches.
+        assert_eq!(project_pairs[0].matches.len(), 5);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.code, w.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(WarningCode::CappedMatches, "1 code snippet(s) occur too many times in both \"P1\" and \"P2\". Only the occurrences at the nearest positions were matched, so some matches may be missing.")]
+        );
+    }
+
+    #[test]
+    fn lex_errors() {
+        let files = vec![
+            File::new(
+                "P1".into(),
+                "P1/main.s".into(),
+                "add r0, r1\nmov ` r2 ``\n".to_owned(),
+            ),
+            File::new("P2".into(), "P2/main.s".into(), "add r0, r1\n".to_owned()),
+        ];
+        let options = |strict_lex| DetectionOptions {
+            noise_threshold: 1,
+            guarantee_threshold: 1,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Naive,
+            strict_lex,
+            ..Default::default()
+        };
+
+        let (pairs, warnings) = detect_plagiarism(&options(false), &files, &[]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(
+            warnings
+                .iter()
+                .map(|w| (w.file.as_deref(), w.code, w.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(Some(Path::new("P1/main.s")), WarningCode::LexErrors, "2 sequence(s) of characters could not be tokenized (line 2, column 5; line 2, column 10). These characters are still compared with other files, which may produce spurious matches or hide real ones.")]
+        );
+
+        let (pairs, warnings) = detect_plagiarism(&options(true), &files, &[]);
+        assert!(pairs.is_empty());
+        assert!(warnings[0]
+            .message
+            .ends_with("The file is excluded from the analysis."));
+    }
+
+    #[test]
+    fn truncated_hashes() {
+        let files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "xbcdefgy".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "zyxwvuts".to_owned()),
+        ];
+        let options = |hash_width| DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            hash_width,
+            ..Default::default()
+        };
+
+        let (pairs_64, warnings_64) = detect_plagiarism(&options(HashWidth::Bits64), &files, &[]);
+        let (pairs_32, warnings_32) = detect_plagiarism(&options(HashWidth::Bits32), &files, &[]);
+
+        assert_eq!(pairs_32, pairs_64);
+        assert_eq!(pairs_32.len(), 1);
+        assert!(warnings_64.is_empty());
+        assert_eq!(
+            warnings_32
+                .iter()
+                .map(|w| (w.code, w.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(WarningCode::TruncatedHashes, "Fingerprint hashes are truncated to 32 bits to save memory. With 14 distinct code snippets, about 0 pair(s) of unrelated snippets are expected to share a hash, which may produce false matches.")]
+        );
+    }
+
+    #[test]
+    fn idf_scoring() {
+        // "xxx" and "yyy" occur in 3 of the 4 projects, while "zzz" and "www" only occur in 2
+        let files = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "xxx1yyy".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "xxx2yyy".to_owned()),
+            File::new(
+                "P3".into(),
+                "P3/file.txt".into(),
+                "xxx3yyy4zzz5www".to_owned(),
+            ),
+            File::new("P4".into(), "P4/file.txt".into(), "zzz6www".to_owned()),
+        ];
+        let run = |scoring| {
+            let (project_pairs, _) = detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    expand_matches: false,
+                    scoring,
+                    ..Default::default()
+                },
+                &files,
+                &[],
+            );
+            project_pairs
+        };
+
+        // Every pair has two matches, so they are ranked by name
+        let project_pairs = run(ScoringMode::Matches);
+        let pair_names = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.project2.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pair_names,
+            vec![("P1", "P2"), ("P1", "P3"), ("P2", "P3"), ("P3", "P4")]
+        );
+        assert!(project_pairs.iter().all(|pp| pp.score.is_none()));
+
+        // The pair sharing the rarer code comes first
+        let project_pairs = run(ScoringMode::Idf);
+        let pair_scores = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.score.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pair_scores,
+            vec![
+                ("P3", 2.0 * scoring::idf(4, 2)),
+                ("P1", 2.0 * scoring::idf(4, 3)),
+                ("P1", 2.0 * scoring::idf(4, 3)),
+                ("P2", 2.0 * scoring::idf(4, 3)),
+            ]
+        );
+        // Each match is weighted by the rarity of its hash
+        assert!(project_pairs[0]
+            .matches
+            .iter()
+            .all(|m| m.weight == Some(scoring::idf(4, 2))));
+    }
+
+    #[test]
+    fn hash_frequency_diagnostics() {
+        // "aaa" occurs in 3 projects, "ccc" in 2, and every other substring of length 3 in 1
+        let files = vec![
+            File::new("Project 1".into(), "File 1".into(), "aaabbbccc".to_owned()),
+            File::new("Project 2".into(), "File 2".into(), "cccxyzaaa".to_owned()),
+            File::new("Project 3".into(), "File 3".into(), "aaa".to_owned()),
+        ];
+        let (_, _, diagnostics) = detect_plagiarism_with_diagnostics(
+            &DetectionOptions {
+                noise_threshold: 3,
+                guarantee_threshold: 3,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: false,
+                // Common code is only removed after the frequencies are counted
+                common_hash_min_projects: 2,
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+
+        assert_eq!(
+            diagnostics.hash_frequencies,
+            HashFrequencies {
+                num_projects: 3,
+                num_hashes_by_projects: BTreeMap::from([(1, 10), (2, 1), (3, 1)]),
+            }
+        );
+    }
+
+    #[test]
+    fn limited_relative_offsets() {
+        let noise = 8;
+        let guarantee = 12;
+        let max_token_offset = 4;
+        let files = vec![
+            File {
+                project: "Project 1".into(),
+                path: "File 1".into(),
+                // The 2nd r1 has an offset of 14
+                contents: "mov r1, sp\nfoo\nbar\nsub r0, r2, r0\nadd r0, r1, r2".to_owned(),
+            },
+            File {
+                project: "Project 2".into(),
+                path: "File 2".into(),
+                // The 2nd r1 has an offset of 12 (different from File 1!)
+                contents: "baz\nwaldo\nmov r1, sp\nsub r0, r2, r0\nadd r0, r1, r2".to_owned(),
+            },
+        ];
+        let (project_pairs, warnings) = detect_plagiarism(
+            &DetectionOptions {
+                noise_threshold: noise,
+                guarantee_threshold: guarantee,
+                max_token_offset,
+                tokenizing_strategy: TokenizingStrategy::Relative,
+                ignore_whitespace: true,
+                expand_matches: true,
+                min_matches: 0,
+                common_hash_threshold: 0.0,
+                ..Default::default()
+            },
+            &files,
+            &[],
+        );
+
         assert!(warnings.is_empty());
         assert_eq!(
             project_pairs,
@@ -709,16 +2566,405 @@ mod tests {
                 project1: "Project 1".into(),
                 project2: "Project 2".into(),
                 matches: vec![Match {
+                    id: None,
                     project_1_location: Location {
                         file: "File 1".into(),
-                        span: 19..48
+                        span: 19..48,
+                        length_bytes: Some(29),
+                        length_tokens: Some(12),
                     },
                     project_2_location: Location {
                         file: "File 2".into(),
-                        span: 21..50
-                    }
-                }]
+                        span: 21..50,
+                        length_bytes: Some(29),
+                        length_tokens: Some(12),
+                    },
+                    weight: None,
+                }],
+                score: None,
+                explanation: Some(PairExplanation {
+                    shared_hashes: 5,
+                    rarest_hash_projects: 2,
+                    project_1_coverage: 2.0 / 3.0,
+                    project_2_coverage: 2.0 / 3.0,
+                    project_1_unmatched_tokens: 6,
+                    project_2_unmatched_tokens: 6,
+                    more_unique_content: Some(MoreUniqueContent::Same),
+                    nearly_filtered_by: Vec::new(),
+                    z_score: None,
+                }),
+                earlier_submission: None,
+                review: None,
             }]
         )
     }
+
+    #[test]
+    fn deterministic_output() {
+        // Every pair has the same number of matches and every file produces a warning, so the order of the output
+        // depends only on the tie-breaking rules
+        let documents = ["P3", "P1", "P4", "P2"]
+            .into_iter()
+            .flat_map(|project| {
+                [
+                    File::new(
+                        project.into(),
+                        format!("{project}/long.txt").into(),
+                        "abcd".to_owned(),
+                    ),
+                    File::new(
+                        project.into(),
+                        format!("{project}/short.txt").into(),
+                        "a".to_owned(),
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let run = || {
+            detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 4,
+                    guarantee_threshold: 4,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    expand_matches: false,
+                    min_matches: 0,
+                    common_hash_threshold: 0.0,
+                    ..Default::default()
+                },
+                &documents,
+                &[],
+            )
+        };
+        let (project_pairs, warnings) = run();
+
+        let pair_names = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.project2.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pair_names,
+            vec![
+                ("P1", "P2"),
+                ("P1", "P3"),
+                ("P1", "P4"),
+                ("P2", "P3"),
+                ("P2", "P4"),
+                ("P3", "P4")
+            ]
+        );
+        let warning_files = warnings
+            .iter()
+            .map(|w| w.file.as_ref().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warning_files,
+            vec![
+                "P1/short.txt",
+                "P2/short.txt",
+                "P3/short.txt",
+                "P4/short.txt"
+            ]
+        );
+
+        // Each run builds new hash maps, which are iterated in a different order
+        for _ in 0..10 {
+            let (rerun_project_pairs, rerun_warnings) = run();
+            assert_eq!(rerun_project_pairs, project_pairs);
+            assert_eq!(rerun_warnings, warnings);
+        }
+    }
+
+    #[test]
+    fn lexed_documents_are_used() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+        ];
+        let options = DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let lexed_documents = Arc::new(LexedDocuments::default());
+        for d in documents.iter() {
+            lexed_documents.insert(d, lex_document(&options, d));
+        }
+        let prelexed_options = DetectionOptions {
+            lexed_documents: Some(lexed_documents.clone()),
+            ..options.clone()
+        };
+
+        let results = detect_plagiarism(&prelexed_options, &documents, &[]);
+        assert!(lexed_documents.is_empty());
+        assert_eq!(results, detect_plagiarism(&options, &documents, &[]));
+        assert_eq!(results.0.len(), 1);
+    }
+
+    #[test]
+    fn interrupted_analysis() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcd".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcd".to_owned()),
+        ];
+        let (project_pairs, warnings) = detect_plagiarism(
+            &DetectionOptions {
+                noise_threshold: 4,
+                guarantee_threshold: 4,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                interrupt: Some(Arc::new(AtomicBool::new(true))),
+                ..Default::default()
+            },
+            &documents,
+            &[],
+        );
+
+        assert!(warnings.is_empty());
+        assert!(project_pairs.is_empty());
+    }
+
+    #[test]
+    fn analysis_stops_at_the_memory_limit() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdef".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdef".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "abcdef".to_owned()),
+        ];
+        let analyze = |max_memory| {
+            detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    max_memory,
+                    ..Default::default()
+                },
+                &documents,
+                &[],
+            )
+        };
+
+        let (project_pairs, warnings) = analyze(None);
+        assert_eq!(project_pairs.len(), 3);
+        assert!(warnings.is_empty());
+
+        // The database of 4 hashes with 3 locations each does not fit
+        let (project_pairs, warnings) = analyze(Some(100));
+        assert!(project_pairs.is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, WarningCode::MemoryLimit);
+        assert!(warnings[0].message.contains("building the hash database"));
+
+        // The database fits, but not the matches
+        let (_, warnings) = analyze(Some(500));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].message.contains("finding the matches"));
+    }
+
+    #[test]
+    fn custom_fingerprint_algorithm() {
+        /// Keeps only the first hash of each document.
+        #[derive(Debug)]
+        struct FirstHash;
+
+        impl FingerprintAlgorithm for FirstHash {
+            fn name(&self) -> String {
+                "first-hash".to_owned()
+            }
+
+            fn fingerprint(
+                &self,
+                noise_threshold: usize,
+                guarantee_threshold: usize,
+                max_token_offset: usize,
+                tokens: &[(u64, Range<usize>)],
+            ) -> Result<Fingerprint, FingerprintError> {
+                let mut f = Winnowing::default().fingerprint(
+                    noise_threshold,
+                    guarantee_threshold,
+                    max_token_offset,
+                    tokens,
+                )?;
+                f.spanned_hashes.truncate(1);
+                Ok(f)
+            }
+        }
+
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+        ];
+        let options = |fingerprint_algorithm: Arc<dyn FingerprintAlgorithm>| DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            fingerprint_algorithm,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            expand_matches: false,
+            ..Default::default()
+        };
+
+        let (project_pairs, _) =
+            detect_plagiarism(&options(Arc::new(Winnowing::default())), &documents, &[]);
+        assert_eq!(project_pairs[0].matches.len(), 5);
+        let (project_pairs, _) = detect_plagiarism(&options(Arc::new(FirstHash)), &documents, &[]);
+        assert_eq!(project_pairs[0].matches.len(), 1);
+    }
+
+    #[test]
+    fn project_sizes_are_reported() {
+        let documents = vec![
+            File::new("P1".into(), "P1/a.txt".into(), "abcd".to_owned()),
+            File::new("P1".into(), "P1/b.txt".into(), "efgh".to_owned()),
+            File::new("P2".into(), "P2/a.txt".into(), "xyz".to_owned()),
+        ];
+        let (_, _, diagnostics) = detect_plagiarism_with_diagnostics(
+            &DetectionOptions {
+                noise_threshold: 4,
+                guarantee_threshold: 4,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                ..Default::default()
+            },
+            &documents,
+            &[],
+        );
+
+        let stats = |project: &str, num_files, num_tokens| ProjectStats {
+            project: project.into(),
+            num_files,
+            num_tokens,
+            starter_code_tokens: 0,
+            metadata: None,
+        };
+        assert_eq!(
+            diagnostics.projects,
+            vec![stats("P1", 2, 8), stats("P2", 1, 3)]
+        );
+    }
+
+    #[test]
+    fn starter_code_tokens_are_counted() {
+        let documents = vec![
+            File::new("P1".into(), "P1/a.txt".into(), "starterabcd".to_owned()),
+            File::new("P1".into(), "P1/b.txt".into(), "efgh".to_owned()),
+            File::new("P2".into(), "P2/a.txt".into(), "xyzw".to_owned()),
+        ];
+        let ignored_documents = vec![File::new(
+            "starter".into(),
+            "starter/a.txt".into(),
+            "starter".to_owned(),
+        )];
+        let (_, _, diagnostics) = detect_plagiarism_with_diagnostics(
+            &DetectionOptions {
+                noise_threshold: 4,
+                guarantee_threshold: 4,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                ..Default::default()
+            },
+            &documents,
+            &ignored_documents,
+        );
+
+        let [p1, p2] = diagnostics.projects.as_slice() else {
+            panic!("expected two projects");
+        };
+        assert!(p1.starter_code_tokens > 0);
+        assert_eq!(p1.num_tokens + p1.starter_code_tokens, 15);
+        assert_eq!((p2.num_tokens, p2.starter_code_tokens), (4, 0));
+    }
+
+    #[test]
+    fn edited_starter_code_is_removed() {
+        // Both projects edited the starter code the same way
+        let documents = vec![
+            File::new(
+                "P1".into(),
+                "P1/a.txt".into(),
+                "abcdefghijkXYZopqrstuvwx".to_owned(),
+            ),
+            File::new(
+                "P2".into(),
+                "P2/a.txt".into(),
+                "abcdefghijkXYZopqrstuvwx".to_owned(),
+            ),
+        ];
+        let ignored_documents = vec![File::new(
+            "starter".into(),
+            "starter/a.txt".into(),
+            "abcdefghijklmnopqrstuvwx".to_owned(),
+        )];
+        let run = |starter_code_similarity| {
+            detect_plagiarism(
+                &DetectionOptions {
+                    noise_threshold: 3,
+                    guarantee_threshold: 3,
+                    max_token_offset: 0,
+                    tokenizing_strategy: TokenizingStrategy::Bytes,
+                    ignore_whitespace: false,
+                    starter_code_similarity,
+                    ..Default::default()
+                },
+                &documents,
+                &ignored_documents,
+            )
+            .0
+        };
+
+        assert_eq!(run(None).len(), 1);
+        assert_eq!(run(Some(0.95)).len(), 1);
+        assert!(run(Some(0.7)).is_empty());
+    }
+
+    #[test]
+    fn small_projects_are_excluded() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/file1.txt".into(), "abc".to_owned()),
+            File::new("P3".into(), "P3/file2.txt".into(), "d".to_owned()),
+        ];
+        let (project_pairs, warnings) = detect_plagiarism(
+            &DetectionOptions {
+                noise_threshold: 4,
+                guarantee_threshold: 4,
+                max_token_offset: 0,
+                tokenizing_strategy: TokenizingStrategy::Bytes,
+                ignore_whitespace: false,
+                expand_matches: true,
+                min_project_tokens: 5,
+                ..Default::default()
+            },
+            &documents,
+            &[],
+        );
+
+        let pair_names = project_pairs
+            .iter()
+            .map(|pp| (pp.project1.to_str().unwrap(), pp.project2.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(pair_names, vec![("P1", "P2")]);
+        assert_eq!(
+            warnings,
+            vec![Warning {
+                file: Some("P3".into()),
+                message: "Project was excluded from the analysis because it contains 4 tokens (not counting starter code), which is less than the minimum of 5.".to_owned(),
+                warn_type: WarningType::Input,
+                code: WarningCode::SmallProject,
+            }]
+        );
+    }
 }
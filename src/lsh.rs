@@ -0,0 +1,135 @@
+//! MinHash signatures and locality-sensitive hashing (LSH), used to quickly select the pairs of projects which are
+//! likely to be similar in very large corpora.
+//!
+//! Each project is summarized by a MinHash signature of its fingerprint hashes. The signature is split into bands, and
+//! two projects become a candidate pair if all the values of at least one of their bands are equal. Two projects whose
+//! sets of hashes have a Jaccard similarity of `s` become a candidate pair with a probability of
+//! `1 - (1 - s^rows)^bands`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+use itertools::Itertools;
+use rustc_hash::FxHasher;
+
+use crate::{
+    identity_hash::{IdentityHashKey, IdentityHashMap},
+    interning::PathId,
+    FileId,
+};
+
+/// Returns the pairs of projects which share at least one band of their MinHash signatures. The first project of each
+/// pair is less than the second.
+///
+/// # Panics
+///
+/// * Panics if `bands == 0` or `rows == 0`
+pub fn candidate_pairs<K: IdentityHashKey>(
+    hash_database: &IdentityHashMap<Vec<(&FileId, Range<usize>)>, K>,
+    bands: usize,
+    rows: usize,
+) -> HashSet<(PathId, PathId)> {
+    assert!(bands != 0 && rows != 0);
+
+    let signatures = minhash_signatures(hash_database, bands * rows);
+
+    // Projects whose band hashes to the same bucket are candidates
+    let mut buckets: HashMap<(usize, u64), Vec<PathId>> = HashMap::new();
+    for (&project, signature) in signatures.iter() {
+        for (band, values) in signature.chunks(rows).enumerate() {
+            let mut hasher = FxHasher::default();
+            values.hash(&mut hasher);
+            buckets
+                .entry((band, hasher.finish()))
+                .or_default()
+                .push(project);
+        }
+    }
+
+    let mut candidates = HashSet::new();
+    for projects in buckets.values() {
+        for (&project1, &project2) in projects.iter().tuple_combinations() {
+            if project1 < project2 {
+                candidates.insert((project1, project2));
+            } else {
+                candidates.insert((project2, project1));
+            }
+        }
+    }
+    candidates
+}
+
+/// Computes the MinHash signature of the set of hashes of every project. Each value of a signature is the minimum of
+/// the project's hashes after applying a different hash function to them.
+fn minhash_signatures<K: IdentityHashKey>(
+    hash_database: &IdentityHashMap<Vec<(&FileId, Range<usize>)>, K>,
+    signature_length: usize,
+) -> HashMap<PathId, Vec<u64>> {
+    let mut signatures: HashMap<PathId, Vec<u64>> = HashMap::new();
+
+    for (&hash, locations) in hash_database.iter() {
+        let hash = hash.into();
+        let permuted_hashes = (0..signature_length)
+            .map(|i| permute(hash, i))
+            .collect::<Vec<_>>();
+        for project in locations.iter().map(|(file_id, _)| file_id.project).dedup() {
+            let signature = signatures
+                .entry(project)
+                .or_insert_with(|| vec![u64::MAX; signature_length]);
+            for (value, &permuted_hash) in signature.iter_mut().zip(&permuted_hashes) {
+                *value = (*value).min(permuted_hash);
+            }
+        }
+    }
+
+    signatures
+}
+
+/// Applies the `i`th hash function to a hash. The hash functions are approximately independent random permutations.
+pub(crate) fn permute(hash: u64, i: usize) -> u64 {
+    // Finalizer of the SplitMix64 generator, with a different offset for each function
+    let mut x = hash ^ (i as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interning::PathArena;
+
+    fn hash_database<'a>(
+        projects: &[(&'a FileId, Range<u64>)],
+    ) -> IdentityHashMap<Vec<(&'a FileId, Range<usize>)>> {
+        let mut hash_database: IdentityHashMap<Vec<(&FileId, Range<usize>)>> =
+            IdentityHashMap::default();
+        for (file_id, hashes) in projects {
+            for hash in hashes.clone() {
+                hash_database.entry(hash).or_default().push((file_id, 0..1));
+            }
+        }
+        hash_database
+    }
+
+    #[test]
+    fn similar_projects_are_candidates() {
+        let mut arena = PathArena::default();
+        let [p1, p2, p3, p4] = ["P1", "P2", "P3", "P4"]
+            .map(|p| arena.intern_file(p.as_ref(), format!("{p}/a.s").as_ref()));
+        // P1 and P2 are identical, P3 is mostly the same as P1, and P4 shares nothing with the others
+        let hash_database = hash_database(&[
+            (&p1, 0..100),
+            (&p2, 0..100),
+            (&p3, 5..100),
+            (&p4, 1000..1100),
+        ]);
+
+        let candidates = candidate_pairs(&hash_database, 10, 4);
+        let [p1, p2, p3] = [p1, p2, p3].map(|file_id| file_id.project);
+        assert_eq!(candidates, HashSet::from([(p1, p2), (p1, p3), (p2, p3)]));
+    }
+}
@@ -1,31 +1,208 @@
 use anyhow::Context;
-use clap::Parser;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use itertools::Itertools;
+use serde::Deserialize;
 use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
     fs,
+    io::{self, BufWriter, Read, Write},
+    mem,
+    num::NonZeroUsize,
+    ops::ControlFlow,
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
 };
 use walkdir::WalkDir;
 
 use fungus_cli::{
-    detect_plagiarism,
-    lexing::TokenizingStrategy,
-    output::{Output, Warning, WarningType},
-    File,
+    allowlist::{self, AllowedPairs},
+    annotations::Annotation,
+    attempts::{self, AttemptPolicy},
+    composite::{self, Components, ScoreWeights, Signal},
+    detect_plagiarism, detect_plagiarism_with_observer, encoding, external_scores, fingerprint,
+    formatting, heuristics,
+    identity_hash::HashWidth,
+    integrity, lex_document,
+    lexing::{HashDialect, KeySymbols, TokenTransform, TokenizingStrategy},
+    manifest::ManifestEntry,
+    matrix::SimilarityMatrix,
+    memory, online_solutions,
+    output::{
+        CompositePair, ExternalScorePair, IntegrityAlgorithm, MatchOrder, Metadata, NdjsonWriter,
+        Output, PairOrder, PathStyle, ProjectPair, Submission, TimestampSource, Warning,
+        WarningCode, WarningType,
+    },
+    pipeline,
+    project_metadata::{ProjectMetadata, PROJECT_METADATA_FILE},
+    report,
+    reproducibility::{self, RunManifest},
+    routines::{self, Granularity},
+    scoring::ScoringMode,
+    self_test,
+    snapshots::{self, Snapshot},
+    starter_diff, stop_list, string_literals, stylometry,
+    submissions::{self, SubmissionTimes},
+    CompareMode, DetectionOptions, File, LexedDocuments, PairOverride, Stage, TieBreaking,
+    Winnowing,
 };
 
+mod fetch;
+mod indexing;
+mod ndjson;
+mod timings;
+mod view;
+
+use ndjson::{NdjsonStream, PairEdits};
+use timings::Timings;
+
 /// A simple copy detection tool for the ARMv7 assembly language.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Directory in which to search for code.
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Directory in which to search for code. With --manifest, the projects are named relative to this directory, which
+    /// defaults to the directory of the manifest.
+    #[arg(required_unless_present = "manifest")]
+    root: Option<PathBuf>,
+    #[command(flatten)]
+    detect: DetectArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Clones the student repositories of a GitHub Classroom assignment into a projects directory.
+    FetchClassroom(Box<fetch::classroom::FetchClassroomArgs>),
+    /// Downloads the submissions to a Canvas assignment into a projects directory.
+    FetchCanvas(Box<fetch::canvas::FetchCanvasArgs>),
+    /// Serves an interactive browser for an existing JSON output file on a local web server.
+    View(view::ViewArgs),
+    /// Writes a case summary for each confirmed pair of an existing JSON output file, ready to attach to a referral.
+    Cases(CasesArgs),
+    /// Maintains an index of the fingerprints of past projects, to compare new projects with them without fingerprinting
+    /// the past projects again.
+    Index(indexing::IndexArgs),
+    /// Reports which projects of an index share code with a new project, e.g. to check a single submission during a
+    /// regrade.
+    Query(indexing::QueryArgs),
+    /// Checks the integrity hash of an existing JSON output file, to detect edits made after it was generated.
+    VerifyOutput(VerifyOutputArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyOutputArgs {
+    /// JSON output file produced with --integrity-hash or --integrity-key-file, possibly compressed.
+    output_file: PathBuf,
+    /// File containing the secret key of the HMAC, which is required if the output was produced with
+    /// --integrity-key-file.
+    #[arg(long)]
+    integrity_key_file: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CasesArgs {
+    /// JSON output file produced by a previous analysis.
+    output_file: PathBuf,
+    /// Projects directory which was analyzed. The paths in the output file are relative to this directory.
+    #[arg(long)]
     root: PathBuf,
+    /// Directory in which to write the case summaries.
+    #[arg(long, default_value = "./fungus-cases")]
+    dir: PathBuf,
+    /// YAML or JSON file of review decisions, which replace those recorded in the output file. The project names are
+    /// relative to the root.
+    #[arg(long)]
+    annotations: Option<PathBuf>,
+    /// Text or Markdown template of the case summaries. Defaults to a built-in Markdown template.
+    #[arg(long)]
+    template: Option<PathBuf>,
+    /// Number of lines of context to show before and after the matched code.
+    #[arg(long, default_value_t = 0)]
+    context: usize,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq, Eq)]
+enum OutputFormat {
+    /// Machine-readable JSON, as documented in the README.
+    Json,
+    /// Newline-delimited JSON with one record per line (e.g., per warning and per project pair or match), where the
+    /// project pairs are written as soon as they are scored.
+    Ndjson,
+    /// Self-contained HTML report embedding all matched code, which can be viewed without the original submissions.
+    Html,
+    /// Printable report listing the ranked project pairs and their highlighted matched code.
+    Pdf,
+    /// GitHub Actions warning annotations on both sides of every match, printed to standard output instead of being
+    /// written to the output file.
+    Github,
+    /// GitLab Code Quality report with an issue on both sides of every match, for display in merge requests.
+    Gitlab,
+    /// CSV with one clone pair per match, in the format used by BigCloneBench and clone detector benchmarking tools.
+    ClonePairs,
+}
+
+/// How the files of a file list are assigned to projects.
+#[derive(Debug, Clone, Copy, clap::ValueEnum, PartialEq, Eq)]
+enum ProjectRule {
+    /// The directory directly inside the root which contains the file.
+    TopLevel,
+    /// The directory containing the file.
+    Parent,
+}
+
+/// Common code threshold given on the command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum CommonCodeThreshold {
+    /// Proportion of the projects.
+    Fraction(f64),
+    /// Chosen automatically for the projects being analyzed.
+    Auto,
+}
+
+impl FromStr for CommonCodeThreshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(CommonCodeThreshold::Auto);
+        }
+        s.parse()
+            .map(CommonCodeThreshold::Fraction)
+            .map_err(|_| format!("expected a number or \"auto\", found \"{s}\""))
+    }
+}
+
+/// Options controlling the analysis of a projects directory.
+#[derive(clap::Args, Debug)]
+pub(crate) struct DetectArgs {
     /// Output file.
     #[arg(short, long, default_value = "./fungus-output.json")]
     output_file: PathBuf,
+    /// Format of the output file. Can be one of "json", "ndjson", "html", "pdf", "github" (annotations printed to
+    /// standard output for GitHub Actions), "gitlab" (GitLab Code Quality report), or "clone-pairs" (BigCloneBench-style
+    /// clone-pair CSV).
+    #[arg(value_enum, long, default_value = "json")]
+    output_format: OutputFormat,
     /// Noise threshold. Matches whose length is less than this value will not be flagged.
     #[arg(short, long, default_value_t = 40)]
     noise: usize,
-    /// Guarantee threshold. Matches at least as long as this value are guaranteed to be flagged.
+    /// Guarantee threshold. Matches at least as long as this value are guaranteed to be flagged. Zero means the
+    /// smallest possible value (the noise threshold plus the max token offset), so that every code snippet is
+    /// fingerprinted.
     #[arg(short, long, default_value_t = 80)]
     guarantee: usize,
     /// Maximum offset for relative tokens. This argument is not applicable for
@@ -41,81 +218,1063 @@ struct Args {
     /// guarantee it will be reported.
     #[arg(long, default_value_t = 0)]
     max_token_offset: usize,
-    /// Files and directories containing starter code. Any matches with this code will be ignored.
+    /// JSON file listing the files of each project, which are read instead of searching the root for code. The file
+    /// contains an array of objects, each with the "project" containing a file and the "path" of the file (relative to
+    /// the manifest), and optionally the "display_name" under which the project is shown and the "timestamp" at which it
+    /// was submitted (see --timestamps).
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+    /// File containing a NUL-delimited list of the files to analyze (e.g., the output of `find -print0`), which are read
+    /// instead of searching the root for code. Use "-" to read the list from standard input.
+    #[arg(long, conflicts_with = "manifest")]
+    files_from: Option<PathBuf>,
+    /// How the files given with --files-from are assigned to projects. Can be one of "top-level" (the directory directly
+    /// inside the root which contains the file, as when searching the root) or "parent" (the directory containing the
+    /// file).
+    #[arg(value_enum, long, default_value = "top-level")]
+    project_rule: ProjectRule,
+    /// Files and directories containing starter code. Any matches with this code will be ignored. A relative path which
+    /// does not exist (e.g., "lib/provided.s") is instead resolved within every project directory, and the files it
+    /// names are left out of the analysis.
     #[arg(short, long)]
     ignore: Vec<PathBuf>,
-    /// Tokenizing strategy to use. Can be one of "bytes", "naive", or "relative".
-    #[arg(value_enum, short, long, default_value = "relative")]
+    /// Also remove the regions of the projects in which at least this proportion of the tokens match starter code, so
+    /// that starter code which was lightly edited is not reported either. The regions are four times as long as the
+    /// noise threshold. The value must be in the range (0, 1].
+    #[arg(long, value_name = "PROPORTION", requires = "ignore")]
+    starter_code_similarity: Option<f64>,
+    /// Instead of comparing the projects with each other, report how much of the starter code each project contains and
+    /// how much code it adds (e.g., to catch near-empty submissions before analyzing the pairs).
+    #[arg(long, default_value_t = false, requires = "ignore")]
+    starter_code_diff: bool,
+    /// Write the token sequence of every analyzed file to a JSON file in this directory (e.g., to train or evaluate other
+    /// similarity models on the same tokenization).
+    #[arg(long, value_name = "DIR")]
+    dump_tokens: Option<PathBuf>,
+    /// Whether to include the text of each token in the files written by --dump-tokens.
+    #[arg(long, default_value_t = false, requires = "dump_tokens")]
+    dump_raw_tokens: bool,
+    /// Entries of --ignore which are resolved within every project directory.
+    #[arg(skip)]
+    ignore_within_projects: Vec<PathBuf>,
+    /// Only analyze the files modified after this time, given in seconds since the Unix epoch, or as a UTC date
+    /// (YYYY-MM-DD) or date and time (YYYY-MM-DDTHH:MM:SS).
+    #[arg(long, value_parser = submissions::parse_timestamp, conflicts_with = "manifest")]
+    modified_after: Option<i64>,
+    /// Only analyze the files modified before this time, in the same format as --modified-after.
+    #[arg(long, value_parser = submissions::parse_timestamp, conflicts_with = "manifest")]
+    modified_before: Option<i64>,
+    /// Which attempts to analyze when projects contain several attempt subdirectories (e.g., "attempt1" and "attempt2").
+    /// Can be one of "all", "latest-by-name" (the attempt with the highest number), or "latest-by-mtime" (the attempt
+    /// containing the most recently modified file), so that a student is not matched against their own earlier attempt.
+    #[arg(value_enum, long, default_value = "all")]
+    attempts: AttemptPolicy,
+    /// Maximum depth of the files read when searching the root, relative to the directory of their project (e.g., 1 for
+    /// the files directly inside it). Deeper directories are skipped with a warning.
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Maximum number of files read from each project when searching the root. The remaining files of a project are
+    /// skipped with a warning.
+    #[arg(long)]
+    max_files_per_project: Option<usize>,
+    /// Maximum number of files read from all projects when searching the root. The remaining projects are skipped with a
+    /// warning.
+    #[arg(long)]
+    max_total_files: Option<usize>,
+    /// Tokenizing strategy to use. Can be one of "bytes", "naive", "relative", "comments" (only compare the words of
+    /// the comments, which are otherwise ignored), or "auto" (use "relative" for the assembly files and "bytes" for the
+    /// files in other languages, detected from their extension or contents).
+    #[arg(value_enum, short, long, default_value = "auto")]
     tokenizing_strategy: TokenizingStrategy,
     /// Whether to ignore comments, whitespace, and newlines while tokenizing. This is only supported by the "naive" and
-    /// "relative" tokenizing strategies.
+    /// "relative" tokenizing strategies, and has no effect with the "comments" tokenizing strategy.
     #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
     ignore_whitespace: bool,
+    /// Comma-separated list of transforms applied to the tokens, in order, after whitespace and comments are removed
+    /// (if --ignore-whitespace is set). Can include "strip-whitespace", "strip-comments", "normalize-registers" (so that
+    /// code which only differs by its choice of registers matches), "abstract-literals" (so that code which only differs
+    /// by its constants matches), "bucket-offsets" (so that small insertions between two uses of a symbol do not change
+    /// its relative token, which is only supported by the "relative" tokenizing strategy), and "canonical-statements"
+    /// (so that statements separated by `;` on one line produce the same tokens as statements on separate lines, which
+    /// is applied first). This is only supported by the "naive" and "relative" tokenizing strategies.
+    #[arg(value_enum, long = "transform", value_delimiter = ',')]
+    transforms: Vec<TokenTransform>,
+    /// Dictionary of the instructions recognized by the "relative" tokenizing strategy, either "armv7" for the built-in
+    /// ARMv7 instructions or a file containing one instruction per line. By default, the first non-label symbol of every
+    /// statement is assumed to be an instruction, which misclassifies operands on continuation lines. Directives (i.e.,
+    /// symbols starting with a period) are always recognized.
+    #[arg(long)]
+    key_symbols: Option<String>,
+    /// Meaning of the `#` character. Can be one of "immediate" (`#` prefixes immediates, as in `mov r0, #1`),
+    /// "comment" (`#` starts a line comment, like `@`), or "auto" (`#` starts a line comment at the start of a line or
+    /// when surrounded by whitespace, and prefixes an immediate otherwise). This is only supported by the "naive" and
+    /// "relative" tokenizing strategies.
+    #[arg(value_enum, long, default_value = "immediate")]
+    hash_dialect: HashDialect,
+    /// Which hash winnowing selects when the minimum hash occurs more than once in a window. Can be one of "leftmost"
+    /// (the first occurrence, which is not selected again while it remains the minimum) or "rightmost" (the last
+    /// occurrence, as in the original winnowing paper, which selects more hashes in repetitive code). The rule is
+    /// reported in the metadata of the output.
+    #[arg(value_enum, long, default_value = "leftmost")]
+    tie_breaking: TieBreaking,
+    /// Whether to exclude the files containing characters which could not be tokenized from the analysis. Either way, a
+    /// warning listing the first few locations of such characters is issued for each file.
+    #[arg(long, default_value_t = false)]
+    strict_lex: bool,
+    /// Whether to concatenate the files of each project (in the order of their paths) into a single stream of tokens,
+    /// so that code split across several files of a project still matches. The matches are still reported in the
+    /// original files, and a match spanning several files is split into one match per file.
+    #[arg(long, default_value_t = false)]
+    stitch_files: bool,
+    /// Unit of code which is matched. Can be one of "file" or "function". With "function", every file is split into
+    /// routines at its labels, matches never span two routines, and the output lists which routines of each pair of
+    /// projects correspond.
+    #[arg(value_enum, long, default_value = "file")]
+    granularity: Granularity,
     /// Whether to expand matches as much as possible before reporting them.
     #[arg(short, long, default_value_t = true, action = clap::ArgAction::Set)]
     expand_matches: bool,
+    /// How paths are represented in the output. Can be one of "relative" (relative to the root, failing for paths outside
+    /// of it), "absolute", or "auto" (relative to the root where possible and absolute otherwise).
+    #[arg(value_enum, long, default_value = "auto")]
+    path_style: PathStyle,
+    /// Order of the project pairs in the output. Can be one of "score" (from the highest to the lowest score, then by
+    /// name) or "name" (by the names of the two projects).
+    #[arg(value_enum, long, default_value = "score")]
+    pair_order: PairOrder,
+    /// Order of the matches within each project pair. Can be one of "location" (by location in the first project, then
+    /// in the second), "length" (from the longest to the shortest, then by location), or "file-pair" (by the pair of
+    /// files they are in, then by location).
+    #[arg(value_enum, long, default_value = "location")]
+    match_order: MatchOrder,
     /// Whether the JSON output should be pretty-printed.
     #[arg(short, long, default_value_t = false)]
     pretty: bool,
+    /// Whether to write one NDJSON record per match rather than per project pair.
+    #[arg(long, default_value_t = false)]
+    ndjson_per_match: bool,
+    /// Whether to compress the JSON output using gzip.
+    #[arg(long, default_value_t = false)]
+    compress: bool,
     /// Similarity threshold. Pairs of projects with fewer than this number of matches will not be shown.
     #[arg(short, long, default_value_t = 0)]
     min_matches: usize,
+    /// Only report the pairs of projects whose score is in the given top percentage of the scores of every compared
+    /// pair (e.g., 1 for the top percentile), rather than using an absolute threshold.
+    #[arg(long, value_name = "PERCENT", conflicts_with = "min_matches")]
+    flag_top_percentile: Option<f64>,
+    /// Leave out of the output the matches with fewer than this number of tokens on either side, and the pairs left
+    /// without matches. Unlike the noise threshold, this does not change which pairs are found or how they are scored.
+    #[arg(long, value_name = "TOKENS")]
+    min_match_tokens: Option<usize>,
+    /// Discard the matches with fewer than this number of tokens on either side once expanded, before the pairs are
+    /// scored and filtered. This removes the short matches which survive fingerprinting (e.g., where a longer match was
+    /// split by edits) without changing the noise threshold. Zero disables this filter.
+    #[arg(long, value_name = "TOKENS", default_value_t = 0)]
+    min_report_length: usize,
+    /// JSON file overriding the thresholds for specific projects or pairs of projects (e.g., declared group partners).
+    /// The file contains an array of objects, each with "projects" (the name of one project, to override all of its
+    /// pairs, or the names of the two projects of a pair) and optionally "min_matches" (replacing --min-matches) and
+    /// "min_coverage" (the proportion of the code of the more covered project which must match, between 0 and 1).
+    /// Overrides of a pair take precedence over overrides of either project.
+    #[arg(long)]
+    pair_overrides: Option<PathBuf>,
+    /// Pair of projects which are allowed to be similar (e.g., declared collaborators), given as "project1:project2".
+    /// Can be repeated. Allowed pairs are removed from the report, but listed in a separate section of the output along
+    /// with the score they would have been ranked by.
+    #[arg(long = "allow-pair", value_name = "PROJECT1:PROJECT2", value_parser = allowlist::parse_pair)]
+    allowed_pairs: Vec<(String, String)>,
+    /// CSV file listing more allowed pairs (see --allow-pair), with the names of the two projects of a pair on each
+    /// line.
+    #[arg(long)]
+    allowed_pairs_file: Option<PathBuf>,
+    /// YAML or JSON file of review decisions about project pairs, which are included in the output along with the pairs
+    /// they name.
+    #[arg(long)]
+    annotations: Option<PathBuf>,
+    /// CSV file listing the only pairs of projects to compare, with the names of the two projects of a pair on each line
+    /// (e.g., the pairs flagged by a first, faster pass). Since there are few pairs, they are analyzed more thoroughly:
+    /// unless they are given explicitly, the noise threshold is lowered to 20 and the guarantee threshold to its
+    /// smallest possible value, so that every code snippet is fingerprinted.
+    #[arg(long)]
+    only_pairs: Option<PathBuf>,
+    /// Earlier snapshot of the projects directory (e.g., the submissions received by the deadline, when the root holds the
+    /// late ones). Only the matches which were not already found between the same projects in this snapshot are
+    /// reported.
+    #[arg(long, conflicts_with_all = ["manifest", "files_from"])]
+    original_snapshot: Option<PathBuf>,
+    /// JSON file with metadata about the projects, as an object mapping the name of each project to an object with an
+    /// optional "section" (e.g., its lab section or cohort), "timestamp", "name", and "student_id". Each project may
+    /// also contain its own metadata in a `fungus-meta.json` file, whose fields are used when this file lacks them.
+    #[arg(long)]
+    project_metadata: Option<PathBuf>,
+    /// Which pairs of projects to compare, according to the sections given in the project metadata. Can be one of
+    /// "all", "within" (only projects from the same section), or "across" (only projects from different sections).
+    /// Projects without a section are only compared with "all".
+    #[arg(value_enum, long, default_value = "all")]
+    compare: CompareMode,
+    /// Whether to record the submission time of each project in the output, and annotate each pair with the project
+    /// submitted first as a hint about the direction of copying. The submission time is the "timestamp" of the project
+    /// in the project metadata (in seconds since the Unix epoch) if there is one, or else the latest modification time
+    /// of its files.
+    #[arg(long, default_value_t = false)]
+    timestamps: bool,
+    /// Whether to list the projects which do not appear in any reported pair, e.g. to confirm that every project was
+    /// analyzed.
+    #[arg(long, default_value_t = false)]
+    list_clean: bool,
+    /// Whether to flag anomalies within single files which may suggest copying: mixed comment styles, registers named
+    /// in different ways (e.g., "sp" and "r13"), and mixed indentation. These are listed separately from the project
+    /// pairs, as informational findings.
+    #[arg(long, default_value_t = false)]
+    heuristics: bool,
     /// Common code threshold. If the proportion of projects containing some code snippet is greater than this value,
-    /// that code will be ignored. The value must be a real number in the range (0, 1].
-    #[arg(short, long, default_value_t = 0.0)]
-    common_code_threshold: f64,
+    /// that code will be ignored. The value must be a real number in the range (0, 1], or "auto" to choose a threshold
+    /// from the distribution of the number of projects in which each code snippet occurs.
+    #[arg(short, long, default_value = "0")]
+    common_code_threshold: CommonCodeThreshold,
+    /// Absolute common code threshold. Code found in at least this number of projects will be ignored. If
+    /// --common-code-threshold is also given, code is only ignored if it exceeds both thresholds, which keeps the
+    /// fractional threshold from being too coarse for small classes. The value must be at least 2.
+    #[arg(long)]
+    common_code_min_projects: Option<usize>,
+    /// File containing code snippets which are never reported as matches (e.g., standard function prologues and
+    /// epilogues), regardless of how many projects contain them. Snippets are separated by blank lines.
+    #[arg(long)]
+    stop_list: Option<PathBuf>,
+    /// Minimum project size. Projects with fewer than this number of tokens (not counting starter code) are excluded
+    /// from the analysis, which avoids noise from empty templates and placeholder submissions.
+    #[arg(long, default_value_t = 0)]
+    min_project_tokens: usize,
+    /// Number of bands of the MinHash signatures used to pre-screen the pairs of projects. If non-zero, only the pairs
+    /// of projects which are likely to be similar are compared, which makes very large corpora tractable at the cost
+    /// of possibly missing pairs which share little code. More bands select more pairs.
+    #[arg(long, default_value_t = 0)]
+    lsh_bands: usize,
+    /// Number of values in each band of the MinHash signatures. More rows select fewer pairs.
+    #[arg(long, default_value_t = 2)]
+    lsh_rows: usize,
+    /// Bloom filter prefilter threshold. Pairs of projects which are estimated to share fewer than this number of
+    /// fingerprinted code snippets are skipped, which saves time when most pairs share little code. The estimate is
+    /// approximate, so pairs just above the threshold may also be skipped.
+    #[arg(long, default_value_t = 0)]
+    bloom_min_shared_hashes: usize,
+    /// How to score and rank the pairs of projects. Can be one of "matches" (by number of matches) or "idf" (by the
+    /// inverse document frequency of the shared code, so that code found in many projects counts for less).
+    #[arg(value_enum, long, default_value = "matches")]
+    scoring: ScoringMode,
+    /// Maximum number of matches between two projects for a single code snippet. If some code occurs so many times in
+    /// two projects that pairing every occurrence would exceed this value, each occurrence is only matched with the
+    /// occurrence at the nearest position in the other project and a warning is issued. Zero means no limit.
+    #[arg(long, default_value_t = 10_000)]
+    max_matches_per_hash: usize,
+    /// Number of bits of the hashes used to find matches. Can be one of "64" or "32". 32-bit hashes save memory for
+    /// enormous corpora, but unrelated code snippets are more likely to share a hash and be reported as a match, so a
+    /// warning estimating the number of such collisions is issued.
+    #[arg(value_enum, long, default_value = "64")]
+    hash_width: HashWidth,
+    /// Estimated memory which the hash database and the matches may take, in bytes or with a suffix (e.g., "512M" or
+    /// "4G"). If the analysis would exceed it, it stops early, writes the partial results, and exits with an error
+    /// instead of being killed by the operating system.
+    #[arg(long, value_name = "SIZE", value_parser = memory::parse_memory_size)]
+    max_memory: Option<usize>,
+    /// Number of threads reading the projects, which pass them on to the lexing threads as they are read. Reading mostly
+    /// waits on the filesystem, so more threads than cores (e.g., 64) overlap more of the latency of a network
+    /// filesystem. Defaults to the number of cores.
+    #[arg(long, value_name = "N")]
+    io_threads: Option<NonZeroUsize>,
+    /// Number of threads lexing and hashing the files and expanding the matches, which are CPU-bound. Defaults to the
+    /// number of cores.
+    #[arg(long, value_name = "N")]
+    cpu_threads: Option<NonZeroUsize>,
+    /// Stylometry threshold. If given, the frequencies of the instructions used by each project are also compared, and
+    /// the pairs of projects whose frequency profiles have at least this cosine similarity are listed in a separate
+    /// section of the output. Instructions are weighted by how rare they are across the projects, so this flags pairs
+    /// with the same unusual mix of instructions even when little code matches literally. The value must be a real
+    /// number in the range (0, 1].
+    #[arg(long)]
+    stylometry_threshold: Option<f64>,
+    /// Formatting threshold. If given, the way each project formats its code (indentation, blank lines, alignment of
+    /// comments, and trailing whitespace) is also compared, and the pairs of projects whose formatting profiles have at
+    /// least this cosine similarity are listed in a separate section of the output. Formatting is ignored by the
+    /// tokenizers, so this corroborates copying which was disguised by editing the code. The value must be a real
+    /// number in the range (0, 1].
+    #[arg(long)]
+    formatting_threshold: Option<f64>,
+    /// Maximum number of projects in which a string literal is considered rare. If given, the pairs of projects which
+    /// share rare string literals (e.g., error messages or prompts) are listed in a separate section of the output.
+    /// Literals found in the starter code are ignored. The value must be at least 2.
+    #[arg(long)]
+    string_literal_max_projects: Option<usize>,
+    /// Weights of the composite score, as a comma-separated list of "signal=weight" entries (e.g.,
+    /// "code=1,comments=0.5,stylometry=0.2"). If given, the pairs of projects are also ranked by the weighted average
+    /// of their scores according to each signal, which are listed in a separate section of the output. The signals are
+    /// "code" (proportion of the code covered by the matches), "comments" (proportion of the comment words covered by
+    /// matching comments), "stylometry" (similarity of the instruction frequencies), "formatting" (similarity of the
+    /// formatting), "string-literals" (number of rare string literals shared), and "external" (the scores given by
+    /// --external-scores).
+    #[arg(long)]
+    score_weights: Option<ScoreWeights>,
+    /// CSV file of similarity scores computed by an external tool (e.g., an embedding model), with the names of the two
+    /// projects of a pair and their score between 0 and 1 on each line. The scores are listed in a separate section of
+    /// the output and can be weighted into the composite score as the "external" signal.
+    #[arg(long, value_name = "CSV")]
+    external_scores: Option<PathBuf>,
+    /// Directory of known solutions found online (e.g., gists or repositories for the assignment), with one solution per
+    /// subdirectory. Each project is also compared with each solution, and the pairs are listed in a separate section of
+    /// the output, revealing a third source from which several projects may have copied.
+    #[arg(long, value_name = "DIR")]
+    online_solutions: Option<PathBuf>,
+    /// Directory in which to write one report per flagged project, summarizing all the pairs it appears in.
+    #[arg(long)]
+    report_dir: Option<PathBuf>,
+    /// Write the coverage of every pair of projects, including the pairs which are not reported, to this CSV file.
+    #[arg(long)]
+    similarity_matrix: Option<PathBuf>,
+    /// Write a heatmap of the coverage of every pair of projects to this SVG file.
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+    /// Number of lines to include before and after each matched code snippet in the HTML, PDF, and per-project reports.
+    #[arg(long, default_value_t = 0)]
+    context: usize,
+    /// Directory in which to periodically save the state of the analysis. If the directory contains the state of an
+    /// earlier run on the same inputs (e.g., one that crashed or was killed), that run is resumed.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Directory in which to cache the completed pairs of projects across runs. When rerunning the analysis after some
+    /// projects change (e.g., a resubmission), only the pairs involving the changed projects are completed again.
+    #[arg(long)]
+    cache: Option<PathBuf>,
+    /// Whether to explain how the noise threshold, guarantee threshold, and max token offset interact, then exit
+    /// without analyzing the projects.
+    #[arg(long, default_value_t = false)]
+    explain_params: bool,
+    /// Whether to record the wall time and peak memory of each stage of the analysis (reading, lexing, fingerprinting,
+    /// etc.), which are printed and included in the output.
+    #[arg(long, default_value_t = false)]
+    timings: bool,
+    /// Whether to print the distribution of the number of projects in which each code snippet occurs and include it in
+    /// the output. This can help choose a common code threshold.
+    #[arg(long, default_value_t = false)]
+    hash_frequencies: bool,
+    /// Whether to print the proportion of the hashes of each file (and of all the files) which winnowing selected as
+    /// fingerprints, and include it in the output. This can help choose the window size through the thresholds.
+    #[arg(long, default_value_t = false)]
+    fingerprint_density: bool,
+    /// Whether to run the analysis twice and fail if the results differ. The output is meant to be identical across
+    /// runs, so this is only useful for diagnosing bugs.
+    #[arg(long, default_value_t = false)]
+    deterministic: bool,
+    /// Whether to check that the current settings report a match of the guarantee threshold's length instead of
+    /// analyzing the projects. A synthetic snippet is planted into two copies of the first file, which are analyzed
+    /// along with the projects, and the command fails if the planted match is not reported.
+    #[arg(long, default_value_t = false)]
+    self_test: bool,
+    /// Whether to write a reproducibility manifest next to the output file (with `.manifest.json` appended to its name),
+    /// listing the SHA-256 of every input file along with the version and the arguments of the run.
+    #[arg(long, default_value_t = false)]
+    reproducibility_manifest: bool,
+    /// Identifier of the run, recorded in the output metadata and the reproducibility manifest. By default, the
+    /// identifier is derived from the inputs and parameters, so runs with the same inputs and parameters have the same
+    /// identifier.
+    #[arg(long)]
+    seeded_run_id: Option<String>,
+    /// Reproducibility manifest of an earlier run. The command fails, listing the differences, if the version, the
+    /// arguments (other than this one), or the input files differ from those of the earlier run.
+    #[arg(long)]
+    verify_manifest: Option<PathBuf>,
+    /// Whether to embed a SHA-256 of the output in its `integrity` field, so that edits made after the output was
+    /// generated can be detected with the `verify-output` subcommand. This is only supported for the 'json' output
+    /// format.
+    #[arg(long, default_value_t = false)]
+    integrity_hash: bool,
+    /// File containing a secret key with which to embed an HMAC-SHA256 of the output instead of a plain SHA-256, so that
+    /// the hash cannot be recomputed after an edit without the key. Trailing whitespace (e.g., a final newline) is not
+    /// part of the key.
+    #[arg(long)]
+    integrity_key_file: Option<PathBuf>,
 }
 
 fn main() -> anyhow::Result<()> {
-    let (args, mut warnings) = parse_args()?;
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    match args.command {
+        Some(Command::FetchClassroom(fetch_args)) => fetch::classroom::fetch_classroom(*fetch_args),
+        Some(Command::FetchCanvas(fetch_args)) => fetch::canvas::fetch_canvas(*fetch_args),
+        Some(Command::View(view_args)) => view::view(view_args),
+        Some(Command::Cases(cases_args)) => write_cases(cases_args),
+        Some(Command::Index(index_args)) => indexing::index(index_args),
+        Some(Command::Query(query_args)) => indexing::query(query_args),
+        Some(Command::VerifyOutput(verify_args)) => verify_output(verify_args),
+        None => {
+            // Safe to unwrap since clap requires the root unless a subcommand or a manifest is given.
+            let root = args.root.unwrap_or_else(|| {
+                let manifest = args.detect.manifest.as_deref().unwrap();
+                parent_dir(manifest).to_owned()
+            });
+            if args.detect.only_pairs.is_some() {
+                lower_default_thresholds(&mut args.detect, &matches);
+            }
+            run(&root, args.detect)
+        }
+    }
+}
+
+/// Project pairs of an existing output file. The rest of the output is not needed.
+#[derive(Deserialize)]
+struct SavedOutput {
+    project_pairs: Vec<ProjectPair>,
+}
+
+/// Writes the case summaries of the confirmed pairs of an existing output file.
+fn write_cases(args: CasesArgs) -> anyhow::Result<()> {
+    let context = || {
+        format!(
+            "Failed to read output file \"{}\".",
+            args.output_file.display()
+        )
+    };
+    let contents = fs::read_to_string(&args.output_file).with_context(context)?;
+    let saved: SavedOutput = serde_json::from_str(&contents).with_context(context)?;
+    let template = match &args.template {
+        None => report::cases::DEFAULT_TEMPLATE.to_owned(),
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the template \"{}\".", path.display()))?,
+    };
+
+    let mut output = Output::new(Vec::new(), saved.project_pairs);
+    if let Some(path) = &args.annotations {
+        // The paths of the output file are already relative to the root
+        let annotations = read_annotations(Path::new(""), path)?;
+        for pair in output.project_pairs.iter_mut() {
+            pair.review = None;
+        }
+        output.add_annotations(annotations);
+    }
+
+    let num_cases = report::cases::write_case_summaries(
+        &output.project_pairs,
+        &args.root,
+        &args.dir,
+        &template,
+        args.context,
+    )?;
+    println!(
+        "Wrote {num_cases} case summaries to \"{}\".",
+        args.dir.display()
+    );
+    Ok(())
+}
+
+/// Noise threshold of the thorough analysis of the pairs given with --only-pairs, unless one is given explicitly.
+const ONLY_PAIRS_NOISE_THRESHOLD: usize = 20;
+
+/// Lowers the thresholds which were not given explicitly, for the thorough analysis of the pairs given with
+/// --only-pairs.
+fn lower_default_thresholds(args: &mut DetectArgs, matches: &ArgMatches) {
+    if matches.value_source("noise") == Some(ValueSource::DefaultValue) {
+        args.noise = ONLY_PAIRS_NOISE_THRESHOLD;
+    }
+    if matches.value_source("guarantee") == Some(ValueSource::DefaultValue) {
+        args.guarantee = 0;
+    }
+}
+
+/// Analyzes the projects in `root` and writes the results to the output file.
+fn run(root: &Path, args: DetectArgs) -> anyhow::Result<()> {
+    let (args, mut warnings) = validate_args(root, args)?;
+
+    if args.explain_params {
+        print!("{}", explain_params(&args));
+        return Ok(());
+    }
 
-    let (documents, mut input_warnings) = read_projects(&args.root, &args.ignore);
+    // The files of the projects are lexed as soon as they are read, with the same lexing options as the analysis
+    let lexing_options = DetectionOptions {
+        max_token_offset: args.max_token_offset,
+        tokenizing_strategy: args.tokenizing_strategy,
+        ignore_whitespace: args.ignore_whitespace,
+        transforms: args.transforms.clone(),
+        key_symbols: read_key_symbols(args.key_symbols.as_deref())?,
+        hash_dialect: args.hash_dialect,
+        strict_lex: args.strict_lex,
+        cpu_threads: args.cpu_threads,
+        lexed_documents: Some(Arc::new(LexedDocuments::default())),
+        ..Default::default()
+    };
+
+    let mut timings = Timings::new(args.timings);
+    timings.start(Stage::Read);
+    let modified = ModifiedRange {
+        after: args.modified_after,
+        before: args.modified_before,
+    };
+    let (mut documents, mut input_warnings, manifest_timestamps) =
+        match (&args.manifest, &args.files_from) {
+            (Some(path), _) => read_manifest(root, path)?,
+            (None, Some(list)) => {
+                let (documents, warnings) =
+                    read_file_list(root, list, args.project_rule, &args.ignore, modified)?;
+                (documents, warnings, BTreeMap::new())
+            }
+            (None, None) => {
+                let (documents, warnings) = read_projects(
+                    root,
+                    &args.ignore,
+                    modified,
+                    TraversalLimits::new(&args),
+                    args.io_threads,
+                    Some(&lexing_options),
+                );
+                (documents, warnings, BTreeMap::new())
+            }
+        };
     warnings.append(&mut input_warnings);
+    let own_project_metadata = take_project_metadata_files(&mut documents, &mut warnings);
+    for path in remove_project_relative_files(&mut documents, &args.ignore_within_projects) {
+        eprintln!(
+            "Ignored path '{}' does not match any file of the projects.",
+            path.display()
+        );
+    }
+    let num_earlier_attempts = attempts::keep_latest_attempts(&mut documents, args.attempts);
+    if num_earlier_attempts > 0 {
+        eprintln!("Left out {num_earlier_attempts} earlier attempt(s) of the projects.");
+    }
 
     let (ignored_documents, mut ignored_dir_warnings) = read_starter_code(&args.ignore);
     warnings.append(&mut ignored_dir_warnings);
 
-    let (project_pairs, mut fingerprinting_warnings) = detect_plagiarism(
-        args.noise,
-        args.guarantee,
-        args.max_token_offset,
-        args.tokenizing_strategy,
-        args.ignore_whitespace,
-        args.expand_matches,
-        args.min_matches,
-        args.common_code_threshold,
-        &documents,
-        &ignored_documents,
-    );
+    let solution_documents = match &args.online_solutions {
+        None => Vec::new(),
+        Some(dir) => {
+            // The solutions may be stored inside the projects directory, but are not projects themselves
+            documents.retain(|d| !is_same_path(d.project(), dir));
+            let (solution_documents, mut solution_warnings) = read_projects(
+                dir,
+                &args.ignore,
+                ModifiedRange::default(),
+                TraversalLimits::default(),
+                args.io_threads,
+                None,
+            );
+            warnings.append(&mut solution_warnings);
+            solution_documents
+        }
+    };
+    timings.finish(Stage::Read);
+
+    let run_manifest = (args.reproducibility_manifest
+        || args.seeded_run_id.is_some()
+        || args.verify_manifest.is_some())
+    .then(|| {
+        RunManifest::new(
+            args.seeded_run_id.clone(),
+            reproducible_arguments(),
+            &documents,
+            &ignored_documents,
+            root,
+        )
+    });
+    if let (Some(path), Some(run_manifest)) = (&args.verify_manifest, &run_manifest) {
+        verify_manifest(path, run_manifest)?;
+    }
+
+    let stop_list = match &args.stop_list {
+        None => Vec::new(),
+        Some(path) => {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the stop-list \"{}\".", path.display()))?;
+            stop_list::parse_stop_list(&contents)
+        }
+    };
+
+    let pair_overrides = match &args.pair_overrides {
+        None => Vec::new(),
+        Some(path) => read_pair_overrides(root, path)?,
+    };
+
+    let allowed_pairs = read_allowed_pairs(root, &args)?;
+    let annotations = match &args.annotations {
+        None => Vec::new(),
+        Some(path) => read_annotations(root, path)?,
+    };
+    let external_scores = match &args.external_scores {
+        None => None,
+        Some(path) => Some(read_external_scores(root, path)?),
+    };
+    let only_pairs = match &args.only_pairs {
+        None => None,
+        Some(path) => Some(read_only_pairs(root, path)?),
+    };
+
+    let mut project_metadata = match &args.project_metadata {
+        None => BTreeMap::new(),
+        Some(path) => read_project_metadata(root, path)?,
+    };
+    // The project metadata file takes precedence over the projects' own metadata files
+    for (project, own_metadata) in own_project_metadata {
+        let metadata = project_metadata.entry(project).or_default();
+        *metadata = mem::take(metadata).or(own_metadata);
+    }
+    // Timestamps in the project metadata take precedence over those in the manifest
+    for (project, timestamp) in manifest_timestamps {
+        let metadata: &mut ProjectMetadata = project_metadata.entry(project).or_default();
+        metadata.timestamp = metadata.timestamp.or(Some(timestamp));
+    }
+    let project_sections = project_metadata
+        .iter()
+        .filter_map(|(project, metadata)| Some((project.clone(), metadata.section.clone()?)))
+        .collect::<BTreeMap<_, _>>();
+    if args.compare != CompareMode::All {
+        for project in documents.iter().map(File::project).collect::<BTreeSet<_>>() {
+            if !project_sections.contains_key(project) {
+                warnings.push(Warning::missing_section(project));
+            }
+        }
+    }
+
+    let interrupt = Arc::new(AtomicBool::new(false));
+    {
+        let interrupt = interrupt.clone();
+        ctrlc::set_handler(move || {
+            if interrupt.swap(true, Ordering::Relaxed) {
+                std::process::exit(130);
+            }
+            eprintln!("Interrupted. Writing the results found so far (press Ctrl-C again to exit immediately).");
+        })
+        .with_context(|| "Failed to install the Ctrl-C handler.")?;
+    }
+
+    let options = DetectionOptions {
+        noise_threshold: args.noise,
+        guarantee_threshold: args.guarantee,
+        fingerprint_algorithm: Arc::new(Winnowing::new(args.tie_breaking)),
+        stitch_files: args.stitch_files,
+        granularity: args.granularity,
+        expand_matches: args.expand_matches,
+        min_report_length: args.min_report_length,
+        min_matches: args.min_matches,
+        pair_overrides,
+        common_hash_threshold: match args.common_code_threshold {
+            CommonCodeThreshold::Fraction(fraction) => fraction,
+            CommonCodeThreshold::Auto => 0.0,
+        },
+        common_hash_min_projects: args.common_code_min_projects.unwrap_or(0),
+        auto_common_hash_threshold: args.common_code_threshold == CommonCodeThreshold::Auto,
+        stop_list,
+        starter_code_similarity: args.starter_code_similarity,
+        min_project_tokens: args.min_project_tokens,
+        compare: args.compare,
+        project_sections,
+        only_pairs,
+        lsh_bands: args.lsh_bands,
+        lsh_rows: args.lsh_rows,
+        bloom_min_shared_hashes: args.bloom_min_shared_hashes,
+        scoring: args.scoring,
+        max_matches_per_hash: args.max_matches_per_hash,
+        hash_width: args.hash_width,
+        max_memory: args.max_memory,
+        checkpoint_dir: args.checkpoint.clone(),
+        cache_dir: args.cache.clone(),
+        interrupt: Some(interrupt.clone()),
+        ..lexing_options
+    };
+    if args.self_test {
+        let result = self_test::self_test(&options, &documents, &ignored_documents)
+            .map_err(|e| anyhow::anyhow!(e))?;
+        if !result.passed {
+            anyhow::bail!(
+                "Self-test failed: a planted match of {} tokens in two copies of \"{}\" was not reported, although it is at least as long as the guarantee threshold of {} tokens. Check the options which drop matches or pairs (e.g., --min-report-length, --min-matches, or the prefilters).",
+                result.planted_tokens,
+                result.sample.display(),
+                args.guarantee
+            );
+        }
+        println!(
+            "Self-test passed: a planted match of {} tokens in two copies of \"{}\" was reported.",
+            result.planted_tokens,
+            result.sample.display()
+        );
+        return Ok(());
+    }
+    if let Some(dir) = &args.dump_tokens {
+        let num_files = report::tokens::write_token_dump(
+            &options,
+            &documents,
+            root,
+            dir,
+            args.dump_raw_tokens,
+        )?;
+        println!(
+            "Wrote the tokens of {num_files} files to \"{}\".",
+            dir.display()
+        );
+    }
+    if args.starter_code_diff {
+        let diffs = starter_diff::starter_code_diffs(&options, &documents, &ignored_documents);
+        let num_unchanged = diffs
+            .iter()
+            .filter(|d| d.new_code_proportion == 0.0)
+            .count();
+        if num_unchanged > 0 {
+            eprintln!("{num_unchanged} project(s) contain no code besides the starter code.");
+        }
+        let mut output = Output::new(warnings, Vec::new());
+        output.starter_code_diff = Some(diffs);
+        return output_results(&mut output, &args, root, None);
+    }
+    let metadata = Metadata {
+        partial: false,
+        tie_breaking: args.tie_breaking,
+        run_id: run_manifest.as_ref().map(|m| m.run_id.clone()),
+    };
+    let submissions = args
+        .timestamps
+        .then(|| project_submissions(&documents, &project_metadata));
+    // The pairs are written as soon as they are scored, unless an option needs every pair before writing any of them
+    let streams_pairs = args.output_format == OutputFormat::Ndjson
+        && args.flag_top_percentile.is_none()
+        && args.original_snapshot.is_none()
+        && !args.deterministic;
+    let (project_pairs, mut fingerprinting_warnings, diagnostics, ndjson) = if streams_pairs {
+        let output_file = &args.output_file;
+        let writer = fs::File::create(output_file)
+            .and_then(|f| NdjsonWriter::new(BufWriter::new(f), &metadata, args.ndjson_per_match))
+            .with_context(|| format!("Failed to write output to \"{}\".", output_file.display()))?;
+        let edits = PairEdits {
+            root,
+            path_style: args.path_style,
+            match_order: args.match_order,
+            min_match_tokens: args.min_match_tokens,
+            allowed_pairs: &allowed_pairs,
+            annotations: &annotations,
+            submission_times: submissions.as_deref().map(SubmissionTimes::new),
+        };
+        let mut stream = NdjsonStream::new(writer, edits, &mut timings);
+        let (project_pairs, fingerprinting_warnings, diagnostics) =
+            detect_plagiarism_with_observer(&options, &documents, &ignored_documents, &mut stream);
+        let writer = stream
+            .into_writer()
+            .with_context(|| format!("Failed to write output to \"{}\".", output_file.display()))?;
+        (
+            project_pairs,
+            fingerprinting_warnings,
+            diagnostics,
+            Some(writer),
+        )
+    } else {
+        let (project_pairs, fingerprinting_warnings, diagnostics) =
+            detect_plagiarism_with_observer(&options, &documents, &ignored_documents, &mut timings);
+        (project_pairs, fingerprinting_warnings, diagnostics, None)
+    };
+    let online_solution_pairs = args.online_solutions.as_ref().map(|_| {
+        let (pairs, solution_warnings) = online_solutions::solution_pairs(
+            &options,
+            &documents,
+            &solution_documents,
+            &ignored_documents,
+        );
+        // The warnings about the projects were already issued by the main analysis
+        fingerprinting_warnings.extend(solution_warnings.into_iter().filter(|w| {
+            w.file.as_ref().is_some_and(|f| {
+                solution_documents
+                    .iter()
+                    .any(|d| f.starts_with(d.project()))
+            })
+        }));
+        pairs
+    });
+    let stylometry_pairs = args
+        .stylometry_threshold
+        .map(|min_similarity| stylometry::similar_projects(&options, &documents, min_similarity));
+    let formatting_pairs = args
+        .formatting_threshold
+        .map(|min_similarity| formatting::similar_projects(&options, &documents, min_similarity));
+    let string_literal_pairs = args.string_literal_max_projects.map(|max_projects| {
+        string_literals::shared_literals(&options, &documents, &ignored_documents, max_projects)
+    });
+    let external_scores = external_scores.map(|mut pairs| {
+        // Scores of projects which were not analyzed cannot be combined with anything
+        let projects = diagnostics
+            .projects
+            .iter()
+            .map(|p| &p.project)
+            .collect::<HashSet<_>>();
+        let num_scores = pairs.len();
+        pairs.retain(|p| projects.contains(&p.project1) && projects.contains(&p.project2));
+        if pairs.len() < num_scores {
+            eprintln!(
+                "{} external score(s) name a project which was not analyzed.",
+                num_scores - pairs.len()
+            );
+        }
+        pairs
+    });
+    let composite_pairs = args.score_weights.as_ref().map(|weights| {
+        composite_pairs(
+            weights,
+            &args,
+            &options,
+            &documents,
+            &ignored_documents,
+            &project_pairs,
+            external_scores.as_deref().unwrap_or_default(),
+        )
+    });
+    let mut similarity_matrix = (args.similarity_matrix.is_some() || args.heatmap.is_some())
+        .then(|| SimilarityMatrix::new(&options, &diagnostics));
+    let memory_exceeded = fingerprinting_warnings
+        .iter()
+        .any(|w| w.code == WarningCode::MemoryLimit);
+    let partial = interrupt.load(Ordering::Relaxed) || memory_exceeded;
+    if args.deterministic && !partial {
+        // The second run uses new hash maps with a different iteration order. It must not resume from the checkpoint
+        // or reuse the cache saved by the first run.
+        let rerun_options = DetectionOptions {
+            checkpoint_dir: None,
+            cache_dir: None,
+            ..options.clone()
+        };
+        let (rerun_project_pairs, rerun_warnings) =
+            detect_plagiarism(&rerun_options, &documents, &ignored_documents);
+        if rerun_project_pairs != project_pairs || rerun_warnings != fingerprinting_warnings {
+            anyhow::bail!(
+                "The results of two runs on the same input differ. Please report this as a bug."
+            );
+        }
+    }
+    let mut project_pairs = project_pairs;
+    if let Some(original_root) = &args.original_snapshot {
+        // The warnings about the original snapshot are left out, since they are not about the projects analyzed
+        let (mut original_documents, _) = read_projects(
+            original_root,
+            &args.ignore,
+            ModifiedRange::default(),
+            TraversalLimits::new(&args),
+            args.io_threads,
+            None,
+        );
+        remove_project_relative_files(&mut original_documents, &args.ignore_within_projects);
+        let original_options = DetectionOptions {
+            checkpoint_dir: None,
+            cache_dir: None,
+            ..options.clone()
+        };
+        let (original_pairs, _) =
+            detect_plagiarism(&original_options, &original_documents, &ignored_documents);
+        snapshots::remove_original_matches(
+            &mut project_pairs,
+            root,
+            &documents,
+            Snapshot {
+                root: original_root,
+                documents: &original_documents,
+                project_pairs: &original_pairs,
+            },
+        );
+    }
     warnings.append(&mut fingerprinting_warnings);
 
     let mut output = Output::new(warnings, project_pairs);
+    output.metadata = Metadata {
+        partial,
+        ..metadata
+    };
+    output.set_projects(diagnostics.projects.clone());
+    output.set_project_metadata(&project_metadata);
+    let scores = diagnostics.scores();
+    if let Some(distribution) = scores.distribution() {
+        output.set_score_distribution(distribution);
+    }
+    if let Some(percentage) = args.flag_top_percentile {
+        output.keep_top_percentile(&scores, percentage);
+        if let Some(min_score) = output.summary.min_flagged_score {
+            eprintln!(
+                "Flagging the pairs with a score of at least {min_score:.2} (top {percentage}% of {} compared pairs).",
+                scores.len()
+            );
+        }
+    }
+    if let Some(min_tokens) = args.min_match_tokens {
+        let num_removed = output.remove_short_matches(min_tokens);
+        if num_removed > 0 {
+            eprintln!("Left out {num_removed} match(es) shorter than {min_tokens} tokens.");
+        }
+    }
+    output.online_solution_pairs = online_solution_pairs;
+    output.stylometry = stylometry_pairs;
+    output.formatting = formatting_pairs;
+    output.string_literals = string_literal_pairs;
+    output.composite = composite_pairs;
+    output.external_scores = external_scores;
+    if !allowed_pairs.is_empty() {
+        output.remove_allowed_pairs(&allowed_pairs);
+    }
+    if !annotations.is_empty() {
+        let unmatched = output.add_annotations(annotations);
+        if !unmatched.is_empty() {
+            eprintln!(
+                "{} annotation(s) do not match any reported pair of projects.",
+                unmatched.len()
+            );
+        }
+    }
+    if let Some(submissions) = submissions {
+        output.add_submissions(submissions);
+    }
+    if args.list_clean {
+        output.add_clean_projects();
+    }
+    if args.heuristics {
+        output.findings = Some(heuristics::file_findings(&documents));
+    }
+    if let Some(num_candidates) = diagnostics.lsh_candidate_pairs {
+        eprintln!("LSH pre-screening selected {num_candidates} pairs of projects to compare.");
+    }
+    if let Some(num_candidates) = diagnostics.bloom_candidate_pairs {
+        eprintln!("Bloom filter prefilter kept {num_candidates} pairs of projects to compare.");
+    }
+    if let Some(min_projects) = diagnostics.auto_common_hash_min_projects {
+        eprintln!("Ignoring code found in at least {min_projects} projects as common code.");
+    }
+    if args.hash_frequencies {
+        eprint!("{}", diagnostics.hash_frequencies);
+    }
+    if args.fingerprint_density {
+        eprint!("{}", diagnostics.fingerprint_density);
+    }
+    if args.hash_frequencies || args.fingerprint_density {
+        output.diagnostics = Some(diagnostics);
+    }
 
-    output_results(&mut output, &args.output_file, args.pretty, &args.root)?;
+    if args.granularity == Granularity::Function {
+        output.routine_pairs = Some(routines::routine_pairs(&output.project_pairs, &documents));
+    }
+
+    output.timings = timings.stages();
+    timings.start(Stage::Serialization);
+    output_results(&mut output, &args, root, ndjson)?;
+    if let Some(run_manifest) = run_manifest.filter(|_| args.reproducibility_manifest) {
+        let path = reproducibility::manifest_path(&args.output_file);
+        write_run_manifest(&run_manifest, &path).with_context(|| {
+            format!(
+                "Failed to write the reproducibility manifest to \"{}\".",
+                path.display()
+            )
+        })?;
+        println!(
+            "Wrote the reproducibility manifest of run {} to \"{}\".",
+            run_manifest.run_id,
+            path.display()
+        );
+    }
+    timings.finish(Stage::Serialization);
+    timings.print();
 
+    if let Some(matrix) = &mut similarity_matrix {
+        matrix
+            .format_paths(root, args.path_style)
+            .with_context(|| "Failed to format the paths in the similarity matrix.")?;
+        if let Some(path) = &args.similarity_matrix {
+            report::heatmap::write_matrix_csv(matrix, path)?;
+            println!("Wrote the similarity matrix to \"{}\".", path.display());
+        }
+        if let Some(path) = &args.heatmap {
+            report::heatmap::write_heatmap_svg(matrix, path)?;
+            println!("Wrote the heatmap to \"{}\".", path.display());
+        }
+    }
+
+    if let Some(report_dir) = &args.report_dir {
+        let num_reports = report::student::write_student_reports(
+            &output.project_pairs,
+            root,
+            report_dir,
+            args.context,
+        )?;
+        println!(
+            "Wrote {num_reports} project reports to \"{}\".",
+            report_dir.display()
+        );
+    }
+
+    if memory_exceeded {
+        anyhow::bail!(
+            "The analysis exceeded the maximum memory given with --max-memory, so the output only contains partial results."
+        );
+    }
     Ok(())
 }
 
-/// Reads, validates, and returns the command-line arguments.
-fn parse_args() -> anyhow::Result<(Args, Vec<Warning>)> {
-    let mut args = Args::parse();
+/// Computes the signals used by the composite score, and combines them for each pair of projects.
+fn composite_pairs(
+    weights: &ScoreWeights,
+    args: &DetectArgs,
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    project_pairs: &[ProjectPair],
+    external_scores: &[ExternalScorePair],
+) -> Vec<CompositePair> {
+    let mut components = Components::default();
+    if weights.uses(Signal::Code) {
+        components.add_matches(Signal::Code, project_pairs);
+    }
+    if weights.uses(Signal::Comments) {
+        let comment_pairs = composite::comment_pairs(options, documents, ignored_documents);
+        components.add_matches(Signal::Comments, &comment_pairs);
+    }
+    if weights.uses(Signal::Stylometry) {
+        // Unless a threshold is given, every pair with some similarity contributes
+        let min_similarity = args.stylometry_threshold.unwrap_or(f64::MIN_POSITIVE);
+        let stylometry_pairs = stylometry::similar_projects(options, documents, min_similarity);
+        components.add_stylometry(&stylometry_pairs);
+    }
+    if weights.uses(Signal::Formatting) {
+        let min_similarity = args.formatting_threshold.unwrap_or(f64::MIN_POSITIVE);
+        let formatting_pairs = formatting::similar_projects(options, documents, min_similarity);
+        components.add_formatting(&formatting_pairs);
+    }
+    if weights.uses(Signal::StringLiterals) {
+        let max_projects = args.string_literal_max_projects.unwrap_or(2);
+        let string_literal_pairs =
+            string_literals::shared_literals(options, documents, ignored_documents, max_projects);
+        components.add_string_literals(&string_literal_pairs);
+    }
+    if weights.uses(Signal::External) {
+        components.add_external_scores(external_scores);
+    }
+    composite::composite_scores(weights, components)
+}
+
+/// Validates the analysis arguments, filling in defaults that depend on other arguments.
+fn validate_args(root: &Path, mut args: DetectArgs) -> anyhow::Result<(DetectArgs, Vec<Warning>)> {
     let mut warnings = Vec::new();
 
-    if !args.root.exists() {
-        anyhow::bail!("Projects directory '{}' not found.", args.root.display());
+    if !root.exists() {
+        anyhow::bail!("Projects directory '{}' not found.", root.display());
     }
-    if !args.root.is_dir() {
+    if !root.is_dir() {
         anyhow::bail!(
             "Projects directory '{}' is not a directory.",
-            args.root.display()
+            root.display()
         );
     }
 
-    if args.ignore.is_empty() {
+    let (ignore, ignore_within_projects) = mem::take(&mut args.ignore)
+        .into_iter()
+        .partition(|path| path.exists() || path.is_absolute());
+    args.ignore = ignore;
+    args.ignore_within_projects = ignore_within_projects;
+
+    if args.ignore.is_empty() && args.ignore_within_projects.is_empty() {
         warnings.push(Warning {
             file: None,
             message: "Results tend to be better when the assignment starter code is provided. Consider doing so using the --ignore argument.".to_owned(),
-            warn_type: WarningType::Args
+            warn_type: WarningType::Args,
+            code: WarningCode::NoStarterCode,
         });
     }
     for path in args.ignore.iter() {
@@ -124,29 +1283,69 @@ fn parse_args() -> anyhow::Result<(Args, Vec<Warning>)> {
         }
     }
 
+    if let Some(original) = &args.original_snapshot {
+        if !original.is_dir() {
+            anyhow::bail!(
+                "Original snapshot '{}' not found or not a directory.",
+                original.display()
+            );
+        }
+    }
+
+    if let (Some(after), Some(before)) = (args.modified_after, args.modified_before) {
+        if after >= before {
+            anyhow::bail!("--modified-after must be earlier than --modified-before.");
+        }
+    }
+
     if args.noise == 0 {
         anyhow::bail!("Noise threshold must be greater than 0.");
     }
+    for (name, limit) in [
+        ("--max-depth", args.max_depth),
+        ("--max-files-per-project", args.max_files_per_project),
+        ("--max-total-files", args.max_total_files),
+    ] {
+        if limit == Some(0) {
+            anyhow::bail!("{name} must be greater than 0.");
+        }
+    }
+
+    if let Some(percentage) = args.flag_top_percentile {
+        if !(percentage > 0.0 && percentage <= 100.0) {
+            anyhow::bail!("--flag-top-percentile must be greater than 0 and at most 100.");
+        }
+    }
 
     match (args.tokenizing_strategy, args.max_token_offset) {
-        (TokenizingStrategy::Relative, 0) => {
+        (TokenizingStrategy::Relative | TokenizingStrategy::Auto, 0) => {
             // Default value
             args.max_token_offset = args.noise - 1;
         }
-        (TokenizingStrategy::Relative, n) if n < args.noise - 1 => {
+        (TokenizingStrategy::Relative | TokenizingStrategy::Auto, n) if n < args.noise - 1 => {
             warnings.push(Warning {
                 file: None,
                 message: "The selected max token offset is very small. This may lead to excessive false positives.".to_owned(),
                 warn_type: WarningType::Args,
+                code: WarningCode::SmallMaxTokenOffset,
             });
         }
-        (TokenizingStrategy::Relative, _) => {}
-        (TokenizingStrategy::Bytes | TokenizingStrategy::Naive, n) if n != 0 => {
+        (TokenizingStrategy::Relative | TokenizingStrategy::Auto, _) => {}
+        (
+            TokenizingStrategy::Bytes | TokenizingStrategy::Naive | TokenizingStrategy::Comments,
+            n,
+        ) if n != 0 => {
             anyhow::bail!("Max token offset must be zero for non-relative tokenizing strategies.");
         }
-        (TokenizingStrategy::Bytes | TokenizingStrategy::Naive, _) => {}
+        (
+            TokenizingStrategy::Bytes | TokenizingStrategy::Naive | TokenizingStrategy::Comments,
+            _,
+        ) => {}
     }
 
+    if args.guarantee == 0 {
+        args.guarantee = args.noise + args.max_token_offset;
+    }
     if args.guarantee < args.noise + args.max_token_offset {
         if args.max_token_offset == 0 {
             anyhow::bail!("Guarantee threshold must be greater than or equal to noise threshold. Received arguments guarantee = {}, noise = {}", args.guarantee, args.noise);
@@ -155,26 +1354,522 @@ fn parse_args() -> anyhow::Result<(Args, Vec<Warning>)> {
         }
     }
 
-    if args.common_code_threshold < 0.0 {
-        anyhow::bail!("Common hash threshold must be non-negative.");
+    if let CommonCodeThreshold::Fraction(fraction) = args.common_code_threshold {
+        if fraction < 0.0 {
+            anyhow::bail!("Common hash threshold must be non-negative.");
+        }
+        if fraction > 1.0 {
+            anyhow::bail!("Common hash threshold must be less than or equal to one.");
+        }
+    }
+    if args.lsh_rows == 0 {
+        anyhow::bail!("The number of LSH rows must be greater than 0.");
+    }
+    if args
+        .score_weights
+        .as_ref()
+        .is_some_and(|w| w.uses(Signal::External))
+        && args.external_scores.is_none()
+    {
+        anyhow::bail!("The \"external\" signal requires the scores given by --external-scores.");
     }
-    if args.common_code_threshold > 1.0 {
-        anyhow::bail!("Common hash threshold must be less than or equal to one.");
+    if args
+        .starter_code_similarity
+        .is_some_and(|s| !(s > 0.0 && s <= 1.0))
+    {
+        anyhow::bail!("Starter code similarity must be in the range (0, 1].");
+    }
+    if args
+        .stylometry_threshold
+        .is_some_and(|t| !(t > 0.0 && t <= 1.0))
+    {
+        anyhow::bail!("Stylometry threshold must be in the range (0, 1].");
+    }
+    if args
+        .formatting_threshold
+        .is_some_and(|t| !(t > 0.0 && t <= 1.0))
+    {
+        anyhow::bail!("Formatting threshold must be in the range (0, 1].");
+    }
+    if args.string_literal_max_projects.is_some_and(|n| n < 2) {
+        anyhow::bail!("String literal maximum number of projects must be at least 2.");
+    }
+    if args.common_code_min_projects.is_some_and(|n| n < 2) {
+        anyhow::bail!("Common code minimum number of projects must be at least 2.");
     }
 
     if args.ignore_whitespace && args.tokenizing_strategy == TokenizingStrategy::Bytes {
         anyhow::bail!("Ignoring whitespace is not supported for the 'bytes' tokenizing strategy.");
     }
+    if !args.transforms.is_empty() && args.tokenizing_strategy == TokenizingStrategy::Bytes {
+        anyhow::bail!("Token transforms are not supported for the 'bytes' tokenizing strategy.");
+    }
+    if !args.transforms.is_empty() && args.tokenizing_strategy == TokenizingStrategy::Comments {
+        anyhow::bail!("Token transforms are not supported for the 'comments' tokenizing strategy.");
+    }
+    if args.stitch_files && args.granularity == Granularity::Function {
+        anyhow::bail!(
+            "Files cannot be stitched together when they are matched routine by routine."
+        );
+    }
+    if args.transforms.contains(&TokenTransform::BucketOffsets)
+        && !matches!(
+            args.tokenizing_strategy,
+            TokenizingStrategy::Relative | TokenizingStrategy::Auto
+        )
+    {
+        anyhow::bail!(
+            "Bucketing offsets is only supported for the 'relative' tokenizing strategy."
+        );
+    }
+    if args.hash_dialect != HashDialect::Immediate
+        && args.tokenizing_strategy == TokenizingStrategy::Bytes
+    {
+        anyhow::bail!("The '#' dialect is not supported for the 'bytes' tokenizing strategy.");
+    }
+    if args.key_symbols.is_some()
+        && !matches!(
+            args.tokenizing_strategy,
+            TokenizingStrategy::Relative | TokenizingStrategy::Auto
+        )
+    {
+        anyhow::bail!(
+            "Key symbol dictionaries are only supported for the 'relative' tokenizing strategy."
+        );
+    }
+
+    if args.compress && args.output_format != OutputFormat::Json {
+        anyhow::bail!("Compression is only supported for the 'json' output format.");
+    }
+    if (args.integrity_hash || args.integrity_key_file.is_some())
+        && args.output_format != OutputFormat::Json
+    {
+        anyhow::bail!("Integrity hashes are only supported for the 'json' output format.");
+    }
+    if args.ndjson_per_match && args.output_format != OutputFormat::Ndjson {
+        anyhow::bail!("--ndjson-per-match is only supported for the 'ndjson' output format.");
+    }
 
     Ok((args, warnings))
 }
 
+/// Explains the effect of the fingerprinting parameters, using the values of the given (validated) arguments.
+fn explain_params(args: &DetectArgs) -> String {
+    let k = args.noise;
+    let t = args.guarantee;
+    let m = args.max_token_offset;
+    let w = fingerprint::window_size(k, t, m);
+    let unit = match args.tokenizing_strategy {
+        TokenizingStrategy::Bytes => "bytes",
+        TokenizingStrategy::Naive | TokenizingStrategy::Relative | TokenizingStrategy::Auto => {
+            "tokens"
+        }
+        TokenizingStrategy::Comments => "words",
+    };
+
+    let mut explanation = format!(
+        "Noise threshold (k): {k} {unit}\n\
+         Guarantee threshold (t): {t} {unit}\n\
+         Max token offset (m): {m}\n\n\
+         Each file is split into overlapping substrings of k {unit}, each of which is hashed. Winnowing then keeps the\n\
+         smallest hash in every window of w consecutive hashes as the file's fingerprint, and matches are found by\n\
+         comparing fingerprints.\n\n"
+    );
+
+    explanation.push_str(match args.tie_breaking {
+        TieBreaking::Leftmost => {
+            "When the smallest hash occurs more than once in a window, the leftmost occurrence is kept, and it is not kept\n\
+             again while it remains the smallest hash of the following windows.\n\n"
+        }
+        TieBreaking::Rightmost => {
+            "When the smallest hash occurs more than once in a window, the rightmost occurrence is kept, as in the\n\
+             original winnowing paper, so every new position of the smallest hash is kept.\n\n"
+        }
+    });
+
+    if matches!(
+        args.tokenizing_strategy,
+        TokenizingStrategy::Relative | TokenizingStrategy::Auto
+    ) {
+        explanation.push_str(&format!(
+            "With relative tokens, the first m tokens of a shared snippet may still differ between projects, since their\n\
+             offsets can refer to code before the snippet. Only the last t - m tokens of a shared snippet of t tokens are\n\
+             guaranteed to be identical, so the window is m hashes smaller than in the original formula (t - k + 1):\n\
+             \x20 w = t - m - k + 1 = {t} - {m} - {k} + 1 = {w}\n\n"
+        ));
+    } else {
+        explanation.push_str(&format!(
+            "The window size guarantees that at least one hash is kept from every shared snippet of t {unit}:\n\
+             \x20 w = t - k + 1 = {t} - {k} + 1 = {w}\n\n"
+        ));
+    }
+
+    explanation.push_str(&format!(
+        "Matches shorter than {k} {unit} are never reported.\n"
+    ));
+    if k < t {
+        explanation.push_str(&format!(
+            "Matches of {k} to {} {unit} are reported only if one of their hashes is kept in both fingerprints.\n",
+            t - 1
+        ));
+    }
+    explanation.push_str(&format!(
+        "Matches of at least {t} {unit} are always reported.\n"
+    ));
+
+    explanation
+}
+
+/// Reads the pair overrides file, resolving the names of its projects relative to the root like the projects read from
+/// it.
+fn read_pair_overrides(root: &Path, path: &Path) -> anyhow::Result<Vec<PairOverride>> {
+    let context = || format!("Failed to read the pair overrides \"{}\".", path.display());
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let mut overrides: Vec<PairOverride> = serde_json::from_str(&contents).with_context(context)?;
+    for o in overrides.iter_mut() {
+        o.validate()
+            .map_err(|e| anyhow::anyhow!("Invalid pair override for {:?}: {e}.", o.projects))?;
+        for project in o.projects.iter_mut() {
+            *project = root.join(&*project);
+        }
+    }
+    Ok(overrides)
+}
+
+/// Reads the review annotations, resolving the names of their projects relative to the root like the projects read
+/// from it. JSON is a subset of YAML, so both formats are read the same way.
+fn read_annotations(root: &Path, path: &Path) -> anyhow::Result<Vec<Annotation>> {
+    let context = || format!("Failed to read the annotations \"{}\".", path.display());
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let mut annotations: Vec<Annotation> = serde_yaml::from_str(&contents).with_context(context)?;
+    for a in annotations.iter_mut() {
+        a.projects = (root.join(&a.projects.0), root.join(&a.projects.1));
+    }
+    Ok(annotations)
+}
+
+/// Reads the CSV file of external scores, resolving the names of their projects relative to the root like the projects
+/// read from it.
+fn read_external_scores(root: &Path, path: &Path) -> anyhow::Result<Vec<ExternalScorePair>> {
+    let context = || format!("Failed to read the external scores \"{}\".", path.display());
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let scores = external_scores::parse_scores_csv(&contents)
+        .map_err(|e| anyhow::anyhow!(e))
+        .with_context(context)?;
+    Ok(external_scores::external_score_pairs(
+        scores
+            .into_iter()
+            .map(|(project1, project2, score)| (root.join(project1), root.join(project2), score))
+            .collect(),
+    ))
+}
+
+/// Reads the CSV file of the only pairs to compare, resolving the names of their projects relative to the root like the
+/// projects read from it.
+fn read_only_pairs(root: &Path, path: &Path) -> anyhow::Result<BTreeSet<(PathBuf, PathBuf)>> {
+    let context = || {
+        format!(
+            "Failed to read the pairs to compare \"{}\".",
+            path.display()
+        )
+    };
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let pairs = allowlist::parse_pairs_csv(&contents)
+        .map_err(|e| anyhow::anyhow!(e))
+        .with_context(context)?;
+    Ok(pairs
+        .into_iter()
+        .map(|(project1, project2)| (root.join(project1), root.join(project2)))
+        .collect())
+}
+
+/// Collects the allowed pairs given on the command line and in the allowed pairs file, resolving the names of their
+/// projects relative to the root like the projects read from it.
+fn read_allowed_pairs(root: &Path, args: &DetectArgs) -> anyhow::Result<AllowedPairs> {
+    let mut pairs = args.allowed_pairs.clone();
+    if let Some(path) = &args.allowed_pairs_file {
+        let context = || format!("Failed to read the allowed pairs \"{}\".", path.display());
+        let contents = fs::read_to_string(path).with_context(context)?;
+        let mut file_pairs = allowlist::parse_pairs_csv(&contents)
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(context)?;
+        pairs.append(&mut file_pairs);
+    }
+
+    let mut allowed_pairs = AllowedPairs::default();
+    for (project1, project2) in pairs {
+        allowed_pairs.insert(root.join(project1), root.join(project2));
+    }
+    Ok(allowed_pairs)
+}
+
+/// Reads the project metadata file, resolving the names of its projects relative to the root like the projects read
+/// from it.
+fn read_project_metadata(
+    root: &Path,
+    path: &Path,
+) -> anyhow::Result<BTreeMap<PathBuf, ProjectMetadata>> {
+    let context = || {
+        format!(
+            "Failed to read the project metadata \"{}\".",
+            path.display()
+        )
+    };
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let metadata: BTreeMap<String, ProjectMetadata> =
+        serde_json::from_str(&contents).with_context(context)?;
+    Ok(metadata
+        .into_iter()
+        .map(|(project, metadata)| (root.join(project), metadata))
+        .collect())
+}
+
+/// Removes the metadata file of each project (i.e., the file named `fungus-meta.json` directly inside the project
+/// directory) from the documents, and returns the metadata of each project which has one. Invalid metadata files are
+/// skipped with a warning.
+fn take_project_metadata_files(
+    documents: &mut Vec<File>,
+    warnings: &mut Vec<Warning>,
+) -> BTreeMap<PathBuf, ProjectMetadata> {
+    let mut project_metadata = BTreeMap::new();
+    documents.retain(|d| {
+        if *d.path() != d.project().join(PROJECT_METADATA_FILE) {
+            return true;
+        }
+        match ProjectMetadata::parse(d.contents()) {
+            Ok(metadata) => {
+                project_metadata.insert(d.project().clone(), metadata);
+            }
+            Err(e) => warnings.push(Warning::invalid_project_metadata(d.path(), &e)),
+        }
+        false
+    });
+    project_metadata
+}
+
+/// Returns the submission time of each project: its timestamp in the project metadata if it has one, or else the latest
+/// modification time of its files. Projects without either are left out.
+fn project_submissions(
+    documents: &[File],
+    project_metadata: &BTreeMap<PathBuf, ProjectMetadata>,
+) -> Vec<Submission> {
+    let mut latest_modified: BTreeMap<&PathBuf, Option<i64>> = BTreeMap::new();
+    for document in documents {
+        let modified = fs::metadata(document.path())
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs() as i64);
+        let latest = latest_modified.entry(document.project()).or_default();
+        *latest = (*latest).max(modified);
+    }
+
+    latest_modified
+        .into_iter()
+        .filter_map(|(project, modified)| {
+            match project_metadata.get(project).and_then(|m| m.timestamp) {
+                Some(timestamp) => Some((timestamp, TimestampSource::Metadata)),
+                None => modified.map(|timestamp| (timestamp, TimestampSource::FileModified)),
+            }
+            .map(|(timestamp, source)| Submission {
+                project: project.clone(),
+                timestamp,
+                source,
+            })
+        })
+        .collect()
+}
+
+/// Submission time of each project, in seconds since the Unix epoch.
+type ProjectTimestamps = BTreeMap<PathBuf, i64>;
+
+/// Reads the files listed in a manifest, whose paths are relative to the manifest. Each project is named by its display
+/// name if it has one, relative to the root like the projects read from it. Also returns the timestamp of each project
+/// which has one.
+fn read_manifest(
+    root: &Path,
+    manifest: &Path,
+) -> anyhow::Result<(Vec<File>, Vec<Warning>, ProjectTimestamps)> {
+    let context = || format!("Failed to read the manifest \"{}\".", manifest.display());
+    let contents = fs::read_to_string(manifest).with_context(context)?;
+    let entries: Vec<ManifestEntry> = serde_json::from_str(&contents).with_context(context)?;
+
+    // Every file of a project must agree on its display name and timestamp
+    let mut names: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut timestamps = BTreeMap::new();
+    for entry in entries.iter() {
+        let name = entry.display_name.as_deref().unwrap_or(&entry.project);
+        if *names.entry(&entry.project).or_insert(name) != name {
+            anyhow::bail!(
+                "Project \"{}\" has several display names in the manifest.",
+                entry.project
+            );
+        }
+        if let Some(timestamp) = entry.timestamp {
+            if *timestamps.entry(root.join(name)).or_insert(timestamp) != timestamp {
+                anyhow::bail!(
+                    "Project \"{}\" has several timestamps in the manifest.",
+                    entry.project
+                );
+            }
+        }
+    }
+    if let Some(name) = names.values().duplicates().next() {
+        anyhow::bail!("Several projects are named \"{name}\" in the manifest.");
+    }
+
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    let base = parent_dir(manifest);
+    for (project, entries) in entries
+        .iter()
+        .into_group_map_by(|entry| names[entry.project.as_str()])
+        .into_iter()
+        .sorted_by_key(|(name, _)| *name)
+    {
+        let project = root.join(project);
+        let num_files = files.len();
+        for entry in entries.into_iter().sorted_by_key(|entry| &entry.path) {
+            match read_file(&project, &base.join(&entry.path)) {
+                Ok((file, warning)) => {
+                    files.push(file);
+                    warnings.extend(warning);
+                }
+                Err(warning) => warnings.push(warning),
+            }
+        }
+        if files.len() == num_files {
+            warnings.push(Warning::empty_project(&project, "no file could be read"));
+        }
+    }
+
+    Ok((files, warnings, timestamps))
+}
+
+/// Reads the files in a NUL-delimited list, read from standard input if `list` is "-". Each file is assigned to a
+/// project according to `rule`. Any paths in `ignore` and the files modified outside of the given range are skipped.
+fn read_file_list(
+    root: &Path,
+    list: &Path,
+    rule: ProjectRule,
+    ignore: &[PathBuf],
+    modified: ModifiedRange,
+) -> anyhow::Result<(Vec<File>, Vec<Warning>)> {
+    let mut contents = Vec::new();
+    if list == Path::new("-") {
+        io::stdin()
+            .read_to_end(&mut contents)
+            .with_context(|| "Failed to read the file list from standard input.")?;
+    } else {
+        contents = fs::read(list)
+            .with_context(|| format!("Failed to read the file list \"{}\".", list.display()))?;
+    }
+
+    let mut paths = Vec::new();
+    for path in contents
+        .split(|&b| b == 0)
+        .filter(|p| !p.is_empty())
+        .map(path_from_bytes)
+    {
+        let project = match rule {
+            ProjectRule::TopLevel => top_level_project(root, &path),
+            ProjectRule::Parent => path.parent().map(Path::to_owned),
+        };
+        let Some(project) = project else {
+            anyhow::bail!(
+                "File \"{}\" from the file list is not inside a project directory of \"{}\".",
+                path.display(),
+                root.display()
+            );
+        };
+        paths.push((project, path));
+    }
+    paths.sort();
+    paths.dedup();
+
+    let mut files = Vec::new();
+    let mut warnings = Vec::new();
+    for (project, path) in paths {
+        if path.is_dir()
+            || ignore.iter().any(|ign| is_same_path(&path, ign))
+            || !modified.contains(&path)
+        {
+            continue;
+        }
+        match read_file(&project, &path) {
+            Ok((file, warning)) => {
+                files.push(file);
+                warnings.extend(warning);
+            }
+            Err(warning) => warnings.push(warning),
+        }
+    }
+    Ok((files, warnings))
+}
+
+/// Returns the directory directly inside the root which contains the file, if any.
+fn top_level_project(root: &Path, path: &Path) -> Option<PathBuf> {
+    // The paths may be spelled differently (e.g., "./root/a.s" and "root"), in which case they are compared absolutely
+    let relative_path = match path.strip_prefix(root) {
+        Ok(relative_path) => relative_path.to_owned(),
+        Err(_) => path
+            .canonicalize()
+            .ok()?
+            .strip_prefix(root.canonicalize().ok()?)
+            .ok()?
+            .to_owned(),
+    };
+    let mut components = relative_path.components();
+    let project = components.next()?;
+    // Files directly inside the root do not belong to any project
+    components.next()?;
+    Some(root.join(project))
+}
+
+/// Converts an entry of a file list to a path. Paths which are not valid UTF-8 are only supported on Unix.
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Returns the directory containing a file, which is the current directory for a relative path without a parent.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
 /// Reads all projects from the given directory. Any paths in `ignore` will be skipped.
-fn read_projects(root: &Path, ignore: &[PathBuf]) -> (Vec<File>, Vec<Warning>) {
+///
+/// The projects are read in parallel by `io_threads` workers (one per core by default), which overlaps the latency of
+/// listing and reading their files (e.g., on a network filesystem). With `lexing` options, the files of each project
+/// are also lexed into their `lexed_documents` by the CPU workers of these options as soon as the project is read,
+/// while the next projects are still being read. The projects are still returned in order of their names.
+fn read_projects(
+    root: &Path,
+    ignore: &[PathBuf],
+    modified: ModifiedRange,
+    limits: TraversalLimits,
+    io_threads: Option<NonZeroUsize>,
+    lexing: Option<&DetectionOptions>,
+) -> (Vec<File>, Vec<Warning>) {
     let mut files = Vec::new();
     let mut warnings = Vec::new();
 
-    for result in WalkDir::new(root).min_depth(1).max_depth(1) {
+    let mut project_dirs = Vec::new();
+    for result in WalkDir::new(root)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_file_name()
+    {
         match result {
             Err(e) => {
                 warnings.push(e.into());
@@ -182,27 +1877,129 @@ fn read_projects(root: &Path, ignore: &[PathBuf]) -> (Vec<File>, Vec<Warning>) {
             Ok(entry) => {
                 // In case an ignored directory or file is inside the projects directory, skip it.
                 // That way we avoid lexing and fingerprinting it twice.
-                if ignore.iter().any(|ign| is_same_path(entry.path(), ign)) {
-                    continue;
+                if !ignore.iter().any(|ign| is_same_path(entry.path(), ign)) {
+                    project_dirs.push(entry.into_path());
                 }
-
-                let (mut fs, mut es) = read_files(entry.path(), ignore);
-                files.append(&mut fs);
-                warnings.append(&mut es);
             }
         }
     }
 
+    let io_workers = io_threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let cpu_workers = lexing.map_or(1, DetectionOptions::num_cpu_threads);
+    let lexed_documents = lexing.and_then(|options| options.lexed_documents.as_deref());
+    let lex = |(files, warnings): (Vec<File>, Vec<Warning>)| {
+        let lexed = match lexing {
+            Some(options) => files.iter().map(|f| lex_document(options, f)).collect(),
+            None => Vec::new(),
+        };
+        (files, warnings, lexed)
+    };
+    // Each IO worker reads a whole project at a time, and each CPU worker lexes a whole project at a time. The projects
+    // are put back in order on this thread, which stops the workers once --max-total-files is reached.
+    let mut next = 0;
+    pipeline::map_in_pipeline(
+        &project_dirs,
+        io_workers,
+        cpu_workers,
+        |dir| read_files(dir, ignore, modified, limits),
+        lex,
+        |dir, (mut fs, mut es, lexed)| {
+            next += 1;
+            let remaining_files = limits.max_total_files.map(|max| max - files.len());
+            if remaining_files == Some(0) {
+                warnings.push(Warning::traversal_limit(
+                    None,
+                    format!(
+                        "Stopped reading the projects after {} files in total (--max-total-files), so {} project(s) were not read.",
+                        files.len(),
+                        project_dirs.len() - next + 1
+                    ),
+                ));
+                return ControlFlow::Break(());
+            }
+            if let Some(remaining) = remaining_files.filter(|&r| r < fs.len()) {
+                // Replace the warning about the limit per project, if any
+                es.retain(|w| *w != too_many_files_warning(dir, fs.len()));
+                es.push(too_many_files_warning(dir, remaining));
+                fs.truncate(remaining);
+            }
+
+            if fs.is_empty() && es.is_empty() && modified.is_bounded() {
+                // None of the files of the project were modified in the given range
+                return ControlFlow::Continue(());
+            }
+            if fs.is_empty() {
+                es.push(Warning::empty_project(dir, "no file could be read"));
+            }
+            if let Some(lexed_documents) = lexed_documents {
+                for (f, lexed) in fs.iter().zip(lexed) {
+                    lexed_documents.insert(f, lexed);
+                }
+            }
+            files.append(&mut fs);
+            warnings.append(&mut es);
+            ControlFlow::Continue(())
+        },
+    );
+
     (files, warnings)
 }
 
+/// Removes the files which are at one of the given paths relative to the directory of their project, or inside a
+/// directory at one of these paths. Returns the paths which do not match any file.
+fn remove_project_relative_files<'a>(
+    documents: &mut Vec<File>,
+    paths: &'a [PathBuf],
+) -> Vec<&'a PathBuf> {
+    let mut matched = vec![false; paths.len()];
+    documents.retain(|d| {
+        let Ok(relative_path) = d.path().strip_prefix(d.project()) else {
+            return true;
+        };
+        let mut keep = true;
+        for (path, matched) in paths.iter().zip(matched.iter_mut()) {
+            if relative_path.starts_with(path) {
+                *matched = true;
+                keep = false;
+            }
+        }
+        keep
+    });
+    paths
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Reads the dictionary of instructions given with --key-symbols, either "armv7" or the path of a file.
+fn read_key_symbols(key_symbols: Option<&str>) -> anyhow::Result<Option<KeySymbols>> {
+    Ok(match key_symbols {
+        None => None,
+        Some("armv7") => Some(KeySymbols::Armv7),
+        Some(path) => {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the key symbol dictionary \"{path}\"."))?;
+            Some(KeySymbols::parse(&contents))
+        }
+    })
+}
+
 /// Reads all files containing starter code.
 fn read_starter_code(ignore: &[PathBuf]) -> (Vec<File>, Vec<Warning>) {
     let mut files = Vec::new();
     let mut warnings = Vec::new();
 
     for path in ignore {
-        let (mut f, mut w) = read_files(path, &[]);
+        let (mut f, mut w) = read_files(
+            path,
+            &[],
+            ModifiedRange::default(),
+            TraversalLimits::default(),
+        );
         files.append(&mut f);
         warnings.append(&mut w);
     }
@@ -210,12 +2007,20 @@ fn read_starter_code(ignore: &[PathBuf]) -> (Vec<File>, Vec<Warning>) {
     (files, warnings)
 }
 
-/// Reads all the files in the given directory or file. The given directory will be used as the project name.
-fn read_files(dir: &Path, files_to_skip: &[PathBuf]) -> (Vec<File>, Vec<Warning>) {
+/// Reads all the files in the given directory or file which were modified in the given range, within the given limits.
+/// The given directory will be used as the project name.
+fn read_files(
+    dir: &Path,
+    files_to_skip: &[PathBuf],
+    modified: ModifiedRange,
+    limits: TraversalLimits,
+) -> (Vec<File>, Vec<Warning>) {
     let mut files = Vec::new();
     let mut warnings = Vec::new();
+    let mut num_deep_directories = 0;
 
-    for result in WalkDir::new(dir) {
+    let mut walker = WalkDir::new(dir).sort_by_file_name().into_iter();
+    while let Some(result) = walker.next() {
         let entry = match result {
             Err(e) => {
                 warnings.push(e.into());
@@ -225,29 +2030,122 @@ fn read_files(dir: &Path, files_to_skip: &[PathBuf]) -> (Vec<File>, Vec<Warning>
         };
         let path = entry.path();
 
-        if path.is_dir() || files_to_skip.iter().any(|f| is_same_path(path, f)) {
+        if path.is_dir() {
+            if limits.max_depth == Some(entry.depth()) {
+                num_deep_directories += 1;
+                walker.skip_current_dir();
+            }
             continue;
         }
+        if files_to_skip.iter().any(|f| is_same_path(path, f)) || !modified.contains(path) {
+            continue;
+        }
+        if limits.max_files_per_project == Some(files.len()) {
+            warnings.push(too_many_files_warning(dir, files.len()));
+            break;
+        }
 
-        match fs::read_to_string(path) {
-            Err(e) => {
-                let warning = Warning {
-                    file: Some(path.to_owned()),
-                    message: e.to_string(),
-                    warn_type: WarningType::Input,
-                };
-                warnings.push(warning);
-            }
-            Ok(contents) => {
-                let file = File::new(dir.to_owned(), path.to_owned(), contents);
+        match read_file(dir, path) {
+            Ok((file, warning)) => {
                 files.push(file);
+                warnings.extend(warning);
             }
+            Err(warning) => warnings.push(warning),
         }
     }
 
+    if num_deep_directories > 0 {
+        warnings.push(Warning::traversal_limit(
+            Some(dir),
+            format!(
+                "{num_deep_directories} director(y/ies) nested more than {} level(s) deep (--max-depth) were not read.",
+                limits.max_depth.unwrap_or_default()
+            ),
+        ));
+    }
+
     (files, warnings)
 }
 
+/// Creates the warning for a project of which only the first `num_files` files were read.
+fn too_many_files_warning(project: &Path, num_files: usize) -> Warning {
+    Warning::traversal_limit(
+        Some(project),
+        format!("Stopped reading the project after {num_files} files (--max-files-per-project or --max-total-files), so the remaining files were not analyzed. Check that the submission does not contain unrelated files."),
+    )
+}
+
+/// Limits on the traversal of the projects directory, so that a project containing unrelated files (e.g., a whole home
+/// directory) does not blow up the analysis. `None` means no limit.
+#[derive(Clone, Copy, Debug, Default)]
+struct TraversalLimits {
+    /// Maximum depth of the files read, relative to the directory of their project.
+    max_depth: Option<usize>,
+    max_files_per_project: Option<usize>,
+    max_total_files: Option<usize>,
+}
+
+impl TraversalLimits {
+    fn new(args: &DetectArgs) -> TraversalLimits {
+        TraversalLimits {
+            max_depth: args.max_depth,
+            max_files_per_project: args.max_files_per_project,
+            max_total_files: args.max_total_files,
+        }
+    }
+}
+
+/// Range of modification times of the files to analyze, in seconds since the Unix epoch. Both bounds are exclusive.
+#[derive(Clone, Copy, Debug, Default)]
+struct ModifiedRange {
+    after: Option<i64>,
+    before: Option<i64>,
+}
+
+impl ModifiedRange {
+    fn is_bounded(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    /// Checks whether the file was modified in the range. Files whose modification time is unknown are kept.
+    fn contains(&self, path: &Path) -> bool {
+        if !self.is_bounded() {
+            return true;
+        }
+        let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else {
+            return true;
+        };
+        let to_time = |seconds: i64| {
+            let offset = Duration::from_secs(seconds.unsigned_abs());
+            if seconds >= 0 {
+                SystemTime::UNIX_EPOCH + offset
+            } else {
+                SystemTime::UNIX_EPOCH - offset
+            }
+        };
+        self.after.is_none_or(|after| modified > to_time(after))
+            && self.before.is_none_or(|before| modified < to_time(before))
+    }
+}
+
+/// Reads a file of the given project, or returns a warning if it cannot be read. Files starting with a UTF-8 byte order
+/// mark or encoded in UTF-16 are converted to plain UTF-8, along with a warning.
+fn read_file(project: &Path, path: &Path) -> Result<(File, Option<Warning>), Warning> {
+    let unreadable = |message: String| Warning {
+        file: Some(path.to_owned()),
+        message,
+        warn_type: WarningType::Input,
+        code: WarningCode::UnreadableFile,
+    };
+    let bytes = fs::read(path).map_err(|e| unreadable(e.to_string()))?;
+    let (contents, encoding) = encoding::decode(bytes).map_err(unreadable)?;
+    let warning = encoding.map(|encoding| Warning::converted_encoding(path, encoding));
+    Ok((
+        File::new(project.to_owned(), path.to_owned(), contents),
+        warning,
+    ))
+}
+
 /// Checks if two paths refer to the same file or directory. The two paths may be the same even if their representation
 /// is different. For example, `.` and `foo/..` refer to the same directory (assuming `foo` exists).
 fn is_same_path(path1: &Path, path2: &Path) -> bool {
@@ -258,17 +2156,33 @@ fn is_same_path(path1: &Path, path2: &Path) -> bool {
     }
 }
 
+/// Writes the output in the requested format. `ndjson` is the writer to which the project pairs were already streamed,
+/// if they were.
 fn output_results(
     output: &mut Output,
-    output_file: &Path,
-    pretty: bool,
+    args: &DetectArgs,
     root: &Path,
+    ndjson: Option<NdjsonWriter<BufWriter<fs::File>>>,
 ) -> anyhow::Result<()> {
+    let output_file = &args.output_file;
+
+    output.sort_project_pairs(args.pair_order, args.match_order);
+    output.assign_match_ids(root);
     output
-        .make_paths_relative_to(root)
-        .with_context(|| "Failed to make paths relative to the projects directory.")?;
+        .format_paths(root, args.path_style)
+        .with_context(|| "Failed to format the paths in the output.")?;
 
-    eprintln!("{} warnings.", output.warnings.len());
+    let warning_counts = output
+        .summary
+        .warnings_by_code
+        .iter()
+        .map(|(code, count)| format!("{count} {}", code.description()))
+        .join(", ");
+    if warning_counts.is_empty() {
+        eprintln!("0 warnings.");
+    } else {
+        eprintln!("{} warnings ({warning_counts}).", output.warnings.len());
+    }
     if !output.warnings.is_empty() {
         for w in output.warnings.iter() {
             eprintln!("{w}");
@@ -276,16 +2190,262 @@ fn output_results(
         eprintln!();
     }
 
-    let json = if pretty {
-        serde_json::to_string_pretty(&output).unwrap()
-    } else {
-        serde_json::to_string(&output).unwrap()
-    };
+    if args.integrity_hash || args.integrity_key_file.is_some() {
+        let key = args
+            .integrity_key_file
+            .as_deref()
+            .map(read_integrity_key)
+            .transpose()?;
+        output.integrity = None;
+        let integrity = integrity::integrity(&*output, key.as_deref())
+            .map_err(anyhow::Error::msg)
+            .context("Failed to compute the integrity hash of the output.")?;
+        output.integrity = Some(integrity);
+    }
 
-    fs::write(output_file, json)
-        .with_context(|| format!("Failed to write output to \"{}\".", output_file.display()))?;
+    match args.output_format {
+        OutputFormat::Json => {
+            write_json(output, output_file, args.pretty, args.compress).with_context(|| {
+                format!("Failed to write output to \"{}\".", output_file.display())
+            })?;
+        }
+        OutputFormat::Ndjson => {
+            write_ndjson(output, output_file, args.ndjson_per_match, ndjson).with_context(
+                || format!("Failed to write output to \"{}\".", output_file.display()),
+            )?;
+        }
+        OutputFormat::Html => {
+            report::html::write_html_report(output, root, output_file, args.context)?;
+        }
+        OutputFormat::Pdf => {
+            report::pdf::write_pdf_report(output, root, output_file, args.context)?;
+        }
+        OutputFormat::Gitlab => {
+            report::gitlab::write_gitlab_report(output, root, output_file)?;
+        }
+        OutputFormat::ClonePairs => {
+            report::clone_pairs::write_clone_pairs(output, root, output_file)?;
+        }
+        OutputFormat::Github => {
+            report::github::print_github_annotations(output, root);
+            return Ok(());
+        }
+    }
 
     println!("Wrote output to \"{}\".", output_file.display());
+    if let Some(integrity) = &output.integrity {
+        let algorithm = match integrity.algorithm {
+            IntegrityAlgorithm::Sha256 => "SHA-256",
+            IntegrityAlgorithm::HmacSha256 => "HMAC-SHA256",
+        };
+        println!("Integrity hash ({algorithm}): {}", integrity.digest);
+    }
+
+    Ok(())
+}
+
+/// Returns the command-line arguments of the run, without the program name and without --verify-manifest, so that a run
+/// reproducing an earlier one has the same arguments.
+fn reproducible_arguments() -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--verify-manifest" {
+            args.next();
+        } else if !arg.starts_with("--verify-manifest=") {
+            arguments.push(arg);
+        }
+    }
+    arguments
+}
+
+/// Fails if the inputs or parameters of the run differ from those recorded in the reproducibility manifest.
+fn verify_manifest(path: &Path, run_manifest: &RunManifest) -> anyhow::Result<()> {
+    let context = || {
+        format!(
+            "Failed to read the reproducibility manifest \"{}\".",
+            path.display()
+        )
+    };
+    let contents = fs::read_to_string(path).with_context(context)?;
+    let original: RunManifest = serde_json::from_str(&contents).with_context(context)?;
+    let mismatches = run_manifest.mismatches(&original);
+    if !mismatches.is_empty() {
+        anyhow::bail!(
+            "This run does not reproduce run {}:\n  {}",
+            original.run_id,
+            mismatches.join("\n  ")
+        );
+    }
+    eprintln!(
+        "The inputs and parameters match those of run {}.",
+        original.run_id
+    );
+    Ok(())
+}
+
+fn write_run_manifest(run_manifest: &RunManifest, path: &Path) -> anyhow::Result<()> {
+    let writer = BufWriter::new(fs::File::create(path)?);
+    serde_json::to_writer_pretty(writer, run_manifest)?;
+    Ok(())
+}
+
+/// Reads the secret key of the integrity HMAC, without its trailing whitespace.
+fn read_integrity_key(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut key = fs::read(path).with_context(|| {
+        format!(
+            "Failed to read the integrity key file \"{}\".",
+            path.display()
+        )
+    })?;
+    while key.last().is_some_and(u8::is_ascii_whitespace) {
+        key.pop();
+    }
+    if key.is_empty() {
+        anyhow::bail!("The integrity key file \"{}\" is empty.", path.display());
+    }
+    Ok(key)
+}
+
+/// Checks the integrity hash of an existing output file, which may be compressed.
+fn verify_output(args: VerifyOutputArgs) -> anyhow::Result<()> {
+    let context = || {
+        format!(
+            "Failed to read output file \"{}\".",
+            args.output_file.display()
+        )
+    };
+    let bytes = fs::read(&args.output_file).with_context(context)?;
+    let mut contents = String::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut contents)
+            .with_context(context)?;
+    } else {
+        contents = String::from_utf8(bytes).with_context(context)?;
+    }
+    let key = args
+        .integrity_key_file
+        .as_deref()
+        .map(read_integrity_key)
+        .transpose()?;
+
+    let intact = integrity::verify(&contents, key.as_deref()).map_err(anyhow::Error::msg)?;
+    if !intact {
+        anyhow::bail!(
+            "The output file \"{}\" does not match its integrity hash, so it was modified after it was generated (or the key is wrong).",
+            args.output_file.display()
+        );
+    }
+    println!(
+        "The output file \"{}\" matches its integrity hash.",
+        args.output_file.display()
+    );
+    Ok(())
+}
+
+/// Streams the output to the given file as JSON, without first building the whole document in memory.
+fn write_json(
+    output: &Output,
+    output_file: &Path,
+    pretty: bool,
+    compress: bool,
+) -> anyhow::Result<()> {
+    let writer = BufWriter::new(fs::File::create(output_file)?);
+
+    if compress {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        serialize_json(&mut encoder, output, pretty)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut writer = writer;
+        serialize_json(&mut writer, output, pretty)?;
+        writer.flush()?;
+    }
 
     Ok(())
 }
+
+/// Writes the output to the given file as newline-delimited JSON, or only the records following the project pairs if
+/// they were already streamed to `streamed`.
+fn write_ndjson(
+    output: &Output,
+    output_file: &Path,
+    per_match: bool,
+    streamed: Option<NdjsonWriter<BufWriter<fs::File>>>,
+) -> anyhow::Result<()> {
+    let mut writer = match streamed {
+        Some(ndjson) => ndjson.finish(output)?,
+        None => {
+            let mut writer = BufWriter::new(fs::File::create(output_file)?);
+            output.write_ndjson(&mut writer, per_match)?;
+            writer
+        }
+    };
+    writer.flush()?;
+    Ok(())
+}
+
+fn serialize_json<W: Write>(writer: W, output: &Output, pretty: bool) -> serde_json::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(writer, output)
+    } else {
+        serde_json::to_writer(writer, output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_counts_must_be_positive() {
+        let parse = |flag, value| Args::try_parse_from(["fungus", "projects", flag, value]);
+        for flag in ["--io-threads", "--cpu-threads"] {
+            assert!(parse(flag, "0").is_err());
+            assert!(parse(flag, "many").is_err());
+        }
+        let args = parse("--io-threads", "64").unwrap();
+        assert_eq!(args.detect.io_threads, NonZeroUsize::new(64));
+        let args = parse("--cpu-threads", "2").unwrap();
+        assert_eq!(args.detect.cpu_threads, NonZeroUsize::new(2));
+    }
+
+    #[test]
+    fn projects_are_lexed_while_they_are_read() {
+        let root =
+            std::env::temp_dir().join(format!("fungus-read-projects-{}", std::process::id()));
+        for project in ["a", "b", "c"] {
+            fs::create_dir_all(root.join(project)).unwrap();
+            fs::write(root.join(project).join("main.s"), "mov r0, #1").unwrap();
+        }
+
+        for (io_threads, cpu_threads) in [(1, 1), (4, 2)] {
+            let lexing_options = DetectionOptions {
+                cpu_threads: NonZeroUsize::new(cpu_threads),
+                lexed_documents: Some(Arc::new(LexedDocuments::default())),
+                ..Default::default()
+            };
+            let limits = TraversalLimits {
+                max_total_files: Some(2),
+                ..Default::default()
+            };
+            let (documents, warnings) = read_projects(
+                &root,
+                &[],
+                ModifiedRange::default(),
+                limits,
+                NonZeroUsize::new(io_threads),
+                Some(&lexing_options),
+            );
+
+            let projects = documents.iter().map(File::project).collect::<Vec<_>>();
+            assert_eq!(projects, [&root.join("a"), &root.join("b")]);
+            assert_eq!(warnings.len(), 1);
+            let lexed_documents = lexing_options.lexed_documents.unwrap();
+            assert_eq!(lexed_documents.len(), 2);
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+//! Manifest listing the files of each project explicitly, for submissions which are exported programmatically (e.g.,
+//! from a database or an object store) rather than laid out in one directory per project.
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// One file of a project, as listed in a manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Name of the project containing the file. The project does not need to exist as a directory.
+    pub project: String,
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Name under which the project is shown in the output, instead of `project`.
+    pub display_name: Option<String>,
+    /// Time at which the project was submitted, in seconds since the Unix epoch.
+    pub timestamp: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_entries() {
+        let entries: Vec<ManifestEntry> = serde_json::from_str(
+            r#"[
+                {"project": "1234", "path": "export/1234/main.s", "display_name": "Alice", "timestamp": 1700000000},
+                {"project": "5678", "path": "export/5678/main.s"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(entries[0].display_name.as_deref(), Some("Alice"));
+        assert_eq!(entries[0].timestamp, Some(1_700_000_000));
+        assert_eq!(entries[1].path, PathBuf::from("export/5678/main.s"));
+        assert!(serde_json::from_str::<ManifestEntry>(r#"{"project": "1234"}"#).is_err());
+    }
+}
@@ -1,44 +1,44 @@
-use std::{
-    collections::{HashMap, HashSet},
-    ops::Range,
-};
+use std::{collections::HashMap, ops::Range};
 
 use crate::{
+    interning::PathArena,
     output::{Location, Match, ProjectPair},
-    FileId,
+    scoring, FileId,
 };
 
 pub fn expand_matches(
     pair: ProjectPair,
     document_hashes: &HashMap<FileId, Vec<(u64, Range<usize>)>>,
+    arena: &PathArena,
 ) -> ProjectPair {
     // For every match, expand the match as much as possible.
-    // Store the expanded matches in a hash set to avoid duplicates.
-    let mut expanded_matches = HashSet::new();
+    // Store the expanded matches in a hash map to avoid duplicates, along with the weights of the matches they were
+    // expanded from.
+    let mut expanded_matches: HashMap<(Location, Location), Vec<f64>> = HashMap::new();
 
     for Match {
         project_1_location,
         project_2_location,
+        weight,
+        ..
     } in pair.matches
     {
-        let file_1_id = FileId::new(pair.project1.clone(), project_1_location.file.clone());
-        let file_2_id = FileId::new(pair.project2.clone(), project_2_location.file.clone());
-
-        let file_1_hashed_tokens = &document_hashes[&file_1_id];
-        let file_2_hashed_tokens = &document_hashes[&file_2_id];
+        let file_id = |project, location: &Location| {
+            arena
+                .file_id(project, &location.file)
+                .expect("the files of the matches are interned")
+        };
+        let file_1_hashed_tokens = &document_hashes[&file_id(&pair.project1, &project_1_location)];
+        let file_2_hashed_tokens = &document_hashes[&file_id(&pair.project2, &project_2_location)];
 
         let mut location_1_match_span = project_1_location.span;
         let mut location_2_match_span = project_2_location.span;
 
         // Find the start of the match in each file
-        let mut location_1_start = file_1_hashed_tokens
-            .iter()
-            .position(|(_, range)| range.start == location_1_match_span.start)
-            .unwrap();
-        let mut location_2_start = file_2_hashed_tokens
-            .iter()
-            .position(|(_, range)| range.start == location_2_match_span.start)
-            .unwrap();
+        let mut location_1_start =
+            token_index_starting_at(file_1_hashed_tokens, location_1_match_span.start).unwrap();
+        let mut location_2_start =
+            token_index_starting_at(file_2_hashed_tokens, location_2_match_span.start).unwrap();
 
         // Expand the match upwards (towards the start of the file) as much as possible
         while location_1_start > 0
@@ -54,14 +54,10 @@ pub fn expand_matches(
         location_2_match_span.start = file_2_hashed_tokens[location_2_start].1.start;
 
         // Find the end of the match in each file
-        let mut location_1_end = file_1_hashed_tokens
-            .iter()
-            .rposition(|(_t, range)| range.end == location_1_match_span.end)
-            .unwrap();
-        let mut location_2_end = file_2_hashed_tokens
-            .iter()
-            .rposition(|(_t, range)| range.end == location_2_match_span.end)
-            .unwrap();
+        let mut location_1_end =
+            token_index_ending_at(file_1_hashed_tokens, location_1_match_span.end).unwrap();
+        let mut location_2_end =
+            token_index_ending_at(file_2_hashed_tokens, location_2_match_span.end).unwrap();
 
         // Expand the match downwards (towards the end of the file) as much as possible
         while location_1_end < file_1_hashed_tokens.len() - 1
@@ -77,25 +73,70 @@ pub fn expand_matches(
         location_2_match_span.end = file_2_hashed_tokens[location_2_end].1.end;
 
         // Store the expanded match
-        expanded_matches.insert(Match {
-            project_1_location: Location {
-                file: project_1_location.file.clone(),
-                span: location_1_match_span,
-            },
-            project_2_location: Location {
-                file: project_2_location.file.clone(),
-                span: location_2_match_span,
-            },
-        });
+        let weights = expanded_matches
+            .entry((
+                Location {
+                    file: project_1_location.file.clone(),
+                    span: location_1_match_span,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                Location {
+                    file: project_2_location.file.clone(),
+                    span: location_2_match_span,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+            ))
+            .or_default();
+        weights.extend(weight);
     }
 
+    let matches = expanded_matches
+        .into_iter()
+        .map(
+            |((project_1_location, project_2_location), weights)| Match {
+                id: None,
+                project_1_location,
+                project_2_location,
+                weight: (!weights.is_empty()).then(|| scoring::sum_weights(weights)),
+            },
+        )
+        .collect();
+
     ProjectPair {
         project1: pair.project1,
         project2: pair.project2,
-        matches: expanded_matches.into_iter().collect(),
+        matches,
+        score: pair.score,
+        explanation: pair.explanation,
+        earlier_submission: pair.earlier_submission,
+        review: pair.review,
     }
 }
 
+/// Returns the index of the token which starts at the given byte offset, if any.
+///
+/// The tokens must be sorted by position and must not overlap, which is the case for the output of the lexer.
+pub(crate) fn token_index_starting_at(
+    tokens: &[(u64, Range<usize>)],
+    start: usize,
+) -> Option<usize> {
+    let index = tokens.partition_point(|(_, range)| range.start < start);
+    tokens
+        .get(index)
+        .filter(|(_, range)| range.start == start)
+        .map(|_| index)
+}
+
+/// Returns the index of the token which ends at the given byte offset, if any.
+///
+/// The tokens must be sorted by position and must not overlap, which is the case for the output of the lexer.
+pub(crate) fn token_index_ending_at(tokens: &[(u64, Range<usize>)], end: usize) -> Option<usize> {
+    let index = tokens.partition_point(|(_, range)| range.end <= end);
+    index.checked_sub(1).filter(|&i| tokens[i].1.end == end)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -103,13 +144,14 @@ mod tests {
 
     #[test]
     fn expands_incomplete_matches() {
+        let mut arena = PathArena::default();
         let document_hashes: HashMap<FileId, Vec<(u64, Range<usize>)>> = HashMap::from([
             (
-                FileId::new("p1".into(), "f1".into()),
+                arena.intern_file("p1".as_ref(), "f1".as_ref()),
                 vec![(1, 0..1), (2, 1..2), (3, 2..3)],
             ),
             (
-                FileId::new("p2".into(), "f2".into()),
+                arena.intern_file("p2".as_ref(), "f2".as_ref()),
                 vec![(1, 0..1), (2, 1..2), (3, 2..3)],
             ),
         ]);
@@ -118,45 +160,66 @@ mod tests {
             project1: "p1".into(),
             project2: "p2".into(),
             matches: vec![Match {
+                id: None,
                 project_1_location: Location {
                     file: "f1".into(),
                     span: 1..2,
+                    length_bytes: None,
+                    length_tokens: None,
                 },
                 project_2_location: Location {
                     file: "f2".into(),
                     span: 1..2,
+                    length_bytes: None,
+                    length_tokens: None,
                 },
+                weight: None,
             }],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
         };
 
         assert_eq!(
-            expand_matches(project_pair, &document_hashes),
+            expand_matches(project_pair, &document_hashes, &arena),
             ProjectPair {
                 project1: "p1".into(),
                 project2: "p2".into(),
                 matches: vec![Match {
+                    id: None,
                     project_1_location: Location {
                         file: "f1".into(),
                         span: 0..3,
+                        length_bytes: None,
+                        length_tokens: None,
                     },
                     project_2_location: Location {
                         file: "f2".into(),
                         span: 0..3,
+                        length_bytes: None,
+                        length_tokens: None,
                     },
-                },]
+                    weight: None,
+                },],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
             }
         );
     }
 
     #[test]
     fn does_not_expand_complete_matches() {
+        let mut arena = PathArena::default();
         let document_hashes: HashMap<FileId, Vec<(u64, Range<usize>)>> = HashMap::from([
             (
-                FileId::new("p1".into(), "f1".into()),
+                arena.intern_file("p1".as_ref(), "f1".as_ref()),
                 vec![(1, 0..1), (2, 1..2), (3, 2..3)],
             ),
             (
-                FileId::new("p2".into(), "f2".into()),
+                arena.intern_file("p2".as_ref(), "f2".as_ref()),
                 vec![(3, 0..1), (2, 1..2), (1, 2..3)],
             ),
         ]);
@@ -165,33 +228,114 @@ mod tests {
             project1: "p1".into(),
             project2: "p2".into(),
             matches: vec![Match {
+                id: None,
                 project_1_location: Location {
                     file: "f1".into(),
                     span: 1..2,
+                    length_bytes: None,
+                    length_tokens: None,
                 },
                 project_2_location: Location {
                     file: "f2".into(),
                     span: 1..2,
+                    length_bytes: None,
+                    length_tokens: None,
                 },
+                weight: None,
             }],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
         };
 
         assert_eq!(
-            expand_matches(project_pair, &document_hashes),
+            expand_matches(project_pair, &document_hashes, &arena),
             ProjectPair {
                 project1: "p1".into(),
                 project2: "p2".into(),
                 matches: vec![Match {
+                    id: None,
                     project_1_location: Location {
                         file: "f1".into(),
                         span: 1..2,
+                        length_bytes: None,
+                        length_tokens: None,
                     },
                     project_2_location: Location {
                         file: "f2".into(),
                         span: 1..2,
+                        length_bytes: None,
+                        length_tokens: None,
                     },
-                },]
+                    weight: None,
+                },],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
             }
         );
     }
+
+    #[test]
+    fn finds_tokens_by_offset() {
+        let tokens = vec![(1, 0..3), (2, 4..5), (3, 5..9)];
+
+        assert_eq!(token_index_starting_at(&tokens, 0), Some(0));
+        assert_eq!(token_index_starting_at(&tokens, 5), Some(2));
+        assert_eq!(token_index_starting_at(&tokens, 3), None);
+        assert_eq!(token_index_starting_at(&tokens, 10), None);
+        assert_eq!(token_index_ending_at(&tokens, 3), Some(0));
+        assert_eq!(token_index_ending_at(&tokens, 9), Some(2));
+        assert_eq!(token_index_ending_at(&tokens, 4), None);
+        assert_eq!(token_index_ending_at(&tokens, 0), None);
+        assert_eq!(token_index_ending_at(&[], 1), None);
+    }
+
+    #[test]
+    fn merged_matches_add_up_their_weights() {
+        let mut arena = PathArena::default();
+        let document_hashes: HashMap<FileId, Vec<(u64, Range<usize>)>> = HashMap::from([
+            (
+                arena.intern_file("p1".as_ref(), "f1".as_ref()),
+                vec![(1, 0..1), (2, 1..2), (3, 2..3)],
+            ),
+            (
+                arena.intern_file("p2".as_ref(), "f2".as_ref()),
+                vec![(1, 0..1), (2, 1..2), (3, 2..3)],
+            ),
+        ]);
+        let seed = |span: Range<usize>, weight| Match {
+            id: None,
+            project_1_location: Location {
+                file: "f1".into(),
+                span: span.clone(),
+                length_bytes: None,
+                length_tokens: None,
+            },
+            project_2_location: Location {
+                file: "f2".into(),
+                span,
+                length_bytes: None,
+                length_tokens: None,
+            },
+            weight: Some(weight),
+        };
+
+        let project_pair = ProjectPair {
+            project1: "p1".into(),
+            project2: "p2".into(),
+            matches: vec![seed(0..1, 0.5), seed(2..3, 0.25)],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+
+        assert_eq!(
+            expand_matches(project_pair, &document_hashes, &arena).matches,
+            vec![seed(0..3, 0.75)]
+        );
+    }
 }
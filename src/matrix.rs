@@ -0,0 +1,118 @@
+//! Similarity matrix over every pair of projects, including the pairs which are not reported, to show the overall
+//! structure of the similarity in a class (e.g., clusters of projects sharing code).
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diagnostics::Diagnostics,
+    output::{self, PathStyle},
+    DetectionOptions,
+};
+
+/// Square matrix of the similarity between every two projects of an analysis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimilarityMatrix {
+    /// Every analyzed project, sorted by name.
+    pub projects: Vec<PathBuf>,
+    /// Proportion of the code of the project of each row (excluding starter code) which is covered by its matches with
+    /// the project of each column. Missing on the diagonal and for the pairs which are not compared (e.g., across
+    /// sections with `CompareMode::Within`). Compared pairs without any match have a coverage of 0.
+    pub coverage: Vec<Vec<Option<f64>>>,
+}
+
+impl SimilarityMatrix {
+    /// Builds the matrix of the projects of an analysis from its diagnostics. Pairs removed by the thresholds (e.g.,
+    /// `DetectionOptions::min_matches`) are included, but those whose matches were not completed because the analysis
+    /// was interrupted are not.
+    pub fn new(options: &DetectionOptions, diagnostics: &Diagnostics) -> SimilarityMatrix {
+        let projects = diagnostics
+            .projects
+            .iter()
+            .map(|p| p.project.clone())
+            .collect::<Vec<_>>();
+        let coverage_by_pair = diagnostics
+            .completed_pairs
+            .iter()
+            .flat_map(|c| {
+                [
+                    ((&c.project1, &c.project2), c.project_1_coverage),
+                    ((&c.project2, &c.project1), c.project_2_coverage),
+                ]
+            })
+            .collect::<HashMap<_, _>>();
+
+        let coverage = projects
+            .iter()
+            .map(|row| {
+                projects
+                    .iter()
+                    .map(|column| {
+                        if row == column || !options.compares(row, column) {
+                            return None;
+                        }
+                        Some(coverage_by_pair.get(&(row, column)).copied().unwrap_or(0.0))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        SimilarityMatrix { projects, coverage }
+    }
+
+    /// Formats the names of the projects in the given style, like the paths of the output.
+    pub fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        for project in self.projects.iter_mut() {
+            *project = output::format_path(project, root, style)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{detect_plagiarism_with_diagnostics, CompareMode, File, TokenizingStrategy};
+
+    #[test]
+    fn unreported_pairs_are_included() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "abcdyyyy".to_owned()),
+            File::new("P4".into(), "P4/file.txt".into(), "zzzzzzzz".to_owned()),
+        ];
+        let options = DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            expand_matches: false,
+            min_matches: 3,
+            compare: CompareMode::Within,
+            project_sections: [("P1", "A"), ("P2", "A"), ("P3", "A"), ("P4", "B")]
+                .into_iter()
+                .map(|(project, section)| (project.into(), section.to_owned()))
+                .collect(),
+            ..Default::default()
+        };
+
+        let (project_pairs, _, diagnostics) =
+            detect_plagiarism_with_diagnostics(&options, &documents, &[]);
+        let matrix = SimilarityMatrix::new(&options, &diagnostics);
+
+        assert_eq!(project_pairs.len(), 1);
+        assert_eq!(
+            matrix.projects,
+            vec![PathBuf::from("P1"), "P2".into(), "P3".into(), "P4".into()]
+        );
+        let row = |i: usize| matrix.coverage[i].clone();
+        assert_eq!(row(0), vec![None, Some(1.0), Some(0.5), None]);
+        assert_eq!(row(2), vec![Some(0.5), Some(0.5), None, None]);
+        assert_eq!(row(3), vec![None, None, None, None]);
+    }
+}
@@ -0,0 +1,106 @@
+//! Estimates of the memory taken by the largest structures of an analysis (the hash database and the matches), so that
+//! an analysis exceeding `DetectionOptions::max_memory` stops with partial results instead of being killed by the
+//! operating system.
+
+use std::{mem, ops::Range};
+
+use crate::{
+    output::{Warning, WarningCode, WarningType},
+    DocumentMatch, FileId,
+};
+
+/// Parses a memory size given in bytes, or with one of the suffixes "K", "M", "G", or "T" (powers of 1024, optionally
+/// followed by "B" or "iB", e.g. "512M" or "4GiB").
+pub fn parse_memory_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let digits_end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits_end);
+    let number = number
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid memory size \"{s}\"."))?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let exponent = match unit.trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(format!("Invalid memory size unit \"{unit}\" in \"{s}\".")),
+    };
+    number
+        .checked_mul(1024usize.pow(exponent))
+        .filter(|&bytes| bytes > 0)
+        .ok_or_else(|| format!("Invalid memory size \"{s}\"."))
+}
+
+/// Running estimate of the memory taken by the structures of an analysis, compared with a limit.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MemoryBudget {
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: Option<usize>) -> MemoryBudget {
+        MemoryBudget { limit, used: 0 }
+    }
+
+    /// Adds the given number of bytes to the estimate, and returns whether the estimate is still within the limit.
+    pub(crate) fn add(&mut self, bytes: usize) -> bool {
+        self.used += bytes;
+        self.limit.is_none_or(|limit| self.used <= limit)
+    }
+
+    /// Estimated number of bytes taken by a new entry of the hash database with a key of type `K`, excluding its
+    /// locations.
+    pub(crate) fn hash_entry_bytes<K>() -> usize {
+        // The key, the vector of locations, and the control byte of the hash table
+        mem::size_of::<K>() + mem::size_of::<Vec<(&FileId, Range<usize>)>>() + 1
+    }
+
+    /// Estimated number of bytes taken by a location in the hash database.
+    pub(crate) fn hash_location_bytes() -> usize {
+        mem::size_of::<(&FileId, Range<usize>)>()
+    }
+
+    /// Estimated number of bytes taken by a match. Its files are interned, so their paths take no extra memory.
+    pub(crate) fn match_bytes() -> usize {
+        mem::size_of::<DocumentMatch>()
+    }
+
+    /// Creates the warning issued when an analysis stops because of the limit, in the given stage.
+    pub(crate) fn exceeded_warning(&self, stage: &str) -> Warning {
+        let mebibytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+        Warning {
+            file: None,
+            message: format!(
+                "The analysis was stopped while {stage} because it would take more than the maximum memory of {:.1} MiB (about {:.1} MiB estimated so far). The results are partial: raise the memory limit, raise the noise threshold, or give the common code threshold to analyze these projects completely.",
+                mebibytes(self.limit.unwrap_or_default()),
+                mebibytes(self.used)
+            ),
+            warn_type: WarningType::Fingerprint,
+            code: WarningCode::MemoryLimit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_memory_sizes() {
+        assert_eq!(parse_memory_size("1024"), Ok(1024));
+        assert_eq!(parse_memory_size("512M"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_memory_size("4GiB"), Ok(4 * 1024 * 1024 * 1024));
+        assert_eq!(parse_memory_size("2 kb"), Ok(2048));
+        assert!(parse_memory_size("0").is_err());
+        assert!(parse_memory_size("4X").is_err());
+        assert!(parse_memory_size("G").is_err());
+
+        let mut budget = MemoryBudget::new(Some(10));
+        assert!(budget.add(10));
+        assert!(!budget.add(1));
+        assert!(MemoryBudget::new(None).add(usize::MAX));
+    }
+}
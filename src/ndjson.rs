@@ -0,0 +1,280 @@
+//! Streams the project pairs to newline-delimited JSON output as soon as the analysis scores them, so that consumers
+//! can start processing them before the analysis ends.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use fungus_cli::{
+    allowlist::AllowedPairs,
+    annotations::{self, Annotation},
+    output::{MatchOrder, NdjsonWriter, PathStyle, ProjectPair},
+    submissions::SubmissionTimes,
+    DetectionObserver, File, Progress, Stage,
+};
+
+/// Changes made to the project pairs after the analysis, which are applied to each pair before it is streamed so that
+/// its record is the same as in the complete output. The z-scores of the pairs depend on every pair, so they are left
+/// out.
+pub struct PairEdits<'a> {
+    pub root: &'a Path,
+    pub path_style: PathStyle,
+    pub match_order: MatchOrder,
+    pub min_match_tokens: Option<usize>,
+    pub allowed_pairs: &'a AllowedPairs,
+    pub annotations: &'a [Annotation],
+    pub submission_times: Option<SubmissionTimes<'a>>,
+}
+
+impl PairEdits<'_> {
+    /// Applies the changes to a pair, or returns `None` if the pair is left out of the output.
+    fn apply(&self, mut pair: ProjectPair) -> io::Result<Option<ProjectPair>> {
+        if self.allowed_pairs.contains(&pair.project1, &pair.project2) {
+            return Ok(None);
+        }
+        if let Some(min_tokens) = self.min_match_tokens {
+            pair.remove_short_matches(min_tokens);
+            if pair.matches.is_empty() {
+                return Ok(None);
+            }
+        }
+        pair.review = annotations::review_for(self.annotations, &pair).cloned();
+        if let Some(times) = &self.submission_times {
+            pair.earlier_submission = times.earlier_submission(&pair);
+        }
+        pair.matches
+            .sort_by(|m1, m2| self.match_order.compare(m1, m2));
+        pair.assign_match_ids(self.root);
+        pair.format_paths(self.root, self.path_style)?;
+        Ok(Some(pair))
+    }
+}
+
+/// Observer writing each project pair to the output as soon as it is scored, in no particular order, and passing every
+/// notification on to another observer.
+pub struct NdjsonStream<'a, W: Write> {
+    writer: NdjsonWriter<W>,
+    edits: PairEdits<'a>,
+    observer: &'a mut dyn DetectionObserver,
+    /// First error which occurred while writing the pairs, after which the remaining pairs are not written.
+    error: Option<io::Error>,
+}
+
+impl<'a, W: Write> NdjsonStream<'a, W> {
+    pub fn new(
+        writer: NdjsonWriter<W>,
+        edits: PairEdits<'a>,
+        observer: &'a mut dyn DetectionObserver,
+    ) -> Self {
+        NdjsonStream {
+            writer,
+            edits,
+            observer,
+            error: None,
+        }
+    }
+
+    /// Returns the writer, so that the rest of the output can be written once the analysis is over, or the first error
+    /// which occurred while writing the pairs.
+    pub fn into_writer(self) -> io::Result<NdjsonWriter<W>> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.writer),
+        }
+    }
+
+    fn write_pair(&mut self, pair: ProjectPair) -> io::Result<()> {
+        if let Some(pair) = self.edits.apply(pair)? {
+            self.writer.write_pair(&pair)?;
+            // Consumers should not have to wait for the buffer to fill up
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> DetectionObserver for NdjsonStream<'_, W> {
+    fn on_file_read(&mut self, file: &File) {
+        self.observer.on_file_read(file);
+    }
+
+    fn on_fingerprinted(&mut self, num_documents: usize) {
+        self.observer.on_fingerprinted(num_documents);
+    }
+
+    fn on_progress(&mut self, progress: Progress) {
+        self.observer.on_progress(progress);
+    }
+
+    fn on_pair_scored(&mut self, pair: &ProjectPair) {
+        self.observer.on_pair_scored(pair);
+        if self.error.is_none() {
+            if let Err(e) = self.write_pair(pair.clone()) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn on_stage_started(&mut self, stage: Stage) {
+        self.observer.on_stage_started(stage);
+    }
+
+    fn on_stage_finished(&mut self, stage: Stage) {
+        self.observer.on_stage_finished(stage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use fungus_cli::{
+        detect_plagiarism_with_observer,
+        output::{Metadata, Output, PairOrder, Submission, TimestampSource},
+        DetectionOptions, TokenizingStrategy,
+    };
+
+    use super::*;
+
+    /// Buffer which can still be read while a writer owns it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn record_types(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(|l| {
+                    let record = serde_json::from_str::<serde_json::Value>(l).unwrap();
+                    record["type"].as_str().unwrap().to_owned()
+                })
+                .collect()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn documents() -> Vec<File> {
+        ["P1", "P2", "P3"]
+            .into_iter()
+            .zip(["abcdefgh", "abcdefgh", "xxabcdyy"])
+            .map(|(project, contents)| {
+                File::new(
+                    Path::new("root").join(project),
+                    Path::new("root").join(project).join("file.txt"),
+                    contents.to_owned(),
+                )
+            })
+            .collect()
+    }
+
+    fn options() -> DetectionOptions {
+        DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        }
+    }
+
+    fn edits<'a>(allowed_pairs: &'a AllowedPairs, annotations: &'a [Annotation]) -> PairEdits<'a> {
+        PairEdits {
+            root: Path::new("root"),
+            path_style: PathStyle::Relative,
+            match_order: MatchOrder::Length,
+            min_match_tokens: Some(5),
+            allowed_pairs,
+            annotations,
+            submission_times: None,
+        }
+    }
+
+    #[test]
+    fn pairs_are_written_before_the_analysis_ends() {
+        let buffer = SharedBuffer::default();
+        let writer = NdjsonWriter::new(buffer.clone(), &Metadata::default(), false).unwrap();
+        let allowed_pairs = AllowedPairs::default();
+        let edits = PairEdits {
+            min_match_tokens: None,
+            ..edits(&allowed_pairs, &[])
+        };
+        // Records which had been written each time a pair was completed
+        let mut written = Vec::new();
+        let mut observer = |progress: Progress| {
+            if progress.completed_pairs > 0 {
+                written.push(buffer.record_types().len());
+            }
+        };
+        let mut stream = NdjsonStream::new(writer, edits, &mut observer);
+
+        let (project_pairs, _, _) =
+            detect_plagiarism_with_observer(&options(), &documents(), &[], &mut stream);
+        let writer = stream.into_writer().unwrap();
+
+        // Every pair was written (after the metadata) as soon as it was completed
+        assert_eq!(project_pairs.len(), 3);
+        assert_eq!(written, [2, 3, 4]);
+        writer.finish(&Output::new(Vec::new(), Vec::new())).unwrap();
+        let types = buffer.record_types();
+        assert_eq!(types[0], "metadata");
+        assert!(types[1..4].iter().all(|t| t == "project_pair"));
+        assert_eq!(types[4], "summary");
+    }
+
+    #[test]
+    fn streamed_pairs_match_the_complete_output() {
+        let mut allowed_pairs = AllowedPairs::default();
+        allowed_pairs.insert("root/P2".into(), "root/P3".into());
+        let annotations: Vec<Annotation> = serde_json::from_str(
+            r#"[{ "projects": ["root/P2", "root/P1"], "status": "confirmed" }]"#,
+        )
+        .unwrap();
+        let submissions = ["P1", "P2", "P3"]
+            .into_iter()
+            .zip([30, 20, 10])
+            .map(|(project, timestamp)| Submission {
+                project: Path::new("root").join(project),
+                timestamp,
+                source: TimestampSource::Metadata,
+            })
+            .collect::<Vec<_>>();
+        let edits = PairEdits {
+            submission_times: Some(SubmissionTimes::new(&submissions)),
+            ..edits(&allowed_pairs, &annotations)
+        };
+
+        let (project_pairs, _, _) =
+            detect_plagiarism_with_observer(&options(), &documents(), &[], &mut |_: Progress| {});
+        let mut streamed = Vec::new();
+        for pair in project_pairs.iter() {
+            streamed.extend(edits.apply(pair.clone()).unwrap());
+        }
+
+        let mut output = Output::new(Vec::new(), project_pairs);
+        output.remove_short_matches(5);
+        output.remove_allowed_pairs(&allowed_pairs);
+        output.add_annotations(annotations.clone());
+        output.add_submissions(submissions.clone());
+        output.sort_project_pairs(PairOrder::Score, MatchOrder::Length);
+        output.assign_match_ids(Path::new("root"));
+        output
+            .format_paths(Path::new("root"), PathStyle::Relative)
+            .unwrap();
+        assert_eq!(streamed, output.project_pairs);
+        assert_eq!(streamed.len(), 1);
+        assert!(streamed[0].review.is_some());
+        assert!(streamed[0].earlier_submission.is_some());
+    }
+}
@@ -0,0 +1,155 @@
+//! Hooks through which embedders follow the progress of an analysis (e.g., to drive a progress bar or log), since the
+//! library never writes to the terminal itself.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{output::ProjectPair, File};
+
+/// Stage of the pipeline which turns the projects into project pairs. The analysis reports the stages from `Lex` to
+/// `Expansion`, while reading the projects and serializing the results are left to the embedder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Reading the files of the projects and of the starter code.
+    Read,
+    /// Tokenizing and hashing the documents.
+    Lex,
+    /// Removing the starter code from the documents.
+    IgnoreRemoval,
+    /// Selecting the fingerprint of each document.
+    Fingerprint,
+    /// Building the database mapping each hash to its locations, and removing the common code.
+    DatabaseBuild,
+    /// Turning the locations sharing a hash into matches between pairs of projects.
+    Matching,
+    /// Expanding and scoring the matches of each pair.
+    Expansion,
+    /// Writing the results.
+    Serialization,
+}
+
+/// Number of project pairs whose matches have been completed, out of those which are compared. Pairs which are then
+/// removed by the thresholds (e.g., `DetectionOptions::min_matches`) still count as completed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub completed_pairs: usize,
+    pub total_pairs: usize,
+}
+
+/// Receives the events of an analysis. Every method does nothing by default, and all of them are called from the thread
+/// running the analysis.
+///
+/// Any `FnMut(Progress)` closure is an observer which only receives the progress.
+pub trait DetectionObserver {
+    /// Called as each document (including the ignored ones) is tokenized. Documents whose tokens are restored from a
+    /// checkpoint are not reported.
+    fn on_file_read(&mut self, _file: &File) {}
+
+    /// Called once every document has been fingerprinted, with the number of documents which could be fingerprinted.
+    fn on_fingerprinted(&mut self, _num_documents: usize) {}
+
+    /// Called once the pairs to compare are known, and again after each of them is completed.
+    fn on_progress(&mut self, _progress: Progress) {}
+
+    /// Called with each project pair which is reported, once it is expanded and scored, in no particular order.
+    fn on_pair_scored(&mut self, _pair: &ProjectPair) {}
+
+    /// Called as a stage of the analysis starts. Stages which are skipped (e.g., because their results are restored
+    /// from a checkpoint) are not reported.
+    fn on_stage_started(&mut self, _stage: Stage) {}
+
+    /// Called as a stage of the analysis ends.
+    fn on_stage_finished(&mut self, _stage: Stage) {}
+}
+
+impl<F: FnMut(Progress)> DetectionObserver for F {
+    fn on_progress(&mut self, progress: Progress) {
+        self(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{detect_plagiarism_with_observer, DetectionOptions, TokenizingStrategy};
+
+    #[derive(Default)]
+    struct Recorder {
+        files_read: Vec<String>,
+        num_fingerprinted: Option<usize>,
+        last_progress: Option<Progress>,
+        pairs_scored: usize,
+        finished_stages: Vec<Stage>,
+    }
+
+    impl DetectionObserver for Recorder {
+        fn on_file_read(&mut self, file: &File) {
+            self.files_read
+                .push(file.path().to_string_lossy().into_owned());
+        }
+
+        fn on_fingerprinted(&mut self, num_documents: usize) {
+            self.num_fingerprinted = Some(num_documents);
+        }
+
+        fn on_progress(&mut self, progress: Progress) {
+            self.last_progress = Some(progress);
+        }
+
+        fn on_pair_scored(&mut self, _pair: &ProjectPair) {
+            self.pairs_scored += 1;
+        }
+
+        fn on_stage_finished(&mut self, stage: Stage) {
+            self.finished_stages.push(stage);
+        }
+    }
+
+    #[test]
+    fn observer_receives_every_event() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "xxabcdyy".to_owned()),
+        ];
+        let options = DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            expand_matches: false,
+            min_matches: 2,
+            ..Default::default()
+        };
+        let mut recorder = Recorder::default();
+        let (project_pairs, _, _) =
+            detect_plagiarism_with_observer(&options, &documents, &[], &mut recorder);
+
+        assert_eq!(
+            recorder.files_read,
+            vec!["P1/file.txt", "P2/file.txt", "P3/file.txt"]
+        );
+        assert_eq!(recorder.num_fingerprinted, Some(3));
+        assert_eq!(
+            recorder.last_progress,
+            Some(Progress {
+                completed_pairs: 3,
+                total_pairs: 3
+            })
+        );
+        assert_eq!(project_pairs.len(), 1);
+        assert_eq!(recorder.pairs_scored, 1);
+        assert_eq!(
+            recorder.finished_stages,
+            vec![
+                Stage::Lex,
+                Stage::IgnoreRemoval,
+                Stage::Fingerprint,
+                Stage::DatabaseBuild,
+                Stage::Matching,
+                Stage::Expansion
+            ]
+        );
+    }
+}
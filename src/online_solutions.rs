@@ -0,0 +1,110 @@
+//! Comparison of the projects with known solutions found online (e.g., gists or repositories for the assignment), which
+//! reveals a third source from which several projects may have copied.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use itertools::Itertools;
+
+use crate::{detect_plagiarism, output::ProjectPair, CompareMode, DetectionOptions, File, Warning};
+
+/// Finds the matches between each project and each known solution, whose files are given as `solution_documents` (with
+/// one project per solution). In each pair, the project comes first and the solution second.
+///
+/// Only the pairs of a project and a solution are compared. The common code thresholds are disabled, since code copied
+/// from a popular solution is precisely what would be found in many projects.
+pub fn solution_pairs(
+    options: &DetectionOptions,
+    documents: &[File],
+    solution_documents: &[File],
+    ignored_documents: &[File],
+) -> (Vec<ProjectPair>, Vec<Warning>) {
+    let projects = documents.iter().map(File::project).dedup().collect_vec();
+    let solutions = solution_documents
+        .iter()
+        .map(File::project)
+        .collect::<BTreeSet<_>>();
+    let only_pairs = projects
+        .iter()
+        .cartesian_product(solutions.iter())
+        .map(|(&project, &solution)| (project.to_owned(), solution.to_owned()))
+        .collect();
+    let solution_options = DetectionOptions {
+        common_hash_threshold: 0.0,
+        common_hash_min_projects: 0,
+        auto_common_hash_threshold: false,
+        compare: CompareMode::All,
+        project_sections: BTreeMap::new(),
+        only_pairs: Some(only_pairs),
+        pair_overrides: Vec::new(),
+        checkpoint_dir: None,
+        cache_dir: None,
+        ..options.clone()
+    };
+
+    let all_documents = documents
+        .iter()
+        .chain(solution_documents)
+        .cloned()
+        .collect_vec();
+    let (mut pairs, warnings) =
+        detect_plagiarism(&solution_options, &all_documents, ignored_documents);
+    for pair in pairs.iter_mut() {
+        if solutions.contains(&pair.project1) {
+            pair.swap_projects();
+        }
+    }
+    (pairs, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenizingStrategy;
+
+    #[test]
+    fn projects_are_only_compared_with_solutions() {
+        let documents = vec![
+            File::new("P1".into(), "P1/a.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/a.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/a.txt".into(), "zyxwvuts".to_owned()),
+        ];
+        // The solution sorts before the projects, but still comes second in its pairs
+        let solution_documents = vec![File::new(
+            "/gists/A".into(),
+            "/gists/A/a.txt".into(),
+            "xxabcdef".to_owned(),
+        )];
+        let options = DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            // Would remove the code shared by the two projects and the solution if it applied
+            common_hash_min_projects: 2,
+            ..Default::default()
+        };
+
+        let (pairs, warnings) = solution_pairs(&options, &documents, &solution_documents, &[]);
+        assert!(warnings.is_empty());
+        let summary = pairs
+            .iter()
+            .map(|p| {
+                let m = &p.matches[0];
+                (
+                    p.project1.to_str().unwrap(),
+                    p.project2.to_str().unwrap(),
+                    m.project_1_location.span.clone(),
+                    m.project_2_location.span.clone(),
+                )
+            })
+            .collect_vec();
+        assert_eq!(
+            summary,
+            vec![
+                ("P1", "/gists/A", 0..6, 2..8),
+                ("P2", "/gists/A", 0..6, 2..8)
+            ]
+        );
+    }
+}
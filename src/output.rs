@@ -1,54 +1,564 @@
 use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashSet},
     fmt::Display,
+    hash::{Hash, Hasher},
+    io::{self, Write},
     ops::Range,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf, Prefix},
 };
 
-use anyhow::Context;
+use itertools::Itertools;
 use relative_path::RelativePathBuf;
-use serde::{Serialize, Serializer};
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{
+    annotations::Review,
+    composite::Signal,
+    diagnostics::Diagnostics,
+    distribution::{ScoreDistribution, Scores},
+    encoding::Encoding,
+    fingerprint::TieBreaking,
+    lexing::TokenizingStrategy,
+    observer::Stage,
+    project_metadata::ProjectMetadata,
+};
 
 #[derive(Serialize)]
 pub struct Output {
+    pub metadata: Metadata,
+    pub summary: Summary,
+    /// Diagnostics about the analysis, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Diagnostics>,
     pub warnings: Vec<Warning>,
+    /// Every project which was analyzed, along with its size.
+    pub projects: Vec<ProjectStats>,
     pub project_pairs: Vec<ProjectPair>,
+    /// Pairs of projects with similar instruction frequencies, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stylometry: Option<Vec<StylometryPair>>,
+    /// Pairs of a project and a known solution found online, which are only included if the solutions are given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub online_solution_pairs: Option<Vec<ProjectPair>>,
+    /// Routines of the pairs of projects which share code, which are only included when the projects are compared routine
+    /// by routine.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub routine_pairs: Option<Vec<RoutinePair>>,
+    /// Pairs of projects with similar formatting, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatting: Option<Vec<FormattingPair>>,
+    /// Pairs of projects sharing rare string literals, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub string_literals: Option<Vec<StringLiteralPair>>,
+    /// Pairs of projects ranked by a weighted combination of the signals, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub composite: Option<Vec<CompositePair>>,
+    /// Anomalies within single files which may suggest copying, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub findings: Option<Vec<Finding>>,
+    /// Scores of pairs of projects computed by an external tool, which are only included if they are given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_scores: Option<Vec<ExternalScorePair>>,
+    /// Pairs of projects which were removed from the other sections because they are allowed to be similar, which are
+    /// only included if an allowlist is given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_pairs: Option<Vec<AllowedPair>>,
+    /// Submission time of each project, which is only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submissions: Option<Vec<Submission>>,
+    /// Analyzed projects which do not appear in any project pair, which are only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clean_projects: Option<Vec<CleanProject>>,
+    /// How much each project differs from the starter code, which is only included on request (instead of the project
+    /// pairs).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starter_code_diff: Option<Vec<StarterCodeDiff>>,
+    /// Time and memory taken by each stage of the analysis, which are only included on request. The serialization of
+    /// the output itself is not included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Vec<StageTiming>>,
+    /// Hash of the rest of the output, which is only included on request to detect later edits of the output file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Integrity>,
 }
 
 impl Output {
     pub fn new(warnings: Vec<Warning>, project_pairs: Vec<ProjectPair>) -> Output {
         Output {
+            metadata: Metadata::default(),
+            summary: Summary::new(&warnings),
+            diagnostics: None,
             warnings,
+            projects: Vec::new(),
             project_pairs,
+            online_solution_pairs: None,
+            stylometry: None,
+            routine_pairs: None,
+            formatting: None,
+            string_literals: None,
+            composite: None,
+            findings: None,
+            external_scores: None,
+            allowed_pairs: None,
+            submissions: None,
+            clean_projects: None,
+            starter_code_diff: None,
+            timings: None,
+            integrity: None,
+        }
+    }
+
+    /// Sets the analyzed projects, counting their starter code in the summary.
+    pub fn set_projects(&mut self, projects: Vec<ProjectStats>) {
+        self.summary.starter_code_tokens = projects.iter().map(|p| p.starter_code_tokens).sum();
+        self.projects = projects;
+    }
+
+    /// Attaches the metadata of each analyzed project which has some.
+    pub fn set_project_metadata(&mut self, metadata: &BTreeMap<PathBuf, ProjectMetadata>) {
+        for stats in self.projects.iter_mut() {
+            stats.metadata = metadata.get(&stats.project).cloned();
+        }
+    }
+
+    /// Records the distribution of the scores of all the compared pairs, and the z-score of each reported pair.
+    pub fn set_score_distribution(&mut self, distribution: ScoreDistribution) {
+        for pair in self.project_pairs.iter_mut() {
+            let z_score = distribution.z_score(pair.ranking_score());
+            if let Some(explanation) = &mut pair.explanation {
+                explanation.z_score = z_score;
+            }
+        }
+        self.summary.score_distribution = Some(distribution);
+    }
+
+    /// Keeps only the pairs whose score is in the top `percentage` percent of the given scores of every compared pair,
+    /// rather than those above an absolute threshold, and records the lowest score which is kept.
+    pub fn keep_top_percentile(&mut self, scores: &Scores, percentage: f64) {
+        let Some(min_score) = scores.percentile(100.0 - percentage) else {
+            return;
+        };
+        self.project_pairs
+            .retain(|p| p.ranking_score() >= min_score);
+        self.summary.min_flagged_score = Some(min_score);
+    }
+
+    /// Removes the matches with fewer than `min_tokens` tokens on either side, and then the pairs left without matches.
+    /// The scores and explanations of the pairs still account for the removed matches. Returns the number of matches
+    /// removed.
+    pub fn remove_short_matches(&mut self, min_tokens: usize) -> usize {
+        let num_removed = self
+            .project_pairs
+            .iter_mut()
+            .map(|pp| pp.remove_short_matches(min_tokens))
+            .sum();
+        self.project_pairs.retain(|p| !p.matches.is_empty());
+        num_removed
+    }
+
+    /// Lists the analyzed projects which do not appear in any project pair, leaving out the empty and excluded projects
+    /// since they were not compared with anything.
+    pub fn add_clean_projects(&mut self) {
+        let flagged_projects = self
+            .project_pairs
+            .iter()
+            .flat_map(|p| [&p.project1, &p.project2])
+            .collect::<HashSet<_>>();
+        let clean_projects = self
+            .projects
+            .iter()
+            .map(|p| &p.project)
+            .filter(|p| !flagged_projects.contains(p))
+            .filter(|p| {
+                !self.summary.empty_projects.contains(p)
+                    && !self.summary.excluded_projects.contains(p)
+            })
+            .map(|p| CleanProject { project: p.clone() })
+            .collect();
+        self.clean_projects = Some(clean_projects);
+    }
+
+    pub fn make_paths_relative_to(&mut self, root: &Path) -> io::Result<()> {
+        self.format_paths(root, PathStyle::Relative)
+    }
+
+    /// Writes the output as newline-delimited JSON (see `NdjsonWriter`).
+    pub fn write_ndjson<W: Write>(&self, writer: &mut W, per_match: bool) -> io::Result<()> {
+        let mut ndjson = NdjsonWriter::new(writer, &self.metadata, per_match)?;
+        for pair in self.project_pairs.iter() {
+            ndjson.write_pair(pair)?;
+        }
+        ndjson.finish(self)?;
+        Ok(())
+    }
+
+    /// Sorts the project pairs, and the matches within each pair, in the given orders. The sorts are stable and every
+    /// order ends with a complete tie-break, so the output is reproducible.
+    pub fn sort_project_pairs(&mut self, pair_order: PairOrder, match_order: MatchOrder) {
+        self.project_pairs
+            .sort_by(|p1, p2| pair_order.compare(p1, p2));
+        for pp in self.project_pairs.iter_mut() {
+            pp.matches.sort_by(|m1, m2| match_order.compare(m1, m2));
+        }
+    }
+
+    /// Assigns the stable identifier of every match of the project pairs. The paths must not be formatted yet, so that
+    /// the identifiers do not depend on the path style or on where the projects directory is.
+    pub fn assign_match_ids(&mut self, root: &Path) {
+        for pair in self.project_pairs.iter_mut() {
+            pair.assign_match_ids(root);
         }
     }
 
-    pub fn make_paths_relative_to(&mut self, root: &Path) -> anyhow::Result<()> {
+    /// Rewrites all paths in the output according to the given style.
+    pub fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.summary.format_paths(root, style)?;
         for e in self.warnings.iter_mut() {
-            e.make_paths_relative_to(root)?;
+            e.format_paths(root, style)?;
+        }
+        for ps in self.projects.iter_mut() {
+            ps.project = format_path(&ps.project, root, style)?;
         }
         for pp in self.project_pairs.iter_mut() {
-            pp.make_paths_relative_to(root)?;
+            pp.format_paths(root, style)?;
+        }
+        for pp in self.online_solution_pairs.iter_mut().flatten() {
+            pp.format_paths(root, style)?;
+        }
+        for sp in self.stylometry.iter_mut().flatten() {
+            sp.format_paths(root, style)?;
+        }
+        for rp in self.routine_pairs.iter_mut().flatten() {
+            rp.format_paths(root, style)?;
+        }
+        for fp in self.formatting.iter_mut().flatten() {
+            fp.format_paths(root, style)?;
+        }
+        for lp in self.string_literals.iter_mut().flatten() {
+            lp.format_paths(root, style)?;
+        }
+        for cp in self.composite.iter_mut().flatten() {
+            cp.format_paths(root, style)?;
+        }
+        for f in self.findings.iter_mut().flatten() {
+            f.file = format_path(&f.file, root, style)?;
+        }
+        for ep in self.external_scores.iter_mut().flatten() {
+            ep.format_paths(root, style)?;
+        }
+        for ap in self.allowed_pairs.iter_mut().flatten() {
+            ap.format_paths(root, style)?;
+        }
+        for s in self.submissions.iter_mut().flatten() {
+            s.project = format_path(&s.project, root, style)?;
+        }
+        for c in self.clean_projects.iter_mut().flatten() {
+            c.project = format_path(&c.project, root, style)?;
+        }
+        for d in self.starter_code_diff.iter_mut().flatten() {
+            d.project = format_path(&d.project, root, style)?;
+        }
+        for d in self.diagnostics.iter_mut() {
+            for f in d.fingerprint_density.files.iter_mut() {
+                f.file = format_path(&f.file, root, style)?;
+            }
         }
         Ok(())
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Serialize)]
+/// Function used to compute the integrity hash of an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IntegrityAlgorithm {
+    /// SHA-256, which anyone can recompute, so it only detects accidental changes or edits made without recomputing
+    /// the hash.
+    Sha256,
+    /// HMAC-SHA256 with a secret key, which cannot be recomputed without the key.
+    HmacSha256,
+}
+
+/// Integrity hash of an output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Integrity {
+    pub algorithm: IntegrityAlgorithm,
+    /// Hexadecimal hash of the canonical form of the output, without this field.
+    pub digest: String,
+}
+
+/// Information about the analysis itself rather than its results.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Metadata {
+    /// Whether the analysis was interrupted, in which case only some of the project pairs are included.
+    pub partial: bool,
+    /// Which hash winnowing selected when the minimum hash occurred more than once in a window.
+    pub tie_breaking: TieBreaking,
+    /// Identifier of the run, if its inputs and parameters were recorded in a reproducibility manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+/// Overview of the results, so that the most important information is available without scanning the whole output.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Summary {
+    /// Number of warnings of each type.
+    pub warnings_by_type: BTreeMap<WarningType, usize>,
+    /// Number of warnings with each code.
+    pub warnings_by_code: BTreeMap<WarningCode, usize>,
+    /// Projects without any files that could be analyzed. These cannot appear in any project pair.
+    #[serde(serialize_with = "serialize_paths")]
+    pub empty_projects: Vec<PathBuf>,
+    /// Projects which were excluded from the analysis because they are too small.
+    #[serde(serialize_with = "serialize_paths")]
+    pub excluded_projects: Vec<PathBuf>,
+    /// Total number of tokens which were removed from the projects because they match starter code.
+    pub starter_code_tokens: usize,
+    /// Distribution of the scores of every compared pair of projects, including the pairs which are not reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score_distribution: Option<ScoreDistribution>,
+    /// Lowest score of the reported pairs, when only the pairs in a top percentile of the scores are reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_flagged_score: Option<f64>,
+}
+
+impl Summary {
+    pub fn new(warnings: &[Warning]) -> Summary {
+        let mut summary = Summary::default();
+        for w in warnings {
+            *summary.warnings_by_type.entry(w.warn_type).or_default() += 1;
+            *summary.warnings_by_code.entry(w.code).or_default() += 1;
+            match (w.code, &w.file) {
+                (WarningCode::EmptyProject, Some(project)) => {
+                    summary.empty_projects.push(project.to_owned());
+                }
+                (WarningCode::SmallProject, Some(project)) => {
+                    summary.excluded_projects.push(project.to_owned());
+                }
+                _ => {}
+            }
+        }
+        summary.empty_projects.sort();
+        summary.excluded_projects.sort();
+        summary
+    }
+
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        for project in self
+            .empty_projects
+            .iter_mut()
+            .chain(self.excluded_projects.iter_mut())
+        {
+            *project = format_path(project, root, style)?;
+        }
+        Ok(())
+    }
+}
+
+/// How paths are represented in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PathStyle {
+    /// Make all paths relative to the projects directory. Paths outside the projects directory are an error.
+    Relative,
+    /// Make all paths absolute.
+    Absolute,
+    /// Make paths relative to the projects directory where possible and absolute otherwise.
+    Auto,
+}
+
+/// Order of the project pairs in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PairOrder {
+    /// From the highest to the lowest ranking score, then by name.
+    Score,
+    /// By the names of the two projects.
+    Name,
+}
+
+impl PairOrder {
+    pub fn compare(self, p1: &ProjectPair, p2: &ProjectPair) -> Ordering {
+        fn names(p: &ProjectPair) -> (&PathBuf, &PathBuf) {
+            (&p.project1, &p.project2)
+        }
+        match self {
+            PairOrder::Score => p2
+                .ranking_score()
+                .total_cmp(&p1.ranking_score())
+                .then_with(|| names(p1).cmp(&names(p2))),
+            PairOrder::Name => names(p1).cmp(&names(p2)),
+        }
+    }
+}
+
+/// Order of the matches within each project pair in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum MatchOrder {
+    /// By their location in the first project, then in the second project.
+    Location,
+    /// From the longest to the shortest (the longer of their two locations, in bytes), then by location.
+    Length,
+    /// By the pair of files they are in, then by location, so that the matches between the same two files are listed
+    /// together.
+    FilePair,
+}
+
+impl MatchOrder {
+    pub fn compare(self, m1: &Match, m2: &Match) -> Ordering {
+        type LocationKey<'a> = (&'a PathBuf, usize, usize);
+        fn location(m: &Match) -> (LocationKey<'_>, LocationKey<'_>) {
+            (
+                location_sort_key(&m.project_1_location),
+                location_sort_key(&m.project_2_location),
+            )
+        }
+        match self {
+            MatchOrder::Location => location(m1).cmp(&location(m2)),
+            MatchOrder::Length => Reverse(m1.length())
+                .cmp(&Reverse(m2.length()))
+                .then_with(|| location(m1).cmp(&location(m2))),
+            MatchOrder::FilePair => (&m1.project_1_location.file, &m1.project_2_location.file)
+                .cmp(&(&m2.project_1_location.file, &m2.project_2_location.file))
+                .then_with(|| location(m1).cmp(&location(m2))),
+        }
+    }
+}
+
+pub(crate) fn location_sort_key(location: &Location) -> (&PathBuf, usize, usize) {
+    (&location.file, location.span.start, location.span.end)
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Warning {
     #[serde(serialize_with = "serialize_path_option")]
     pub file: Option<PathBuf>,
     pub message: String,
     pub warn_type: WarningType,
+    pub code: WarningCode,
 }
 
+/// Maximum number of locations listed in a lexer error warning.
+const MAX_REPORTED_LEX_ERRORS: usize = 3;
+
 impl Warning {
-    fn make_paths_relative_to(&mut self, root: &Path) -> anyhow::Result<()> {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
         if let Some(f) = &self.file {
-            let relative_path = make_path_relative_to(f, root)?;
-            self.file = Some(relative_path);
+            let formatted_path = format_path(f, root, style)?;
+            self.file = Some(formatted_path);
         }
         Ok(())
     }
+
+    /// Creates the warning for a project without any files that could be analyzed.
+    pub fn empty_project(project: &Path, reason: &str) -> Warning {
+        Warning {
+            file: Some(project.to_owned()),
+            message: format!("Project does not contain any files which could be analyzed ({reason}), so it cannot be matched with other projects. Check that the submission is complete."),
+            warn_type: WarningType::Input,
+            code: WarningCode::EmptyProject,
+        }
+    }
+
+    /// Creates the warning for a project (or for the projects directory, if no project is given) which was not read
+    /// completely because a traversal limit was reached (e.g., the maximum number of files per project).
+    pub fn traversal_limit(project: Option<&Path>, message: String) -> Warning {
+        Warning {
+            file: project.map(Path::to_owned),
+            message,
+            warn_type: WarningType::Input,
+            code: WarningCode::TraversalLimit,
+        }
+    }
+
+    /// Creates the warning for a file whose language was only guessed by the "auto" tokenizing strategy, which then
+    /// tokenizes it with the given strategy.
+    pub fn ambiguous_language(file: &Path, strategy: TokenizingStrategy) -> Warning {
+        let language = match strategy {
+            TokenizingStrategy::Bytes => "not assembly, so it is compared as bytes",
+            _ => "assembly",
+        };
+        Warning {
+            file: Some(file.to_owned()),
+            message: format!("The file looks as much like assembly as like another language (or like neither). It was assumed to be {language}."),
+            warn_type: WarningType::Input,
+            code: WarningCode::AmbiguousLanguage,
+        }
+    }
+
+    /// Creates the warning for a file which was converted to UTF-8 from the given encoding.
+    pub fn converted_encoding(file: &Path, encoding: Encoding) -> Warning {
+        let conversion = match encoding {
+            Encoding::Utf8Bom => "the byte order mark was removed",
+            _ => "it was converted to UTF-8",
+        };
+        Warning {
+            file: Some(file.to_owned()),
+            message: format!("The file is encoded in {encoding}, so {conversion}. The spans of its matches refer to the converted contents."),
+            warn_type: WarningType::Input,
+            code: WarningCode::ConvertedEncoding,
+        }
+    }
+
+    /// Creates the warning for a project metadata file which could not be parsed.
+    pub fn invalid_project_metadata(file: &Path, error: &str) -> Warning {
+        Warning {
+            file: Some(file.to_owned()),
+            message: format!("Invalid project metadata: {error}"),
+            warn_type: WarningType::Input,
+            code: WarningCode::InvalidProjectMetadata,
+        }
+    }
+
+    /// Creates the warning for a project which is not compared with any other project because it has no section.
+    pub fn missing_section(project: &Path) -> Warning {
+        Warning {
+            file: Some(project.to_owned()),
+            message: "Project has no section in the project metadata, so it is not compared with any other project when comparing within or across sections.".to_owned(),
+            warn_type: WarningType::Input,
+            code: WarningCode::MissingSection,
+        }
+    }
+
+    /// Creates the warning for a file containing characters which could not be tokenized. `error_spans` are the byte
+    /// spans of those characters in `contents`.
+    pub fn lex_errors(
+        file: &Path,
+        contents: &str,
+        error_spans: &[Range<usize>],
+        excluded: bool,
+    ) -> Warning {
+        let mut locations = error_spans
+            .iter()
+            .take(MAX_REPORTED_LEX_ERRORS)
+            .map(|span| {
+                let before = &contents[..span.start];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                let line = before.matches('\n').count() + 1;
+                let column = before[line_start..].chars().count() + 1;
+                format!("line {line}, column {column}")
+            })
+            .join("; ");
+        if error_spans.len() > MAX_REPORTED_LEX_ERRORS {
+            locations.push_str("; ...");
+        }
+        let consequence = if excluded {
+            "The file is excluded from the analysis."
+        } else {
+            "These characters are still compared with other files, which may produce spurious matches or hide real ones."
+        };
+        Warning {
+            file: Some(file.to_owned()),
+            message: format!(
+                "{} sequence(s) of characters could not be tokenized ({locations}). {consequence}",
+                error_spans.len()
+            ),
+            warn_type: WarningType::Lex,
+            code: WarningCode::LexErrors,
+        }
+    }
 }
 
 impl Display for Warning {
@@ -61,25 +571,103 @@ impl Display for Warning {
     }
 }
 
+#[cfg(feature = "cli")]
 impl From<walkdir::Error> for Warning {
     fn from(error: walkdir::Error) -> Self {
         Warning {
             file: error.path().map(|p| p.to_owned()),
             message: error.to_string(),
             warn_type: WarningType::Input,
+            code: WarningCode::InaccessiblePath,
         }
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum WarningType {
     Args,
     Input,
+    Lex,
     Fingerprint,
+    Checkpoint,
+}
+
+/// Specific cause of a warning. This is more fine-grained than the warning's type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCode {
+    /// No starter code was provided.
+    NoStarterCode,
+    /// The max token offset is small enough to cause many false positives.
+    SmallMaxTokenOffset,
+    /// A file or directory could not be traversed.
+    InaccessiblePath,
+    /// A file could not be read (e.g., because it is not valid UTF-8).
+    UnreadableFile,
+    /// A file contains fewer tokens than the noise threshold.
+    TooFewTokens,
+    /// A project does not contain any files which could be analyzed.
+    EmptyProject,
+    /// A project was excluded from the analysis because it is too small.
+    SmallProject,
+    /// The checkpoint could not be opened or updated.
+    CheckpointFailed,
+    /// The cache of completed pairs could not be opened or saved.
+    CacheFailed,
+    /// The common code threshold could not be chosen automatically.
+    NoAutoThreshold,
+    /// A stop-list entry contains fewer tokens than the noise threshold.
+    ShortStopListEntry,
+    /// Some code occurs so many times in two projects that not all of its matches were reported.
+    CappedMatches,
+    /// Fingerprint hashes were truncated to 32 bits, so unrelated code may be matched.
+    TruncatedHashes,
+    /// A file contains characters which could not be tokenized.
+    LexErrors,
+    /// A project has no section, so it is not compared within or across sections.
+    MissingSection,
+    /// A directory was not read completely because of --max-depth, --max-files-per-project, or --max-total-files.
+    TraversalLimit,
+    /// The analysis was stopped early because it would have exceeded the maximum memory.
+    MemoryLimit,
+    /// The language of a file could not be detected reliably by the "auto" tokenizing strategy.
+    AmbiguousLanguage,
+    /// A file was converted to UTF-8 (e.g., from UTF-16) before it was analyzed.
+    ConvertedEncoding,
+    /// A project metadata file could not be parsed, so it was skipped.
+    InvalidProjectMetadata,
+}
+
+impl WarningCode {
+    /// Short description of the warnings with this code, used when counting them (e.g., "3 unreadable file(s)").
+    pub fn description(&self) -> &'static str {
+        match self {
+            WarningCode::NoStarterCode => "missing starter code",
+            WarningCode::SmallMaxTokenOffset => "small max token offset",
+            WarningCode::InaccessiblePath => "inaccessible path(s)",
+            WarningCode::UnreadableFile => "unreadable file(s)",
+            WarningCode::TooFewTokens => "too-small file(s)",
+            WarningCode::EmptyProject => "empty project(s)",
+            WarningCode::SmallProject => "excluded small project(s)",
+            WarningCode::CheckpointFailed => "checkpoint failure(s)",
+            WarningCode::CacheFailed => "cache failure(s)",
+            WarningCode::NoAutoThreshold => "unavailable automatic threshold",
+            WarningCode::ShortStopListEntry => "too-short stop-list entr(y/ies)",
+            WarningCode::CappedMatches => "capped pair(s)",
+            WarningCode::TruncatedHashes => "truncated hashes",
+            WarningCode::LexErrors => "file(s) with lexer errors",
+            WarningCode::MissingSection => "project(s) without a section",
+            WarningCode::TraversalLimit => "traversal limit(s) reached",
+            WarningCode::MemoryLimit => "memory limit reached",
+            WarningCode::AmbiguousLanguage => "file(s) of ambiguous language",
+            WarningCode::ConvertedEncoding => "file(s) converted to UTF-8",
+            WarningCode::InvalidProjectMetadata => "invalid project metadata file(s)",
+        }
+    }
 }
 
 /// Contains information about the similarity of two projects.
-#[derive(Debug, Eq, PartialEq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ProjectPair {
     /// Name of the first project.
     #[serde(serialize_with = "serialize_path")]
@@ -89,77 +677,724 @@ pub struct ProjectPair {
     pub project2: PathBuf,
     /// Matches between the two projects.
     pub matches: Vec<Match>,
+    /// Weighted score of the pair, if the pairs are not simply ranked by their number of matches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Evidence behind the pair. Always present in the output of an analysis, but absent from pairs read from the
+    /// output of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<PairExplanation>,
+    /// Which project was submitted first, if the submission times of both projects are known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub earlier_submission: Option<EarlierSubmission>,
+    /// Decision of the reviewers, if the pair was annotated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<Review>,
+}
+
+/// Project of a pair which was submitted first, hinting at the direction in which code may have been copied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EarlierSubmission {
+    Project1,
+    Project2,
+    /// Both projects were submitted at the same time.
+    Same,
+}
+
+/// Project of a pair with more content of its own (i.e., more tokens not covered by the matches). If the other project
+/// is mostly covered, it may be a subset of this one, whereas two projects with much content of their own may share a
+/// common third source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoreUniqueContent {
+    Project1,
+    Project2,
+    /// Both projects have the same number of unmatched tokens.
+    Same,
+}
+
+/// Machine-readable evidence behind a pair of projects, so that the decision to report it can be justified.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PairExplanation {
+    /// Number of distinct fingerprinted code snippets shared by the two projects.
+    pub shared_hashes: usize,
+    /// Number of projects containing the rarest code snippet shared by the two projects.
+    pub rarest_hash_projects: usize,
+    /// Proportion of the tokens of project 1 (excluding starter code) covered by the matches.
+    pub project_1_coverage: f64,
+    /// Proportion of the tokens of project 2 (excluding starter code) covered by the matches.
+    pub project_2_coverage: f64,
+    /// Number of tokens of project 1 (excluding starter code) not covered by the matches.
+    #[serde(default)]
+    pub project_1_unmatched_tokens: usize,
+    /// Number of tokens of project 2 (excluding starter code) not covered by the matches.
+    #[serde(default)]
+    pub project_2_unmatched_tokens: usize,
+    /// Project with more unmatched tokens. Unknown for pairs read from the output of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub more_unique_content: Option<MoreUniqueContent>,
+    /// Filters which would have removed the pair had they been slightly stricter.
+    pub nearly_filtered_by: Vec<Filter>,
+    /// Number of standard deviations by which the score of the pair is above the mean score of all the compared pairs.
+    /// Unknown if every pair has the same score.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub z_score: Option<f64>,
+}
+
+/// Filter which removes pairs of projects from the output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    /// The common code thresholds, which ignore code found in many projects.
+    CommonCode,
+    /// The minimum number of matches.
+    MinMatches,
+    /// The minimum coverage of a pair, which is only set by pair overrides.
+    MinCoverage,
 }
 
 impl ProjectPair {
-    fn make_paths_relative_to(&mut self, root: &Path) -> anyhow::Result<()> {
-        self.project1 = make_path_relative_to(&self.project1, root)?;
-        self.project2 = make_path_relative_to(&self.project2, root)?;
+    /// Returns the value by which the pair is ranked: its score if it has one, or else its number of matches.
+    pub fn ranking_score(&self) -> f64 {
+        self.score.unwrap_or(self.matches.len() as f64)
+    }
+
+    /// Removes the matches with fewer than `min_tokens` tokens on either side (see `Output::remove_short_matches`).
+    /// Returns the number of matches removed.
+    pub fn remove_short_matches(&mut self, min_tokens: usize) -> usize {
+        let num_matches = self.matches.len();
+        // Matches of unknown length (e.g., read from older outputs) are kept
+        self.matches.retain(|m| {
+            [&m.project_1_location, &m.project_2_location]
+                .iter()
+                .all(|l| l.length_tokens.is_none_or(|n| n >= min_tokens))
+        });
+        num_matches - self.matches.len()
+    }
+
+    /// Assigns the stable identifier of every match (see `Output::assign_match_ids`).
+    pub fn assign_match_ids(&mut self, root: &Path) {
+        let project1 = stable_path(&self.project1, root);
+        let project2 = stable_path(&self.project2, root);
+        for m in self.matches.iter_mut() {
+            m.id = Some(m.stable_id(&project1, &project2, root));
+        }
+    }
+
+    /// Returns the percentage of the tokens of each project (excluding starter code) covered by the matches, counting
+    /// overlapping matches once. Unknown for pairs read from the output of older versions.
+    pub fn coverage_percent(&self) -> Option<(f64, f64)> {
+        self.explanation
+            .as_ref()
+            .map(|e| (e.project_1_coverage * 100.0, e.project_2_coverage * 100.0))
+    }
+
+    /// Swaps the two projects of the pair, along with everything which refers to one of them.
+    pub(crate) fn swap_projects(&mut self) {
+        std::mem::swap(&mut self.project1, &mut self.project2);
         for m in self.matches.iter_mut() {
-            m.make_paths_relative_to(root)?;
+            std::mem::swap(&mut m.project_1_location, &mut m.project_2_location);
+        }
+        if let Some(e) = &mut self.explanation {
+            std::mem::swap(&mut e.project_1_coverage, &mut e.project_2_coverage);
+            std::mem::swap(
+                &mut e.project_1_unmatched_tokens,
+                &mut e.project_2_unmatched_tokens,
+            );
+            e.more_unique_content = e.more_unique_content.map(|c| match c {
+                MoreUniqueContent::Project1 => MoreUniqueContent::Project2,
+                MoreUniqueContent::Project2 => MoreUniqueContent::Project1,
+                MoreUniqueContent::Same => MoreUniqueContent::Same,
+            });
         }
+        self.earlier_submission = self.earlier_submission.map(|s| match s {
+            EarlierSubmission::Project1 => EarlierSubmission::Project2,
+            EarlierSubmission::Project2 => EarlierSubmission::Project1,
+            EarlierSubmission::Same => EarlierSubmission::Same,
+        });
+    }
+
+    /// Rewrites the paths of the pair according to the given style (see `Output::format_paths`).
+    pub fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        for m in self.matches.iter_mut() {
+            m.format_paths(root, style)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pair of routines of two projects which share code.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RoutinePair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Routine of the first project.
+    pub routine1: RoutineLocation,
+    /// Routine of the second project.
+    pub routine2: RoutineLocation,
+    /// Proportion of the bytes of the first routine covered by the matches with the second routine.
+    pub routine_1_coverage: f64,
+    /// Proportion of the bytes of the second routine covered by the matches with the first routine.
+    pub routine_2_coverage: f64,
+}
+
+impl RoutinePair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        self.routine1.file = format_path(&self.routine1.file, root, style)?;
+        self.routine2.file = format_path(&self.routine2.file, root, style)?;
         Ok(())
     }
 }
 
+/// Routine of a file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RoutineLocation {
+    #[serde(serialize_with = "serialize_path")]
+    pub file: PathBuf,
+    /// Label of the routine, or `None` for the code before the first routine label of the file.
+    pub name: Option<String>,
+    /// Position of the routine within the file (in bytes).
+    pub span: Range<usize>,
+}
+
+/// Pair of projects which use instructions with similar frequencies.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StylometryPair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Cosine similarity of the instruction frequency profiles of the two projects, between 0 and 1.
+    pub similarity: f64,
+    /// Instructions which contribute the most to the similarity, in decreasing order of contribution.
+    pub distinctive_instructions: Vec<String>,
+}
+
+impl StylometryPair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Pair of projects which format their code similarly (indentation, blank lines, and alignment of comments).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FormattingPair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Cosine similarity of the formatting pattern profiles of the two projects, between 0 and 1.
+    pub similarity: f64,
+    /// Formatting patterns which contribute the most to the similarity, in decreasing order of contribution. Each
+    /// pattern describes consecutive lines, separated by " / ".
+    pub distinctive_patterns: Vec<String>,
+}
+
+impl FormattingPair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Pair of projects which share rare string literals (e.g., error messages or prompts).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StringLiteralPair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Literals found in both projects, from the rarest to the most common.
+    pub literals: Vec<SharedLiteral>,
+}
+
+/// String literal found in several projects.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SharedLiteral {
+    /// Literal, including its quotes.
+    pub literal: String,
+    /// Number of projects containing the literal.
+    pub num_projects: usize,
+}
+
+impl StringLiteralPair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Pair of projects along with its composite score.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CompositePair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Weighted average of the components, between 0 and 1.
+    pub score: f64,
+    /// Score of the pair according to each signal in which it appears, between 0 and 1.
+    pub components: BTreeMap<Signal, f64>,
+}
+
+impl CompositePair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Size of a project which was analyzed, giving context to the number of matches and the coverage of its pairs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectStats {
+    /// Name of the project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project: PathBuf,
+    /// Number of files of the project which were tokenized.
+    pub num_files: usize,
+    /// Number of tokens in those files, excluding starter code.
+    pub num_tokens: usize,
+    /// Number of tokens which were removed from those files because they match starter code.
+    pub starter_code_tokens: usize,
+    /// Metadata of the project (e.g., the name of the student), from the project metadata file or the project's own
+    /// metadata file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ProjectMetadata>,
+}
+
+/// Analyzed project which does not appear in any project pair.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CleanProject {
+    /// Name of the project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project: PathBuf,
+}
+
+/// Anomaly within a file which may suggest that part of it was copied from elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    #[serde(serialize_with = "serialize_path")]
+    pub file: PathBuf,
+    pub heuristic: Heuristic,
+    /// Line on which the less common style first appears (one-based).
+    pub line: usize,
+    pub message: String,
+}
+
+/// Heuristic flagging an anomaly within a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Heuristic {
+    /// Comments start with different markers (e.g., "@" and "//").
+    MixedCommentStyles,
+    /// The same register is named in different ways (e.g., "sp" and "r13").
+    MixedRegisterNames,
+    /// Lines are indented with tabs and with spaces.
+    MixedIndentation,
+}
+
+/// Pair of projects along with the score computed by an external tool.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExternalScorePair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Score given by the external tool, between 0 and 1.
+    pub score: f64,
+}
+
+impl ExternalScorePair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Comparison of a project with the starter code.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StarterCodeDiff {
+    /// Name of the project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project: PathBuf,
+    /// Number of tokens in the files of the project, including starter code.
+    pub num_tokens: usize,
+    /// Proportion of the distinct code snippets of the starter code which are found in the project, between 0 and 1.
+    pub starter_code_coverage: f64,
+    /// Proportion of the distinct code snippets of the project which are not found in the starter code, between 0 and
+    /// 1.
+    pub new_code_proportion: f64,
+}
+
+/// Time and memory taken by a stage of the analysis.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    /// Wall time of the stage, in seconds.
+    pub seconds: f64,
+    /// Peak resident memory of the process during the stage, in bytes, if it could be measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
+}
+
+/// Pair of projects which was removed from the report because it is allowed to be similar.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AllowedPair {
+    /// Name of the first project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: PathBuf,
+    /// Name of the second project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: PathBuf,
+    /// Number of matches the pair would have been reported with.
+    pub num_matches: usize,
+    /// Score by which the pair would have been ranked (see `ProjectPair::ranking_score`).
+    pub score: f64,
+}
+
+impl AllowedPair {
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project1 = format_path(&self.project1, root, style)?;
+        self.project2 = format_path(&self.project2, root, style)?;
+        Ok(())
+    }
+}
+
+/// Time at which a project was submitted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Submission {
+    /// Name of the project.
+    #[serde(serialize_with = "serialize_path")]
+    pub project: PathBuf,
+    /// Submission time, in seconds since the Unix epoch.
+    pub timestamp: i64,
+    /// Where the submission time comes from.
+    pub source: TimestampSource,
+}
+
+/// Source of the submission time of a project.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// The project metadata file or the manifest.
+    Metadata,
+    /// The latest modification time of the files of the project.
+    FileModified,
+}
+
 /// Contains information about a specific code snippet that is shared between two projects.
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Match {
+    /// Identifier derived from the projects, files and spans of the match, which stays the same across runs as long as
+    /// the match does (e.g., to refer to a specific match in a later run). Only assigned to the output of an analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     /// Location in which the code snippet appears in project 1.
     pub project_1_location: Location,
     /// Location in which the code snippet appears in project 2.
     pub project_2_location: Location,
+    /// How rare the code snippet is across all projects, if the matches are weighted. An expanded match has the total
+    /// weight of the matches it was expanded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
 }
 
 impl Match {
-    fn make_paths_relative_to(&mut self, root: &Path) -> anyhow::Result<()> {
-        self.project_1_location.make_paths_relative_to(root)?;
-        self.project_2_location.make_paths_relative_to(root)?;
+    /// Returns the length in bytes of the longer of the two locations of the match.
+    pub fn length(&self) -> usize {
+        self.project_1_location
+            .span
+            .len()
+            .max(self.project_2_location.span.len())
+    }
+
+    /// Computes the stable identifier of the match, given the names of the projects of its pair as returned by
+    /// `stable_path`.
+    fn stable_id(&self, project1: &str, project2: &str, root: &Path) -> String {
+        let mut hasher = FxHasher::default();
+        for location in [&self.project_1_location, &self.project_2_location] {
+            stable_path(&location.file, root).hash(&mut hasher);
+            (location.span.start as u64).hash(&mut hasher);
+            (location.span.end as u64).hash(&mut hasher);
+        }
+        project1.hash(&mut hasher);
+        project2.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.project_1_location.format_paths(root, style)?;
+        self.project_2_location.format_paths(root, style)?;
         Ok(())
     }
 }
 
 /// Absolute reference to a code snippet.
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Location {
     /// File in which the code snippet is found.
     #[serde(serialize_with = "serialize_path")]
     pub file: PathBuf,
     /// Position of the code snippet within the file (in bytes).
     pub span: Range<usize>,
+    /// Length of the code snippet in bytes. Only assigned to the matches of an analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_bytes: Option<usize>,
+    /// Number of tokens in the code snippet, not counting starter code. Only assigned to the matches of an analysis.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length_tokens: Option<usize>,
 }
 
 impl Location {
-    fn make_paths_relative_to(&mut self, root: &Path) -> anyhow::Result<()> {
-        self.file = make_path_relative_to(&self.file, root)?;
+    fn format_paths(&mut self, root: &Path, style: PathStyle) -> io::Result<()> {
+        self.file = format_path(&self.file, root, style)?;
         Ok(())
     }
 }
 
-fn make_path_relative_to(path: &Path, root: &Path) -> anyhow::Result<PathBuf> {
-    let canonical_path = path
-        .canonicalize()
-        .with_context(|| format!("Failed to make path '{}' absolute.", path.display()))?;
-    let canonical_root = root.canonicalize().with_context(|| {
-        format!(
-            "Failed to make projects directory path '{}' absolute.",
-            &root.display()
-        )
-    })?;
+/// One line of newline-delimited JSON output. Each record is tagged with its `type`.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NdjsonRecord<'a> {
+    Metadata(&'a Metadata),
+    Summary(&'a Summary),
+    Diagnostics(&'a Diagnostics),
+    Warning(&'a Warning),
+    Project(&'a ProjectStats),
+    ProjectPair(&'a ProjectPair),
+    Match(MatchRecord<'a>),
+    OnlineSolutionPair(&'a ProjectPair),
+    StylometryPair(&'a StylometryPair),
+    RoutinePair(&'a RoutinePair),
+    FormattingPair(&'a FormattingPair),
+    StringLiteralPair(&'a StringLiteralPair),
+    CompositePair(&'a CompositePair),
+    ExternalScorePair(&'a ExternalScorePair),
+    Finding(&'a Finding),
+    AllowedPair(&'a AllowedPair),
+    Submission(&'a Submission),
+    CleanProject(&'a CleanProject),
+    StarterCodeDiff(&'a StarterCodeDiff),
+    Timing(&'a StageTiming),
+}
+
+/// Writes newline-delimited JSON output, with one record per line tagged with its `type`: the metadata first, then the
+/// project pairs (or their matches), and then the rest of the output. The pairs can be written one at a time while the
+/// analysis is still running, so that consumers can process them before it ends.
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    metadata: Metadata,
+    per_match: bool,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    /// Writes the metadata record.
+    pub fn new(mut writer: W, metadata: &Metadata, per_match: bool) -> io::Result<Self> {
+        write_ndjson_record(&mut writer, &NdjsonRecord::Metadata(metadata))?;
+        Ok(NdjsonWriter {
+            writer,
+            metadata: metadata.clone(),
+            per_match,
+        })
+    }
 
-    let relative_path = canonical_path
-        .strip_prefix(&canonical_root)
-        .with_context(|| {
+    /// Writes the record of a project pair, or the records of its matches.
+    pub fn write_pair(&mut self, pair: &ProjectPair) -> io::Result<()> {
+        if !self.per_match {
+            return write_ndjson_record(&mut self.writer, &NdjsonRecord::ProjectPair(pair));
+        }
+        for m in pair.matches.iter() {
+            let record = NdjsonRecord::Match(MatchRecord {
+                project1: &pair.project1,
+                project2: &pair.project2,
+                m,
+            });
+            write_ndjson_record(&mut self.writer, &record)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Writes the records of the rest of the output, leaving out its project pairs, and returns the underlying writer.
+    /// The metadata is written again if it changed since it was first written (e.g., because the analysis was
+    /// interrupted).
+    pub fn finish(mut self, output: &Output) -> io::Result<W> {
+        let writer = &mut self.writer;
+        write_ndjson_record(writer, &NdjsonRecord::Summary(&output.summary))?;
+        if let Some(diagnostics) = &output.diagnostics {
+            write_ndjson_record(writer, &NdjsonRecord::Diagnostics(diagnostics))?;
+        }
+        for warning in output.warnings.iter() {
+            write_ndjson_record(writer, &NdjsonRecord::Warning(warning))?;
+        }
+        for project in output.projects.iter() {
+            write_ndjson_record(writer, &NdjsonRecord::Project(project))?;
+        }
+        for pair in output.online_solution_pairs.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::OnlineSolutionPair(pair))?;
+        }
+        for pair in output.stylometry.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::StylometryPair(pair))?;
+        }
+        for pair in output.routine_pairs.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::RoutinePair(pair))?;
+        }
+        for pair in output.formatting.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::FormattingPair(pair))?;
+        }
+        for pair in output.string_literals.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::StringLiteralPair(pair))?;
+        }
+        for pair in output.composite.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::CompositePair(pair))?;
+        }
+        for finding in output.findings.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::Finding(finding))?;
+        }
+        for pair in output.external_scores.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::ExternalScorePair(pair))?;
+        }
+        for pair in output.allowed_pairs.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::AllowedPair(pair))?;
+        }
+        for submission in output.submissions.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::Submission(submission))?;
+        }
+        for timing in output.timings.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::Timing(timing))?;
+        }
+        for project in output.clean_projects.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::CleanProject(project))?;
+        }
+        for diff in output.starter_code_diff.iter().flatten() {
+            write_ndjson_record(writer, &NdjsonRecord::StarterCodeDiff(diff))?;
+        }
+        if output.metadata != self.metadata {
+            write_ndjson_record(writer, &NdjsonRecord::Metadata(&output.metadata))?;
+        }
+        Ok(self.writer)
+    }
+}
+
+fn write_ndjson_record<W: Write>(writer: &mut W, record: &NdjsonRecord) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")
+}
+
+/// A match along with the pair of projects in which it was found.
+#[derive(Serialize)]
+pub struct MatchRecord<'a> {
+    #[serde(serialize_with = "serialize_path")]
+    pub project1: &'a PathBuf,
+    #[serde(serialize_with = "serialize_path")]
+    pub project2: &'a PathBuf,
+    #[serde(flatten)]
+    pub m: &'a Match,
+}
+
+/// Returns the path relative to the root where possible, with forward slashes on every platform.
+pub(crate) fn stable_path(path: &Path, root: &Path) -> String {
+    make_path_relative_to(path, root)
+        .unwrap_or_else(|_| path.to_owned())
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .join("/")
+}
+
+/// Formats a path according to the given style.
+pub(crate) fn format_path(path: &Path, root: &Path, style: PathStyle) -> io::Result<PathBuf> {
+    match style {
+        PathStyle::Relative => make_path_relative_to(path, root),
+        PathStyle::Absolute => match path.strip_prefix(root) {
+            // Projects listed in a manifest do not need to exist, but the root does
+            Ok(relative_path) if !path.exists() => {
+                Ok(canonicalize(root, "projects directory path")?.join(relative_path))
+            }
+            _ => canonicalize(path, "path"),
+        },
+        // Fall back to the absolute path (or the path as given if even that fails) rather than aborting
+        PathStyle::Auto => Ok(make_path_relative_to(path, root)
+            .or_else(|_| path.canonicalize())
+            .unwrap_or_else(|_| path.to_owned())),
+    }
+}
+
+fn make_path_relative_to(path: &Path, root: &Path) -> io::Result<PathBuf> {
+    // Projects listed in a manifest do not need to exist, so they can only be made relative lexically
+    if !path.exists() {
+        if let Ok(relative_path) = path.strip_prefix(root) {
+            return Ok(relative_path.to_owned());
+        }
+    }
+    let canonical_path = canonicalize(path, "path")?;
+    let canonical_root = canonicalize(root, "projects directory path")?;
+
+    let relative_path = canonical_path.strip_prefix(&canonical_root).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
             format!(
                 "Failed to strip prefix '{}' from '{}'.",
                 &canonical_root.display(),
                 &canonical_path.display()
-            )
-        })?;
+            ),
+        )
+    })?;
 
     Ok(relative_path.to_owned())
 }
 
+/// Canonicalizes a path, adding the path to the error message on failure.
+fn canonicalize(path: &Path, description: &str) -> io::Result<PathBuf> {
+    path.canonicalize().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "Failed to make {description} '{}' absolute: {e}",
+                path.display()
+            ),
+        )
+    })
+}
+
+/// Serializes a list of paths using `serialize_path`.
+fn serialize_paths<S>(value: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    #[derive(Serialize)]
+    struct SerializablePath<'a>(#[serde(serialize_with = "serialize_path")] &'a PathBuf);
+
+    serializer.collect_seq(value.iter().map(SerializablePath))
+}
+
 /// Serializes an `Option<PathBuf>` using `serialize_path`.
 fn serialize_path_option<S>(value: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -173,11 +1408,19 @@ where
 
 /// Serializes a `PathBuf`.
 ///
-/// The `relative-path` crate is used to ensure the path separator is always '/'.
-fn serialize_path<S>(value: &PathBuf, serializer: S) -> Result<S::Ok, S::Error>
+/// The `relative-path` crate is used to ensure the path separator is always '/'. Absolute paths are converted manually
+/// since `relative-path` rejects them.
+pub(crate) fn serialize_path<P, S>(value: &P, serializer: S) -> Result<S::Ok, S::Error>
 where
+    P: AsRef<Path>,
     S: Serializer,
 {
+    // Absolute paths (e.g., starter code outside the projects directory) cannot be represented as relative paths.
+    let path = value.as_ref();
+    if path.has_root() || matches!(path.components().next(), Some(Component::Prefix(_))) {
+        return serializer.serialize_str(&absolute_path_to_string(path));
+    }
+
     let relative_path = match RelativePathBuf::from_path(value) {
         Err(_) => {
             return Err(serde::ser::Error::custom(
@@ -189,3 +1432,354 @@ where
     let path_str = format!("{relative_path}");
     serializer.serialize_str(&path_str)
 }
+
+/// Converts an absolute path to a string using '/' as the path separator.
+///
+/// On Windows, the verbatim prefix added by `canonicalize` is dropped, so `\\?\C:\foo\bar.s` becomes `C:/foo/bar.s`
+/// and `\\?\UNC\server\share\foo.s` becomes `//server/share/foo.s`.
+fn absolute_path_to_string(path: &Path) -> String {
+    let mut result = String::new();
+    let mut needs_separator = false;
+
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(disk) | Prefix::VerbatimDisk(disk) => {
+                    result.push(char::from(disk));
+                    result.push(':');
+                }
+                Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+                    result.push_str("//");
+                    result.push_str(&server.to_string_lossy());
+                    result.push('/');
+                    result.push_str(&share.to_string_lossy());
+                }
+                Prefix::DeviceNS(device) => {
+                    result.push_str("//./");
+                    result.push_str(&device.to_string_lossy());
+                }
+                Prefix::Verbatim(name) => {
+                    result.push_str("//?/");
+                    result.push_str(&name.to_string_lossy());
+                }
+            },
+            Component::RootDir => {
+                result.push('/');
+                needs_separator = false;
+                continue;
+            }
+            c => {
+                if needs_separator {
+                    result.push('/');
+                }
+                result.push_str(&c.as_os_str().to_string_lossy());
+            }
+        }
+        needs_separator = true;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn summary_counts_warnings() {
+        let warning = |warn_type, code| Warning {
+            file: None,
+            message: String::new(),
+            warn_type,
+            code,
+        };
+        let warnings = vec![
+            warning(WarningType::Input, WarningCode::UnreadableFile),
+            warning(WarningType::Fingerprint, WarningCode::TooFewTokens),
+            warning(WarningType::Input, WarningCode::InaccessiblePath),
+            warning(WarningType::Fingerprint, WarningCode::TooFewTokens),
+            Warning::empty_project(Path::new("P2"), "reason"),
+            Warning::empty_project(Path::new("P1"), "reason"),
+        ];
+
+        let summary = serde_json::to_value(Summary::new(&warnings)).unwrap();
+        assert_eq!(
+            summary,
+            serde_json::json!({
+                "warnings_by_type": { "Input": 4, "Fingerprint": 2 },
+                "warnings_by_code": { "inaccessible_path": 1, "unreadable_file": 1, "too_few_tokens": 2, "empty_project": 2 },
+                "empty_projects": ["P1", "P2"],
+                "excluded_projects": [],
+                "starter_code_tokens": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn clean_projects_leave_out_flagged_and_empty_projects() {
+        let pair = ProjectPair {
+            project1: "a".into(),
+            project2: "b".into(),
+            matches: Vec::new(),
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        let mut output = Output::new(
+            vec![Warning::empty_project(Path::new("d"), "reason")],
+            vec![pair],
+        );
+        output.set_projects(
+            ["a", "b", "c", "d"]
+                .into_iter()
+                .map(|project| ProjectStats {
+                    project: project.into(),
+                    num_files: 1,
+                    num_tokens: 0,
+                    starter_code_tokens: 0,
+                    metadata: None,
+                })
+                .collect(),
+        );
+
+        output.add_clean_projects();
+        assert_eq!(
+            output.clean_projects,
+            Some(vec![CleanProject {
+                project: "c".into()
+            }])
+        );
+    }
+
+    #[test]
+    fn match_ids_do_not_depend_on_the_root() {
+        let output_under = |root: &Path| {
+            let location = |file: &str, span| Location {
+                file: root.join(file),
+                span,
+                length_bytes: None,
+                length_tokens: None,
+            };
+            let pair = ProjectPair {
+                project1: root.join("a"),
+                project2: root.join("b"),
+                matches: vec![
+                    Match {
+                        id: None,
+                        project_1_location: location("a/x.s", 0..3),
+                        project_2_location: location("b/y.s", 4..7),
+                        weight: None,
+                    },
+                    Match {
+                        id: None,
+                        project_1_location: location("a/x.s", 0..3),
+                        project_2_location: location("b/y.s", 5..8),
+                        weight: None,
+                    },
+                ],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
+            };
+            let mut output = Output::new(Vec::new(), vec![pair]);
+            output.assign_match_ids(root);
+            output.project_pairs.remove(0).matches
+        };
+
+        let matches = output_under(Path::new("/no-such-dir/2024"));
+        let ids = matches.iter().map(|m| m.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids[0].as_ref().map(String::len), Some(16));
+        assert_ne!(ids[0], ids[1]);
+        let other_ids = output_under(Path::new("/elsewhere"))
+            .iter()
+            .map(|m| m.id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, other_ids);
+    }
+
+    #[test]
+    fn ndjson_has_one_tagged_record_per_line() {
+        let location = |file: &str, start: usize| Location {
+            file: file.into(),
+            span: start..start + 3,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        let m = |start| Match {
+            id: None,
+            project_1_location: location("a/x.s", start),
+            project_2_location: location("b/y.s", start),
+            weight: None,
+        };
+        let pair = ProjectPair {
+            project1: "a".into(),
+            project2: "b".into(),
+            matches: vec![m(0), m(5)],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        let mut output = Output::new(
+            vec![Warning::empty_project(Path::new("c"), "reason")],
+            vec![pair],
+        );
+
+        let records = |per_match| {
+            let mut ndjson = Vec::new();
+            output.write_ndjson(&mut ndjson, per_match).unwrap();
+            String::from_utf8(ndjson)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let types = |records: &[serde_json::Value]| {
+            records
+                .iter()
+                .map(|r| r["type"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let per_pair = records(false);
+        assert_eq!(
+            types(&per_pair),
+            ["metadata", "project_pair", "summary", "warning"]
+        );
+        assert_eq!(per_pair[1]["project1"], "a");
+        assert_eq!(per_pair[1]["matches"].as_array().unwrap().len(), 2);
+
+        let per_match = records(true);
+        assert_eq!(
+            types(&per_match),
+            ["metadata", "match", "match", "summary", "warning"]
+        );
+        assert_eq!(per_match[2]["project2"], "b");
+        assert_eq!(per_match[2]["project_1_location"]["span"]["start"], 5);
+
+        // Metadata which changed after the pairs were streamed is written again at the end
+        let ndjson = NdjsonWriter::new(Vec::new(), &Metadata::default(), false).unwrap();
+        output.metadata.partial = true;
+        let ndjson = String::from_utf8(ndjson.finish(&output).unwrap()).unwrap();
+        let last =
+            serde_json::from_str::<serde_json::Value>(ndjson.lines().last().unwrap()).unwrap();
+        assert_eq!(last["type"], "metadata");
+        assert_eq!(last["partial"], true);
+    }
+
+    #[test]
+    fn matches_are_sorted_in_the_requested_order() {
+        let m = |file1: &str, span1: Range<usize>, file2: &str, span2: Range<usize>| Match {
+            id: None,
+            project_1_location: Location {
+                file: file1.into(),
+                span: span1,
+                length_bytes: None,
+                length_tokens: None,
+            },
+            project_2_location: Location {
+                file: file2.into(),
+                span: span2,
+                length_bytes: None,
+                length_tokens: None,
+            },
+            weight: None,
+        };
+        let matches = vec![
+            m("a/x.s", 0..5, "b/z.s", 0..5),
+            m("a/x.s", 10..30, "b/y.s", 0..20),
+            m("a/y.s", 0..5, "b/y.s", 30..40),
+            m("a/x.s", 40..45, "b/y.s", 50..55),
+        ];
+        let sorted = |order: MatchOrder| {
+            let mut matches = matches.clone();
+            matches.sort_by(|m1, m2| order.compare(m1, m2));
+            matches
+                .iter()
+                .map(|m| {
+                    (
+                        m.project_1_location.span.start,
+                        m.project_2_location.span.start,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(MatchOrder::Location),
+            vec![(0, 0), (10, 0), (40, 50), (0, 30)]
+        );
+        assert_eq!(
+            sorted(MatchOrder::Length),
+            vec![(10, 0), (0, 30), (0, 0), (40, 50)]
+        );
+        assert_eq!(
+            sorted(MatchOrder::FilePair),
+            vec![(10, 0), (40, 50), (0, 0), (0, 30)]
+        );
+    }
+
+    #[test]
+    fn auto_path_style_falls_back_to_absolute_paths() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let inside = root.join("output.rs");
+        let outside = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+        assert_eq!(
+            format_path(&inside, &root, PathStyle::Auto).unwrap(),
+            PathBuf::from("output.rs")
+        );
+        assert_eq!(
+            format_path(&outside, &root, PathStyle::Auto).unwrap(),
+            outside.canonicalize().unwrap()
+        );
+        assert!(format_path(&outside, &root, PathStyle::Relative).is_err());
+    }
+
+    #[test]
+    fn missing_projects_are_formatted_lexically() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let project = root.join("no-such-project");
+
+        for style in [PathStyle::Relative, PathStyle::Auto] {
+            assert_eq!(
+                format_path(&project, &root, style).unwrap(),
+                PathBuf::from("no-such-project")
+            );
+        }
+        assert_eq!(
+            format_path(&project, &root, PathStyle::Absolute).unwrap(),
+            root.canonicalize().unwrap().join("no-such-project")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn absolute_unix_paths_are_serialized_verbatim() {
+        assert_eq!(absolute_path_to_string(Path::new("/")), "/");
+        assert_eq!(
+            absolute_path_to_string(Path::new("/home/user/starter.s")),
+            "/home/user/starter.s"
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn absolute_windows_paths_use_forward_slashes() {
+        assert_eq!(
+            absolute_path_to_string(Path::new(r"\\?\C:\Users\me\starter.s")),
+            "C:/Users/me/starter.s"
+        );
+        assert_eq!(
+            absolute_path_to_string(Path::new(r"C:\Users\me\starter.s")),
+            "C:/Users/me/starter.s"
+        );
+        assert_eq!(
+            absolute_path_to_string(Path::new(r"\\?\UNC\server\share\starter.s")),
+            "//server/share/starter.s"
+        );
+    }
+}
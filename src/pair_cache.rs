@@ -0,0 +1,330 @@
+//! Caches completed project pairs across runs, so that rerunning an analysis after some projects change (e.g., a student
+//! resubmits) only expands and scores again the pairs involving the changed projects.
+//!
+//! The cache directory contains a single `pairs.ndjson` file with one entry per pair, keyed by hashes of the contents of
+//! its two projects and of the options. The matches found before expansion may also depend on the other projects (e.g.,
+//! when common code is removed or the matches are weighted by IDF), so each entry also records a hash of those matches
+//! and is only reused if they are unchanged.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    output::{Match, ProjectPair, Warning, WarningCode, WarningType},
+    DetectionOptions, File,
+};
+
+const PAIRS_FILE: &str = "pairs.ndjson";
+
+/// Key of a cached pair: the hashes of its two projects and of the options.
+type EntryKey = (u64, u64, u64);
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    project1_hash: u64,
+    project2_hash: u64,
+    options_hash: u64,
+    matches_hash: u64,
+    project_pair: ProjectPair,
+}
+
+impl Entry {
+    fn key(&self) -> EntryKey {
+        (self.project1_hash, self.project2_hash, self.options_hash)
+    }
+}
+
+/// Cache of the project pairs completed by earlier runs, along with those completed by the current run.
+pub struct PairCache {
+    dir: PathBuf,
+    options_hash: u64,
+    project_hashes: HashMap<PathBuf, u64>,
+    entries: HashMap<EntryKey, Entry>,
+    /// Hashes of the matches of the pairs which were looked up but not found, to be recorded once they are completed.
+    missed_matches_hashes: HashMap<(PathBuf, PathBuf), u64>,
+}
+
+impl PairCache {
+    /// Opens the cache in `dir` for an analysis of the given documents, creating the directory if necessary.
+    pub fn open(
+        dir: &Path,
+        options: &DetectionOptions,
+        documents: &[File],
+        ignored_documents: &[File],
+    ) -> io::Result<PairCache> {
+        fs::create_dir_all(dir)?;
+
+        // Unreadable lines (e.g., one cut short by a crash) are skipped
+        let entries = match fs::File::open(dir.join(PAIRS_FILE)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str::<Entry>(&line).ok())
+                .map(|entry| (entry.key(), entry))
+                .collect(),
+        };
+
+        Ok(PairCache {
+            dir: dir.to_owned(),
+            options_hash: options_hash(options, ignored_documents),
+            project_hashes: project_hashes(documents),
+            entries,
+            missed_matches_hashes: HashMap::new(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the cached version of a pair whose matches have just been found, if its projects, the options, and the
+    /// matches are unchanged. Its explanation is kept, except for the coverage, since the rest of it depends on the other
+    /// projects.
+    pub fn lookup(&mut self, pair: &ProjectPair) -> Option<ProjectPair> {
+        let key = self.key(pair)?;
+        let matches_hash = matches_hash(&pair.matches);
+        let entry = match self.entries.get(&key) {
+            Some(entry) if entry.matches_hash == matches_hash => entry,
+            _ => {
+                let projects = (pair.project1.clone(), pair.project2.clone());
+                self.missed_matches_hashes.insert(projects, matches_hash);
+                return None;
+            }
+        };
+        let mut cached = entry.project_pair.clone();
+        cached.explanation = match (pair.explanation.clone(), &cached.explanation) {
+            (Some(mut explanation), Some(cached_explanation)) => {
+                explanation.project_1_coverage = cached_explanation.project_1_coverage;
+                explanation.project_2_coverage = cached_explanation.project_2_coverage;
+                explanation.project_1_unmatched_tokens =
+                    cached_explanation.project_1_unmatched_tokens;
+                explanation.project_2_unmatched_tokens =
+                    cached_explanation.project_2_unmatched_tokens;
+                explanation.more_unique_content = cached_explanation.more_unique_content;
+                Some(explanation)
+            }
+            (explanation, _) => explanation,
+        };
+        Some(cached)
+    }
+
+    /// Records a completed pair which was looked up without being found.
+    pub fn insert(&mut self, project_pair: &ProjectPair) {
+        let projects = (project_pair.project1.clone(), project_pair.project2.clone());
+        let (Some(key), Some(matches_hash)) = (
+            self.key(project_pair),
+            self.missed_matches_hashes.remove(&projects),
+        ) else {
+            return;
+        };
+        let (project1_hash, project2_hash, options_hash) = key;
+        let entry = Entry {
+            project1_hash,
+            project2_hash,
+            options_hash,
+            matches_hash,
+            project_pair: project_pair.clone(),
+        };
+        self.entries.insert(key, entry);
+    }
+
+    /// Saves the entries of the pairs whose projects are still part of the analysis. The entries of other options are
+    /// kept, but those of projects which have since changed are discarded so that the cache does not keep growing.
+    pub fn save(&self) -> io::Result<()> {
+        let current_hashes = self.project_hashes.values().collect::<HashSet<_>>();
+        let entries = self
+            .entries
+            .values()
+            .filter(|e| {
+                current_hashes.contains(&e.project1_hash)
+                    && current_hashes.contains(&e.project2_hash)
+            })
+            .sorted_by_key(|e| e.key());
+
+        // Write to a temporary file first so that a crash never leaves a truncated cache behind
+        let temp_path = self.dir.join(format!("{PAIRS_FILE}.tmp"));
+        let mut writer = BufWriter::new(fs::File::create(&temp_path)?);
+        for entry in entries {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(temp_path, self.dir.join(PAIRS_FILE))
+    }
+
+    fn key(&self, pair: &ProjectPair) -> Option<EntryKey> {
+        Some((
+            *self.project_hashes.get(&pair.project1)?,
+            *self.project_hashes.get(&pair.project2)?,
+            self.options_hash,
+        ))
+    }
+}
+
+/// Converts a cache error into a warning. The analysis continues without the cache.
+pub fn cache_warning(dir: &Path, error: io::Error) -> Warning {
+    Warning {
+        file: Some(dir.to_owned()),
+        message: format!("Failed to update the cache: {error}"),
+        warn_type: WarningType::Checkpoint,
+        code: WarningCode::CacheFailed,
+    }
+}
+
+/// Hashes the files of each project, along with their paths.
+fn project_hashes(documents: &[File]) -> HashMap<PathBuf, u64> {
+    documents
+        .iter()
+        .into_group_map_by(|f| f.project().clone())
+        .into_iter()
+        .map(|(project, files)| {
+            let mut hasher = FxHasher::default();
+            for f in files.into_iter().sorted_by_key(|f| f.path()) {
+                f.hash(&mut hasher);
+            }
+            (project, hasher.finish())
+        })
+        .collect()
+}
+
+/// Hashes the matches of a pair, regardless of their order.
+fn matches_hash(matches: &[Match]) -> u64 {
+    let mut hasher = FxHasher::default();
+    let sorted_matches = matches.iter().sorted_by_key(|m| {
+        (
+            &m.project_1_location.file,
+            m.project_1_location.span.start,
+            m.project_1_location.span.end,
+            &m.project_2_location.file,
+            m.project_2_location.span.start,
+            m.project_2_location.span.end,
+        )
+    });
+    for m in sorted_matches {
+        m.project_1_location.file.hash(&mut hasher);
+        m.project_1_location.span.hash(&mut hasher);
+        m.project_2_location.file.hash(&mut hasher);
+        m.project_2_location.span.hash(&mut hasher);
+        m.weight.map(f64::to_bits).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Hashes everything besides the matches and the contents of the two projects that affects a completed pair.
+fn options_hash(options: &DetectionOptions, ignored_documents: &[File]) -> u64 {
+    let mut hasher = FxHasher::default();
+
+    options.noise_threshold.hash(&mut hasher);
+    options.guarantee_threshold.hash(&mut hasher);
+    options.max_token_offset.hash(&mut hasher);
+    options.fingerprint_algorithm.name().hash(&mut hasher);
+    options.tokenizing_strategy.hash(&mut hasher);
+    options.ignore_whitespace.hash(&mut hasher);
+    options.transforms.hash(&mut hasher);
+    options.key_symbols.hash(&mut hasher);
+    options.hash_dialect.hash(&mut hasher);
+    options.strict_lex.hash(&mut hasher);
+    options.stitch_files.hash(&mut hasher);
+    options.granularity.hash(&mut hasher);
+    options.expand_matches.hash(&mut hasher);
+    options.min_report_length.hash(&mut hasher);
+    options.scoring.hash(&mut hasher);
+    options
+        .starter_code_similarity
+        .map(f64::to_bits)
+        .hash(&mut hasher);
+
+    ignored_documents.len().hash(&mut hasher);
+    for f in ignored_documents {
+        f.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Location;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fungus-pair-cache-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn pair(project2: &str, span: std::ops::Range<usize>) -> ProjectPair {
+        ProjectPair {
+            project1: "P1".into(),
+            project2: project2.into(),
+            matches: vec![Match {
+                id: None,
+                project_1_location: Location {
+                    file: "P1/a.s".into(),
+                    span: span.clone(),
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                project_2_location: Location {
+                    file: format!("{project2}/a.s").into(),
+                    span,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                weight: None,
+            }],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        }
+    }
+
+    #[test]
+    fn only_pairs_of_unchanged_projects_are_reused() {
+        let dir = temp_dir("reuse");
+        let options = DetectionOptions::default();
+        let file = |project: &str, contents: &str| {
+            File::new(
+                project.into(),
+                format!("{project}/a.s").into(),
+                contents.to_owned(),
+            )
+        };
+        let documents = vec![file("P1", "mov"), file("P2", "add"), file("P3", "sub")];
+        let (found_12, found_13) = (pair("P2", 0..3), pair("P3", 0..3));
+        let (completed_12, completed_13) = (pair("P2", 0..5), pair("P3", 0..5));
+
+        let mut cache = PairCache::open(&dir, &options, &documents, &[]).unwrap();
+        assert!(cache.lookup(&found_12).is_none());
+        assert!(cache.lookup(&found_13).is_none());
+        cache.insert(&completed_12);
+        cache.insert(&completed_13);
+        cache.save().unwrap();
+
+        // P3 resubmits
+        let documents = vec![file("P1", "mov"), file("P2", "add"), file("P3", "mul")];
+        let mut cache = PairCache::open(&dir, &options, &documents, &[]).unwrap();
+        assert_eq!(cache.lookup(&found_12), Some(completed_12));
+        assert!(cache.lookup(&found_13).is_none());
+        assert!(cache.lookup(&pair("P2", 1..3)).is_none());
+        cache.save().unwrap();
+
+        let cache = PairCache::open(&dir, &options, &documents, &[]).unwrap();
+        assert_eq!(cache.entries.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
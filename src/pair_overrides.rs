@@ -0,0 +1,129 @@
+//! Thresholds overridden for specific projects or pairs of projects, e.g. so that declared group partners may share
+//! more code before being reported.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Thresholds replacing the global ones for the pairs of some projects.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairOverride {
+    /// Either a single project, in which case every pair containing it is overridden, or the two projects of the
+    /// overridden pair.
+    pub projects: Vec<PathBuf>,
+    /// Pairs with fewer than this number of matches are not reported. Replaces `DetectionOptions::min_matches`.
+    pub min_matches: Option<usize>,
+    /// Pairs where neither project has at least this proportion of its tokens covered by the matches are not
+    /// reported. There is no global coverage threshold, so this is only applied to the overridden pairs.
+    pub min_coverage: Option<f64>,
+}
+
+impl PairOverride {
+    /// Checks that the override names one or two projects and that its thresholds are in range.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=2).contains(&self.projects.len()) {
+            return Err(format!(
+                "expected one or two projects, found {}",
+                self.projects.len()
+            ));
+        }
+        if let Some(min_coverage) = self.min_coverage {
+            if !(0.0..=1.0).contains(&min_coverage) {
+                return Err(format!(
+                    "expected a minimum coverage in the range [0, 1], found {min_coverage}"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn applies_to(&self, project1: &Path, project2: &Path) -> bool {
+        match self.projects.as_slice() {
+            [project] => project == project1 || project == project2,
+            [p1, p2] => (p1 == project1 && p2 == project2) || (p1 == project2 && p2 == project1),
+            _ => false,
+        }
+    }
+}
+
+/// Thresholds applied to a pair of projects once its matches are complete.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PairThresholds {
+    pub(crate) min_matches: usize,
+    pub(crate) min_coverage: f64,
+}
+
+/// Returns the thresholds of a pair of projects. Overrides of the pair take precedence over overrides of either
+/// project, and later overrides take precedence over earlier ones of the same kind. Thresholds which no override sets
+/// keep their global value.
+pub(crate) fn pair_thresholds(
+    overrides: &[PairOverride],
+    project1: &Path,
+    project2: &Path,
+    min_matches: usize,
+) -> PairThresholds {
+    let mut thresholds = PairThresholds {
+        min_matches,
+        min_coverage: 0.0,
+    };
+    let project_overrides = overrides.iter().filter(|o| o.projects.len() == 1);
+    let pair_overrides = overrides.iter().filter(|o| o.projects.len() == 2);
+    for o in project_overrides.chain(pair_overrides) {
+        if o.applies_to(project1, project2) {
+            thresholds.min_matches = o.min_matches.unwrap_or(thresholds.min_matches);
+            thresholds.min_coverage = o.min_coverage.unwrap_or(thresholds.min_coverage);
+        }
+    }
+    thresholds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pair_overrides_take_precedence() {
+        let overrides: Vec<PairOverride> = serde_json::from_str(
+            r#"[
+                {"projects": ["bob", "alice"], "min_matches": 50},
+                {"projects": ["alice"], "min_matches": 20, "min_coverage": 0.5}
+            ]"#,
+        )
+        .unwrap();
+        let thresholds =
+            |p1: &str, p2: &str| pair_thresholds(&overrides, Path::new(p1), Path::new(p2), 5);
+
+        assert_eq!(
+            thresholds("alice", "bob"),
+            PairThresholds {
+                min_matches: 50,
+                min_coverage: 0.5
+            }
+        );
+        assert_eq!(
+            thresholds("alice", "carol"),
+            PairThresholds {
+                min_matches: 20,
+                min_coverage: 0.5
+            }
+        );
+        assert_eq!(
+            thresholds("bob", "carol"),
+            PairThresholds {
+                min_matches: 5,
+                min_coverage: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        let parse = |s: &str| serde_json::from_str::<PairOverride>(s).unwrap().validate();
+        assert!(parse(r#"{"projects": ["a"], "min_coverage": 1.0}"#).is_ok());
+        assert!(parse(r#"{"projects": []}"#).is_err());
+        assert!(parse(r#"{"projects": ["a", "b", "c"]}"#).is_err());
+        assert!(parse(r#"{"projects": ["a"], "min_coverage": 1.5}"#).is_err());
+        assert!(serde_json::from_str::<PairOverride>(r#"{"projects": ["a"], "min": 1}"#).is_err());
+    }
+}
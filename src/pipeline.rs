@@ -0,0 +1,244 @@
+//! Helpers running the stages of the analysis on worker threads. The results are streamed back through bounded
+//! channels, so the workers wait for the next stage when it falls behind instead of piling up results, and they are
+//! put back in the order of the items.
+
+use std::{
+    collections::BTreeMap,
+    ops::ControlFlow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+/// Applies `f` to every item on the given number of worker threads, and passes the results to `on_result` on this
+/// thread in the order of the items.
+pub fn map_in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    num_workers: usize,
+    f: impl Fn(&T) -> R + Sync,
+    mut on_result: impl FnMut(&T, R),
+) {
+    map_in_pipeline(
+        items,
+        num_workers,
+        1,
+        f,
+        |r| r,
+        |item, r| {
+            on_result(item, r);
+            ControlFlow::Continue(())
+        },
+    );
+}
+
+/// Applies `read` to every item on `io_workers` threads, then `process` to every result on `cpu_workers` threads, and
+/// passes the final results to `on_result` on this thread in the order of the items. The two stages overlap: the first
+/// items are processed while the next ones are still being read, which hides the latency of an IO-bound `read` behind a
+/// CPU-bound `process`.
+///
+/// Once `on_result` breaks, the workers stop after their current item and the remaining results are dropped.
+pub fn map_in_pipeline<T: Sync, R: Send, S: Send>(
+    items: &[T],
+    io_workers: usize,
+    cpu_workers: usize,
+    read: impl Fn(&T) -> R + Sync,
+    process: impl Fn(R) -> S + Sync,
+    mut on_result: impl FnMut(&T, S) -> ControlFlow<()>,
+) {
+    let pending_items = Mutex::new(items.iter().enumerate());
+    let stop = AtomicBool::new(false);
+    let io_workers = io_workers.min(items.len()).max(1);
+    let cpu_workers = cpu_workers.min(items.len()).max(1);
+    thread::scope(|s| {
+        let (read_sender, read_receiver) = mpsc::sync_channel(cpu_workers);
+        // Shared by the CPU workers, so that the readers cannot block forever once all of them have stopped
+        let read_receiver = Arc::new(Mutex::new(read_receiver));
+        for _ in 0..io_workers {
+            let read_sender = read_sender.clone();
+            let (pending_items, stop, read) = (&pending_items, &stop, &read);
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some((i, item)) = pending_items.lock().unwrap().next() else {
+                        break;
+                    };
+                    if read_sender.send((i, read(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(read_sender);
+
+        let (sender, receiver) = mpsc::sync_channel(cpu_workers);
+        for _ in 0..cpu_workers {
+            let sender = sender.clone();
+            let (read_receiver, process) = (read_receiver.clone(), &process);
+            s.spawn(move || loop {
+                let Ok((i, r)) = read_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                if sender.send((i, process(r))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop((sender, read_receiver));
+
+        let mut results = BTreeMap::new();
+        let mut next = 0;
+        for (i, result) in receiver {
+            results.insert(i, result);
+            while let Some(result) = results.remove(&next) {
+                if on_result(&items[next], result).is_break() {
+                    stop.store(true, Ordering::Relaxed);
+                    return;
+                }
+                next += 1;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, time::Duration};
+
+    use super::*;
+
+    /// Counts the workers running a stage at the same time.
+    #[derive(Default)]
+    struct Concurrency {
+        current: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    impl Concurrency {
+        fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+            let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max.fetch_max(current, Ordering::SeqCst);
+            let result = f();
+            self.current.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+    }
+
+    /// Makes the workers finish out of order.
+    fn pause(i: u64) {
+        thread::sleep(Duration::from_micros((i * 7919) % 500));
+    }
+
+    #[test]
+    fn parallel_results_are_in_order() {
+        let items = (0..100).collect::<Vec<u64>>();
+        for num_workers in [1, 3, 16] {
+            let mut results = Vec::new();
+            map_in_parallel(
+                &items,
+                num_workers,
+                |&i| {
+                    pause(i);
+                    i * i
+                },
+                |&i, r| results.push((i, r)),
+            );
+            assert_eq!(
+                results,
+                items.iter().map(|&i| (i, i * i)).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn pipeline_stages_use_their_own_workers() {
+        let items = (0..200).collect::<Vec<u64>>();
+        for (io_workers, cpu_workers) in [(1, 1), (8, 2), (2, 8)] {
+            let (reading, processing) = (Concurrency::default(), Concurrency::default());
+            let mut results = Vec::new();
+            map_in_pipeline(
+                &items,
+                io_workers,
+                cpu_workers,
+                |&i| {
+                    reading.run(|| {
+                        pause(i);
+                        i + 1
+                    })
+                },
+                |r| {
+                    processing.run(|| {
+                        pause(r);
+                        r * 2
+                    })
+                },
+                |&i, r| {
+                    results.push((i, r));
+                    ControlFlow::Continue(())
+                },
+            );
+            assert_eq!(
+                results,
+                items.iter().map(|&i| (i, (i + 1) * 2)).collect::<Vec<_>>()
+            );
+            assert!(reading.max.into_inner() <= io_workers);
+            assert!(processing.max.into_inner() <= cpu_workers);
+        }
+    }
+
+    #[test]
+    fn pipeline_overlaps_reading_and_processing() {
+        // The first item can only be processed while the second one is being read
+        let first_processed = AtomicBool::new(false);
+        let mut results = Vec::new();
+        map_in_pipeline(
+            &[0, 1],
+            1,
+            1,
+            |&i| {
+                if i == 1 {
+                    while !first_processed.load(Ordering::SeqCst) {
+                        thread::yield_now();
+                    }
+                }
+                i
+            },
+            |r| {
+                first_processed.store(true, Ordering::SeqCst);
+                r
+            },
+            |_, r| {
+                results.push(r);
+                ControlFlow::Continue(())
+            },
+        );
+        assert_eq!(results, [0, 1]);
+    }
+
+    #[test]
+    fn pipeline_stops_when_asked() {
+        let items = (0..1000).collect::<Vec<u64>>();
+        let num_read = AtomicUsize::new(0);
+        let mut results = Vec::new();
+        map_in_pipeline(
+            &items,
+            2,
+            2,
+            |&i| {
+                num_read.fetch_add(1, Ordering::SeqCst);
+                i
+            },
+            |r| r,
+            |_, r| {
+                results.push(r);
+                if r == 9 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+        assert_eq!(results, (0..10).collect::<Vec<_>>());
+        assert!(num_read.into_inner() < items.len());
+    }
+}
@@ -0,0 +1,121 @@
+//! Information about the projects which is not found in their files, such as the lab section of each student.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the file inside a project directory which holds the metadata of that project. It is not analyzed as code.
+pub const PROJECT_METADATA_FILE: &str = "fungus-meta.json";
+
+/// Metadata of one project, as given in the project metadata file or in the project's own metadata file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectMetadata {
+    /// Name of the student (or team) who submitted the project.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Identifier of the student (or team), such as a student number.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub student_id: Option<String>,
+    /// Section or cohort to which the project belongs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    /// Time at which the project was submitted, in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+impl ProjectMetadata {
+    /// Parses the contents of a project's own metadata file.
+    pub fn parse(contents: &str) -> Result<ProjectMetadata, String> {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    }
+
+    /// Returns the metadata with the fields it is missing taken from the other metadata.
+    pub fn or(self, other: ProjectMetadata) -> ProjectMetadata {
+        ProjectMetadata {
+            name: self.name.or(other.name),
+            student_id: self.student_id.or(other.student_id),
+            section: self.section.or(other.section),
+            timestamp: self.timestamp.or(other.timestamp),
+        }
+    }
+}
+
+/// Which pairs of projects are compared, according to their sections.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum CompareMode {
+    /// Compare every pair of projects.
+    #[default]
+    All,
+    /// Only compare projects from the same section.
+    Within,
+    /// Only compare projects from different sections.
+    Across,
+}
+
+/// Returns whether a pair of projects is compared, given the section of each project. Projects without a section are
+/// only compared with the "all" mode, since they cannot be placed within or across sections.
+pub(crate) fn is_compared(
+    mode: CompareMode,
+    sections: &BTreeMap<PathBuf, String>,
+    project1: &PathBuf,
+    project2: &PathBuf,
+) -> bool {
+    match mode {
+        CompareMode::All => true,
+        CompareMode::Within | CompareMode::Across => {
+            match (sections.get(project1), sections.get(project2)) {
+                (Some(s1), Some(s2)) => (s1 == s2) == (mode == CompareMode::Within),
+                _ => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_are_taken_from_other_metadata() {
+        let own = ProjectMetadata::parse(
+            r#"{ "name": "Alice", "student_id": "260000001", "section": "L02" }"#,
+        )
+        .unwrap();
+        let given = ProjectMetadata {
+            section: Some("L01".to_owned()),
+            timestamp: Some(1_700_000_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            given.or(own),
+            ProjectMetadata {
+                name: Some("Alice".to_owned()),
+                student_id: Some("260000001".to_owned()),
+                section: Some("L01".to_owned()),
+                timestamp: Some(1_700_000_000),
+            }
+        );
+        assert!(ProjectMetadata::parse(r#"{ "grade": "A" }"#).is_err());
+    }
+
+    #[test]
+    fn compares_within_or_across_sections() {
+        let sections = BTreeMap::from([
+            ("alice".into(), "L01".to_owned()),
+            ("bob".into(), "L01".to_owned()),
+            ("carol".into(), "L02".to_owned()),
+        ]);
+        let compared =
+            |mode, p1: &str, p2: &str| is_compared(mode, &sections, &p1.into(), &p2.into());
+
+        assert!(compared(CompareMode::Within, "alice", "bob"));
+        assert!(!compared(CompareMode::Within, "alice", "carol"));
+        assert!(!compared(CompareMode::Across, "alice", "bob"));
+        assert!(compared(CompareMode::Across, "alice", "carol"));
+        assert!(!compared(CompareMode::Across, "alice", "dave"));
+        assert!(compared(CompareMode::All, "alice", "dave"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Case summaries: one Markdown document per confirmed pair, with the evidence needed to refer the case to an academic
+//! integrity office.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use super::{coverage_cells, pair_file_name_for, score_cell, SnippetReader};
+use crate::{
+    annotations::ReviewStatus,
+    output::{Location, ProjectPair},
+};
+
+/// Template used when none is given. See `render_case_summary` for the placeholders it may contain.
+pub const DEFAULT_TEMPLATE: &str = include_str!("case_template.md");
+
+/// Writes one case summary per confirmed pair into `dir`, filling in `template`. Returns the number of summaries
+/// written. Each excerpt includes up to `context_lines` lines before and after the matched code.
+///
+/// The paths in `project_pairs` must be relative to `root`.
+pub fn write_case_summaries(
+    project_pairs: &[ProjectPair],
+    root: &Path,
+    dir: &Path,
+    template: &str,
+    context_lines: usize,
+) -> anyhow::Result<usize> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create case directory '{}'.", dir.display()))?;
+
+    let mut reader = SnippetReader::new(root, context_lines);
+    let confirmed_pairs = project_pairs
+        .iter()
+        .filter(|p| p.review.as_ref().map(|r| r.status) == Some(ReviewStatus::Confirmed))
+        .collect::<Vec<_>>();
+
+    for pair in confirmed_pairs.iter() {
+        let summary = render_case_summary(pair, template, &mut reader);
+        let path = dir.join(format!("{}.md", pair_file_name_for(pair)));
+        fs::write(&path, summary)
+            .with_context(|| format!("Failed to write case summary to '{}'.", path.display()))?;
+    }
+
+    Ok(confirmed_pairs.len())
+}
+
+/// Fills in the template for a single pair. The template may contain the following placeholders, which are replaced
+/// wherever they appear:
+///
+/// - `{{project1}}` and `{{project2}}`: names of the projects.
+/// - `{{num_matches}}`: number of matches.
+/// - `{{score}}`: score of the pair, or its number of matches if it has no score.
+/// - `{{coverage1}}` and `{{coverage2}}`: percentage of the code of each project covered by the matches.
+/// - `{{notes}}`: notes of the reviewer.
+/// - `{{files}}`: list of the files containing matched code, with their SHA-256 checksums.
+/// - `{{matches}}`: excerpts of the matched code of both projects.
+fn render_case_summary(pair: &ProjectPair, template: &str, reader: &mut SnippetReader) -> String {
+    let (coverage1, coverage2) = coverage_cells(pair);
+    let score = score_cell(pair);
+    let notes = pair
+        .review
+        .as_ref()
+        .and_then(|r| r.notes.as_deref())
+        .unwrap_or("none");
+
+    // The matches are rendered last, since they may contain text which looks like a placeholder
+    template
+        .replace("{{project1}}", &pair.project1.to_string_lossy())
+        .replace("{{project2}}", &pair.project2.to_string_lossy())
+        .replace("{{num_matches}}", &pair.matches.len().to_string())
+        .replace("{{score}}", &score)
+        .replace("{{coverage1}}", &coverage1)
+        .replace("{{coverage2}}", &coverage2)
+        .replace("{{notes}}", notes)
+        .replace("{{files}}", &render_files(pair, reader))
+        .replace("{{matches}}", &render_matches(pair, reader))
+}
+
+/// Lists the files containing matched code with their checksums.
+fn render_files(pair: &ProjectPair, reader: &mut SnippetReader) -> String {
+    let files = pair
+        .matches
+        .iter()
+        .flat_map(|m| [&m.project_1_location.file, &m.project_2_location.file])
+        .collect::<BTreeSet<&PathBuf>>();
+
+    let mut list = String::new();
+    // Writing to a `String` cannot fail, so the results are ignored throughout.
+    for file in files {
+        let checksum = match reader.contents(file) {
+            Some(contents) => format!("`{:x}`", Sha256::digest(contents.as_bytes())),
+            None => "unavailable (the file could not be read)".to_owned(),
+        };
+        let _ = writeln!(list, "- `{}`: {checksum}", file.display());
+    }
+    list.trim_end().to_owned()
+}
+
+fn render_matches(pair: &ProjectPair, reader: &mut SnippetReader) -> String {
+    let mut matches = String::new();
+    for (i, m) in pair.matches.iter().enumerate() {
+        let _ = writeln!(matches, "### Match {}\n", i + 1);
+        write_excerpt(&mut matches, &m.project_1_location, reader);
+        write_excerpt(&mut matches, &m.project_2_location, reader);
+    }
+    matches.trim_end().to_owned()
+}
+
+fn write_excerpt(text: &mut String, location: &Location, reader: &mut SnippetReader) {
+    let lines = match reader.lines(location) {
+        Some((first, last)) if first == last => format!("line {first}"),
+        Some((first, last)) => format!("lines {first}-{last}"),
+        None => format!("bytes {}-{}", location.span.start, location.span.end),
+    };
+    let _ = writeln!(text, "`{}` ({lines}):\n", location.file.display());
+    match reader.snippet(location) {
+        None => {
+            let _ = writeln!(text, "*Excerpt unavailable.*\n");
+        }
+        Some(snippet) => {
+            let snippet = snippet.trim_end();
+            let _ = writeln!(
+                text,
+                "```\n{}{}{}\n```\n",
+                snippet.before, snippet.matched, snippet.after
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        annotations::Review,
+        output::{Match, ProjectPair},
+    };
+
+    #[test]
+    fn fills_in_the_template() {
+        let pair = ProjectPair {
+            project1: "a".into(),
+            project2: "b".into(),
+            matches: vec![Match {
+                id: None,
+                project_1_location: Location {
+                    file: "a/x.s".into(),
+                    span: 0..3,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                project_2_location: Location {
+                    file: "b/y.s".into(),
+                    span: 4..7,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                weight: None,
+            }],
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: Some(Review {
+                status: ReviewStatus::Confirmed,
+                notes: Some("Same typo".to_owned()),
+            }),
+        };
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("a/x.s".into(), Some("add r0".to_owned()));
+        reader
+            .files
+            .insert("b/y.s".into(), Some("mov\nadd".to_owned()));
+
+        let summary = render_case_summary(&pair, DEFAULT_TEMPLATE, &mut reader);
+        assert!(summary.starts_with("# Similarity case: `a` and `b`"));
+        assert!(summary.contains("- Matches: 1\n- Score: 1\n"));
+        assert!(summary.contains("- Reviewer notes: Same typo"));
+        assert!(summary.contains("- `b/y.s`: `"));
+        assert!(summary.contains("`b/y.s` (line 2):\n\n```\nadd\n```"));
+        assert!(!summary.contains("{{"));
+
+        let summary = render_case_summary(&pair, "{{project2}}: {{files}}", &mut reader);
+        let checksum = format!("{:x}", Sha256::digest("add r0"));
+        assert_eq!(
+            summary,
+            format!(
+                "b: - `a/x.s`: `{checksum}`\n- `b/y.s`: `{:x}`",
+                Sha256::digest("mov\nadd")
+            )
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Clone-pair CSV, the interchange format of BigCloneBench and of the tools used to evaluate clone detectors against
+//! it. Each line describes one clone pair as `dir1,file1,start1,end1,dir2,file2,start2,end2`, where the lines are
+//! one-based and inclusive.
+
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Context;
+
+use super::SnippetReader;
+use crate::output::{Location, Output};
+
+/// Writes one clone pair per match to `path`. Matches in files which cannot be read are skipped, since their lines are
+/// unknown.
+///
+/// The paths in `output` must be relative to `root`.
+pub fn write_clone_pairs(output: &Output, root: &Path, path: &Path) -> anyhow::Result<()> {
+    let mut reader = SnippetReader::new(root, 0);
+    let csv = render_clone_pairs(output, &mut reader);
+    fs::write(path, csv)
+        .with_context(|| format!("Failed to write clone pairs to \"{}\".", path.display()))
+}
+
+fn render_clone_pairs(output: &Output, reader: &mut SnippetReader) -> String {
+    let mut csv = String::new();
+
+    for pair in output.project_pairs.iter() {
+        for m in pair.matches.iter() {
+            let (Some(clone1), Some(clone2)) = (
+                clone(&m.project_1_location, reader),
+                clone(&m.project_2_location, reader),
+            ) else {
+                continue;
+            };
+            // Writing to a `String` cannot fail
+            let _ = writeln!(csv, "{clone1},{clone2}");
+        }
+    }
+
+    csv
+}
+
+/// Formats the directory, file name, and first and last lines of the code at the given location.
+fn clone(location: &Location, reader: &mut SnippetReader) -> Option<String> {
+    let (first, last) = reader.lines(location)?;
+    let dir = location
+        .file
+        .parent()
+        .map(|dir| dir.to_string_lossy())
+        .unwrap_or_default();
+    let file = location
+        .file
+        .file_name()
+        .map(|file| file.to_string_lossy())
+        .unwrap_or_default();
+    Some(format!("{},{},{first},{last}", escape(&dir), escape(&file)))
+}
+
+/// Quotes a field if it contains a delimiter, a quote, or a newline.
+pub(super) fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::{Match, ProjectPair};
+
+    #[test]
+    fn writes_one_line_per_match() {
+        let location = |file: &str, span| Location {
+            file: PathBuf::from(file),
+            span,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        let output = Output::new(
+            Vec::new(),
+            vec![ProjectPair {
+                project1: "alice".into(),
+                project2: "bob, jr".into(),
+                matches: vec![
+                    Match {
+                        id: None,
+                        project_1_location: location("alice/src/a.s", 3..9),
+                        project_2_location: location("bob, jr/b.s", 0..3),
+                        weight: None,
+                    },
+                    Match {
+                        id: None,
+                        project_1_location: location("alice/src/a.s", 0..3),
+                        project_2_location: location("bob, jr/missing.s", 0..3),
+                        weight: None,
+                    },
+                ],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
+            }],
+        );
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("alice/src/a.s".into(), Some("l1\nl2\nl3\n".to_owned()));
+        reader
+            .files
+            .insert("bob, jr/b.s".into(), Some("l2\nl3\n".to_owned()));
+
+        assert_eq!(
+            render_clone_pairs(&output, &mut reader),
+            "alice/src,a.s,2,3,\"bob, jr\",b.s,1,1\n"
+        );
+    }
+}
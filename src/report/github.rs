@@ -0,0 +1,125 @@
+//! GitHub Actions workflow commands. Printed by a step of a workflow, they annotate the matched code on the commit
+//! being checked, e.g. when each student's repository is compared with a baseline corpus in CI.
+
+use std::{fmt::Write, path::Path};
+
+use super::SnippetReader;
+use crate::output::{Location, Output};
+
+/// Prints one warning annotation for each side of every match to standard output, where GitHub Actions picks them up.
+///
+/// The paths in `output` must be relative to `root`. GitHub resolves the annotated files relative to the root of the
+/// repository.
+pub fn print_github_annotations(output: &Output, root: &Path) {
+    let mut reader = SnippetReader::new(root, 0);
+    print!("{}", render_github_annotations(output, &mut reader));
+}
+
+fn render_github_annotations(output: &Output, reader: &mut SnippetReader) -> String {
+    let mut annotations = String::new();
+
+    for pair in output.project_pairs.iter() {
+        for m in pair.matches.iter() {
+            for (location, other_location, other_project) in [
+                (&m.project_1_location, &m.project_2_location, &pair.project2),
+                (&m.project_2_location, &m.project_1_location, &pair.project1),
+            ] {
+                let Some((first, last)) = reader.lines(location) else {
+                    continue;
+                };
+                let message = format!(
+                    "This code is similar to {} in project {}.",
+                    describe_location(other_location, reader),
+                    other_project.display()
+                );
+                // Writing to a `String` cannot fail
+                let _ = writeln!(
+                    annotations,
+                    "::warning file={},line={first},endLine={last},title={}::{}",
+                    escape_property(&location.file.to_string_lossy()),
+                    escape_property("Similar code"),
+                    escape_data(&message)
+                );
+            }
+        }
+    }
+
+    annotations
+}
+
+/// Describes the lines at the given location, or its bytes if the file cannot be read.
+fn describe_location(location: &Location, reader: &mut SnippetReader) -> String {
+    let file = location.file.display();
+    match reader.lines(location) {
+        Some((first, last)) if first == last => format!("{file} (line {first})"),
+        Some((first, last)) => format!("{file} (lines {first}-{last})"),
+        None => format!(
+            "{file} (bytes {}-{})",
+            location.span.start, location.span.end
+        ),
+    }
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes the value of a property of a workflow command, which also cannot contain the property delimiters.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::{Match, ProjectPair};
+
+    #[test]
+    fn annotates_both_sides_of_matches() {
+        let location = |file: &str, span| Location {
+            file: PathBuf::from(file),
+            span,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        let output = Output::new(
+            Vec::new(),
+            vec![ProjectPair {
+                project1: "alice".into(),
+                project2: "bob,jr".into(),
+                matches: vec![Match {
+                    id: None,
+                    project_1_location: location("alice/a.s", 3..9),
+                    project_2_location: location("bob,jr/b.s", 0..3),
+                    weight: None,
+                }],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
+            }],
+        );
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("alice/a.s".into(), Some("l1\nl2\nl3\n".to_owned()));
+
+        // The file of project 2 cannot be read, so it is only described by its bytes
+        assert_eq!(
+            render_github_annotations(&output, &mut reader),
+            "::warning file=alice/a.s,line=2,endLine=3,title=Similar code::This code is similar to bob,jr/b.s \
+             (bytes 0-3) in project bob,jr.\n"
+        );
+    }
+
+    #[test]
+    fn escapes_workflow_commands() {
+        assert_eq!(escape_data("100%\r\na: b"), "100%25%0D%0Aa: b");
+        assert_eq!(escape_property("a:b,c"), "a%3Ab%2Cc");
+    }
+}
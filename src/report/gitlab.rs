@@ -0,0 +1,146 @@
+//! GitLab Code Quality report. Uploaded as a `codequality` artifact, it surfaces the matched code in the merge request
+//! widget.
+
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use anyhow::Context;
+use rustc_hash::FxHasher;
+use serde::Serialize;
+
+use super::SnippetReader;
+use crate::output::{Location, Output};
+
+/// Issue in the Code Quality format, as documented by GitLab.
+#[derive(Debug, PartialEq, Serialize)]
+struct Issue {
+    description: String,
+    check_name: &'static str,
+    /// Unique identifier of the issue, which GitLab uses to tell which issues are new in a merge request.
+    fingerprint: String,
+    severity: &'static str,
+    location: IssueLocation,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct IssueLocation {
+    path: String,
+    lines: Lines,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Lines {
+    begin: usize,
+    end: usize,
+}
+
+/// Writes a Code Quality report with one issue for each side of every match to `path`.
+///
+/// The paths in `output` must be relative to `root`. GitLab resolves them relative to the root of the repository.
+pub fn write_gitlab_report(output: &Output, root: &Path, path: &Path) -> anyhow::Result<()> {
+    let mut reader = SnippetReader::new(root, 0);
+    let issues = gitlab_issues(output, &mut reader);
+    let json = serde_json::to_string_pretty(&issues)?;
+    fs::write(path, json).with_context(|| {
+        format!(
+            "Failed to write Code Quality report to \"{}\".",
+            path.display()
+        )
+    })
+}
+
+fn gitlab_issues(output: &Output, reader: &mut SnippetReader) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for pair in output.project_pairs.iter() {
+        for m in pair.matches.iter() {
+            for (location, other_location, other_project) in [
+                (&m.project_1_location, &m.project_2_location, &pair.project2),
+                (&m.project_2_location, &m.project_1_location, &pair.project1),
+            ] {
+                let Some((begin, end)) = reader.lines(location) else {
+                    continue;
+                };
+                issues.push(Issue {
+                    description: format!(
+                        "Code similar to {} in project {}.",
+                        other_location.file.display(),
+                        other_project.display()
+                    ),
+                    check_name: "fungus-similar-code",
+                    fingerprint: fingerprint(location, other_location),
+                    severity: "major",
+                    location: IssueLocation {
+                        path: location.file.to_string_lossy().into_owned(),
+                        lines: Lines { begin, end },
+                    },
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+fn fingerprint(location: &Location, other_location: &Location) -> String {
+    let mut hasher = FxHasher::default();
+    location.hash(&mut hasher);
+    other_location.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::output::{Match, ProjectPair};
+
+    #[test]
+    fn reports_both_sides_of_matches() {
+        let location = |file: &str, span| Location {
+            file: PathBuf::from(file),
+            span,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        let output = Output::new(
+            Vec::new(),
+            vec![ProjectPair {
+                project1: "alice".into(),
+                project2: "bob".into(),
+                matches: vec![Match {
+                    id: None,
+                    project_1_location: location("alice/a.s", 3..9),
+                    project_2_location: location("bob/b.s", 0..3),
+                    weight: None,
+                }],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
+            }],
+        );
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("alice/a.s".into(), Some("l1\nl2\nl3\n".to_owned()));
+        reader
+            .files
+            .insert("bob/b.s".into(), Some("l2\nl3\n".to_owned()));
+
+        let issues = gitlab_issues(&output, &mut reader);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            issues[0].description,
+            "Code similar to bob/b.s in project bob."
+        );
+        assert_eq!(issues[0].location.path, "alice/a.s");
+        assert_eq!(issues[0].location.lines, Lines { begin: 2, end: 3 });
+        assert_eq!(issues[1].location.lines, Lines { begin: 1, end: 1 });
+        assert_ne!(issues[0].fingerprint, issues[1].fingerprint);
+    }
+}
@@ -0,0 +1,140 @@
+//! Exports of the similarity matrix: a CSV file for further analysis, and an SVG heatmap on which clusters of similar
+//! projects stand out at a glance.
+
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Context;
+
+use super::{clone_pairs, html};
+use crate::matrix::SimilarityMatrix;
+
+/// Side of a cell of the heatmap, in pixels.
+const CELL_SIZE: usize = 14;
+/// Approximate width of a character of the project names, in pixels.
+const CHAR_WIDTH: usize = 7;
+
+/// Writes the matrix to `path` as CSV, with the names of the projects in the first row and column. Cells of pairs which
+/// are not compared are left empty.
+pub fn write_matrix_csv(matrix: &SimilarityMatrix, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, render_csv(matrix)).with_context(|| {
+        format!(
+            "Failed to write the similarity matrix to \"{}\".",
+            path.display()
+        )
+    })
+}
+
+/// Writes the matrix to `path` as an SVG heatmap. Hovering over a cell shows the projects and coverage it represents.
+pub fn write_heatmap_svg(matrix: &SimilarityMatrix, path: &Path) -> anyhow::Result<()> {
+    fs::write(path, render_svg(matrix))
+        .with_context(|| format!("Failed to write the heatmap to \"{}\".", path.display()))
+}
+
+fn render_csv(matrix: &SimilarityMatrix) -> String {
+    let names = matrix
+        .projects
+        .iter()
+        .map(|p| clone_pairs::escape(&p.to_string_lossy()))
+        .collect::<Vec<_>>();
+
+    // Writing to a `String` cannot fail, so the results are ignored throughout.
+    let mut csv = String::new();
+    let _ = writeln!(csv, "project,{}", names.join(","));
+    for (name, row) in names.iter().zip(matrix.coverage.iter()) {
+        let cells = row
+            .iter()
+            .map(|c| c.map(|c| format!("{c:.3}")).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let _ = writeln!(csv, "{name},{}", cells.join(","));
+    }
+    csv
+}
+
+fn render_svg(matrix: &SimilarityMatrix) -> String {
+    let names = matrix
+        .projects
+        .iter()
+        .map(|p| html::escape(&p.to_string_lossy()))
+        .collect::<Vec<_>>();
+    let label_size = matrix
+        .projects
+        .iter()
+        .map(|p| p.to_string_lossy().chars().count())
+        .max()
+        .unwrap_or(0)
+        * CHAR_WIDTH
+        + CELL_SIZE;
+    let size = label_size + names.len() * CELL_SIZE;
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" font-family=\"monospace\" font-size=\"11\">"
+    );
+    let _ = writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>");
+    for (i, name) in names.iter().enumerate() {
+        let offset = label_size + i * CELL_SIZE + CELL_SIZE * 3 / 4;
+        let end = label_size - CELL_SIZE / 2;
+        // Row labels are right-aligned to the left of the matrix, and column labels are rotated above it
+        let _ = writeln!(
+            svg,
+            "<text x=\"{end}\" y=\"{offset}\" text-anchor=\"end\">{name}</text>"
+        );
+        let _ = writeln!(
+            svg,
+            "<text transform=\"translate({offset} {end}) rotate(-90)\">{name}</text>"
+        );
+    }
+    for (i, row) in matrix.coverage.iter().enumerate() {
+        for (j, coverage) in row.iter().enumerate() {
+            let (fill, description) = match coverage {
+                None => ("#eeeeee".to_owned(), "not compared".to_owned()),
+                Some(c) => (color(*c), format!("{:.0}%", c * 100.0)),
+            };
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{CELL_SIZE}\" height=\"{CELL_SIZE}\" fill=\"{fill}\" stroke=\"white\"><title>{} / {}: {description}</title></rect>",
+                label_size + j * CELL_SIZE,
+                label_size + i * CELL_SIZE,
+                names[i],
+                names[j]
+            );
+        }
+    }
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+/// Returns the color of a cell, from white for no coverage to dark red for full coverage.
+fn color(coverage: f64) -> String {
+    let c = coverage.clamp(0.0, 1.0);
+    let channel = |full: f64| (255.0 - (255.0 - full) * c).round() as u8;
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(178.0),
+        channel(24.0),
+        channel(43.0)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_csv_and_svg() {
+        let matrix = SimilarityMatrix {
+            projects: vec!["a".into(), "b,c".into()],
+            coverage: vec![vec![None, Some(0.25)], vec![Some(1.0), None]],
+        };
+
+        assert_eq!(
+            render_csv(&matrix),
+            "project,a,\"b,c\"\na,,0.250\n\"b,c\",1.000,\n"
+        );
+        let svg = render_svg(&matrix);
+        assert!(svg.contains("<title>b,c / a: 100%</title>"));
+        assert!(svg.contains("fill=\"#b2182b\""));
+        assert_eq!(svg.matches("<rect x=").count(), 4);
+    }
+}
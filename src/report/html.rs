@@ -0,0 +1,228 @@
+//! Self-contained HTML report. All code snippets, styles, and scripts are embedded in a single file so that the report
+//! can be emailed or archived without access to the original submissions.
+
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Context;
+
+use super::{coverage_cells, ranking_description, SnippetReader};
+use crate::output::{Location, Output};
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em auto; max-width: 80em; color: #222; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }
+.pair { border-top: 2px solid #888; margin-top: 2em; }
+.match { display: flex; gap: 1em; margin: 1em 0; }
+.location { flex: 1; min-width: 0; }
+.location h4 { font-weight: normal; font-family: monospace; margin: 0.2em 0; }
+pre { background: #f7f7f7; padding: 0.5em; overflow-x: auto; margin: 0; }
+mark { background: #fff3a0; }
+.unavailable { font-style: italic; color: #888; }
+.warning { white-space: pre-wrap; font-family: monospace; }
+.review { white-space: pre-wrap; background: #eef3fb; padding: 0.5em; }
+"#;
+
+const SCRIPT: &str = r#"
+function filterPairs(query) {
+  query = query.toLowerCase();
+  for (const element of document.querySelectorAll("[data-projects]")) {
+    const visible = element.dataset.projects.toLowerCase().includes(query);
+    element.style.display = visible ? "" : "none";
+  }
+}
+"#;
+
+/// Writes a self-contained HTML report of the output to `path`. Each snippet includes up to `context_lines` lines
+/// before and after the matched code.
+///
+/// The paths in `output` must be relative to `root`.
+pub fn write_html_report(
+    output: &Output,
+    root: &Path,
+    path: &Path,
+    context_lines: usize,
+) -> anyhow::Result<()> {
+    let mut reader = SnippetReader::new(root, context_lines);
+    let html = render_html_report(output, &mut reader);
+    fs::write(path, html)
+        .with_context(|| format!("Failed to write HTML report to \"{}\".", path.display()))
+}
+
+fn render_html_report(output: &Output, reader: &mut SnippetReader) -> String {
+    let mut html = String::new();
+
+    // Writing to a `String` cannot fail, so the results are ignored throughout.
+    let _ = writeln!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>");
+    let _ = writeln!(html, "<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>FUNGUS similarity report</title>");
+    let _ = writeln!(html, "<style>{STYLE}</style>\n<script>{SCRIPT}</script>");
+    let _ = writeln!(html, "</head>\n<body>\n<h1>FUNGUS similarity report</h1>");
+    let _ = writeln!(
+        html,
+        "<p>{} project pair(s) were flagged. {}</p>",
+        output.project_pairs.len(),
+        ranking_description(&output.project_pairs)
+    );
+    let _ = writeln!(
+        html,
+        "<p><input type=\"search\" placeholder=\"Filter by project\" oninput=\"filterPairs(this.value)\"></p>"
+    );
+
+    let _ = writeln!(
+        html,
+        "<table>\n<tr><th>Rank</th><th>Project 1</th><th>Project 2</th><th>Matches</th><th>Coverage 1</th><th>Coverage 2</th><th>Review</th></tr>"
+    );
+    for (rank, pair) in output.project_pairs.iter().enumerate() {
+        let project1 = escape(&pair.project1.to_string_lossy());
+        let project2 = escape(&pair.project2.to_string_lossy());
+        let (coverage1, coverage2) = coverage_cells(pair);
+        let status = pair.review.as_ref().map_or("", |r| r.status.description());
+        let _ = writeln!(
+            html,
+            "<tr data-projects=\"{project1} {project2}\"><td><a href=\"#pair-{rank}\">{}</a></td><td>{project1}</td><td>{project2}</td><td>{}</td><td>{coverage1}</td><td>{coverage2}</td><td>{status}</td></tr>",
+            rank + 1,
+            pair.matches.len()
+        );
+    }
+    let _ = writeln!(html, "</table>");
+
+    for (rank, pair) in output.project_pairs.iter().enumerate() {
+        let project1 = escape(&pair.project1.to_string_lossy());
+        let project2 = escape(&pair.project2.to_string_lossy());
+        let _ = writeln!(
+            html,
+            "<section class=\"pair\" id=\"pair-{rank}\" data-projects=\"{project1} {project2}\">"
+        );
+        let _ = writeln!(
+            html,
+            "<h2>{}. {project1} and {project2} ({} matches)</h2>",
+            rank + 1,
+            pair.matches.len()
+        );
+        if let Some(review) = &pair.review {
+            let notes = review
+                .notes
+                .as_deref()
+                .map(|notes| format!(": {}", escape(notes)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<p class=\"review\">Review: <strong>{}</strong>{notes}</p>",
+                review.status.description()
+            );
+        }
+
+        for (i, m) in pair.matches.iter().enumerate() {
+            let _ = writeln!(html, "<h3>Match {}</h3>\n<div class=\"match\">", i + 1);
+            write_location(&mut html, &m.project_1_location, reader);
+            write_location(&mut html, &m.project_2_location, reader);
+            let _ = writeln!(html, "</div>");
+        }
+
+        let _ = writeln!(html, "</section>");
+    }
+
+    if !output.warnings.is_empty() {
+        let _ = writeln!(html, "<h2>Warnings</h2>");
+        for warning in output.warnings.iter() {
+            let _ = writeln!(
+                html,
+                "<p class=\"warning\">{}</p>",
+                escape(&warning.to_string())
+            );
+        }
+    }
+
+    let _ = writeln!(html, "</body>\n</html>");
+    html
+}
+
+fn write_location(html: &mut String, location: &Location, reader: &mut SnippetReader) {
+    let _ = writeln!(
+        html,
+        "<div class=\"location\"><h4>{} (bytes {}-{})</h4>",
+        escape(&location.file.to_string_lossy()),
+        location.span.start,
+        location.span.end
+    );
+    match reader.snippet(location) {
+        None => {
+            let _ = writeln!(html, "<p class=\"unavailable\">Snippet unavailable.</p>");
+        }
+        Some(snippet) => {
+            let snippet = snippet.trim_end();
+            let _ = writeln!(
+                html,
+                "<pre>{}<mark>{}</mark>{}</pre>",
+                escape(snippet.before),
+                escape(snippet.matched),
+                escape(snippet.after)
+            );
+        }
+    }
+    let _ = writeln!(html, "</div>");
+}
+
+/// Escapes the characters which have a special meaning in HTML text and attribute values.
+pub(super) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{Match, ProjectPair};
+
+    #[test]
+    fn embeds_escaped_snippets() {
+        let output = Output::new(
+            vec![],
+            vec![ProjectPair {
+                project1: "a".into(),
+                project2: "b".into(),
+                matches: vec![Match {
+                    id: None,
+                    project_1_location: Location {
+                        file: "a/x.s".into(),
+                        span: 0..10,
+                        length_bytes: None,
+                        length_tokens: None,
+                    },
+                    project_2_location: Location {
+                        file: "b/y.s".into(),
+                        span: 0..9,
+                        length_bytes: None,
+                        length_tokens: None,
+                    },
+                    weight: None,
+                }],
+                score: None,
+                explanation: None,
+                earlier_submission: None,
+                review: None,
+            }],
+        );
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("a/x.s".into(), Some("ldr r0, <x>".to_owned()));
+
+        let html = render_html_report(&output, &mut reader);
+        assert!(html.contains("<pre><mark>ldr r0, &lt;x</mark></pre>"));
+        assert!(html.contains("<p class=\"unavailable\">Snippet unavailable.</p>"));
+        assert!(html.contains("<style>"));
+        assert!(!html.contains("<link"));
+    }
+}
@@ -0,0 +1,273 @@
+//! Human-readable reports generated from the analysis output.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::output::{Location, ProjectPair};
+
+pub mod cases;
+pub mod clone_pairs;
+pub mod github;
+pub mod gitlab;
+pub mod heatmap;
+pub mod html;
+pub mod pdf;
+pub mod student;
+pub mod tokens;
+
+/// Reads code snippets from the projects directory, caching the contents of each file.
+pub struct SnippetReader {
+    root: PathBuf,
+    context_lines: usize,
+    files: HashMap<PathBuf, Option<String>>,
+}
+
+/// Matched code along with the lines surrounding it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snippet<'a> {
+    /// Lines preceding the matched code, including the start of the line on which the match begins.
+    pub before: &'a str,
+    pub matched: &'a str,
+    /// Lines following the matched code, including the end of the line on which the match ends.
+    pub after: &'a str,
+}
+
+impl<'a> Snippet<'a> {
+    /// Removes trailing whitespace from the end of the snippet.
+    pub fn trim_end(self) -> Snippet<'a> {
+        if self.after.trim_end().is_empty() {
+            Snippet {
+                matched: self.matched.trim_end(),
+                after: "",
+                ..self
+            }
+        } else {
+            Snippet {
+                after: self.after.trim_end(),
+                ..self
+            }
+        }
+    }
+}
+
+impl SnippetReader {
+    /// Creates a reader for snippets whose locations are relative to `root`. Each snippet includes up to
+    /// `context_lines` lines before and after the matched code.
+    pub fn new(root: &Path, context_lines: usize) -> SnippetReader {
+        SnippetReader {
+            root: root.to_owned(),
+            context_lines,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Returns the code at the given location, or `None` if the file cannot be read or the span is invalid.
+    pub fn snippet(&mut self, location: &Location) -> Option<Snippet<'_>> {
+        let context_lines = self.context_lines;
+        let contents = self.contents(&location.file)?;
+
+        let span = location.span.clone();
+        let matched = contents.get(span.clone())?;
+        let context_start = context_start(contents, span.start, context_lines);
+        let context_end = context_end(contents, span.end, context_lines);
+
+        Some(Snippet {
+            before: &contents[context_start..span.start],
+            matched,
+            after: &contents[span.end..context_end],
+        })
+    }
+
+    /// Returns the (one-based) numbers of the first and last lines of the code at the given location, or `None` if the
+    /// file cannot be read or the span is invalid.
+    pub fn lines(&mut self, location: &Location) -> Option<(usize, usize)> {
+        let contents = self.contents(&location.file)?;
+
+        let span = location.span.clone();
+        let matched = contents.get(span.clone())?;
+        let first = contents[..span.start].matches('\n').count() + 1;
+        // A trailing newline belongs to the last line of the match
+        let last = first + matched.trim_end_matches('\n').matches('\n').count();
+        Some((first, last))
+    }
+
+    fn contents(&mut self, file: &Path) -> Option<&str> {
+        let root = &self.root;
+        self.files
+            .entry(file.to_owned())
+            .or_insert_with(|| fs::read_to_string(root.join(file)).ok())
+            .as_deref()
+    }
+}
+
+/// Returns the start of the context preceding `start`: the beginning of the `lines`-th line before the one containing
+/// `start`, or `start` itself if no context is requested.
+fn context_start(contents: &str, start: usize, lines: usize) -> usize {
+    if lines == 0 {
+        return start;
+    }
+    contents[..start]
+        .rmatch_indices('\n')
+        .nth(lines)
+        .map_or(0, |(i, _)| i + 1)
+}
+
+/// Returns the end of the context following `end`: the end of the `lines`-th line after the one containing `end`
+/// (excluding the newline), or `end` itself if no context is requested.
+fn context_end(contents: &str, end: usize, lines: usize) -> usize {
+    if lines == 0 {
+        return end;
+    }
+    contents[end..]
+        .match_indices('\n')
+        .nth(lines)
+        .map_or(contents.len(), |(i, _)| end + i)
+}
+
+/// Describes how the project pairs are ranked, for the introduction of a report.
+fn ranking_description<'a>(
+    project_pairs: impl IntoIterator<Item = &'a ProjectPair>,
+) -> &'static str {
+    if project_pairs.into_iter().any(|p| p.score.is_some()) {
+        "Pairs are ranked by their score, which counts code found in many projects for less."
+    } else {
+        "Pairs are ranked by their number of matches."
+    }
+}
+
+/// Formats the value by which a pair is ranked: its score if it has one, or else its number of matches.
+fn score_cell(pair: &ProjectPair) -> String {
+    match pair.score {
+        Some(score) => format!("{score:.2}"),
+        None => pair.matches.len().to_string(),
+    }
+}
+
+/// Formats the coverage of each project of a pair as percentages, for the tables of the reports.
+fn coverage_cells(pair: &ProjectPair) -> (String, String) {
+    match pair.coverage_percent() {
+        Some((coverage1, coverage2)) => (format!("{coverage1:.0}%"), format!("{coverage2:.0}%")),
+        None => ("?".to_owned(), "?".to_owned()),
+    }
+}
+
+/// Characters which cannot appear in a file name on some platforms, and `%` so that the encoding can be reversed.
+const FILE_NAME_UNSAFE: &AsciiSet = &CONTROLS
+    .add(b'%')
+    .add(b'/')
+    .add(b'\\')
+    .add(b':')
+    .add(b'*')
+    .add(b'?')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'|');
+
+/// Converts a project path into a name that can safely be used as a file name. Unsafe characters are percent-encoded,
+/// so that different projects (e.g., `a/b` and `a_b`) never share a file name.
+fn file_name_for(project: &Path) -> String {
+    utf8_percent_encode(&project.to_string_lossy(), FILE_NAME_UNSAFE).to_string()
+}
+
+/// Converts the projects of a pair into a file name, joining them with `--`. The `-` of the project names is also
+/// encoded, so that the separator is unambiguous.
+fn pair_file_name_for(pair: &ProjectPair) -> String {
+    const UNSAFE: &AsciiSet = &FILE_NAME_UNSAFE.add(b'-');
+    let encode =
+        |project: &Path| utf8_percent_encode(&project.to_string_lossy(), UNSAFE).to_string();
+    format!("{}--{}", encode(&pair.project1), encode(&pair.project2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(
+        context_lines: usize,
+        span: std::ops::Range<usize>,
+    ) -> Option<(String, String, String)> {
+        let mut reader = SnippetReader::new(Path::new("root"), context_lines);
+        reader
+            .files
+            .insert("a.s".into(), Some("l1\nl2\nl3 match\nl4\nl5\n".to_owned()));
+        let location = Location {
+            file: "a.s".into(),
+            span,
+            length_bytes: None,
+            length_tokens: None,
+        };
+        reader.snippet(&location).map(|s| {
+            (
+                s.before.to_owned(),
+                s.matched.to_owned(),
+                s.after.to_owned(),
+            )
+        })
+    }
+
+    #[test]
+    fn file_names_are_distinct() {
+        let names = ["a/b", "a_b", "a%2Fb", "x:y", "x_y", "c\\d"]
+            .map(|name| file_name_for(Path::new(name)));
+        assert_eq!(names, ["a%2Fb", "a_b", "a%252Fb", "x%3Ay", "x_y", "c%5Cd"]);
+
+        let pair = |project1: &str, project2: &str| ProjectPair {
+            project1: project1.into(),
+            project2: project2.into(),
+            matches: Vec::new(),
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        assert_eq!(pair_file_name_for(&pair("a-b", "c")), "a%2Db--c");
+        assert_ne!(
+            pair_file_name_for(&pair("a--b", "c")),
+            pair_file_name_for(&pair("a", "b--c"))
+        );
+    }
+
+    #[test]
+    fn includes_context_lines() {
+        let snippet = |before: &str, matched: &str, after: &str| {
+            Some((before.to_owned(), matched.to_owned(), after.to_owned()))
+        };
+
+        assert_eq!(read(0, 9..14), snippet("", "match", ""));
+        assert_eq!(read(1, 9..14), snippet("l2\nl3 ", "match", "\nl4"));
+        // The context is cut off at the start and end of the file
+        assert_eq!(
+            read(5, 9..14),
+            snippet("l1\nl2\nl3 ", "match", "\nl4\nl5\n")
+        );
+        assert_eq!(read(1, 9..30), None);
+    }
+
+    #[test]
+    fn finds_line_numbers() {
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("a.s".into(), Some("l1\nl2\nl3 match\nl4\nl5\n".to_owned()));
+        let mut lines = |span| {
+            reader.lines(&Location {
+                file: "a.s".into(),
+                span,
+                length_bytes: None,
+                length_tokens: None,
+            })
+        };
+
+        assert_eq!(lines(0..2), Some((1, 1)));
+        assert_eq!(lines(9..14), Some((3, 3)));
+        assert_eq!(lines(9..18), Some((3, 4)));
+        assert_eq!(lines(9..15), Some((3, 3)));
+        assert_eq!(lines(9..30), None);
+    }
+}
@@ -0,0 +1,299 @@
+//! Printable PDF report listing the ranked project pairs along with their matched code.
+
+use std::{fs, io::BufWriter, path::Path};
+
+use anyhow::Context;
+use printpdf::{
+    BuiltinFont, Color, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference,
+    Rect, Rgb,
+};
+
+use super::{coverage_cells, ranking_description, Snippet, SnippetReader};
+use crate::output::{Location, Output};
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 15.0;
+
+const TITLE_SIZE: f32 = 18.0;
+const HEADING_SIZE: f32 = 13.0;
+const TEXT_SIZE: f32 = 10.0;
+const CODE_SIZE: f32 = 8.0;
+
+/// Number of characters after which lines of regular text are wrapped.
+const TEXT_LINE_WIDTH: usize = 100;
+/// Number of characters after which lines of code are wrapped. Courier characters are 0.6 em wide, so this fits
+/// within the margins at `CODE_SIZE`.
+const CODE_LINE_WIDTH: usize = 105;
+
+/// Writes a PDF report of the output to `path`. Each snippet includes up to `context_lines` lines before and after the
+/// matched code.
+///
+/// The paths in `output` must be relative to `root`.
+pub fn write_pdf_report(
+    output: &Output,
+    root: &Path,
+    path: &Path,
+    context_lines: usize,
+) -> anyhow::Result<()> {
+    let mut reader = SnippetReader::new(root, context_lines);
+    let mut writer = PdfWriter::new("FUNGUS similarity report")?;
+
+    writer.text("FUNGUS similarity report", TITLE_SIZE, Font::Bold);
+    writer.space(4.0);
+    writer.paragraph(&format!(
+        "{} project pair(s) were flagged. {} Matched code is highlighted.",
+        output.project_pairs.len(),
+        ranking_description(&output.project_pairs)
+    ));
+    writer.space(2.0);
+
+    for (rank, pair) in output.project_pairs.iter().enumerate() {
+        let (coverage1, coverage2) = coverage_cells(pair);
+        writer.paragraph(&format!(
+            "{}. {} and {}: {} match(es), covering {coverage1} and {coverage2} of their code",
+            rank + 1,
+            pair.project1.display(),
+            pair.project2.display(),
+            pair.matches.len()
+        ));
+    }
+
+    for (rank, pair) in output.project_pairs.iter().enumerate() {
+        writer.new_page();
+        writer.text(
+            &format!(
+                "{}. {} and {}",
+                rank + 1,
+                pair.project1.display(),
+                pair.project2.display()
+            ),
+            HEADING_SIZE,
+            Font::Bold,
+        );
+        writer.space(2.0);
+
+        for (i, m) in pair.matches.iter().enumerate() {
+            writer.space(2.0);
+            writer.text(&format!("Match {}", i + 1), TEXT_SIZE, Font::Bold);
+            writer.location(&m.project_1_location, &mut reader);
+            writer.location(&m.project_2_location, &mut reader);
+        }
+    }
+
+    if !output.warnings.is_empty() {
+        writer.new_page();
+        writer.text("Warnings", HEADING_SIZE, Font::Bold);
+        writer.space(2.0);
+        for warning in output.warnings.iter() {
+            writer.paragraph(&warning.to_string());
+        }
+    }
+
+    let file = fs::File::create(path)
+        .with_context(|| format!("Failed to create \"{}\".", path.display()))?;
+    writer
+        .document
+        .save(&mut BufWriter::new(file))
+        .with_context(|| format!("Failed to write PDF report to \"{}\".", path.display()))?;
+
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+    Code,
+}
+
+/// Lays out text from top to bottom, starting new pages as necessary.
+struct PdfWriter {
+    document: PdfDocumentReference,
+    layer: PdfLayerReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    code: IndirectFontRef,
+    /// Vertical position of the next line, measured from the bottom of the page.
+    y: f32,
+}
+
+impl PdfWriter {
+    fn new(title: &str) -> anyhow::Result<PdfWriter> {
+        let (document, page, layer) =
+            PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        let regular = document.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = document.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let code = document.add_builtin_font(BuiltinFont::Courier)?;
+        let layer = document.get_page(page).get_layer(layer);
+
+        Ok(PdfWriter {
+            document,
+            layer,
+            regular,
+            bold,
+            code,
+            y: PAGE_HEIGHT - MARGIN,
+        })
+    }
+
+    fn new_page(&mut self) {
+        let (page, layer) = self
+            .document
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Layer 1");
+        self.layer = self.document.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    fn space(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// Writes a single line of text.
+    fn text(&mut self, text: &str, size: f32, font: Font) {
+        self.line(text, size, font, false);
+    }
+
+    /// Writes regular text, wrapping it as necessary.
+    fn paragraph(&mut self, text: &str) {
+        for line in text.lines() {
+            for wrapped in wrap(line, TEXT_LINE_WIDTH) {
+                self.line(&wrapped, TEXT_SIZE, Font::Regular, false);
+            }
+        }
+    }
+
+    /// Writes a location followed by the highlighted code it refers to.
+    fn location(&mut self, location: &Location, reader: &mut SnippetReader) {
+        self.text(
+            &format!(
+                "{} (bytes {}-{})",
+                location.file.display(),
+                location.span.start,
+                location.span.end
+            ),
+            TEXT_SIZE,
+            Font::Regular,
+        );
+
+        match reader.snippet(location) {
+            None => self.text("Snippet unavailable.", TEXT_SIZE, Font::Regular),
+            Some(snippet) => {
+                self.space(1.0);
+                for (line, highlighted) in highlighted_lines(snippet.trim_end()) {
+                    for wrapped in wrap(&line, CODE_LINE_WIDTH) {
+                        self.line(&wrapped, CODE_SIZE, Font::Code, highlighted);
+                    }
+                }
+            }
+        }
+        self.space(2.0);
+    }
+
+    fn line(&mut self, text: &str, size: f32, font: Font, highlighted: bool) {
+        // Points to millimetres, with some leading between lines
+        let line_height = size * 0.3528 * 1.3;
+        if self.y - line_height < MARGIN {
+            self.new_page();
+        }
+        self.y -= line_height;
+
+        if highlighted {
+            self.layer
+                .set_fill_color(Color::Rgb(Rgb::new(1.0, 0.95, 0.6, None)));
+            self.layer.add_rect(Rect::new(
+                Mm(MARGIN - 1.0),
+                Mm(self.y - line_height * 0.25),
+                Mm(PAGE_WIDTH - MARGIN + 1.0),
+                Mm(self.y + line_height * 0.75),
+            ));
+        }
+
+        let font = match font {
+            Font::Regular => &self.regular,
+            Font::Bold => &self.bold,
+            Font::Code => &self.code,
+        };
+        self.layer
+            .set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        self.layer
+            .use_text(sanitize(text), size, Mm(MARGIN), Mm(self.y), font);
+    }
+}
+
+/// Splits a snippet into lines, along with whether each line contains part of the matched code.
+fn highlighted_lines(snippet: Snippet<'_>) -> Vec<(String, bool)> {
+    let match_start = snippet.before.len();
+    let match_end = match_start + snippet.matched.len();
+    let text = [snippet.before, snippet.matched, snippet.after].concat();
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    for line in text.split('\n') {
+        let line_end = line_start + line.len();
+        let highlighted = line_start < match_end && match_start <= line_end;
+        lines.push((line.to_owned(), highlighted));
+        line_start = line_end + 1;
+    }
+    lines
+}
+
+/// Replaces characters which cannot be represented by the built-in PDF fonts.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\t' => ' ',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '?',
+        })
+        .collect()
+}
+
+/// Splits a line into chunks of at most `width` characters.
+fn wrap(line: &str, width: usize) -> Vec<String> {
+    let line = line.replace('\t', "    ");
+    let chars = line.chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+
+    chars
+        .chunks(width)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_long_lines() {
+        assert_eq!(wrap("", 3), vec![""]);
+        assert_eq!(wrap("abcdefg", 3), vec!["abc", "def", "g"]);
+        assert_eq!(wrap("\tab", 3), vec!["   ", " ab"]);
+    }
+
+    #[test]
+    fn highlights_matched_lines() {
+        let snippet = Snippet {
+            before: "l1\nl2 ",
+            matched: "a\nb",
+            after: " l3\nl4",
+        };
+        assert_eq!(
+            highlighted_lines(snippet),
+            vec![
+                ("l1".to_owned(), false),
+                ("l2 a".to_owned(), true),
+                ("b l3".to_owned(), true),
+                ("l4".to_owned(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn sanitizes_unsupported_characters() {
+        assert_eq!(sanitize("mov r0, #1 @ é\t"), "mov r0, #1 @ ? ");
+    }
+}
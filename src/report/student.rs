@@ -0,0 +1,233 @@
+//! Per-student reports: one Markdown file per flagged project, summarizing every pair in which it appears.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use super::{coverage_cells, file_name_for, ranking_description, score_cell, SnippetReader};
+use crate::output::{Location, ProjectPair};
+
+/// Writes one report per project appearing in at least one of the given pairs into `dir`. Returns the number of
+/// reports written. Each snippet includes up to `context_lines` lines before and after the matched code.
+///
+/// The paths in `project_pairs` must be relative to `root`.
+pub fn write_student_reports(
+    project_pairs: &[ProjectPair],
+    root: &Path,
+    dir: &Path,
+    context_lines: usize,
+) -> anyhow::Result<usize> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create report directory '{}'.", dir.display()))?;
+
+    let mut reader = SnippetReader::new(root, context_lines);
+    let pairs_by_project = group_pairs_by_project(project_pairs);
+
+    for (project, pairs) in pairs_by_project.iter() {
+        let report = render_student_report(project, pairs, &mut reader);
+        let path = dir.join(format!("{}.md", file_name_for(project)));
+        fs::write(&path, report)
+            .with_context(|| format!("Failed to write report to '{}'.", path.display()))?;
+    }
+
+    Ok(pairs_by_project.len())
+}
+
+/// Maps each project to the pairs in which it appears, preserving the order of the pairs.
+fn group_pairs_by_project(project_pairs: &[ProjectPair]) -> BTreeMap<&PathBuf, Vec<&ProjectPair>> {
+    let mut pairs_by_project: BTreeMap<&PathBuf, Vec<&ProjectPair>> = BTreeMap::new();
+
+    for pair in project_pairs {
+        pairs_by_project
+            .entry(&pair.project1)
+            .or_default()
+            .push(pair);
+        pairs_by_project
+            .entry(&pair.project2)
+            .or_default()
+            .push(pair);
+    }
+
+    pairs_by_project
+}
+
+/// Renders the report for a single project as Markdown.
+fn render_student_report(
+    project: &Path,
+    pairs: &[&ProjectPair],
+    reader: &mut SnippetReader,
+) -> String {
+    let mut report = String::new();
+    let project_name = project.display();
+
+    // Writing to a `String` cannot fail, so the results are ignored throughout.
+    let _ = writeln!(report, "# Similarity report for `{project_name}`\n");
+    let _ = writeln!(
+        report,
+        "`{project_name}` appears in {} flagged pair(s). {}\n",
+        pairs.len(),
+        ranking_description(pairs.iter().copied())
+    );
+    let _ = writeln!(
+        report,
+        "| Other project | Score | Coverage of `{project_name}` | Coverage of other project |"
+    );
+    let _ = writeln!(report, "| --- | --- | --- | --- |");
+    for pair in pairs {
+        let (other, is_project1) = orient(project, pair);
+        let (coverage1, coverage2) = coverage_cells(pair);
+        let (own_coverage, other_coverage) = if is_project1 {
+            (coverage1, coverage2)
+        } else {
+            (coverage2, coverage1)
+        };
+        let _ = writeln!(
+            report,
+            "| `{}` | {} | {own_coverage} | {other_coverage} |",
+            other.display(),
+            score_cell(pair)
+        );
+    }
+
+    for pair in pairs {
+        let (other, is_project1) = orient(project, pair);
+        let _ = writeln!(
+            report,
+            "\n## `{project_name}` and `{}` (score {})",
+            other.display(),
+            score_cell(pair)
+        );
+
+        for (i, m) in pair.matches.iter().enumerate() {
+            let (own_location, other_location) = if is_project1 {
+                (&m.project_1_location, &m.project_2_location)
+            } else {
+                (&m.project_2_location, &m.project_1_location)
+            };
+
+            let _ = writeln!(report, "\n### Match {}\n", i + 1);
+            write_location(&mut report, own_location, reader);
+            write_location(&mut report, other_location, reader);
+        }
+    }
+
+    report
+}
+
+/// Returns the other project in the pair and whether `project` is the pair's first project.
+fn orient<'a>(project: &Path, pair: &'a ProjectPair) -> (&'a Path, bool) {
+    if pair.project1 == project {
+        (&pair.project2, true)
+    } else {
+        (&pair.project1, false)
+    }
+}
+
+fn write_location(report: &mut String, location: &Location, reader: &mut SnippetReader) {
+    let _ = writeln!(
+        report,
+        "`{}` (bytes {}-{}):\n",
+        location.file.display(),
+        location.span.start,
+        location.span.end
+    );
+    match reader.snippet(location) {
+        None => {
+            let _ = writeln!(report, "*Snippet unavailable.*\n");
+        }
+        Some(snippet) => {
+            // Markdown code blocks cannot highlight the matched code, so the context is included as is
+            let snippet = snippet.trim_end();
+            let _ = writeln!(
+                report,
+                "```\n{}{}{}\n```\n",
+                snippet.before, snippet.matched, snippet.after
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{Match, PairExplanation};
+    use pretty_assertions::assert_eq;
+
+    fn pairs(score: Option<f64>) -> Vec<ProjectPair> {
+        vec![ProjectPair {
+            project1: "a".into(),
+            project2: "b".into(),
+            matches: vec![Match {
+                id: None,
+                project_1_location: Location {
+                    file: "a/x.s".into(),
+                    span: 0..3,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                project_2_location: Location {
+                    file: "b/y.s".into(),
+                    span: 4..7,
+                    length_bytes: None,
+                    length_tokens: None,
+                },
+                weight: None,
+            }],
+            score,
+            explanation: Some(PairExplanation {
+                shared_hashes: 1,
+                rarest_hash_projects: 2,
+                project_1_coverage: 0.5,
+                project_2_coverage: 0.25,
+                nearly_filtered_by: Vec::new(),
+                z_score: None,
+                ..Default::default()
+            }),
+            earlier_submission: None,
+            review: None,
+        }]
+    }
+
+    #[test]
+    fn reports_pairs_from_both_sides() {
+        let pairs = pairs(None);
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+        reader
+            .files
+            .insert("a/x.s".into(), Some("add r0".to_owned()));
+        reader
+            .files
+            .insert("b/y.s".into(), Some("mov add".to_owned()));
+
+        let grouped = group_pairs_by_project(&pairs);
+        assert_eq!(grouped.len(), 2);
+
+        let report =
+            render_student_report(Path::new("b"), &grouped[&PathBuf::from("b")], &mut reader);
+        assert!(report.starts_with("# Similarity report for `b`"));
+        assert!(report.contains("| `a` | 1 | 25% | 50% |"));
+        // The project's own location should come first
+        let own = report.find("`b/y.s` (bytes 4-7)").unwrap();
+        let other = report.find("`a/x.s` (bytes 0-3)").unwrap();
+        assert!(own < other);
+        assert!(report.contains("```\nadd\n```"));
+    }
+
+    #[test]
+    fn reports_the_score_of_scored_pairs() {
+        let pairs = pairs(Some(0.75));
+        let mut reader = SnippetReader::new(Path::new("root"), 0);
+
+        let grouped = group_pairs_by_project(&pairs);
+        let report =
+            render_student_report(Path::new("a"), &grouped[&PathBuf::from("a")], &mut reader);
+        assert!(report.contains(ranking_description(&pairs)));
+        assert!(report.contains("| `b` | 0.75 | 50% | 25% |"));
+        assert!(report.contains("## `a` and `b` (score 0.75)"));
+    }
+}
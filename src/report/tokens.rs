@@ -0,0 +1,121 @@
+//! Export of the token sequence of every analyzed file, so that other similarity models can be trained or evaluated on
+//! the same tokenization.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{lexing, DetectionOptions, File};
+
+/// Token sequence of a single file, as written to the dump.
+#[derive(Debug, PartialEq, Serialize)]
+struct TokenDump<'a> {
+    project: &'a Path,
+    file: &'a Path,
+    /// Hash of each token, after the transforms of the options.
+    hashes: Vec<u64>,
+    /// Byte span of each token in the file, as a pair of its inclusive start and exclusive end.
+    spans: Vec<(usize, usize)>,
+    /// Text of each token, only included on request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tokens: Option<Vec<&'a str>>,
+}
+
+/// Writes the token sequence of each document to a JSON file in `dir`, at the path of the document relative to `root`
+/// followed by ".json". The text of each token is included if `raw` is set. Returns the number of files written.
+pub fn write_token_dump(
+    options: &DetectionOptions,
+    documents: &[File],
+    root: &Path,
+    dir: &Path,
+    raw: bool,
+) -> anyhow::Result<usize> {
+    for document in documents {
+        let relative_path = document.path().strip_prefix(root).with_context(|| {
+            format!(
+                "Cannot dump the tokens of \"{}\", which is outside the projects directory.",
+                document.path().display()
+            )
+        })?;
+        let mut path = dir.join(relative_path).into_os_string();
+        path.push(".json");
+        let path = Path::new(&path);
+
+        let dump = token_dump(options, document, root, raw);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create token directory '{}'.", parent.display())
+            })?;
+        }
+        let json = serde_json::to_string(&dump)?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write tokens to '{}'.", path.display()))?;
+    }
+    Ok(documents.len())
+}
+
+fn token_dump<'a>(
+    options: &DetectionOptions,
+    document: &'a File,
+    root: &Path,
+    raw: bool,
+) -> TokenDump<'a> {
+    let (tokens, _lex_errors) = lexing::tokenize_and_hash(
+        &document.contents,
+        options.tokenizing_strategy,
+        &options.token_transforms(),
+        options.key_symbols.as_ref(),
+        options.hash_dialect,
+        options.max_token_offset,
+    );
+    let relative = |path: &'a Path| path.strip_prefix(root).unwrap_or(path);
+    TokenDump {
+        project: relative(document.project()),
+        file: relative(document.path()),
+        hashes: tokens.iter().map(|(hash, _)| *hash).collect(),
+        spans: tokens
+            .iter()
+            .map(|(_, span)| (span.start, span.end))
+            .collect(),
+        tokens: raw.then(|| {
+            tokens
+                .iter()
+                .map(|(_, span)| &document.contents[span.clone()])
+                .collect()
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenizingStrategy;
+
+    #[test]
+    fn dumps_hashes_spans_and_text() {
+        let document = File::new(
+            "root/a".into(),
+            "root/a/x.s".into(),
+            "mov r0, r1\n".to_owned(),
+        );
+        let options = DetectionOptions {
+            tokenizing_strategy: TokenizingStrategy::Naive,
+            max_token_offset: 0,
+            ..Default::default()
+        };
+
+        let dump = token_dump(&options, &document, Path::new("root"), true);
+        assert_eq!(dump.project, Path::new("a"));
+        assert_eq!(dump.file, Path::new("a/x.s"));
+        assert_eq!(dump.hashes.len(), dump.spans.len());
+        let text = dump.tokens.as_ref().unwrap();
+        assert_eq!(text.len(), dump.spans.len());
+        assert!(text.contains(&"mov"));
+
+        let json = serde_json::to_value(token_dump(&options, &document, Path::new("root"), false))
+            .unwrap();
+        assert_eq!(json["file"], "a/x.s");
+        assert!(json.get("tokens").is_none());
+    }
+}
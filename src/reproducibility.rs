@@ -0,0 +1,180 @@
+//! Manifests of the inputs and parameters of a run, so that its results can be reproduced exactly later (e.g., for an
+//! appeal), and so that a later run with different inputs or parameters is detected.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{output::stable_path, File};
+
+/// Number of hexadecimal digits of the SHA-256 of the manifest used as the run identifier when none is given.
+const RUN_ID_LEN: usize = 16;
+
+/// Inputs and parameters of a run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunManifest {
+    /// Identifier of the run, either given explicitly or derived from the rest of the manifest, in which case two runs
+    /// with the same inputs and parameters have the same identifier.
+    pub run_id: String,
+    /// Version of FUNGUS which produced the results.
+    pub version: String,
+    /// Command-line arguments of the run, which reproduce it when passed again.
+    pub arguments: Vec<String>,
+    /// Files of the projects, sorted by path.
+    pub inputs: Vec<InputFile>,
+    /// Files containing starter code, sorted by path.
+    pub starter_code: Vec<InputFile>,
+}
+
+/// File read by a run, along with the hash of its contents.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFile {
+    /// Path of the file, relative to the projects directory where possible.
+    pub path: String,
+    /// Hexadecimal SHA-256 of the contents of the file as they were analyzed (i.e., after any conversion to UTF-8).
+    pub sha256: String,
+}
+
+impl RunManifest {
+    /// Records the inputs and parameters of a run. Without an explicit run identifier, the identifier is derived from
+    /// the SHA-256 of the version, the arguments, and the inputs.
+    pub fn new(
+        run_id: Option<String>,
+        arguments: Vec<String>,
+        documents: &[File],
+        ignored_documents: &[File],
+        root: &Path,
+    ) -> RunManifest {
+        let mut manifest = RunManifest {
+            run_id: String::new(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            arguments,
+            inputs: input_files(documents, root),
+            starter_code: input_files(ignored_documents, root),
+        };
+        manifest.run_id = run_id.unwrap_or_else(|| {
+            let digest = Sha256::digest(
+                serde_json::to_vec(&manifest).expect("the manifest can always be serialized"),
+            );
+            format!("{digest:x}")[..RUN_ID_LEN].to_owned()
+        });
+        manifest
+    }
+
+    /// Lists the differences between the inputs and parameters of this run and those of another run (e.g., the
+    /// original run being reproduced). The run identifiers are not compared.
+    pub fn mismatches(&self, original: &RunManifest) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if self.version != original.version {
+            mismatches.push(format!(
+                "The version is {} instead of {}.",
+                self.version, original.version
+            ));
+        }
+        if self.arguments != original.arguments {
+            mismatches.push(format!(
+                "The arguments are \"{}\" instead of \"{}\".",
+                self.arguments.join(" "),
+                original.arguments.join(" ")
+            ));
+        }
+        for (kind, files, original_files) in [
+            ("Input", &self.inputs, &original.inputs),
+            ("Starter code", &self.starter_code, &original.starter_code),
+        ] {
+            let hashes = file_hashes(files);
+            let original_hashes = file_hashes(original_files);
+            for (path, hash) in hashes.iter() {
+                match original_hashes.get(path) {
+                    None => mismatches.push(format!("{kind} file \"{path}\" was added.")),
+                    Some(original_hash) if original_hash != hash => {
+                        mismatches.push(format!("{kind} file \"{path}\" was modified."))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for path in original_hashes.keys() {
+                if !hashes.contains_key(path) {
+                    mismatches.push(format!("{kind} file \"{path}\" was removed."));
+                }
+            }
+        }
+        mismatches
+    }
+}
+
+/// Returns the path of the manifest written next to the output file, which is the path of the output file followed by
+/// `.manifest.json`.
+pub fn manifest_path(output_file: &Path) -> PathBuf {
+    let mut path = output_file.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+fn input_files(documents: &[File], root: &Path) -> Vec<InputFile> {
+    let mut files = documents
+        .iter()
+        .map(|f| InputFile {
+            path: stable_path(&f.path, root),
+            sha256: format!("{:x}", Sha256::digest(f.contents.as_bytes())),
+        })
+        .collect::<Vec<_>>();
+    files.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+    files
+}
+
+fn file_hashes(files: &[InputFile]) -> BTreeMap<&str, &str> {
+    files
+        .iter()
+        .map(|f| (f.path.as_str(), f.sha256.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documents(contents: &str) -> Vec<File> {
+        ["alice", "bob"]
+            .map(|project| {
+                let project = Path::new("root").join(project);
+                File::new(project.clone(), project.join("main.s"), contents.to_owned())
+            })
+            .to_vec()
+    }
+
+    #[test]
+    fn mismatches_are_detected() {
+        let root = Path::new("root");
+        let arguments = vec!["root".to_owned(), "-n".to_owned(), "20".to_owned()];
+        let original = RunManifest::new(None, arguments.clone(), &documents("add"), &[], root);
+        let same = RunManifest::new(None, arguments.clone(), &documents("add"), &[], root);
+        assert_eq!(same.run_id, original.run_id);
+        assert_eq!(same.run_id.len(), RUN_ID_LEN);
+        assert!(same.mismatches(&original).is_empty());
+
+        let mut modified = documents("sub");
+        modified.truncate(1);
+        let rerun = RunManifest::new(Some("appeal".to_owned()), arguments, &modified, &[], root);
+        assert_eq!(rerun.run_id, "appeal");
+        assert_eq!(
+            rerun.mismatches(&original),
+            [
+                "Input file \"alice/main.s\" was modified.",
+                "Input file \"bob/main.s\" was removed."
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_is_next_to_the_output() {
+        assert_eq!(
+            manifest_path(Path::new("out/results.json")),
+            PathBuf::from("out/results.json.manifest.json")
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! Segmentation of files into routines at their labels, so that the projects can be compared routine by routine.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    checkpoint::DocumentHashes,
+    interning::PathArena,
+    output::{ProjectPair, RoutineLocation, RoutinePair},
+    File, FileId,
+};
+
+/// Unit of code which is matched and scored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Granularity {
+    /// Files are fingerprinted as a whole.
+    #[default]
+    File,
+    /// Every routine of a file is fingerprinted on its own, so that matches never span two routines.
+    Function,
+}
+
+/// Code of a file from a routine label up to the next one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Routine {
+    /// Label of the routine, or `None` for the code before the first routine label.
+    pub name: Option<String>,
+    /// Span of the routine in the file, from the start of the line of its label.
+    pub span: Range<usize>,
+}
+
+/// Splits a file into routines. A routine starts at the label of a symbol declared as global (with `.global` or
+/// `.globl`) or as a function (with `.type`), or at every label which is not local (i.e., `.L` labels and numeric
+/// labels) if the file declares none of its labels. The code before the first routine is a routine without a name if
+/// it contains anything besides whitespace.
+pub fn routines(contents: &str) -> Vec<Routine> {
+    let mut labels = Vec::new();
+    let mut declared = HashSet::new();
+    let mut line_start = 0;
+    for line in contents.split_inclusive('\n') {
+        let statement = line.trim_start();
+        if let Some(name) = label(statement) {
+            labels.push((name, line_start));
+        } else if let Some(names) = declared_names(statement) {
+            declared.extend(names);
+        }
+        line_start += line.len();
+    }
+
+    let is_declared = |name: &&str| declared.contains(name);
+    let starts = if labels.iter().any(|(name, _)| is_declared(name)) {
+        labels
+            .into_iter()
+            .filter(|(name, _)| is_declared(name))
+            .collect::<Vec<_>>()
+    } else {
+        labels
+            .into_iter()
+            .filter(|(name, _)| {
+                !name.starts_with(".L") && !name.starts_with(|c: char| c.is_ascii_digit())
+            })
+            .collect()
+    };
+
+    let mut routines = Vec::new();
+    let first_start = starts.first().map_or(contents.len(), |&(_, start)| start);
+    if !contents[..first_start].trim().is_empty() {
+        routines.push(Routine {
+            name: None,
+            span: 0..first_start,
+        });
+    }
+    for (i, &(name, start)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(contents.len(), |&(_, end)| end);
+        routines.push(Routine {
+            name: Some(name.to_owned()),
+            span: start..end,
+        });
+    }
+    routines
+}
+
+/// Returns the name of the label at the start of the statement, if any.
+fn label(statement: &str) -> Option<&str> {
+    let end = statement
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "_.$".contains(c)))
+        .unwrap_or(statement.len());
+    (end > 0 && statement[end..].starts_with(':')).then(|| &statement[..end])
+}
+
+/// Returns the names declared by a `.global`, `.globl`, or `.type ..., %function` directive.
+fn declared_names(statement: &str) -> Option<Vec<&str>> {
+    let statement = statement.split(['@', ';']).next().unwrap_or_default();
+    let (directive, operands) = statement.split_once(char::is_whitespace)?;
+    match directive {
+        ".global" | ".globl" => Some(operands.split(',').map(str::trim).collect()),
+        ".type" => {
+            let (name, kind) = operands.split_once(',')?;
+            kind.trim()
+                .trim_start_matches(['%', '@', '#', '"'])
+                .starts_with("function")
+                .then(|| vec![name.trim()])
+        }
+        _ => None,
+    }
+}
+
+/// Original file and routine of each document into which the files were split.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct RoutineFiles {
+    files: HashMap<PathBuf, PathBuf>,
+}
+
+/// Splits the tokens of every file into one document per routine, whose path is that of the file followed by `#` and
+/// the name of the routine.
+pub(crate) fn split_into_routines(
+    document_hashes: DocumentHashes,
+    documents: &[File],
+    arena: &mut PathArena,
+) -> (DocumentHashes, RoutineFiles) {
+    let contents = documents
+        .iter()
+        .filter_map(|f| Some((arena.file_id(&f.project, &f.path)?, f.contents.as_str())))
+        .collect::<HashMap<_, _>>();
+    let file_routines = document_hashes
+        .keys()
+        .map(|file_id| {
+            let routines = contents.get(file_id).map(|c| routines(c));
+            (*file_id, routines.unwrap_or_default())
+        })
+        .collect::<HashMap<_, _>>();
+    // Tokens before the first routine (without any code of their own) belong to the file itself
+    let routine_name = |r: &Routine| r.name.clone().unwrap_or_default();
+
+    // The paths of the routines are interned in order, so that their identifiers compare like them
+    let routine_paths = file_routines
+        .iter()
+        .flat_map(|(file_id, routines)| {
+            let file = arena.path(file_id.path);
+            routines
+                .iter()
+                .map(routine_name)
+                .chain([String::new()])
+                .map(|name| (routine_path(file, &name), file.clone()))
+        })
+        .collect::<BTreeMap<_, _>>();
+    for path in routine_paths.keys() {
+        arena.intern(path);
+    }
+
+    let mut routine_hashes: DocumentHashes = HashMap::new();
+    for (file_id, hashes) in document_hashes {
+        let routines = &file_routines[&file_id];
+        let file = arena.path(file_id.path);
+        let routine_id = |name: &str| {
+            let path = arena.id(&routine_path(file, name));
+            FileId::new(file_id.project, path.expect("the routines are interned"))
+        };
+        let routine_ids = routines
+            .iter()
+            .map(|r| routine_id(&routine_name(r)))
+            .collect::<Vec<_>>();
+        let file_routine_id = routine_id("");
+        for (hash, span) in hashes {
+            let routine = routines.iter().position(|r| r.span.contains(&span.start));
+            routine_hashes
+                .entry(routine.map_or(file_routine_id, |i| routine_ids[i]))
+                .or_default()
+                .push((hash, span));
+        }
+    }
+    let routine_files = RoutineFiles {
+        files: routine_paths.into_iter().collect(),
+    };
+    (routine_hashes, routine_files)
+}
+
+/// Returns the path of the document of a routine, which is the path of its file followed by `#` and the name of the
+/// routine.
+fn routine_path(file: &Path, name: &str) -> PathBuf {
+    let mut path = OsString::from(file.as_os_str());
+    path.push("#");
+    path.push(name);
+    PathBuf::from(path)
+}
+
+impl RoutineFiles {
+    /// Returns whether the path is that of a routine rather than a file.
+    pub(crate) fn is_routine(&self, path: &PathBuf) -> bool {
+        self.files.contains_key(path)
+    }
+
+    /// Replaces the routines of the matches of a pair with their original files. The spans are already those of the
+    /// files.
+    pub(crate) fn restore_pair(&self, mut pair: ProjectPair) -> ProjectPair {
+        for m in pair.matches.iter_mut() {
+            for location in [&mut m.project_1_location, &mut m.project_2_location] {
+                if let Some(file) = self.files.get(&location.file) {
+                    location.file = file.clone();
+                }
+            }
+        }
+        pair
+    }
+}
+
+/// File and routine of both projects of a pair.
+type RoutinesOfPair<'a> = (&'a PathBuf, &'a Routine, &'a PathBuf, &'a Routine);
+
+/// Lists the routines of each pair of projects which share code, along with the proportion of each routine covered by
+/// the matches. The pairs of routines are sorted from the most to the least covered.
+pub fn routine_pairs(project_pairs: &[ProjectPair], documents: &[File]) -> Vec<RoutinePair> {
+    let file_routines = documents
+        .iter()
+        .map(|f| (&f.path, routines(&f.contents)))
+        .collect::<HashMap<_, _>>();
+    let find_routine = |file: &PathBuf, position: usize| {
+        file_routines
+            .get(file)?
+            .iter()
+            .find(|r| r.span.contains(&position))
+    };
+
+    let mut routine_pairs = Vec::new();
+    for pair in project_pairs {
+        // Spans of the matches within each pair of routines, on both sides
+        let mut covered: HashMap<RoutinesOfPair, [Vec<Range<usize>>; 2]> = HashMap::new();
+        for m in pair.matches.iter() {
+            let (location_1, location_2) = (&m.project_1_location, &m.project_2_location);
+            let (Some(routine_1), Some(routine_2)) = (
+                find_routine(&location_1.file, location_1.span.start),
+                find_routine(&location_2.file, location_2.span.start),
+            ) else {
+                continue;
+            };
+            let [spans_1, spans_2] = covered
+                .entry((&location_1.file, routine_1, &location_2.file, routine_2))
+                .or_default();
+            spans_1.push(location_1.span.clone());
+            spans_2.push(location_2.span.clone());
+        }
+
+        for ((file_1, routine_1, file_2, routine_2), [spans_1, spans_2]) in covered {
+            let coverage =
+                |routine: &Routine, spans| covered_bytes(spans) as f64 / routine.span.len() as f64;
+            let location = |file: &PathBuf, routine: &Routine| RoutineLocation {
+                file: file.clone(),
+                name: routine.name.clone(),
+                span: routine.span.clone(),
+            };
+            routine_pairs.push(RoutinePair {
+                project1: pair.project1.clone(),
+                project2: pair.project2.clone(),
+                routine_1_coverage: coverage(routine_1, spans_1),
+                routine_2_coverage: coverage(routine_2, spans_2),
+                routine1: location(file_1, routine_1),
+                routine2: location(file_2, routine_2),
+            });
+        }
+    }
+    routine_pairs.sort_by(|p1, p2| {
+        let coverage = |p: &RoutinePair| p.routine_1_coverage.max(p.routine_2_coverage);
+        coverage(p2)
+            .total_cmp(&coverage(p1))
+            .then_with(|| (&p1.project1, &p1.project2).cmp(&(&p2.project1, &p2.project2)))
+            .then_with(|| location_key(&p1.routine1).cmp(&location_key(&p2.routine1)))
+            .then_with(|| location_key(&p1.routine2).cmp(&location_key(&p2.routine2)))
+    });
+    routine_pairs
+}
+
+fn location_key(location: &RoutineLocation) -> (&PathBuf, usize) {
+    (&location.file, location.span.start)
+}
+
+/// Returns the number of bytes covered by at least one of the spans.
+fn covered_bytes(mut spans: Vec<Range<usize>>) -> usize {
+    spans.sort_by_key(|s| s.start);
+    let mut covered = 0;
+    let mut end = 0;
+    for span in spans {
+        let start = span.start.max(end);
+        if span.end > start {
+            covered += span.end - start;
+            end = span.end;
+        }
+    }
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{detect_plagiarism, DetectionOptions};
+
+    #[test]
+    fn declared_labels_start_routines() {
+        let contents = ".text\n.global main\n.type helper, %function\nmain:\n\tbl helper\nloop:\n\tb loop\nhelper:\n\tbx lr\n";
+        let names = routines(contents)
+            .into_iter()
+            .map(|r| r.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [None, Some("main".to_owned()), Some("helper".to_owned())]
+        );
+        let helper = &routines(contents)[2];
+        assert_eq!(&contents[helper.span.clone()], "helper:\n\tbx lr\n");
+    }
+
+    #[test]
+    fn undeclared_labels_start_routines() {
+        let contents = "first:\n\tmov r0, #1\n.L2:\n\tb .L2\n1:\nsecond: @ comment\n\tbx lr";
+        let names = routines(contents)
+            .into_iter()
+            .map(|r| r.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("first".to_owned()), Some("second".to_owned())]);
+    }
+
+    #[test]
+    fn corresponding_routines_are_listed() {
+        let routine = |name: &str, seed: usize| {
+            let body = (0..12)
+                .map(|i| {
+                    format!(
+                        "\tadd r{}, r{}, #{}\n",
+                        (i + seed) % 5,
+                        (i * seed + 2) % 7,
+                        i * seed
+                    )
+                })
+                .collect::<String>();
+            format!("{name}:\n{body}\tbx lr\n")
+        };
+        let file = |project: &str, contents: String| {
+            File::new(
+                PathBuf::from(project),
+                PathBuf::from(project).join("main.s"),
+                contents,
+            )
+        };
+        let documents = [
+            file("alice", routine("sum", 3) + &routine("product", 5)),
+            file("bob", routine("total", 7) + &routine("sum", 3)),
+        ];
+        let options = DetectionOptions {
+            noise_threshold: 10,
+            guarantee_threshold: 20,
+            max_token_offset: 5,
+            granularity: Granularity::Function,
+            ..Default::default()
+        };
+        let (project_pairs, _) = detect_plagiarism(&options, &documents, &[]);
+        for m in project_pairs.iter().flat_map(|p| p.matches.iter()) {
+            assert_eq!(m.project_1_location.file, PathBuf::from("alice/main.s"));
+        }
+
+        let pairs = routine_pairs(&project_pairs, &documents);
+        let first = &pairs[0];
+        assert_eq!(first.routine1.name.as_deref(), Some("sum"));
+        assert_eq!(first.routine2.name.as_deref(), Some("sum"));
+        assert!(first.routine_1_coverage > 0.95);
+    }
+
+    #[test]
+    fn overlapping_spans_are_counted_once() {
+        assert_eq!(covered_bytes(vec![0..4, 2..6, 10..12, 11..12]), 8);
+    }
+}
@@ -0,0 +1,56 @@
+//! Scores which determine how project pairs are ranked.
+
+use itertools::Itertools;
+
+use crate::output::Match;
+
+/// How project pairs are scored, and therefore ranked, in the output.
+#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ScoringMode {
+    /// Rank pairs by their number of matches.
+    #[default]
+    Matches,
+    /// Weight each match by the inverse document frequency of its hashes, i.e. by how rare they are across the
+    /// projects, and rank pairs by the total weight of their matches. Code found in many projects contributes little
+    /// to the score, so the ranking depends less on the exact common code threshold.
+    Idf,
+}
+
+/// Returns the weight of a hash which occurs in `num_projects_with_hash` of the `num_projects` projects.
+///
+/// This is a smoothed inverse document frequency: it is always positive (so that a hash shared by every project still
+/// counts for something) and decreases as the hash becomes more common.
+pub fn idf(num_projects: usize, num_projects_with_hash: usize) -> f64 {
+    (1.0 + num_projects as f64 / num_projects_with_hash as f64).ln()
+}
+
+/// Returns the sum of the weights of the matches, which is the score of a pair when scoring by IDF.
+pub fn total_weight(matches: &[Match]) -> f64 {
+    sum_weights(matches.iter().filter_map(|m| m.weight))
+}
+
+/// Sums the weights in sorted order, so that the result does not depend on the order in which they are given.
+pub fn sum_weights(weights: impl IntoIterator<Item = f64>) -> f64 {
+    weights.into_iter().sorted_by(f64::total_cmp).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rarer_hashes_weigh_more() {
+        assert!(idf(10, 2) > idf(10, 5));
+        assert!(idf(10, 5) > idf(10, 10));
+        assert!(idf(10, 10) > 0.0);
+        assert_eq!(idf(4, 4), 2f64.ln());
+    }
+
+    #[test]
+    fn sum_is_order_independent() {
+        // Floating-point addition is not associative: 0.1 + 0.2 + 0.3 != 0.3 + 0.2 + 0.1
+        assert_eq!(sum_weights([0.1, 0.2, 0.3]), sum_weights([0.3, 0.2, 0.1]));
+        assert_eq!(sum_weights([]), 0.0);
+    }
+}
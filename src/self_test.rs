@@ -0,0 +1,191 @@
+//! Self-test of the guarantee threshold: a known match is planted into two copies of a sample file, and the analysis
+//! must report it with the given options. Combinations of options which silently drop matches longer than the guarantee
+//! threshold (e.g., a minimum report length above it, or a prefilter skipping the pair) make the self-test fail.
+
+use std::{collections::BTreeMap, ops::Range, path::PathBuf};
+
+use crate::{
+    detect_plagiarism, lexing, output::Location, project_metadata::CompareMode, DetectionOptions,
+    File,
+};
+
+/// Maximum number of lines of the planted snippet, in case the tokenizing strategy finds no tokens in them.
+const MAX_SNIPPET_LINES: usize = 10_000;
+
+/// Outcome of a self-test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTest {
+    /// File into whose copies the snippet was planted.
+    pub sample: PathBuf,
+    /// Number of tokens of the planted snippet, which is at least the guarantee threshold.
+    pub planted_tokens: usize,
+    /// Whether the analysis reported a match overlapping the snippet in both copies.
+    pub passed: bool,
+}
+
+/// Plants a synthetic snippet of at least the guarantee threshold's length into two copies of the first document, and
+/// checks whether the analysis of the documents along with the copies reports it. The copies differ everywhere but in
+/// the snippet, since the lines of the second one are reversed.
+///
+/// The options which select pairs of projects by name (the sections and the pairs to compare), the checkpoint, and the
+/// cache do not apply to the copies, so they are left out.
+pub fn self_test(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+) -> Result<SelfTest, String> {
+    let sample = documents
+        .first()
+        .ok_or_else(|| "There is no file to plant the known match into.".to_owned())?;
+    let (snippet, planted_tokens) = planted_snippet(options)?;
+
+    let reversed_contents = sample.contents.lines().rev().collect::<Vec<_>>().join("\n");
+    let copies = [
+        ("self-test-1", plant(&sample.contents, &snippet)),
+        ("self-test-2", plant(&reversed_contents, &snippet)),
+    ]
+    .map(|(project, (contents, span))| {
+        let project = PathBuf::from(project);
+        let path = project.join(sample.path.file_name().unwrap_or_default());
+        (File::new(project, path, contents), span)
+    });
+
+    let options = DetectionOptions {
+        compare: CompareMode::All,
+        project_sections: BTreeMap::new(),
+        only_pairs: None,
+        checkpoint_dir: None,
+        cache_dir: None,
+        ..options.clone()
+    };
+    let all_documents = documents
+        .iter()
+        .chain(copies.iter().map(|(file, _)| file))
+        .cloned()
+        .collect::<Vec<_>>();
+    let (project_pairs, _warnings) = detect_plagiarism(&options, &all_documents, ignored_documents);
+
+    let overlaps_snippet = |location: &Location| {
+        copies.iter().any(|(file, span)| {
+            location.file == file.path
+                && location.span.start < span.end
+                && span.start < location.span.end
+        })
+    };
+    let passed = project_pairs
+        .iter()
+        .filter(|pair| {
+            let projects = [&pair.project1, &pair.project2];
+            copies
+                .iter()
+                .all(|(file, _)| projects.contains(&&file.project))
+        })
+        .flat_map(|pair| pair.matches.iter())
+        .any(|m| {
+            overlaps_snippet(&m.project_1_location) && overlaps_snippet(&m.project_2_location)
+        });
+
+    Ok(SelfTest {
+        sample: sample.path.clone(),
+        planted_tokens,
+        passed,
+    })
+}
+
+/// Returns a synthetic snippet with at least as many tokens as the guarantee threshold, along with its number of
+/// tokens. Every line differs from the previous ones, and ends with a comment for the "comments" tokenizing strategy.
+fn planted_snippet(options: &DetectionOptions) -> Result<(String, usize), String> {
+    const MNEMONICS: [&str; 6] = ["add", "sub", "eor", "orr", "and", "rsb"];
+
+    let mut snippet = String::new();
+    for i in 0..MAX_SNIPPET_LINES {
+        snippet.push_str(&format!(
+            "\t{} r{}, r{}, #{}\t@ planted line {i} of the self-test\n",
+            MNEMONICS[i % MNEMONICS.len()],
+            i % 11,
+            (i * 7 + 3) % 11,
+            (i * 37) % 251,
+        ));
+        let (tokens, _lex_errors) = lexing::tokenize_and_hash(
+            &snippet,
+            options.tokenizing_strategy,
+            &options.token_transforms(),
+            options.key_symbols.as_ref(),
+            options.hash_dialect,
+            options.max_token_offset,
+        );
+        if tokens.len() >= options.guarantee_threshold {
+            return Ok((snippet, tokens.len()));
+        }
+    }
+    Err(format!(
+        "The planted snippet does not reach the guarantee threshold of {} tokens with the {:?} tokenizing strategy.",
+        options.guarantee_threshold, options.tokenizing_strategy
+    ))
+}
+
+/// Inserts the snippet at the line in the middle of the contents, returning the new contents and the span of the
+/// snippet in them.
+fn plant(contents: &str, snippet: &str) -> (String, Range<usize>) {
+    let lines = contents.lines().collect::<Vec<_>>();
+    let (before, after) = lines.split_at(lines.len() / 2);
+    let mut planted = before
+        .iter()
+        .map(|line| format!("{line}\n"))
+        .collect::<String>();
+    let start = planted.len();
+    planted.push_str(snippet);
+    let span = start..planted.len();
+    planted.push_str(&after.join("\n"));
+    (planted, span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> File {
+        let contents = (0..40)
+            .map(|i| format!("\tmov r{}, #{}\n\tbl func{i}\n", i % 4, i * 3))
+            .collect::<String>();
+        File::new(
+            PathBuf::from("alice"),
+            PathBuf::from("alice/main.s"),
+            contents,
+        )
+    }
+
+    #[test]
+    fn planted_match_is_reported() {
+        let options = DetectionOptions {
+            noise_threshold: 10,
+            guarantee_threshold: 20,
+            max_token_offset: 5,
+            ..Default::default()
+        };
+        let result = self_test(&options, &[sample()], &[]).unwrap();
+        assert!(result.passed);
+        assert!(result.planted_tokens >= 20);
+        assert_eq!(result.sample, PathBuf::from("alice/main.s"));
+    }
+
+    #[test]
+    fn dropped_match_fails() {
+        // Matches shorter than the minimum report length are dropped, even if they are longer than the guarantee
+        let options = DetectionOptions {
+            noise_threshold: 10,
+            guarantee_threshold: 20,
+            max_token_offset: 5,
+            expand_matches: false,
+            min_report_length: 10_000,
+            ..Default::default()
+        };
+        let result = self_test(&options, &[sample()], &[]).unwrap();
+        assert!(!result.passed);
+    }
+
+    #[test]
+    fn no_sample() {
+        assert!(self_test(&DetectionOptions::default(), &[], &[]).is_err());
+    }
+}
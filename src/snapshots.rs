@@ -0,0 +1,171 @@
+//! Compares two snapshots of the same projects (e.g., the submissions received by the deadline and the late ones) to
+//! report only the similarity introduced by the second snapshot.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    output::{Location, Match, ProjectPair},
+    File,
+};
+
+/// Side of a match, identified by its file relative to the root of the snapshot and by the matched code, so that a
+/// match is recognized in both snapshots even if the code around it moved.
+type Side<'a> = (&'a Path, &'a str);
+
+/// Analysis of a snapshot of the projects.
+pub struct Snapshot<'a> {
+    /// Directory containing the projects of the snapshot.
+    pub root: &'a Path,
+    pub documents: &'a [File],
+    pub project_pairs: &'a [ProjectPair],
+}
+
+/// Removes from the project pairs of the second snapshot (in `root`) the matches which were already found between the
+/// same projects in the original snapshot, then the pairs left without any match. The projects of both snapshots are
+/// paired by their paths relative to their roots. The scores and explanations of the remaining pairs are unchanged,
+/// and still describe all of their similarity.
+pub fn remove_original_matches(
+    project_pairs: &mut Vec<ProjectPair>,
+    root: &Path,
+    documents: &[File],
+    original: Snapshot,
+) {
+    let original_index = SnapshotIndex::new(original.root, original.documents);
+    let original_matches = original
+        .project_pairs
+        .iter()
+        .map(|p| {
+            let matches = p
+                .matches
+                .iter()
+                .map(|m| original_index.match_key(m))
+                .collect::<HashSet<_>>();
+            (original_index.pair_key(p), matches)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let index = SnapshotIndex::new(root, documents);
+    for pair in project_pairs.iter_mut() {
+        if let Some(matches) = original_matches.get(&index.pair_key(pair)) {
+            pair.matches
+                .retain(|m| !matches.contains(&index.match_key(m)));
+        }
+    }
+    project_pairs.retain(|p| !p.matches.is_empty());
+    // Pairs without a score are ranked by their number of matches, which may have changed
+    project_pairs.sort_by(|p1, p2| p2.ranking_score().total_cmp(&p1.ranking_score()));
+}
+
+/// Contents of the files of a snapshot.
+struct SnapshotIndex<'a> {
+    root: &'a Path,
+    contents: HashMap<&'a Path, &'a str>,
+}
+
+impl<'a> SnapshotIndex<'a> {
+    fn new(root: &'a Path, documents: &'a [File]) -> SnapshotIndex<'a> {
+        let contents = documents
+            .iter()
+            .map(|f| (f.path.as_path(), f.contents.as_str()))
+            .collect();
+        SnapshotIndex { root, contents }
+    }
+
+    fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(self.root).unwrap_or(path)
+    }
+
+    /// Returns the projects of the pair relative to the root, in a fixed order.
+    fn pair_key(&self, pair: &ProjectPair) -> (PathBuf, PathBuf) {
+        let (project1, project2) = (self.relative(&pair.project1), self.relative(&pair.project2));
+        (
+            project1.min(project2).to_owned(),
+            project1.max(project2).to_owned(),
+        )
+    }
+
+    /// Returns the sides of the match in a fixed order.
+    fn match_key<'m>(&'m self, m: &'m Match) -> (Side<'m>, Side<'m>) {
+        let (side1, side2) = (
+            self.side(&m.project_1_location),
+            self.side(&m.project_2_location),
+        );
+        (side1.min(side2), side1.max(side2))
+    }
+
+    fn side<'m>(&'m self, location: &'m Location) -> Side<'m> {
+        let code = self
+            .contents
+            .get(location.file.as_path())
+            .and_then(|contents| contents.get(location.span.clone()))
+            .unwrap_or_default();
+        (self.relative(&location.file), code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{detect_plagiarism, DetectionOptions, TokenizingStrategy};
+
+    #[test]
+    fn only_new_matches_are_kept() {
+        let file = |root: &str, project: &str, name: &str, contents: &str| {
+            File::new(
+                format!("{root}/{project}").into(),
+                format!("{root}/{project}/{name}").into(),
+                contents.to_owned(),
+            )
+        };
+        let options = DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            expand_matches: false,
+            ..Default::default()
+        };
+        let original_documents = vec![
+            file("old", "P1", "a.txt", "abcdefgh"),
+            file("old", "P2", "a.txt", "abcdefgh"),
+            file("old", "P3", "a.txt", "zzzzzzzz"),
+        ];
+        // P2 adds a copy of P1's file in a new one, and P3 copies part of it
+        let documents = vec![
+            file("new", "P1", "a.txt", "abcdefgh"),
+            file("new", "P2", "a.txt", "abcdefgh"),
+            file("new", "P2", "b.txt", "xxabcdefgh"),
+            file("new", "P3", "a.txt", "zzzzabcd"),
+        ];
+        let (original_pairs, _) = detect_plagiarism(&options, &original_documents, &[]);
+        let (mut project_pairs, _) = detect_plagiarism(&options, &documents, &[]);
+
+        remove_original_matches(
+            &mut project_pairs,
+            Path::new("new"),
+            &documents,
+            Snapshot {
+                root: Path::new("old"),
+                documents: &original_documents,
+                project_pairs: &original_pairs,
+            },
+        );
+
+        let p1_p2 = project_pairs
+            .iter()
+            .find(|p| p.project1 == Path::new("new/P1") && p.project2 == Path::new("new/P2"))
+            .unwrap();
+        assert!(!p1_p2.matches.is_empty());
+        assert!(p1_p2
+            .matches
+            .iter()
+            .all(|m| m.project_2_location.file == Path::new("new/P2/b.txt")));
+        assert!(project_pairs
+            .iter()
+            .any(|p| p.project1 == Path::new("new/P1") && p.project2 == Path::new("new/P3")));
+    }
+}
@@ -0,0 +1,129 @@
+//! Comparison of each project with the starter code alone, rather than with the other projects. Submissions which are
+//! little more than the template stand out, and the results give a baseline before analyzing the pairs of projects.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+};
+
+use crate::{fingerprint, lexing, output::StarterCodeDiff, DetectionOptions, File};
+
+/// Returns how much of the starter code each project contains and how much code it adds, from the projects with the
+/// least code of their own to those with the most.
+pub fn starter_code_diffs(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+) -> Vec<StarterCodeDiff> {
+    let starter_code_hashes = ignored_documents
+        .iter()
+        .flat_map(|f| file_hashes(options, f).0)
+        .collect::<HashSet<_>>();
+
+    let mut projects: BTreeMap<&PathBuf, (HashSet<u64>, usize)> = BTreeMap::new();
+    for document in documents {
+        let (hashes, num_tokens) = file_hashes(options, document);
+        let (project_hashes, project_tokens) = projects.entry(&document.project).or_default();
+        project_hashes.extend(hashes);
+        *project_tokens += num_tokens;
+    }
+
+    let mut diffs = projects
+        .into_iter()
+        .map(|(project, (hashes, num_tokens))| {
+            let shared_hashes = hashes.intersection(&starter_code_hashes).count();
+            let proportion = |n: usize, total: usize| match total {
+                0 => 0.0,
+                _ => n as f64 / total as f64,
+            };
+            StarterCodeDiff {
+                project: project.to_owned(),
+                num_tokens,
+                starter_code_coverage: proportion(shared_hashes, starter_code_hashes.len()),
+                new_code_proportion: proportion(hashes.len() - shared_hashes, hashes.len()),
+            }
+        })
+        .collect::<Vec<_>>();
+    diffs.sort_by(|d1, d2| {
+        d1.new_code_proportion
+            .total_cmp(&d2.new_code_proportion)
+            .then_with(|| d1.project.cmp(&d2.project))
+    });
+    diffs
+}
+
+/// Returns the hash of every substring of the file which is long enough to be fingerprinted, along with its number of
+/// tokens. Files which are too short to be fingerprinted have no hashes.
+fn file_hashes(options: &DetectionOptions, file: &File) -> (Vec<u64>, usize) {
+    let (tokens, _lex_errors) = lexing::tokenize_and_hash(
+        &file.contents,
+        options.tokenizing_strategy,
+        &options.token_transforms(),
+        options.key_symbols.as_ref(),
+        options.hash_dialect,
+        options.max_token_offset,
+    );
+    // Choose the fingerprinting parameters so that the window size is 1, like for starter code during the analysis
+    let hashes = fingerprint::fingerprint(
+        options.noise_threshold,
+        options.noise_threshold + options.max_token_offset,
+        options.max_token_offset,
+        &tokens,
+    )
+    .map(|f| f.spanned_hashes.into_iter().map(|(hash, _)| hash).collect())
+    .unwrap_or_default();
+    (hashes, tokens.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TokenizingStrategy;
+
+    #[test]
+    fn projects_with_little_new_code_come_first() {
+        let documents = vec![
+            File::new("P1".into(), "P1/a.txt".into(), "abcdefgh".to_owned()),
+            File::new("P1".into(), "P1/b.txt".into(), "ijkl".to_owned()),
+            File::new("P2".into(), "P2/a.txt".into(), "abcd".to_owned()),
+            File::new("P3".into(), "P3/a.txt".into(), "xy".to_owned()),
+        ];
+        let ignored_documents = vec![File::new(
+            "starter".into(),
+            "starter/a.txt".into(),
+            "abcdefgh".to_owned(),
+        )];
+        let options = DetectionOptions {
+            noise_threshold: 3,
+            guarantee_threshold: 3,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+
+        let diffs = starter_code_diffs(&options, &documents, &ignored_documents);
+
+        let summary = diffs
+            .iter()
+            .map(|d| {
+                (
+                    d.project.to_str().unwrap(),
+                    d.num_tokens,
+                    d.starter_code_coverage,
+                    d.new_code_proportion,
+                )
+            })
+            .collect::<Vec<_>>();
+        // P1 contains the whole starter code and adds "ijkl" (2 substrings of 3 bytes), P2 only contains part of it, and
+        // P3 is too short to be fingerprinted
+        assert_eq!(
+            summary,
+            vec![
+                ("P2", 4, 2.0 / 6.0, 0.0),
+                ("P3", 2, 0.0, 0.0),
+                ("P1", 12, 1.0, 0.25)
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Stitching of the files of each project into a single stream of tokens, so that a routine split across two files of
+//! one project still matches the same routine kept in a single file of another project.
+
+use std::{cmp::min, collections::HashMap, ops::Range, path::PathBuf};
+
+use itertools::Itertools;
+
+use crate::{
+    checkpoint::DocumentHashes,
+    interning::PathArena,
+    lexing::HashedTokens,
+    output::{Location, Match, ProjectPair},
+    File, FileId,
+};
+
+/// Position of a file within the stitched stream of its project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StitchedFile {
+    path: PathBuf,
+    /// Position of the first byte of the file in the stream.
+    offset: usize,
+    /// Length of the file in bytes.
+    len: usize,
+}
+
+/// Layout of the stitched stream of every project, used to map the spans of the matches back to the original files.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct StitchedProjects {
+    projects: HashMap<PathBuf, Vec<StitchedFile>>,
+}
+
+/// Concatenates the tokens of the files of each project in the order of their paths. Returns the tokens of one stream
+/// per project, whose path is that of the project itself, along with the layout of the streams. The spans of the
+/// tokens are offset by the length of the files before them in the stream, plus one byte between consecutive files so
+/// that no span touches two files.
+pub(crate) fn stitch(
+    document_hashes: DocumentHashes,
+    documents: &[File],
+    arena: &PathArena,
+) -> (DocumentHashes, StitchedProjects) {
+    let lengths = documents
+        .iter()
+        .filter_map(|f| Some((arena.file_id(&f.project, &f.path)?, f.contents.len())))
+        .collect::<HashMap<_, _>>();
+
+    let mut streams: DocumentHashes = HashMap::new();
+    let mut layout = StitchedProjects::default();
+    let files = document_hashes
+        .into_iter()
+        .sorted_unstable_by(|(file_1, _), (file_2, _)| file_1.cmp(file_2));
+    for (file_id, hashes) in files {
+        let stream = streams
+            .entry(FileId::new(file_id.project, file_id.project))
+            .or_default();
+        let project_files = layout
+            .projects
+            .entry(arena.path(file_id.project).clone())
+            .or_default();
+        let offset = project_files.last().map_or(0, |f| f.offset + f.len + 1);
+        let len = lengths
+            .get(&file_id)
+            .copied()
+            .unwrap_or_else(|| hashes.last().map_or(0, |(_, span)| span.end));
+        stream.extend(
+            hashes
+                .into_iter()
+                .map(|(hash, span)| (hash, span.start + offset..span.end + offset)),
+        );
+        project_files.push(StitchedFile {
+            path: arena.path(file_id.path).clone(),
+            offset,
+            len,
+        });
+    }
+    (streams, layout)
+}
+
+impl StitchedProjects {
+    /// Maps the matches of a pair found in the stitched streams back to the original files. A match which spans several
+    /// files on either side is split where either side moves on to another file, and its weight is shared between the
+    /// pieces in proportion to their numbers of tokens. `document_hashes` are the tokens of the streams.
+    pub(crate) fn unstitch_pair(
+        &self,
+        mut pair: ProjectPair,
+        document_hashes: &DocumentHashes,
+        arena: &PathArena,
+    ) -> ProjectPair {
+        // The stream of each project has the path of the project itself
+        let stream = |project| {
+            let project = arena.id(project).expect("the projects are interned");
+            &document_hashes[&FileId::new(project, project)]
+        };
+        let tokens = (stream(&pair.project1), stream(&pair.project2));
+        let matches = std::mem::take(&mut pair.matches);
+        pair.matches = matches
+            .into_iter()
+            .flat_map(|m| self.unstitch_match(&pair.project1, &pair.project2, m, tokens))
+            .collect();
+        pair
+    }
+
+    fn unstitch_match(
+        &self,
+        project1: &PathBuf,
+        project2: &PathBuf,
+        m: Match,
+        (tokens_1, tokens_2): (&HashedTokens, &HashedTokens),
+    ) -> Vec<Match> {
+        let range_1 = token_range(tokens_1, &m.project_1_location.span);
+        let range_2 = token_range(tokens_2, &m.project_2_location.span);
+        let num_tokens = min(range_1.len(), range_2.len());
+
+        let file_index =
+            |project, tokens: &HashedTokens, i: usize| self.locate(project, tokens[i].1.start).0;
+        let changes_file = |i: usize| {
+            file_index(project1, tokens_1, range_1.start + i)
+                != file_index(project1, tokens_1, range_1.start + i - 1)
+                || file_index(project2, tokens_2, range_2.start + i)
+                    != file_index(project2, tokens_2, range_2.start + i - 1)
+        };
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for i in 1..=num_tokens {
+            if i == num_tokens || changes_file(i) {
+                pieces.push(start..i);
+                start = i;
+            }
+        }
+
+        pieces
+            .into_iter()
+            .map(|piece| Match {
+                id: None,
+                project_1_location: self.location(
+                    project1,
+                    tokens_1,
+                    range_1.start + piece.start..range_1.start + piece.end,
+                ),
+                project_2_location: self.location(
+                    project2,
+                    tokens_2,
+                    range_2.start + piece.start..range_2.start + piece.end,
+                ),
+                weight: m.weight.map(|w| w * piece.len() as f64 / num_tokens as f64),
+            })
+            .collect()
+    }
+
+    /// Returns the index of the file containing the position in the stream of the project, and the position within
+    /// that file.
+    fn locate(&self, project: &PathBuf, position: usize) -> (usize, usize) {
+        let files = &self.projects[project];
+        // The first file starts at 0, so at least one file starts at or before the position
+        let i = files.partition_point(|f| f.offset <= position) - 1;
+        (i, position - files[i].offset)
+    }
+
+    /// Returns the location in the original file of a non-empty range of tokens of the stream of the project, which must
+    /// all be in the same file.
+    fn location(&self, project: &PathBuf, tokens: &HashedTokens, range: Range<usize>) -> Location {
+        let (i, start) = self.locate(project, tokens[range.start].1.start);
+        let file = &self.projects[project][i];
+        let end = tokens[range.end - 1].1.end - file.offset;
+        Location {
+            file: file.path.clone(),
+            span: start..end,
+            length_bytes: Some(end - start),
+            length_tokens: Some(range.len()),
+        }
+    }
+}
+
+/// Returns the range of the tokens which lie within the span.
+fn token_range(tokens: &HashedTokens, span: &Range<usize>) -> Range<usize> {
+    let start = tokens.partition_point(|(_, range)| range.start < span.start);
+    let end = tokens.partition_point(|(_, range)| range.end <= span.end);
+    start..end.max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{detect_plagiarism, DetectionOptions};
+
+    #[test]
+    fn routine_split_across_files_matches() {
+        let routine = (0..10)
+            .map(|i| format!("\tadd r{}, r{}, #{}\n", i % 5, (i + 2) % 7, i * 13))
+            .collect::<Vec<_>>();
+        let (first_half, second_half) = routine.split_at(5);
+        let file = |project: &str, name: &str, lines: &[String]| {
+            File::new(
+                PathBuf::from(project),
+                PathBuf::from(project).join(name),
+                lines.concat(),
+            )
+        };
+        let documents = [
+            file("alice", "main.s", &routine),
+            file("bob", "a.s", first_half),
+            file("bob", "b.s", second_half),
+        ];
+        let options = |stitch_files| DetectionOptions {
+            noise_threshold: 40,
+            guarantee_threshold: 60,
+            max_token_offset: 10,
+            stitch_files,
+            ..Default::default()
+        };
+
+        // Each half is too short to be matched on its own
+        let (pairs, _) = detect_plagiarism(&options(false), &documents, &[]);
+        assert!(pairs.is_empty());
+
+        let (pairs, _) = detect_plagiarism(&options(true), &documents, &[]);
+        let [pair] = pairs.as_slice() else {
+            panic!("expected a single pair, got {pairs:?}");
+        };
+        let files = pair
+            .matches
+            .iter()
+            .map(|m| m.project_2_location.file.clone())
+            .sorted()
+            .dedup()
+            .collect::<Vec<_>>();
+        assert_eq!(files, [PathBuf::from("bob/a.s"), PathBuf::from("bob/b.s")]);
+        // The spans are within the original files
+        for m in pair.matches.iter() {
+            let contents = match m.project_2_location.file.to_str() {
+                Some("bob/a.s") => first_half.concat(),
+                _ => second_half.concat(),
+            };
+            assert!(m.project_2_location.span.end <= contents.len());
+            assert_eq!(m.project_1_location.file, PathBuf::from("alice/main.s"));
+        }
+    }
+}
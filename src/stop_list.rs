@@ -0,0 +1,83 @@
+//! Stop-list of code snippets (e.g., standard function prologues and epilogues) which are never reported as matches,
+//! regardless of how many projects contain them.
+
+use std::collections::HashSet;
+
+use crate::{
+    fingerprint, lexing,
+    output::{Warning, WarningCode, WarningType},
+    DetectionOptions,
+};
+
+/// Splits the contents of a stop-list file into its entries, which are separated by blank lines.
+pub fn parse_stop_list(contents: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut entry = String::new();
+    for line in contents.lines() {
+        if line.trim().is_empty() {
+            if !entry.is_empty() {
+                entries.push(std::mem::take(&mut entry));
+            }
+        } else {
+            entry.push_str(line);
+            entry.push('\n');
+        }
+    }
+    if !entry.is_empty() {
+        entries.push(entry);
+    }
+    entries
+}
+
+/// Returns the hash of every substring of the stop-list entries which is long enough to be fingerprinted, along with a
+/// warning for every entry which is too short to match anything.
+pub fn stop_list_hashes(options: &DetectionOptions) -> (HashSet<u64>, Vec<Warning>) {
+    let mut hashes = HashSet::new();
+    let mut warnings = Vec::new();
+
+    let transforms = options.token_transforms();
+    for (i, entry) in options.stop_list.iter().enumerate() {
+        let (tokens, _lex_errors) = lexing::tokenize_and_hash(
+            entry,
+            options.tokenizing_strategy,
+            &transforms,
+            options.key_symbols.as_ref(),
+            options.hash_dialect,
+            options.max_token_offset,
+        );
+        // Choose the fingerprinting parameters so that the window size is 1, like for starter code. Every substring of
+        // the entry is then excluded, not only those which happen to be picked for its fingerprint.
+        let result = fingerprint::fingerprint(
+            options.noise_threshold,
+            options.noise_threshold + options.max_token_offset,
+            options.max_token_offset,
+            &tokens,
+        );
+        match result {
+            Err(e) => warnings.push(Warning {
+                file: None,
+                message: format!("Stop-list entry {} is ignored. {e}", i + 1),
+                warn_type: WarningType::Args,
+                code: WarningCode::ShortStopListEntry,
+            }),
+            Ok(f) => hashes.extend(f.spanned_hashes.into_iter().map(|(hash, _)| hash)),
+        }
+    }
+
+    (hashes, warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_separated_by_blank_lines() {
+        let contents = "push {lr}\nmov r0, #0\n\n  \npop {pc}\n\n";
+        assert_eq!(
+            parse_stop_list(contents),
+            vec!["push {lr}\nmov r0, #0\n", "pop {pc}\n"]
+        );
+        assert!(parse_stop_list("\n\n").is_empty());
+    }
+}
@@ -0,0 +1,126 @@
+//! Streams the project pairs of an analysis as they are completed, so that front-ends can show results before the whole
+//! analysis is done.
+
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    detect_plagiarism_streaming,
+    diagnostics::Diagnostics,
+    observer::DetectionObserver,
+    output::{ProjectPair, Warning},
+    DetectionOptions, File,
+};
+
+/// Project pairs of an analysis running in the background, yielded as they are completed and in no particular order.
+pub struct PairStream {
+    receiver: mpsc::Receiver<ProjectPair>,
+    handle: JoinHandle<(Vec<Warning>, Diagnostics)>,
+}
+
+impl Iterator for PairStream {
+    type Item = ProjectPair;
+
+    fn next(&mut self) -> Option<ProjectPair> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl PairStream {
+    /// Waits for the analysis to end and returns its warnings and diagnostics. The pairs which have not been yielded yet
+    /// are discarded, so this is usually called once the stream is exhausted. Set `DetectionOptions::interrupt` to stop
+    /// the analysis early.
+    pub fn finish(self) -> (Vec<Warning>, Diagnostics) {
+        drop(self.receiver);
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+/// Same as `detect_plagiarism_with_diagnostics`, but runs the analysis in a background thread and yields each project
+/// pair as soon as it is complete. `observer` is notified from that thread; a `FnMut(Progress)` closure is enough to
+/// follow the number of completed pairs.
+pub fn detect_plagiarism_iter(
+    options: DetectionOptions,
+    documents: Vec<File>,
+    ignored_documents: Vec<File>,
+    mut observer: impl DetectionObserver + Send + 'static,
+) -> PairStream {
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        detect_plagiarism_streaming(
+            &options,
+            &documents,
+            &ignored_documents,
+            // The consumer may stop reading before the end, in which case the remaining pairs are dropped
+            &mut |p| {
+                let _ = sender.send(p);
+            },
+            &mut observer,
+        )
+    });
+    PairStream { receiver, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{detect_plagiarism_with_diagnostics, Progress, TokenizingStrategy};
+
+    #[test]
+    fn streamed_pairs_match_the_batch_results() {
+        let documents = vec![
+            File::new("P1".into(), "P1/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P2".into(), "P2/file.txt".into(), "abcdefgh".to_owned()),
+            File::new("P3".into(), "P3/file.txt".into(), "xxabcdyy".to_owned()),
+        ];
+        let options = DetectionOptions {
+            noise_threshold: 4,
+            guarantee_threshold: 4,
+            max_token_offset: 0,
+            tokenizing_strategy: TokenizingStrategy::Bytes,
+            ignore_whitespace: false,
+            ..Default::default()
+        };
+        let sort = |pairs: &mut Vec<ProjectPair>| {
+            pairs.sort_by(|a, b| (&a.project1, &a.project2).cmp(&(&b.project1, &b.project2)))
+        };
+        let (mut expected_pairs, expected_warnings, _) =
+            detect_plagiarism_with_diagnostics(&options, &documents, &[]);
+        sort(&mut expected_pairs);
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&progress);
+        let mut stream =
+            detect_plagiarism_iter(options, documents, Vec::new(), move |p: Progress| {
+                reported.lock().unwrap().push(p)
+            });
+        let mut pairs = stream.by_ref().collect::<Vec<_>>();
+        let (warnings, _) = stream.finish();
+        sort(&mut pairs);
+
+        assert_eq!(pairs, expected_pairs);
+        assert_eq!(warnings, expected_warnings);
+        let progress = progress.lock().unwrap();
+        assert_eq!(
+            progress.first(),
+            Some(&Progress {
+                completed_pairs: 0,
+                total_pairs: 3
+            })
+        );
+        assert_eq!(
+            progress.last(),
+            Some(&Progress {
+                completed_pairs: 3,
+                total_pairs: 3
+            })
+        );
+    }
+}
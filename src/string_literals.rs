@@ -0,0 +1,119 @@
+//! Auxiliary detector listing the pairs of projects which share rare string literals. Distinctive error messages and
+//! prompts are hard to explain away, even when the code around them has been rewritten.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    lexing,
+    output::{SharedLiteral, StringLiteralPair},
+    DetectionOptions, File,
+};
+
+/// Returns the pairs of projects sharing string literals which are found in at most `max_projects` projects, from the
+/// pair sharing the most literals to the one sharing the fewest. Literals found in the ignored documents (i.e., starter
+/// code) are never reported.
+pub fn shared_literals(
+    options: &DetectionOptions,
+    documents: &[File],
+    ignored_documents: &[File],
+    max_projects: usize,
+) -> Vec<StringLiteralPair> {
+    let ignored_literals = ignored_documents
+        .iter()
+        .flat_map(|d| lexing::string_literals(&d.contents, options.hash_dialect))
+        .collect::<BTreeSet<_>>();
+
+    let mut projects_by_literal: BTreeMap<&str, BTreeSet<&PathBuf>> = BTreeMap::new();
+    for document in documents {
+        for literal in lexing::string_literals(&document.contents, options.hash_dialect) {
+            if !ignored_literals.contains(literal) {
+                projects_by_literal
+                    .entry(literal)
+                    .or_default()
+                    .insert(&document.project);
+            }
+        }
+    }
+
+    let mut literals_by_pair: BTreeMap<(&PathBuf, &PathBuf), Vec<SharedLiteral>> = BTreeMap::new();
+    for (literal, projects) in projects_by_literal.iter() {
+        if projects.len() > max_projects {
+            continue;
+        }
+        for (&project1, &project2) in projects.iter().tuple_combinations() {
+            if !options.compares(project1, project2) {
+                continue;
+            }
+            literals_by_pair
+                .entry((project1, project2))
+                .or_default()
+                .push(SharedLiteral {
+                    literal: (*literal).to_owned(),
+                    num_projects: projects.len(),
+                });
+        }
+    }
+
+    literals_by_pair
+        .into_iter()
+        .map(|((project1, project2), mut literals)| {
+            literals.sort_by(|l1, l2| {
+                (l1.num_projects, &l1.literal).cmp(&(l2.num_projects, &l2.literal))
+            });
+            StringLiteralPair {
+                project1: project1.to_owned(),
+                project2: project2.to_owned(),
+                literals,
+            }
+        })
+        // The sort is stable, so pairs sharing as many literals stay ordered by name
+        .sorted_by(|p1, p2| p2.literals.len().cmp(&p1.literals.len()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rare_literals_are_shared() {
+        let file = |project: &str, contents: &str| {
+            File::new(
+                project.into(),
+                format!("{project}/main.s").into(),
+                contents.to_owned(),
+            )
+        };
+        let documents = vec![
+            file(
+                "alice",
+                ".asciz \"Enter a nmuber:\"\n.asciz \"%d\"\n.asciz \"Bye\"",
+            ),
+            file(
+                "bob",
+                ".asciz \"Enter a nmuber:\" @ \"typo\"\n.asciz \"%d\"",
+            ),
+            file("carol", ".asciz \"Enter a number:\"\n.asciz \"%d\""),
+            file("dave", ".asciz \"%d\"\n.asciz \"Bye\""),
+        ];
+        let starter_code = vec![file("starter", ".asciz \"Bye\"")];
+
+        let pairs = shared_literals(&DetectionOptions::default(), &documents, &starter_code, 2);
+        assert_eq!(
+            pairs,
+            vec![StringLiteralPair {
+                project1: "alice".into(),
+                project2: "bob".into(),
+                literals: vec![SharedLiteral {
+                    literal: "\"Enter a nmuber:\"".to_owned(),
+                    num_projects: 2,
+                }],
+            }]
+        );
+    }
+}
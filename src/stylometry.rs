@@ -0,0 +1,170 @@
+//! Secondary detector comparing how often each project uses each instruction. Pairs of projects with the same unusual
+//! mix of instructions are flagged even when little of their code matches literally.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use itertools::Itertools;
+
+use crate::{lexing, output::StylometryPair, scoring, DetectionOptions, File};
+
+/// Number of instructions listed as contributing the most to the similarity of a pair.
+const MAX_DISTINCTIVE_INSTRUCTIONS: usize = 5;
+
+/// Returns the pairs of projects whose instruction frequency profiles have a cosine similarity of at least
+/// `min_similarity`, from the most to the least similar.
+///
+/// Each instruction is weighted by its frequency in the project and by how rare it is across the projects (TF-IDF).
+/// Instructions used by every project carry no weight, so the similarity reflects the unusual instructions.
+pub fn similar_projects(
+    options: &DetectionOptions,
+    documents: &[File],
+    min_similarity: f64,
+) -> Vec<StylometryPair> {
+    let profiles = weight_profiles(&instruction_profiles(options, documents));
+
+    let mut pairs = profiles
+        .iter()
+        .tuple_combinations()
+        .filter(|((project1, _), (project2, _))| options.compares(project1, project2))
+        .filter_map(|((project1, profile1), (project2, profile2))| {
+            let (similarity, distinctive_instructions) = compare_profiles(profile1, profile2);
+            (similarity >= min_similarity).then(|| StylometryPair {
+                project1: (*project1).to_owned(),
+                project2: (*project2).to_owned(),
+                similarity,
+                distinctive_instructions,
+            })
+        })
+        .collect::<Vec<_>>();
+    pairs.sort_by(|p1, p2| {
+        p2.similarity
+            .total_cmp(&p1.similarity)
+            .then_with(|| (&p1.project1, &p1.project2).cmp(&(&p2.project1, &p2.project2)))
+    });
+    pairs
+}
+
+/// Counts the occurrences of each instruction in each project.
+fn instruction_profiles<'a>(
+    options: &DetectionOptions,
+    documents: &'a [File],
+) -> BTreeMap<&'a PathBuf, BTreeMap<String, usize>> {
+    let mut profiles: BTreeMap<&PathBuf, BTreeMap<String, usize>> = BTreeMap::new();
+    for document in documents {
+        let profile = profiles.entry(&document.project).or_default();
+        let instructions = lexing::instructions(
+            &document.contents,
+            options.key_symbols.as_ref(),
+            options.hash_dialect,
+        );
+        for instruction in instructions {
+            *profile.entry(instruction).or_default() += 1;
+        }
+    }
+    profiles
+}
+
+/// Weights the instruction counts of each project by TF-IDF and normalizes the profiles to unit length. Projects whose
+/// profiles have no weight (e.g., because they only use instructions found in every project) are left out.
+///
+/// This is also used for profiles counting other features than instructions.
+pub(crate) fn weight_profiles<'a>(
+    profiles: &BTreeMap<&'a PathBuf, BTreeMap<String, usize>>,
+) -> BTreeMap<&'a PathBuf, BTreeMap<String, f64>> {
+    let mut num_projects_by_instruction: BTreeMap<&str, usize> = BTreeMap::new();
+    for profile in profiles.values() {
+        for instruction in profile.keys() {
+            *num_projects_by_instruction.entry(instruction).or_default() += 1;
+        }
+    }
+
+    let num_projects = profiles.len() as f64;
+    profiles
+        .iter()
+        .filter_map(|(&project, profile)| {
+            let total = profile.values().sum::<usize>() as f64;
+            let weights = profile
+                .iter()
+                .map(|(instruction, &count)| {
+                    // Unlike the smoothed IDF used for scoring, this is zero for instructions found in every project
+                    let idf = (num_projects
+                        / num_projects_by_instruction[instruction.as_str()] as f64)
+                        .ln();
+                    (instruction.clone(), count as f64 / total * idf)
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect::<BTreeMap<_, _>>();
+            let norm = scoring::sum_weights(weights.values().map(|w| w * w)).sqrt();
+            (norm > 0.0).then(|| {
+                let weights = weights
+                    .into_iter()
+                    .map(|(instruction, weight)| (instruction, weight / norm))
+                    .collect();
+                (project, weights)
+            })
+        })
+        .collect()
+}
+
+/// Returns the cosine similarity of two normalized profiles, along with the instructions contributing the most to it.
+pub(crate) fn compare_profiles(
+    profile1: &BTreeMap<String, f64>,
+    profile2: &BTreeMap<String, f64>,
+) -> (f64, Vec<String>) {
+    let contributions = profile1
+        .iter()
+        .filter_map(|(instruction, weight1)| {
+            profile2
+                .get(instruction)
+                .map(|weight2| (instruction, weight1 * weight2))
+        })
+        .collect::<Vec<_>>();
+    // Rounding errors can push the similarity of identical profiles slightly above 1
+    let similarity = scoring::sum_weights(contributions.iter().map(|&(_, c)| c)).min(1.0);
+    let distinctive_instructions = contributions
+        .into_iter()
+        .sorted_by(|(i1, c1), (i2, c2)| c2.total_cmp(c1).then_with(|| i1.cmp(i2)))
+        .take(MAX_DISTINCTIVE_INSTRUCTIONS)
+        .map(|(instruction, _)| instruction.clone())
+        .collect();
+    (similarity, distinctive_instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unusual_instruction_mixes_are_similar() {
+        let file = |project: &str, contents: &str| {
+            File::new(
+                project.into(),
+                format!("{project}/main.s").into(),
+                contents.to_owned(),
+            )
+        };
+        let documents = vec![
+            file(
+                "alice",
+                "mov r0, r1\nvmul.f32 s0, s1, s2\nvadd.f32 s0, s0, s1\nbx lr\n",
+            ),
+            file(
+                "bob",
+                "mov r2, r3\nvmul.f32 s2, s3, s4\nvadd.f32 s4, s2, s3\nbx lr\n",
+            ),
+            file("carol", "mov r0, r1\nadd r0, r0, #1\nbx lr\n"),
+            file("dave", "mov r0, r1\nsub r0, r0, #1\nbx lr\n"),
+        ];
+
+        let pairs = similar_projects(&DetectionOptions::default(), &documents, 0.9);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].project1, PathBuf::from("alice"));
+        assert_eq!(pairs[0].project2, PathBuf::from("bob"));
+        assert!((pairs[0].similarity - 1.0).abs() < 1e-9);
+        // Instructions used by every project carry no weight
+        assert_eq!(
+            pairs[0].distinctive_instructions,
+            vec!["vadd.f32", "vmul.f32"]
+        );
+    }
+}
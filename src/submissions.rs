@@ -0,0 +1,160 @@
+//! Submission times of the projects, which hint at the direction in which code may have been copied.
+
+use std::{cmp::Ordering, collections::HashMap, path::PathBuf};
+
+use crate::output::{EarlierSubmission, Output, ProjectPair, Submission};
+
+impl Output {
+    /// Records the submission time of each project, and annotates each pair of projects which both have one with the
+    /// project submitted first.
+    pub fn add_submissions(&mut self, submissions: Vec<Submission>) {
+        let times = SubmissionTimes::new(&submissions);
+        for pair in self.project_pairs.iter_mut() {
+            pair.earlier_submission = times.earlier_submission(pair);
+        }
+        self.submissions = Some(submissions);
+    }
+}
+
+/// Submission times of the projects, indexed by project.
+pub struct SubmissionTimes<'a>(HashMap<&'a PathBuf, i64>);
+
+impl<'a> SubmissionTimes<'a> {
+    pub fn new(submissions: &'a [Submission]) -> Self {
+        SubmissionTimes(
+            submissions
+                .iter()
+                .map(|s| (&s.project, s.timestamp))
+                .collect(),
+        )
+    }
+
+    /// Returns which project of the pair was submitted first, if both have a submission time.
+    pub fn earlier_submission(&self, pair: &ProjectPair) -> Option<EarlierSubmission> {
+        match (self.0.get(&pair.project1), self.0.get(&pair.project2)) {
+            (Some(t1), Some(t2)) => Some(match t1.cmp(t2) {
+                Ordering::Less => EarlierSubmission::Project1,
+                Ordering::Greater => EarlierSubmission::Project2,
+                Ordering::Equal => EarlierSubmission::Same,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a time given on the command line, either in seconds since the Unix epoch or as a UTC date (`YYYY-MM-DD`) or
+/// date and time (`YYYY-MM-DDTHH:MM:SS`), and returns it in seconds since the Unix epoch.
+pub fn parse_timestamp(s: &str) -> Result<i64, String> {
+    if let Ok(seconds) = s.parse() {
+        return Ok(seconds);
+    }
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) => (date, time),
+        None => (s, "00:00:00"),
+    };
+    let fields = |s: &str, separator| {
+        s.split(separator)
+            .map(|f| f.parse::<i64>().ok().filter(|_| !f.starts_with(['+', '-'])))
+            .collect::<Option<Vec<_>>>()
+    };
+    match (fields(date, '-').as_deref(), fields(time, ':').as_deref()) {
+        (Some(&[year, month, day]), Some(&[hour, minute, second]))
+            if (1..=12).contains(&month)
+                && (1..=days_in_month(year, month)).contains(&day)
+                && hour < 24
+                && minute < 60
+                && second < 60 =>
+        {
+            Ok(days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second)
+        }
+        _ => Err(format!(
+            "expected seconds since the Unix epoch, YYYY-MM-DD, or YYYY-MM-DDTHH:MM:SS, found \"{s}\""
+        )),
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days between the Unix epoch and the given date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years start in March, so that the leap day is the last day of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{ProjectPair, TimestampSource};
+
+    #[test]
+    fn pairs_are_annotated_with_earlier_submission() {
+        let pair = |project1: &str, project2: &str| ProjectPair {
+            project1: project1.into(),
+            project2: project2.into(),
+            matches: Vec::new(),
+            score: None,
+            explanation: None,
+            earlier_submission: None,
+            review: None,
+        };
+        let submission = |project: &str, timestamp| Submission {
+            project: project.into(),
+            timestamp,
+            source: TimestampSource::Metadata,
+        };
+        let mut output = Output::new(
+            Vec::new(),
+            vec![
+                pair("a", "b"),
+                pair("a", "c"),
+                pair("b", "c"),
+                pair("c", "d"),
+            ],
+        );
+
+        output.add_submissions(vec![
+            submission("a", 200),
+            submission("b", 100),
+            submission("c", 100),
+        ]);
+        let earlier = output
+            .project_pairs
+            .iter()
+            .map(|p| p.earlier_submission)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            earlier,
+            vec![
+                Some(EarlierSubmission::Project2),
+                Some(EarlierSubmission::Project2),
+                Some(EarlierSubmission::Same),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_parsed() {
+        assert_eq!(parse_timestamp("1700000000"), Ok(1_700_000_000));
+        assert_eq!(parse_timestamp("1970-01-01"), Ok(0));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20"), Ok(1_700_000_000));
+        assert_eq!(parse_timestamp("2024-02-29"), Ok(1_709_164_800));
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59"), Ok(-1));
+        assert!(parse_timestamp("2023-02-29").is_err());
+        assert!(parse_timestamp("2023-11-14T24:00:00").is_err());
+        assert!(parse_timestamp("2023-11").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+    }
+}
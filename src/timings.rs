@@ -0,0 +1,97 @@
+//! Measurement of the wall time and peak memory of each stage of an analysis, requested with --timings.
+
+use std::{fs, time::Instant};
+
+use fungus_cli::{output::StageTiming, DetectionObserver, Stage};
+
+/// Records the stages of an analysis as they start and finish. Nothing is measured unless enabled.
+#[derive(Debug, Default)]
+pub struct Timings {
+    enabled: bool,
+    started: Option<(Stage, Instant)>,
+    stages: Vec<StageTiming>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Timings {
+        Timings {
+            enabled,
+            ..Default::default()
+        }
+    }
+
+    pub fn start(&mut self, stage: Stage) {
+        if self.enabled {
+            reset_peak_memory();
+            self.started = Some((stage, Instant::now()));
+        }
+    }
+
+    pub fn finish(&mut self, stage: Stage) {
+        let Some((started_stage, start)) = self.started.take() else {
+            return;
+        };
+        debug_assert_eq!(started_stage, stage);
+        self.stages.push(StageTiming {
+            stage,
+            seconds: start.elapsed().as_secs_f64(),
+            peak_memory_bytes: peak_memory(),
+        });
+    }
+
+    /// Returns the stages measured so far, or `None` if the timings are disabled.
+    pub fn stages(&self) -> Option<Vec<StageTiming>> {
+        self.enabled.then(|| self.stages.clone())
+    }
+
+    /// Prints a table of the stages measured so far to standard error.
+    pub fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        eprintln!("{:<16} {:>10} {:>12}", "Stage", "Time (s)", "Peak memory");
+        for timing in &self.stages {
+            let memory = match timing.peak_memory_bytes {
+                Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+                None => "unknown".to_owned(),
+            };
+            let stage = serde_json::to_value(timing.stage).unwrap_or_default();
+            eprintln!(
+                "{:<16} {:>10.3} {:>12}",
+                stage.as_str().unwrap_or_default(),
+                timing.seconds,
+                memory
+            );
+        }
+    }
+}
+
+impl DetectionObserver for Timings {
+    fn on_stage_started(&mut self, stage: Stage) {
+        self.start(stage);
+    }
+
+    fn on_stage_finished(&mut self, stage: Stage) {
+        self.finish(stage);
+    }
+}
+
+/// Resets the peak resident memory of the process, so that the next measurement only covers the following stage. Only
+/// supported on Linux.
+fn reset_peak_memory() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+/// Returns the peak resident memory of the process since it was last reset, in bytes. Only supported on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
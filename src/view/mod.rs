@@ -0,0 +1,253 @@
+//! Local web server for browsing existing JSON output interactively.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+
+const VIEWER_HTML: &str = include_str!("viewer.html");
+
+#[derive(clap::Args, Debug)]
+pub struct ViewArgs {
+    /// JSON output file produced by a previous analysis, which may be compressed.
+    output_file: PathBuf,
+    /// Projects directory which was analyzed. The paths in the output file are relative to this directory.
+    #[arg(long)]
+    root: PathBuf,
+    /// Port on which to serve the viewer.
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+}
+
+/// Serves the match browser until the process is interrupted.
+pub fn view(args: ViewArgs) -> anyhow::Result<()> {
+    let json = fs::read(&args.output_file)
+        .map_err(anyhow::Error::from)
+        .and_then(decompress)
+        .with_context(|| {
+            format!(
+                "Failed to read output file \"{}\".",
+                args.output_file.display()
+            )
+        })?;
+    check_viewable(&json).with_context(|| {
+        format!(
+            "Cannot view output file \"{}\".",
+            args.output_file.display()
+        )
+    })?;
+    if !args.root.is_dir() {
+        anyhow::bail!(
+            "Projects directory '{}' is not a directory.",
+            args.root.display()
+        );
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port))
+        .with_context(|| format!("Failed to listen on port {}.", args.port))?;
+    println!(
+        "Serving \"{}\" at http://127.0.0.1:{}/ (press Ctrl-C to stop).",
+        args.output_file.display(),
+        args.port
+    );
+
+    for stream in listener.incoming() {
+        let result = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle_connection(stream, &json, &args.root));
+        if let Err(e) = result {
+            eprintln!("Failed to handle request: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompresses output written with `--compress`, which is recognized by the magic number of gzip.
+fn decompress(bytes: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes);
+    }
+    let mut json = Vec::new();
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+    Ok(json)
+}
+
+/// Checks that the output is JSON output whose paths are relative to the projects directory, since the source files are
+/// looked up relative to it.
+fn check_viewable(json: &[u8]) -> anyhow::Result<()> {
+    let output: Value = serde_json::from_slice(json).map_err(|_| {
+        anyhow::anyhow!("It is not JSON output. Only the \"json\" output format can be viewed.")
+    })?;
+    let projects = output["project_pairs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|pair| [&pair["project1"], &pair["project2"]]);
+    for project in projects.filter_map(Value::as_str) {
+        if Path::new(project).has_root() {
+            anyhow::bail!(
+                "The project \"{project}\" has an absolute path. Only output whose paths are relative to the projects directory (the default --path-style) can be viewed."
+            );
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, json: &[u8], root: &Path) -> anyhow::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let target = match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, _] => target,
+        _ => return respond(&mut stream, "405 Method Not Allowed", "text/plain", b""),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    match path {
+        "/" => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            VIEWER_HTML.as_bytes(),
+        ),
+        "/output.json" => respond(&mut stream, "200 OK", "application/json", json),
+        "/source" => {
+            let file = query
+                .split('&')
+                .find_map(|param| param.strip_prefix("path="))
+                .map(percent_decode);
+            match file.and_then(|f| resolve_source_path(root, &f)) {
+                None => respond(&mut stream, "400 Bad Request", "text/plain", b""),
+                Some(source_path) => match fs::read(source_path) {
+                    Err(_) => respond(&mut stream, "404 Not Found", "text/plain", b""),
+                    Ok(contents) => {
+                        respond(&mut stream, "200 OK", "application/octet-stream", &contents)
+                    }
+                },
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b""),
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+/// Resolves a path from the output file against the projects directory. Returns `None` for paths which could escape
+/// the projects directory, or which do not name anything inside it.
+fn resolve_source_path(root: &Path, file: &str) -> Option<PathBuf> {
+    let file = Path::new(file);
+    if file.as_os_str().is_empty()
+        || file
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(root.join(file))
+}
+
+/// Decodes a percent-encoded URL query value.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        // `from_str_radix` alone would also accept a sign (e.g., "%+1")
+        let escaped_byte = value
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped_byte) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => decoded.push(b' '),
+            (b, _) => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn source_paths_stay_in_the_root() {
+        let root = Path::new("projects");
+        assert_eq!(
+            resolve_source_path(root, "alice/main.s"),
+            Some(root.join("alice/main.s"))
+        );
+        for path in [
+            "../secret",
+            "alice/../../secret",
+            "/etc/passwd",
+            "./alice/main.s",
+            "",
+        ] {
+            assert_eq!(resolve_source_path(root, path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn escapes_are_decoded() {
+        assert_eq!(percent_decode("alice%2Fmain.s"), "alice/main.s");
+        assert_eq!(percent_decode("a+b%20c"), "a b c");
+        assert_eq!(percent_decode("%2e%2e%2fsecret"), "../secret");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        // Malformed escapes are kept as they are
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%2"), "%2");
+        assert_eq!(percent_decode("%zz%+1%é"), "%zz% 1%é");
+
+        let root = Path::new("projects");
+        for query in [
+            "%2e%2e%2fsecret",
+            "%2Fetc%2Fpasswd",
+            "alice%2F..%2F..%2Fsecret",
+        ] {
+            assert_eq!(resolve_source_path(root, &percent_decode(query)), None);
+        }
+    }
+
+    #[test]
+    fn only_relative_json_output_can_be_viewed() {
+        let json = br#"{"project_pairs": [{"project1": "alice", "project2": "bob"}]}"#;
+        assert!(check_viewable(json).is_ok());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(json).unwrap();
+        assert_eq!(decompress(encoder.finish().unwrap()).unwrap(), json);
+
+        let absolute = br#"{"project_pairs": [{"project1": "/srv/alice", "project2": "bob"}]}"#;
+        assert!(check_viewable(absolute).is_err());
+        assert!(check_viewable(b"{\"type\":\"metadata\"}\n{\"type\":\"summary\"}\n").is_err());
+    }
+}